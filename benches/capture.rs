@@ -0,0 +1,117 @@
+//! How much sentinel-rs adds to a command that prints a lot: the capture
+//! loop on its own, fed from memory, and whole runs of a command that
+//! prints 512 MiB next to the same command piped into `cat`. Run with
+//! `cargo bench`; criterion reports the throughput of each.
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use sentinel_rs::runner::{self, Exec, READ_BUFFER};
+use std::io::Read;
+use std::process::{Command, Stdio};
+
+const MIB: usize = 1024 * 1024;
+
+/// `len` bytes of 80-character lines, made up as they are read.
+struct Lines {
+    line: Vec<u8>,
+    at: usize,
+    left: usize,
+}
+
+impl Lines {
+    fn new(len: usize) -> Self {
+        let mut line = vec![b'x'; 79];
+        line.push(b'\n');
+        Lines {
+            line,
+            at: 0,
+            left: len,
+        }
+    }
+}
+
+impl Read for Lines {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut n = 0;
+        while n < buf.len() && self.left > 0 {
+            let chunk = (self.line.len() - self.at)
+                .min(buf.len() - n)
+                .min(self.left);
+            buf[n..n + chunk].copy_from_slice(&self.line[self.at..self.at + chunk]);
+            self.at = (self.at + chunk) % self.line.len();
+            self.left -= chunk;
+            n += chunk;
+        }
+        Ok(n)
+    }
+}
+
+fn capture_loop(c: &mut Criterion) {
+    let size = 64 * MIB;
+    let mut group = c.benchmark_group("capture loop");
+    group.throughput(Throughput::Bytes(size as u64));
+    group.bench_function("read_stream", |b| {
+        b.iter(|| runner::read_stream(Lines::new(size), std::io::sink(), false))
+    });
+    group.bench_function("read_stream, teed", |b| {
+        b.iter(|| runner::read_stream(Lines::new(size), std::io::sink(), true))
+    });
+    group.bench_function("read_stream_lines, with a line hook", |b| {
+        b.iter(|| {
+            let mut lines = 0usize;
+            let mut emit = |_: &str| lines += 1;
+            runner::read_stream_lines(
+                Lines::new(size),
+                std::io::sink(),
+                false,
+                Some(&mut emit),
+                READ_BUFFER,
+            )
+        })
+    });
+    group.bench_function("read_stream_lines, 64 KiB reads", |b| {
+        b.iter(|| {
+            runner::read_stream_lines(Lines::new(size), std::io::sink(), false, None, 64 * 1024)
+        })
+    });
+    group.finish();
+}
+
+fn whole_runs(c: &mut Criterion) {
+    let size = 512 * MIB;
+    let command = format!("head -c {size} /dev/zero");
+    let mut group = c.benchmark_group("whole runs");
+    group.throughput(Throughput::Bytes(size as u64));
+    group.sample_size(10);
+    group.bench_function("the command | cat > /dev/null", |b| {
+        b.iter(|| {
+            Command::new("bash")
+                .arg("-c")
+                .arg(format!("{command} | cat > /dev/null"))
+                .stdout(Stdio::null())
+                .status()
+        })
+    });
+    group.bench_function("run_bash", |b| {
+        b.iter(|| runner::run_bash_with_hook(&command, &Exec::default(), false, None))
+    });
+    let log = std::env::temp_dir().join(format!("sentinel-bench-{}.log", std::process::id()));
+    group.bench_function("run_bash, logging all of it", |b| {
+        let exec = Exec {
+            log: Some(log.clone()),
+            ..Exec::default()
+        };
+        b.iter(|| runner::run_bash_with_hook(&command, &exec, false, None))
+    });
+    std::fs::remove_file(&log).ok();
+    group.bench_function("run_bash, merging stdout and stderr", |b| {
+        let exec = Exec {
+            merge_output: true,
+            ..Exec::default()
+        };
+        b.iter(|| runner::run_bash_with_hook(&command, &exec, false, None))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, capture_loop, whole_runs);
+criterion_main!(benches);
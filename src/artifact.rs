@@ -0,0 +1,92 @@
+//! `--artifact GLOB`: files a run is meant to leave behind, e.g.
+//! `dist/*.tar.gz`. Once it is done, the finish notification lists what the
+//! patterns match, with sizes and modification times, marks files the run
+//! did not write (left over from an earlier one) and names the patterns
+//! that match nothing.
+
+use crate::clock;
+use crate::event::{Artifact, Artifacts, format_bytes};
+use std::time::{Duration, SystemTime};
+
+/// At most this many files are listed.
+const MAX_LISTED: usize = 20;
+
+pub fn parse_pattern(s: &str) -> Result<String, String> {
+    glob::Pattern::new(s)
+        .map(|_| s.to_string())
+        .map_err(|e| e.to_string())
+}
+
+/// The files `patterns` match, relative to the current directory, for a
+/// run that started at `since`.
+pub fn collect(patterns: &[String], since: SystemTime) -> Artifacts {
+    let mut artifacts = Artifacts::default();
+    for pattern in patterns {
+        let mut matched = false;
+        for path in glob::glob(pattern).into_iter().flatten().flatten() {
+            let Ok(meta) = std::fs::metadata(&path) else {
+                continue;
+            };
+            if !meta.is_file() {
+                continue;
+            }
+            matched = true;
+            let path = path.display().to_string();
+            if artifacts.found.iter().any(|a| a.path == path) {
+                continue;
+            }
+            let modified = meta.modified().ok();
+            artifacts.found.push(Artifact {
+                path,
+                bytes: meta.len(),
+                size: format_bytes(meta.len()),
+                modified: modified.map(clock::format).unwrap_or_default(),
+                // File systems keep coarser times than the clock.
+                stale: modified.is_some_and(|t| t + Duration::from_secs(1) < since),
+            });
+        }
+        if !matched {
+            artifacts.missing.push(pattern.clone());
+        }
+    }
+    artifacts.more = artifacts.found.len().saturating_sub(MAX_LISTED);
+    artifacts.found.truncate(MAX_LISTED);
+    artifacts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_matches_and_missing_patterns() {
+        let dir = std::env::temp_dir().join(format!("sentinel-artifacts-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("dist")).unwrap();
+        std::fs::write(dir.join("dist/app.tar.gz"), vec![0; 2048]).unwrap();
+        std::fs::write(dir.join("dist/app.sha256"), "x").unwrap();
+        let pattern = |p: &str| dir.join(p).display().to_string();
+
+        let later = SystemTime::now() + Duration::from_secs(60);
+        let artifacts = collect(
+            &[
+                pattern("dist/*.tar.gz"),
+                pattern("dist/*"),
+                pattern("*.deb"),
+            ],
+            later,
+        );
+        let found: Vec<_> = artifacts
+            .found
+            .iter()
+            .map(|a| (a.path.strip_prefix(&pattern("")).unwrap(), a.size.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [("dist/app.tar.gz", "2.0 KiB"), ("dist/app.sha256", "1 B")]
+        );
+        assert!(artifacts.found.iter().all(|a| a.stale));
+        assert_eq!(artifacts.missing, [pattern("*.deb")]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
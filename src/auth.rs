@@ -0,0 +1,66 @@
+//! `sentinel-rs auth`: the Telegram bot token and chat id kept in the OS
+//! keyring (Secret Service on Linux, the macOS Keychain, the Windows
+//! Credential Manager) instead of a shell profile. Needs the `keyring`
+//! feature; [`crate::config`] falls back to these entries when the
+//! environment and credential files provide nothing.
+
+/// Keyring service the entries are stored under.
+pub const SERVICE: &str = "sentinel-rs";
+pub const BOT_TOKEN: &str = "telegram-bot-token";
+pub const CHAT_ID: &str = "telegram-chat-id";
+
+/// The value stored for `key`, if there is one and the keyring can be
+/// reached.
+pub fn lookup(key: &str) -> Option<String> {
+    #[cfg(feature = "keyring")]
+    {
+        match keyring::Entry::new(SERVICE, key).and_then(|entry| entry.get_password()) {
+            Ok(value) => Some(value),
+            Err(keyring::Error::NoEntry) => None,
+            Err(e) => {
+                tracing::info!("could not read {key} from the keyring: {e}");
+                None
+            }
+        }
+    }
+    #[cfg(not(feature = "keyring"))]
+    {
+        let _ = key;
+        None
+    }
+}
+
+pub fn store(key: &str, value: &str) -> Result<(), String> {
+    #[cfg(feature = "keyring")]
+    {
+        keyring::Entry::new(SERVICE, key)
+            .and_then(|entry| entry.set_password(value))
+            .map_err(|e| format!("could not store {key} in the keyring: {e}"))
+    }
+    #[cfg(not(feature = "keyring"))]
+    {
+        let _ = (key, value);
+        Err(unsupported())
+    }
+}
+
+/// Removes `key`; removing an entry that is not there is not an error.
+pub fn delete(key: &str) -> Result<(), String> {
+    #[cfg(feature = "keyring")]
+    {
+        match keyring::Entry::new(SERVICE, key).and_then(|entry| entry.delete_credential()) {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(format!("could not remove {key} from the keyring: {e}")),
+        }
+    }
+    #[cfg(not(feature = "keyring"))]
+    {
+        let _ = key;
+        Err(unsupported())
+    }
+}
+
+#[cfg(not(feature = "keyring"))]
+fn unsupported() -> String {
+    "sentinel-rs was built without the keyring feature".to_string()
+}
@@ -0,0 +1,140 @@
+//! `sentinel-rs test`: one test notification through every configured
+//! backend, each timed on its own, for running from cron as a canary. The
+//! notification is a successful run of `sentinel-rs test`, so backends that
+//! only report finished runs (Grafana, OpenTelemetry) send something too. It
+//! goes through the plugins in turn, as a run's would, with the requests
+//! each asks for made and timed, and then to Telegram. The run history of
+//! the [dashboard](crate::dashboard) is left out, since this is not a run,
+//! and so are [quiet hours](crate::quiet) and the [repeated failures
+//! window](crate::repeat): a canary is never held back.
+
+use crate::RunOptions;
+use crate::config::TgConfig;
+use crate::doctor;
+use crate::event::{Event, EventKind};
+use crate::notifier::http_client_with;
+use crate::plugin::{Action, perform_http};
+use crate::telegram::telegram_payload;
+use std::time::{Duration, Instant};
+
+/// How one backend did.
+#[derive(Debug)]
+pub struct Outcome {
+    pub backend: String,
+    pub took: Duration,
+    /// What it did, or what went wrong.
+    pub result: Result<String, String>,
+}
+
+impl Outcome {
+    /// `ok`/`failing`, the backend, how long it took and what it did.
+    pub fn line(&self) -> String {
+        let ms = self.took.as_millis();
+        match &self.result {
+            Ok(detail) => format!("ok       {:<14} {ms:>6} ms  {detail}", self.backend),
+            Err(e) => format!("failing  {:<14} {ms:>6} ms  {e}", self.backend),
+        }
+    }
+}
+
+/// Sends the test notification through the plugins in `opts` and then to
+/// Telegram, returning how each did in that order.
+pub fn run(cfg: &TgConfig, mut opts: RunOptions) -> Vec<Outcome> {
+    let client = match http_client_with(&cfg.http) {
+        Ok(client) => client,
+        Err(e) => {
+            return vec![Outcome {
+                backend: "HTTP client".to_string(),
+                took: Duration::ZERO,
+                result: Err(e),
+            }];
+        }
+    };
+    let mut event = Event {
+        exit_code: Some(0),
+        ..Event::new(EventKind::Success, "sentinel-rs test")
+    };
+    event.set_duration(Duration::ZERO);
+    event.text = opts.templates.render(&event);
+
+    let mut outcomes = Vec::new();
+    for plugin in opts.plugins.iter_mut() {
+        if ["dashboard", "quiet-hours", "repeats"].contains(&plugin.name()) {
+            continue;
+        }
+        let started = Instant::now();
+        let result = plugin
+            .on_event(&event.to_json())
+            .map_err(|e| e.to_string())
+            .and_then(|actions| {
+                let mut requests = 0;
+                let mut notes: Vec<String> = Vec::new();
+                for action in actions {
+                    match action {
+                        Action::Http { .. } => {
+                            perform_http(&client, &action).map_err(|e| e.to_string())?;
+                            requests += 1;
+                        }
+                        Action::Rewrite { text } => {
+                            event.text = text;
+                            notes.push("rewrote the text".to_string());
+                        }
+                        Action::SetSeverity { severity } => event.severity = severity,
+                        Action::Drop => notes.push("would drop the notification".to_string()),
+                    }
+                }
+                notes.insert(
+                    0,
+                    match requests {
+                        0 => "no requests".to_string(),
+                        1 => "1 request".to_string(),
+                        n => format!("{n} requests"),
+                    },
+                );
+                Ok(notes.join(", "))
+            });
+        outcomes.push(Outcome {
+            backend: plugin.name().to_string(),
+            took: started.elapsed(),
+            result,
+        });
+    }
+
+    let started = Instant::now();
+    // Unlike a run's, this message must be seen to arrive: the Bot API's
+    // answer is checked, not only that there was one.
+    let payload = telegram_payload(&cfg.chat_id, &event.text);
+    let result = doctor::call(&client, cfg, "sendMessage", payload)
+        .map(|_| "sent".to_string())
+        .map_err(|e| e.finding("Telegram", cfg).detail);
+    outcomes.push(Outcome {
+        backend: "Telegram".to_string(),
+        took: started.elapsed(),
+        result,
+    });
+    outcomes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outcome_lines_line_up() {
+        let ok = Outcome {
+            backend: "grafana".to_string(),
+            took: Duration::from_millis(88),
+            result: Ok("1 request".to_string()),
+        };
+        assert_eq!(ok.line(), "ok       grafana            88 ms  1 request");
+        let failing = Outcome {
+            backend: "Telegram".to_string(),
+            took: Duration::from_millis(10_250),
+            result: Err("timed out".to_string()),
+        };
+        assert_eq!(
+            failing.line(),
+            "failing  Telegram        10250 ms  timed out"
+        );
+    }
+}
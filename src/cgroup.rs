@@ -0,0 +1,348 @@
+//! `--memory-max` and `--cpu-max`: resource limits for a local command,
+//! enforced by a transient cgroup (v2) that exists only for the run.
+//!
+//! The cgroup is created next to the one sentinel-rs runs in (a cgroup with
+//! processes cannot also have limited children), and the child joins it
+//! just before it execs bash, so everything it starts is covered. When the
+//! run ends, `memory.events` and `cpu.stat` say whether the limits were hit;
+//! that goes into the finish notification along with the CPU and memory the
+//! command used. Needs a cgroup v2 hierarchy we may write to: root, or a
+//! delegated subtree such as a systemd user session.
+
+use crate::event::{ResourceUsage, format_bytes};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+/// CFS period `cpu.max` quotas are given in.
+const CPU_PERIOD_USEC: u64 = 100_000;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Limits {
+    pub memory_max: Option<u64>,
+    /// In percent of one core, e.g. 150.0 for one and a half.
+    pub cpu_max_percent: Option<f64>,
+}
+
+impl Limits {
+    pub fn is_empty(&self) -> bool {
+        *self == Limits::default()
+    }
+
+    /// The limits for the start message, e.g. `["memory max 2.0 GiB"]`.
+    pub fn describe(&self) -> Vec<String> {
+        let mut settings = Vec::new();
+        if let Some(bytes) = self.memory_max {
+            settings.push(format!("memory max {}", format_bytes(bytes)));
+        }
+        if let Some(percent) = self.cpu_max_percent {
+            settings.push(format!("CPU max {percent}%"));
+        }
+        settings
+    }
+}
+
+/// Parses a size such as `512M`, `2G` or `1.5GiB` (binary units, as for
+/// docker and systemd) or a plain number of bytes.
+pub fn parse_memory(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("expected a size like 512M or 2G, got {s:?}"))?;
+    let unit = unit.to_ascii_uppercase();
+    let unit = unit
+        .strip_suffix("IB")
+        .or_else(|| unit.strip_suffix('B'))
+        .unwrap_or(&unit);
+    let scale: u64 = match unit {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => {
+            return Err(format!(
+                "unknown size unit in {s:?} (expected K, M, G or T)"
+            ));
+        }
+    };
+    let bytes = number * scale as f64;
+    if bytes < 1.0 {
+        return Err(format!("{s:?} is too small to be a memory limit"));
+    }
+    Ok(bytes as u64)
+}
+
+/// Parses `150%` or a number of CPUs such as `1.5`, into percent of one core.
+pub fn parse_cpu(s: &str) -> Result<f64, String> {
+    let s = s.trim();
+    let percent = match s.strip_suffix('%') {
+        Some(percent) => percent.trim().parse::<f64>(),
+        None => s.parse::<f64>().map(|cpus| cpus * 100.0),
+    }
+    .map_err(|_| format!("expected a CPU limit like 150% or 1.5, got {s:?}"))?;
+    // The kernel's smallest quota is 1ms per 100ms period.
+    if !percent.is_finite() || percent < 1.0 {
+        return Err(format!("{s:?} is below the smallest CPU limit (1%)"));
+    }
+    Ok(percent)
+}
+
+/// A cgroup created for one run; [`Cgroup::finish`] removes it.
+pub struct Cgroup {
+    path: PathBuf,
+    limits: Limits,
+}
+
+impl Cgroup {
+    pub fn create(limits: &Limits) -> io::Result<Self> {
+        let parent = parent_dir()?;
+        let controllers = std::fs::read_to_string(parent.join("cgroup.subtree_control"))?;
+        let needed = [
+            ("memory", limits.memory_max.is_some()),
+            ("cpu", limits.cpu_max_percent.is_some()),
+        ];
+        for (controller, _) in needed.iter().filter(|(_, used)| *used) {
+            if !controllers.split_whitespace().any(|c| c == *controller) {
+                return Err(io::Error::other(format!(
+                    "the {controller} controller is not enabled for children of {}",
+                    parent.display()
+                )));
+            }
+        }
+        let path = parent.join(crate::runner::run_name());
+        std::fs::create_dir(&path).map_err(|e| {
+            io::Error::new(e.kind(), format!("cannot create {}: {e}", path.display()))
+        })?;
+        let cgroup = Cgroup {
+            path,
+            limits: *limits,
+        };
+        let configure = || -> io::Result<()> {
+            if let Some(bytes) = limits.memory_max {
+                std::fs::write(cgroup.path.join("memory.max"), bytes.to_string())?;
+            }
+            if let Some(percent) = limits.cpu_max_percent {
+                let quota = (percent / 100.0 * CPU_PERIOD_USEC as f64) as u64;
+                std::fs::write(
+                    cgroup.path.join("cpu.max"),
+                    format!("{quota} {CPU_PERIOD_USEC}"),
+                )?;
+            }
+            Ok(())
+        };
+        if let Err(e) = configure() {
+            // Nothing has joined it yet, so it can go at once.
+            std::fs::remove_dir(&cgroup.path).ok();
+            return Err(io::Error::new(
+                e.kind(),
+                format!("cannot set limits on {}: {e}", cgroup.path.display()),
+            ));
+        }
+        Ok(cgroup)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads what the run used and whether it hit the limits, then removes
+    /// the cgroup (killing anything the command left running in it).
+    pub fn finish(self, elapsed: Duration) -> ResourceUsage {
+        let read = |file: &str| std::fs::read_to_string(self.path.join(file)).unwrap_or_default();
+        let memory_events = read("memory.events");
+        let cpu_stat = read("cpu.stat");
+        let cpu_avg = field(&cpu_stat, "usage_usec")
+            .filter(|_| !elapsed.is_zero())
+            .map(|usec| usec as f64 / elapsed.as_micros() as f64 * 100.0);
+        let memory_peak = read("memory.peak").trim().parse().ok();
+        let hits = limit_hits(&self.limits, &memory_events, &cpu_stat);
+        let usage = ResourceUsage {
+            oom_kills: field(&memory_events, "oom_kill"),
+            ..ResourceUsage::new(cpu_avg, None, memory_peak)
+        }
+        .with_limit_hits(hits);
+        self.remove();
+        usage
+    }
+
+    fn remove(&self) {
+        if std::fs::remove_dir(&self.path).is_ok() {
+            return;
+        }
+        // Still busy: the command left processes behind (kernel 5.14+).
+        std::fs::write(self.path.join("cgroup.kill"), "1").ok();
+        for _ in 0..20 {
+            std::thread::sleep(Duration::from_millis(50));
+            if std::fs::remove_dir(&self.path).is_ok() {
+                return;
+            }
+        }
+        tracing::info!("could not remove cgroup {}", self.path.display());
+    }
+}
+
+/// Where the run's cgroup goes: beside our own, or under the root if that
+/// is where we are.
+fn parent_dir() -> io::Result<PathBuf> {
+    let mounts = std::fs::read_to_string("/proc/self/mounts")?;
+    let mount = mounts
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .find(|fields| fields.get(2) == Some(&"cgroup2"))
+        .and_then(|fields| fields.get(1).map(PathBuf::from))
+        .ok_or_else(|| io::Error::other("no cgroup v2 hierarchy is mounted"))?;
+    let own = std::fs::read_to_string("/proc/self/cgroup")?;
+    let own = own
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .ok_or_else(|| io::Error::other("this process is not in a cgroup v2 hierarchy"))?;
+    let own = Path::new(own.trim_start_matches('/'));
+    match own.parent().filter(|p| !p.as_os_str().is_empty()) {
+        Some(parent) => Ok(mount.join(parent)),
+        None => Ok(mount),
+    }
+}
+
+/// A `key value` entry of a flat-keyed cgroup file.
+fn field(text: &str, key: &str) -> Option<u64> {
+    text.lines().find_map(|line| {
+        let (k, value) = line.split_once(' ')?;
+        (k == key).then(|| value.trim().parse().ok()).flatten()
+    })
+}
+
+/// The limits the run ran into, for the finish notification.
+fn limit_hits(limits: &Limits, memory_events: &str, cpu_stat: &str) -> Vec<String> {
+    let mut hits = Vec::new();
+    if let Some(bytes) = limits.memory_max {
+        let limit = format_bytes(bytes);
+        match (
+            field(memory_events, "max"),
+            field(memory_events, "oom_kill"),
+        ) {
+            (_, Some(kills)) if kills > 0 => hits.push(format!(
+                "memory limit {limit} hit, {kills} process{} OOM-killed",
+                if kills == 1 { "" } else { "es" }
+            )),
+            (Some(max), _) if max > 0 => hits.push(format!("memory limit {limit} hit")),
+            _ => {}
+        }
+    }
+    if let Some(percent) = limits.cpu_max_percent
+        && let Some(throttled) = field(cpu_stat, "nr_throttled").filter(|&n| n > 0)
+    {
+        let secs = field(cpu_stat, "throttled_usec").unwrap_or_default() as f64 / 1e6;
+        hits.push(format!(
+            "CPU limit {percent}% throttled {throttled} times ({secs:.1}s)"
+        ));
+    }
+    hits
+}
+
+/// Has `cmd` move itself into the cgroup at `dir` before it execs.
+pub fn join(cmd: &mut Command, dir: &Path) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::process::CommandExt;
+
+    let procs = std::ffi::CString::new(dir.join("cgroup.procs").as_os_str().as_bytes())
+        .map_err(io::Error::other)?;
+    // SAFETY: only async-signal-safe system calls on prepared data.
+    unsafe {
+        cmd.pre_exec(move || {
+            let fd = libc::open(procs.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // "0" means the writing process.
+            let written = libc::write(fd, c"0".as_ptr().cast(), 1);
+            let error = io::Error::last_os_error();
+            libc::close(fd);
+            if written != 1 {
+                return Err(error);
+            }
+            Ok(())
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_memory_sizes() {
+        assert_eq!(parse_memory("2G"), Ok(2 << 30));
+        assert_eq!(parse_memory("512M"), Ok(512 << 20));
+        assert_eq!(parse_memory("1.5GiB"), Ok(3 << 29));
+        assert_eq!(parse_memory("64kb"), Ok(64 << 10));
+        assert_eq!(parse_memory("4096"), Ok(4096));
+        assert!(parse_memory("2X").is_err());
+        assert!(parse_memory("lots").is_err());
+        assert!(parse_memory("0").is_err());
+    }
+
+    #[test]
+    fn parses_cpu_limits() {
+        assert_eq!(parse_cpu("150%"), Ok(150.0));
+        assert_eq!(parse_cpu("1.5"), Ok(150.0));
+        assert_eq!(parse_cpu("2"), Ok(200.0));
+        assert!(parse_cpu("0%").is_err());
+        assert!(parse_cpu("fast").is_err());
+    }
+
+    #[test]
+    fn reports_limits_that_were_hit() {
+        let limits = Limits {
+            memory_max: Some(2 << 30),
+            cpu_max_percent: Some(150.0),
+        };
+        let events = "low 0\nhigh 0\nmax 12\noom 1\noom_kill 1\noom_group_kill 0\n";
+        let stat = "usage_usec 2500000\nnr_periods 40\nnr_throttled 7\nthrottled_usec 350000\n";
+        assert_eq!(
+            limit_hits(&limits, events, stat),
+            [
+                "memory limit 2.0 GiB hit, 1 process OOM-killed",
+                "CPU limit 150% throttled 7 times (0.3s)"
+            ]
+        );
+        let events = "low 0\nhigh 0\nmax 3\noom 0\noom_kill 0\n";
+        assert_eq!(
+            limit_hits(&limits, events, "nr_throttled 0\n"),
+            ["memory limit 2.0 GiB hit"]
+        );
+        assert!(limit_hits(&limits, "max 0\noom_kill 0\n", "").is_empty());
+        assert!(limit_hits(&Limits::default(), events, stat).is_empty());
+    }
+
+    #[test]
+    fn command_runs_in_a_limited_cgroup() {
+        let limits = Limits {
+            memory_max: Some(256 << 20),
+            cpu_max_percent: None,
+        };
+        let Ok(cgroup) = Cgroup::create(&limits) else {
+            // No writable cgroup v2 hierarchy here.
+            return;
+        };
+        let exec = crate::runner::Exec {
+            cgroup: Some(cgroup.path().to_path_buf()),
+            ..crate::runner::Exec::default()
+        };
+        let output = crate::runner::run_bash_with_hook("cat /proc/self/cgroup", &exec, false, None);
+        let name = cgroup
+            .path()
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+        let usage = cgroup.finish(Duration::from_secs(1));
+        assert!(String::from_utf8_lossy(&output.unwrap().output.stdout).contains(&name));
+        assert!(!usage.summary.contains("limit"));
+    }
+}
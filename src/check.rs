@@ -0,0 +1,212 @@
+//! `sentinel-rs check …`: health checks to run from cron. Every run prints
+//! what it found and exits 1 unless all is well, but only notifies when a
+//! component got worse than it was the last time (or better again), so a
+//! disk that has had 8 reallocated sectors for a year does not send a
+//! message every hour. What each check found is kept in the state dir.
+
+pub mod storage;
+
+use crate::RunOptions;
+use crate::config::{self, TgConfig};
+use crate::event::{Event, Severity};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Health {
+    Ok,
+    Warning,
+    Failing,
+}
+
+impl Health {
+    pub fn name(self) -> &'static str {
+        match self {
+            Health::Ok => "ok",
+            Health::Warning => "warning",
+            Health::Failing => "failing",
+        }
+    }
+}
+
+/// What a check found about one component, e.g. a disk or a pool.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Status {
+    /// e.g. "SMART /dev/sda" or "zpool tank".
+    pub component: String,
+    pub health: Health,
+    /// Why it is not healthy, e.g. "8 reallocated sectors". A change in
+    /// it counts as a change of the component, so it leaves out what moves
+    /// on its own (temperatures, hours).
+    pub detail: String,
+}
+
+impl Status {
+    pub fn new(component: impl Into<String>, health: Health, detail: impl Into<String>) -> Self {
+        Status {
+            component: component.into(),
+            health,
+            detail: detail.into(),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self.detail.as_str() {
+            "" => format!("{}: {}", self.component, self.health.name()),
+            detail => format!("{}: {}, {detail}", self.component, self.health.name()),
+        }
+    }
+}
+
+type State = BTreeMap<String, Status>;
+
+/// Prints `statuses`, notifies about those that changed since the last run
+/// of the check `name` and remembers them for the next. Returns the exit
+/// code: 0 if everything is healthy, else 1.
+pub fn report(name: &str, statuses: &[Status], cfg: TgConfig, opts: RunOptions) -> i32 {
+    for status in statuses {
+        println!("{}", status.describe());
+    }
+    let file = config::state_dir().map(|dir| dir.join(format!("check-{name}.json")));
+    let previous = file.as_deref().map(load).unwrap_or_default();
+    if let Some((text, severity)) = message(name, &previous, statuses) {
+        let mut event = Event::message(&format!("sentinel-rs check {name}"), &text);
+        event.severity = severity;
+        crate::notify(event, cfg, opts);
+    }
+    if let Some(file) = &file {
+        let state: State = statuses
+            .iter()
+            .map(|s| (s.component.clone(), s.clone()))
+            .collect();
+        if let Err(e) = save(file, &state) {
+            tracing::warn!("Failed to save check results to {}: {e}", file.display());
+        }
+    }
+    let healthy = statuses.iter().all(|s| s.health == Health::Ok);
+    if healthy { 0 } else { 1 }
+}
+
+/// The message for what changed since `previous`, and how bad it is, if
+/// anything did.
+fn message(name: &str, previous: &State, current: &[Status]) -> Option<(String, Severity)> {
+    let mut degraded = Vec::new();
+    let mut recovered = Vec::new();
+    for status in current {
+        let before = previous.get(&status.component);
+        let was = before.map_or(Health::Ok, |b| b.health);
+        let worse = status.health > was
+            || (status.health != Health::Ok
+                && status.health == was
+                && before.is_some_and(|b| b.detail != status.detail));
+        if worse {
+            let was = before.map_or(String::new(), |b| format!(" (was {})", b.health.name()));
+            degraded.push((status.health, format!("{}{was}", status.describe())));
+        } else if status.health < was {
+            recovered.push(format!("{} (was {})", status.describe(), was.name()));
+        }
+    }
+    if degraded.is_empty() && recovered.is_empty() {
+        return None;
+    }
+    let mut lines = Vec::new();
+    let severity = match degraded.iter().map(|(health, _)| *health).max() {
+        Some(worst) => {
+            lines.push(format!("{} check: degraded", capitalize(name)));
+            lines.extend(degraded.into_iter().map(|(_, line)| line));
+            if worst == Health::Failing {
+                Severity::Critical
+            } else {
+                Severity::Warning
+            }
+        }
+        None => Severity::Info,
+    };
+    if !recovered.is_empty() {
+        lines.push(format!("{} check: recovered", capitalize(name)));
+        lines.extend(recovered);
+    }
+    Some((lines.join("\n"), severity))
+}
+
+fn capitalize(name: &str) -> String {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+fn load(path: &Path) -> State {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+fn save(path: &Path, state: &State) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(state)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(statuses: &[Status]) -> State {
+        statuses
+            .iter()
+            .map(|s| (s.component.clone(), s.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn notifies_only_about_changes() {
+        let healthy = [
+            Status::new("SMART /dev/sda", Health::Ok, ""),
+            Status::new("zpool tank", Health::Ok, ""),
+        ];
+        assert_eq!(message("storage", &State::new(), &healthy), None);
+
+        let worn = [
+            Status::new("SMART /dev/sda", Health::Warning, "8 reallocated sectors"),
+            Status::new("zpool tank", Health::Ok, ""),
+        ];
+        assert_eq!(
+            message("storage", &state(&healthy), &worn),
+            Some((
+                "Storage check: degraded\n\
+                 SMART /dev/sda: warning, 8 reallocated sectors (was ok)"
+                    .to_string(),
+                Severity::Warning
+            ))
+        );
+        // The same again is not news; more of it is.
+        assert_eq!(message("storage", &state(&worn), &worn), None);
+        let worse = [Status::new(
+            "SMART /dev/sda",
+            Health::Warning,
+            "16 reallocated sectors",
+        )];
+        assert!(message("storage", &state(&worn), &worse).is_some());
+
+        let fixed = [Status::new("SMART /dev/sda", Health::Ok, "")];
+        assert_eq!(
+            message("storage", &state(&worn), &fixed),
+            Some((
+                "Storage check: recovered\nSMART /dev/sda: ok (was warning)".to_string(),
+                Severity::Info
+            ))
+        );
+        // Broken on the first run is news too.
+        let failing = [Status::new("zpool tank", Health::Failing, "FAULTED")];
+        assert_eq!(
+            message("storage", &State::new(), &failing).map(|(_, s)| s),
+            Some(Severity::Critical)
+        );
+    }
+}
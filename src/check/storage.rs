@@ -0,0 +1,239 @@
+//! `check storage`: SMART health of disks (`smartctl`) and the state of ZFS
+//! pools (`zpool`). A disk whose self-assessment failed or an NVMe drive
+//! raising a critical warning is failing; reallocated, pending or
+//! uncorrectable sectors, media errors or a worn-out drive are a warning.
+//! A degraded pool is a warning and a faulted or unavailable one failing.
+//! Either tool being missing skips its checks.
+
+use super::{Health, Status};
+use serde_json::Value;
+use std::process::Command;
+
+/// Which storage checks to run.
+#[derive(Clone, Debug)]
+pub struct Checks {
+    /// Disks to ask `smartctl` about; all it finds if empty.
+    pub devices: Vec<String>,
+    pub smart: bool,
+    pub zfs: bool,
+}
+
+pub fn check(checks: &Checks) -> Result<Vec<Status>, String> {
+    let mut statuses = Vec::new();
+    let mut ran = false;
+    if checks.smart
+        && let Some(smart) = smart(&checks.devices)?
+    {
+        ran = true;
+        statuses.extend(smart);
+    }
+    if checks.zfs
+        && let Some(pools) = zfs()?
+    {
+        ran = true;
+        statuses.extend(pools);
+    }
+    if !ran {
+        return Err("nothing to check: neither smartctl nor zpool is installed".to_string());
+    }
+    Ok(statuses)
+}
+
+/// The stdout of `program`, or `None` if it is not installed.
+fn output(program: &str, args: &[&str]) -> Result<Option<String>, String> {
+    match Command::new(program).args(args).output() {
+        Ok(output) => Ok(Some(String::from_utf8_lossy(&output.stdout).into_owned())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("failed to run {program}: {e}")),
+    }
+}
+
+fn smart(devices: &[String]) -> Result<Option<Vec<Status>>, String> {
+    let devices = if devices.is_empty() {
+        let Some(scan) = output("smartctl", &["--json", "--scan"])? else {
+            return Ok(None);
+        };
+        scanned_devices(&scan)
+    } else {
+        devices.to_vec()
+    };
+    let mut statuses = Vec::new();
+    for device in devices {
+        // smartctl's exit code is a bit mask mixing errors with findings;
+        // the JSON says which.
+        let Some(report) = output("smartctl", &["--json", "-H", "-A", &device])? else {
+            return Err("smartctl is not installed".to_string());
+        };
+        statuses.push(match serde_json::from_str(&report) {
+            Ok(report) => smart_status(&device, &report),
+            Err(_) => Status::new(
+                format!("SMART {device}"),
+                Health::Warning,
+                "smartctl gave no report",
+            ),
+        });
+    }
+    Ok(Some(statuses))
+}
+
+fn scanned_devices(scan: &str) -> Vec<String> {
+    let scan: Value = serde_json::from_str(scan).unwrap_or_default();
+    scan["devices"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|device| device["name"].as_str().map(str::to_string))
+        .collect()
+}
+
+fn smart_status(device: &str, report: &Value) -> Status {
+    let component = format!("SMART {device}");
+    if report["smart_status"]["passed"] == Value::Bool(false) {
+        return Status::new(component, Health::Failing, "SMART self-assessment failed");
+    }
+    let nvme = &report["nvme_smart_health_information_log"];
+    if let Some(warning) = nvme["critical_warning"].as_u64().filter(|&w| w != 0) {
+        return Status::new(
+            component,
+            Health::Failing,
+            format!("NVMe critical warning 0x{warning:02x}"),
+        );
+    }
+    let mut problems = Vec::new();
+    let attributes = report["ata_smart_attributes"]["table"].as_array();
+    for attribute in attributes.into_iter().flatten() {
+        let what = match attribute["id"].as_u64() {
+            Some(5) => "reallocated sectors",
+            Some(197) => "pending sectors",
+            Some(198) => "uncorrectable sectors",
+            _ => continue,
+        };
+        if let Some(count) = attribute["raw"]["value"].as_u64().filter(|&n| n > 0) {
+            problems.push(format!("{count} {what}"));
+        }
+    }
+    if let Some(errors) = nvme["media_errors"].as_u64().filter(|&n| n > 0) {
+        problems.push(format!("{errors} media errors"));
+    }
+    if let Some(used) = nvme["percentage_used"].as_u64().filter(|&n| n >= 90) {
+        problems.push(format!("{used}% of rated endurance used"));
+    }
+    if !problems.is_empty() {
+        return Status::new(component, Health::Warning, problems.join(", "));
+    }
+    if report["smart_status"].is_null() {
+        // No verdict: the device could not be opened, has SMART off, ….
+        let message = report["smartctl"]["messages"][0]["string"]
+            .as_str()
+            .unwrap_or("no SMART status");
+        return Status::new(component, Health::Warning, message);
+    }
+    Status::new(component, Health::Ok, "")
+}
+
+fn zfs() -> Result<Option<Vec<Status>>, String> {
+    let Some(list) = output("zpool", &["list", "-H", "-o", "name,health"])? else {
+        return Ok(None);
+    };
+    let problems = output("zpool", &["status", "-x"])?.unwrap_or_default();
+    Ok(Some(pool_statuses(&list, &problems)))
+}
+
+/// The pools in `zpool list -H -o name,health` output, with the reason
+/// `zpool status -x` gives for any it lists.
+fn pool_statuses(list: &str, problems: &str) -> Vec<Status> {
+    let mut reasons = Vec::new();
+    let mut pool = None;
+    for line in problems.lines().map(str::trim) {
+        if let Some(name) = line.strip_prefix("pool:") {
+            pool = Some(name.trim());
+        } else if let (Some(name), Some(status)) = (pool, line.strip_prefix("status:")) {
+            reasons.push((name, status.trim()));
+            pool = None;
+        }
+    }
+    list.lines()
+        .filter_map(|line| line.split_once('\t'))
+        .map(|(name, state)| {
+            let reason = reasons.iter().find(|(pool, _)| *pool == name);
+            let health = match state {
+                "ONLINE" if reason.is_none() => Health::Ok,
+                "ONLINE" | "DEGRADED" => Health::Warning,
+                _ => Health::Failing,
+            };
+            let detail = match (health, reason) {
+                (Health::Ok, _) => String::new(),
+                (_, Some((_, reason))) => format!("{state}: {reason}"),
+                (_, None) => state.to_string(),
+            };
+            Status::new(format!("zpool {name}"), health, detail)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_smartctl_reports() {
+        let ata: Value = serde_json::from_str(
+            r#"{"smart_status": {"passed": true}, "ata_smart_attributes": {"table": [
+                {"id": 5, "name": "Reallocated_Sector_Ct", "raw": {"value": 8}},
+                {"id": 194, "name": "Temperature_Celsius", "raw": {"value": 41}},
+                {"id": 197, "name": "Current_Pending_Sector", "raw": {"value": 0}}]}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            smart_status("/dev/sda", &ata),
+            Status::new("SMART /dev/sda", Health::Warning, "8 reallocated sectors")
+        );
+        let nvme: Value = serde_json::from_str(
+            r#"{"smart_status": {"passed": true}, "nvme_smart_health_information_log":
+                {"critical_warning": 0, "media_errors": 0, "percentage_used": 3}}"#,
+        )
+        .unwrap();
+        assert_eq!(smart_status("/dev/nvme0", &nvme).health, Health::Ok);
+        let failed: Value = serde_json::from_str(r#"{"smart_status": {"passed": false}}"#).unwrap();
+        assert_eq!(smart_status("/dev/sdb", &failed).health, Health::Failing);
+        let denied: Value = serde_json::from_str(
+            r#"{"smartctl": {"messages": [{"string": "Smartctl open device: /dev/sdc failed: Permission denied", "severity": "error"}]}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            smart_status("/dev/sdc", &denied).detail,
+            "Smartctl open device: /dev/sdc failed: Permission denied"
+        );
+        assert_eq!(
+            scanned_devices(r#"{"devices": [{"name": "/dev/sda"}, {"name": "/dev/nvme0"}]}"#),
+            ["/dev/sda", "/dev/nvme0"]
+        );
+    }
+
+    #[test]
+    fn reads_pool_states() {
+        let list = "tank\tDEGRADED\nbackup\tONLINE\nscratch\tONLINE\n";
+        let problems = "  pool: tank\n state: DEGRADED\nstatus: One or more devices has been removed.\n\n  \
+                        pool: scratch\n state: ONLINE\nstatus: One or more devices has experienced an unrecoverable error.\n";
+        assert_eq!(
+            pool_statuses(list, problems),
+            [
+                Status::new(
+                    "zpool tank",
+                    Health::Warning,
+                    "DEGRADED: One or more devices has been removed."
+                ),
+                Status::new("zpool backup", Health::Ok, ""),
+                Status::new(
+                    "zpool scratch",
+                    Health::Warning,
+                    "ONLINE: One or more devices has experienced an unrecoverable error."
+                ),
+            ]
+        );
+        assert_eq!(
+            pool_statuses("tank\tFAULTED\n", "")[0],
+            Status::new("zpool tank", Health::Failing, "FAULTED")
+        );
+    }
+}
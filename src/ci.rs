@@ -0,0 +1,123 @@
+//! Bits shared by the integrations that report run results to other services
+//! ([`crate::github`], [`crate::gitlab`], [`crate::grafana`], [`crate::otel`]).
+//! They work on the event JSON their plugins are handed.
+
+use serde_json::Value;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Running,
+    Success,
+    Failure,
+    /// The command could not be started at all.
+    Error,
+}
+
+/// What a start or finish event means for a CI status, with a one-line
+/// description. `None` for events that do not change the status.
+pub fn outcome(event: &Value) -> Option<(Outcome, String)> {
+    Some(match event["kind"].as_str()? {
+        "start" => (Outcome::Running, "Running".to_string()),
+        "success" => (Outcome::Success, "Succeeded".to_string()),
+        "failure" => (
+            Outcome::Failure,
+            match (
+                event["failure_reason"].as_str(),
+                event["failed_stage"].as_str(),
+            ) {
+                (Some(reason), _) => reason.to_string(),
+                (None, Some(stage)) => {
+                    format!("Failed with exit code {} ({stage})", event["exit_code"])
+                }
+                (None, None) => format!("Failed with exit code {}", event["exit_code"]),
+            },
+        ),
+        "signal" => (
+            Outcome::Failure,
+            format!(
+                "Terminated by {}",
+                event["signal"].as_str().unwrap_or("signal")
+            ),
+        ),
+        "spawn_error" => (Outcome::Error, "Could not start".to_string()),
+        "report" => {
+            let ok = event["steps"]
+                .as_array()
+                .is_some_and(|steps| steps.iter().all(|s| s["ok"] == true));
+            let outcome = if ok {
+                Outcome::Success
+            } else {
+                Outcome::Failure
+            };
+            (outcome, event["summary"].as_str()?.to_string())
+        }
+        _ => return None,
+    })
+}
+
+/// A finished run as Markdown: a table of the basics, per-host results for
+/// reports, and the output tails folded away.
+pub fn markdown(event: &Value, headline: &str) -> String {
+    let field = |key: &str| event[key].as_str().unwrap_or_default().to_string();
+    let mut md = format!(
+        "### sentinel-rs: {headline}\n\n| | |\n|---|---|\n| Command | `{}` |\n| Host | {} |\n",
+        field("command").replace('|', "\\|"),
+        field("host"),
+    );
+    if let Some(duration) = event["duration"].as_str() {
+        md.push_str(&format!("| Duration | {duration} |\n"));
+    }
+    if let Some(steps) = event["steps"].as_array() {
+        if !steps.is_empty() {
+            md.push_str("\n| Host | Result | Duration |\n|---|---|---|\n");
+        }
+        for step in steps {
+            let result = if step["ok"] == true {
+                "ok"
+            } else {
+                "**FAILED**"
+            };
+            md.push_str(&format!(
+                "| {} | {result} ({}) | {} |\n",
+                step["name"].as_str().unwrap_or_default(),
+                step["status"].as_str().unwrap_or_default(),
+                step["duration"].as_str().unwrap_or_default(),
+            ));
+        }
+    }
+    for stream in ["stdout", "stderr"] {
+        let text = field(stream);
+        if !text.trim().is_empty() {
+            md.push_str(&format!(
+                "\n<details><summary>{stream}</summary>\n\n```\n{}\n```\n</details>\n",
+                text.trim_end()
+            ));
+        }
+    }
+    md.push('\n');
+    md
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{Event, EventKind};
+
+    #[test]
+    fn markdown_folds_output_tails() {
+        let event = Event {
+            exit_code: Some(3),
+            stdout: Some(String::new()),
+            stderr: Some("boom\n".to_string()),
+            ..Event::new(EventKind::Failure, "make | tee log")
+        };
+        let event: Value = serde_json::from_str(&event.to_json()).unwrap();
+        let (outcome, headline) = outcome(&event).unwrap();
+        assert_eq!(outcome, Outcome::Failure);
+        let md = markdown(&event, &headline);
+        assert!(md.starts_with("### sentinel-rs: Failed with exit code 3\n"));
+        assert!(md.contains("| Command | `make \\| tee log` |"));
+        assert!(md.contains("<summary>stderr</summary>\n\n```\nboom\n```"));
+        assert!(!md.contains("<summary>stdout</summary>"));
+    }
+}
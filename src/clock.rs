@@ -0,0 +1,124 @@
+//! How notifications write the time: as local time by default, or in UTC
+//! (`--utc`) or a named zone (`--timezone Europe/Berlin`), in a strftime
+//! format of choice (`--time-format`). Chosen once, at startup, for the
+//! whole process; a fleet spread over several zones can then agree on one.
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, Utc};
+use chrono_tz::Tz;
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+
+const FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+/// Times not in local time say which zone they are in.
+const ZONED_FORMAT: &str = "%Y-%m-%d %H:%M:%S %Z";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Zone {
+    #[default]
+    Local,
+    Utc,
+    Named(Tz),
+}
+
+#[derive(Clone, Debug, Default)]
+struct Clock {
+    zone: Zone,
+    format: Option<String>,
+}
+
+static CLOCK: OnceLock<Clock> = OnceLock::new();
+
+/// Writes times in `zone`, as `format` says if given. Only the first call
+/// counts.
+pub fn set(zone: Zone, format: Option<String>) {
+    CLOCK.set(Clock { zone, format }).ok();
+}
+
+pub fn parse_zone(s: &str) -> Result<Tz, String> {
+    s.parse()
+        .map_err(|_| format!("unknown time zone {s:?} (expected a name such as Europe/Berlin)"))
+}
+
+pub fn parse_format(s: &str) -> Result<String, String> {
+    if StrftimeItems::new(s).any(|item| item == Item::Error) {
+        return Err(format!("{s:?} is not a valid strftime format"));
+    }
+    Ok(s.to_string())
+}
+
+/// The time now, as notifications write it.
+pub fn now() -> String {
+    format(SystemTime::now())
+}
+
+/// `time` as notifications write it.
+pub fn format(time: SystemTime) -> String {
+    let clock = CLOCK.get_or_init(Clock::default);
+    format_in(&clock.zone, clock.format.as_deref(), time)
+}
+
+/// `length` after `now`, both in seconds since the epoch, unless that is
+/// further ahead than a time can be written.
+pub fn seconds_after(now: i64, length: Duration) -> Result<i64, String> {
+    i64::try_from(length.as_secs())
+        .ok()
+        .and_then(|secs| now.checked_add(secs))
+        .filter(|&until| DateTime::<Utc>::from_timestamp(until, 0).is_some())
+        .ok_or_else(|| format!("{} is too long", crate::event::format_duration(length)))
+}
+
+/// How many minutes into the day `time` is, in the zone notifications
+/// are written in.
+pub fn minute_of_day(time: SystemTime) -> u32 {
+    use chrono::Timelike;
+    let utc = DateTime::<Utc>::from(time);
+    let (hour, minute) = match CLOCK.get_or_init(Clock::default).zone {
+        Zone::Local => {
+            let local = utc.with_timezone(&Local);
+            (local.hour(), local.minute())
+        }
+        Zone::Utc => (utc.hour(), utc.minute()),
+        Zone::Named(tz) => {
+            let named = utc.with_timezone(&tz);
+            (named.hour(), named.minute())
+        }
+    };
+    hour * 60 + minute
+}
+
+fn format_in(zone: &Zone, format: Option<&str>, time: SystemTime) -> String {
+    let utc = DateTime::<Utc>::from(time);
+    match zone {
+        Zone::Local => utc
+            .with_timezone(&Local)
+            .format(format.unwrap_or(FORMAT))
+            .to_string(),
+        Zone::Utc => utc.format(format.unwrap_or(ZONED_FORMAT)).to_string(),
+        Zone::Named(tz) => utc
+            .with_timezone(tz)
+            .format(format.unwrap_or(ZONED_FORMAT))
+            .to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn writes_times_in_the_chosen_zone() {
+        // 2026-01-15 12:30:00 UTC
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_768_480_200);
+        assert_eq!(format_in(&Zone::Utc, None, time), "2026-01-15 12:30:00 UTC");
+        let berlin = Zone::Named(parse_zone("Europe/Berlin").unwrap());
+        assert_eq!(format_in(&berlin, None, time), "2026-01-15 13:30:00 CET");
+        assert_eq!(
+            format_in(&berlin, Some("%d.%m. %H:%M %z"), time),
+            "15.01. 13:30 +0100"
+        );
+        assert!(parse_zone("Mars/Olympus").is_err());
+        assert!(parse_format("%Y-%m-%d %Q").is_err());
+    }
+}
@@ -0,0 +1,164 @@
+//! `--cloud`: which cloud instance a run is on, for autoscaled workers whose
+//! hostnames come and go. The start notification names the instance id,
+//! region and zone and the public IP, as the instance metadata service of
+//! EC2 (IMDSv2) or GCE tells them. Off a cloud instance nothing is shown;
+//! the metadata service is given about a second to answer.
+
+use reqwest::blocking::Client;
+use serde::Serialize;
+use serde_json::Value;
+use std::time::Duration;
+
+/// Where both clouds serve instance metadata; `SENTINEL_METADATA_URL`
+/// overrides it.
+const METADATA_URL: &str = "http://169.254.169.254";
+const TIMEOUT: Duration = Duration::from_millis(1000);
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Instance {
+    /// "aws" or "gcp".
+    pub provider: String,
+    pub id: String,
+    pub region: String,
+    pub zone: String,
+    pub public_ip: Option<String>,
+    /// The above as one line, e.g.
+    /// "aws i-0abc in eu-west-1 (eu-west-1a), public IP 203.0.113.7".
+    pub summary: String,
+}
+
+/// The instance this process runs on, if the metadata service says.
+pub fn detect() -> Option<Instance> {
+    let base = std::env::var("SENTINEL_METADATA_URL")
+        .ok()
+        .filter(|url| !url.trim().is_empty())
+        .unwrap_or_else(|| METADATA_URL.to_string());
+    let base = base.trim_end_matches('/');
+    // The metadata service is link-local; a proxy would only get in the way.
+    let client = Client::builder().timeout(TIMEOUT).no_proxy().build().ok()?;
+    let token = client
+        .put(format!("{base}/latest/api/token"))
+        .header("X-aws-ec2-metadata-token-ttl-seconds", "60")
+        .send()
+        .ok()?;
+    if token.status().is_success() {
+        let token = token.text().ok()?;
+        let get = |path: &str| {
+            client
+                .get(format!("{base}{path}"))
+                .header("X-aws-ec2-metadata-token", &token)
+                .send()
+                .ok()
+                .filter(|response| response.status().is_success())?
+                .text()
+                .ok()
+        };
+        let document = get("/latest/dynamic/instance-identity/document")?;
+        let public_ip = get("/latest/meta-data/public-ipv4");
+        return ec2_instance(&serde_json::from_str(&document).ok()?, public_ip);
+    }
+    let metadata: Value = client
+        .get(format!(
+            "{base}/computeMetadata/v1/instance/?recursive=true"
+        ))
+        .header("Metadata-Flavor", "Google")
+        .send()
+        .ok()
+        .filter(|response| response.status().is_success())?
+        .json()
+        .ok()?;
+    gce_instance(&metadata)
+}
+
+fn ec2_instance(document: &Value, public_ip: Option<String>) -> Option<Instance> {
+    let field = |key: &str| document[key].as_str().map(str::to_string);
+    Some(instance(
+        "aws",
+        field("instanceId")?,
+        field("region")?,
+        field("availabilityZone").unwrap_or_default(),
+        public_ip,
+    ))
+}
+
+fn gce_instance(metadata: &Value) -> Option<Instance> {
+    // The id is a number too big for some JSON readers, and a string to others.
+    let id = match &metadata["id"] {
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => s.clone(),
+        _ => return None,
+    };
+    // "projects/123/zones/europe-west1-b"
+    let zone = metadata["zone"].as_str()?.rsplit('/').next()?.to_string();
+    let region = zone
+        .rsplit_once('-')
+        .map_or(zone.as_str(), |(region, _)| region)
+        .to_string();
+    let public_ip = metadata["networkInterfaces"][0]["accessConfigs"][0]["externalIp"]
+        .as_str()
+        .filter(|ip| !ip.is_empty())
+        .map(str::to_string);
+    Some(instance("gcp", id, region, zone, public_ip))
+}
+
+fn instance(
+    provider: &str,
+    id: String,
+    region: String,
+    zone: String,
+    public_ip: Option<String>,
+) -> Instance {
+    let public_ip = public_ip
+        .map(|ip| ip.trim().to_string())
+        .filter(|ip| !ip.is_empty());
+    let mut summary = format!("{provider} {id} in {region}");
+    if !zone.is_empty() {
+        summary.push_str(&format!(" ({zone})"));
+    }
+    if let Some(ip) = &public_ip {
+        summary.push_str(&format!(", public IP {ip}"));
+    }
+    Instance {
+        provider: provider.to_string(),
+        id,
+        region,
+        zone,
+        public_ip,
+        summary,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn reads_ec2_and_gce_metadata() {
+        let document = json!({
+            "instanceId": "i-0abc123",
+            "region": "eu-west-1",
+            "availabilityZone": "eu-west-1a",
+            "instanceType": "m5.large",
+        });
+        let ec2 = ec2_instance(&document, Some("203.0.113.7".to_string())).unwrap();
+        assert_eq!(
+            ec2.summary,
+            "aws i-0abc123 in eu-west-1 (eu-west-1a), public IP 203.0.113.7"
+        );
+
+        let metadata = json!({
+            "id": 4_520_031_799_277_581_759_u64,
+            "zone": "projects/123/zones/europe-west1-b",
+            "networkInterfaces": [{"accessConfigs": [{"externalIp": ""}]}],
+        });
+        let gce = gce_instance(&metadata).unwrap();
+        assert_eq!(gce.region, "europe-west1");
+        assert_eq!(gce.public_ip, None);
+        assert_eq!(
+            gce.summary,
+            "gcp 4520031799277581759 in europe-west1 (europe-west1-b)"
+        );
+        assert_eq!(ec2_instance(&json!({}), None), None);
+    }
+}
@@ -0,0 +1,524 @@
+//! `--notify-if`: conditions over the JSON a command prints, deciding
+//! whether its successful run is worth a notification, and how bad it is.
+//! They are written in a small part of jq: paths (`.errors`,
+//! `.results[0].status`, `.["a key"]`), numbers, strings, `true`, `false`
+//! and `null`, the comparisons `==`, `!=`, `<`, `<=`, `>` and `>=`, `and`,
+//! `or`, parentheses and pipes into `length` and `not`. A severity can be put
+//! in front: `critical: .errors > 100`.
+//!
+//! ```text
+//! critical: .failed > 10
+//! .failed > 0 or (.skipped | length) > 5
+//! .status != "ok"
+//! ```
+
+use crate::event::Severity;
+use serde_json::Value;
+use std::cmp::Ordering;
+
+/// One `--notify-if` condition.
+#[derive(Clone, Debug)]
+pub struct Condition {
+    /// What a notification it lets through is sent as; a warning if unset.
+    pub severity: Option<Severity>,
+    /// The condition as written, without the severity.
+    pub text: String,
+    expr: Expr,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Path(Vec<Step>),
+    Literal(Value),
+    Compare(Box<Expr>, Op, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Pipe(Box<Expr>, Box<Expr>),
+    Length,
+    Not,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Step {
+    Key(String),
+    Index(i64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+pub fn parse_condition(s: &str) -> Result<Condition, String> {
+    let (severity, text) = match s.split_once(':') {
+        Some((name, rest)) => match parse_severity(name.trim()) {
+            Some(severity) => (Some(severity), rest.trim()),
+            None => (None, s.trim()),
+        },
+        None => (None, s.trim()),
+    };
+    let tokens = lex(text)?;
+    let mut parser = Parser { tokens, at: 0 };
+    let expr = parser.pipe()?;
+    if let Some(token) = parser.tokens.get(parser.at) {
+        return Err(format!("unexpected {} in {text:?}", token.describe()));
+    }
+    Ok(Condition {
+        severity,
+        text: text.to_string(),
+        expr,
+    })
+}
+
+fn parse_severity(name: &str) -> Option<Severity> {
+    match name {
+        "info" => Some(Severity::Info),
+        "warning" => Some(Severity::Warning),
+        "error" => Some(Severity::Error),
+        "critical" => Some(Severity::Critical),
+        _ => None,
+    }
+}
+
+impl Condition {
+    /// Whether the condition holds for `input`: it comes out as anything
+    /// but `false` or `null`, as in jq.
+    pub fn holds(&self, input: &Value) -> Result<bool, String> {
+        eval(&self.expr, input).map(|value| truthy(&value))
+    }
+}
+
+/// The JSON a command printed: all of `stdout`, or failing that its last
+/// line, for tools that log before they print their result.
+pub fn output_json(stdout: &[u8]) -> Option<Value> {
+    let text = String::from_utf8_lossy(stdout);
+    serde_json::from_str(&text).ok().or_else(|| {
+        let last = text.lines().rfind(|line| !line.trim().is_empty())?;
+        serde_json::from_str(last).ok()
+    })
+}
+
+/// The first of `conditions` that holds for `stdout`, or `None` if none
+/// does. An error if the output is not JSON or a condition cannot be
+/// evaluated on it.
+pub fn first_holding<'a>(
+    conditions: &'a [Condition],
+    stdout: &[u8],
+) -> Result<Option<&'a Condition>, String> {
+    let input = output_json(stdout).ok_or("the output is not JSON")?;
+    for condition in conditions {
+        if condition
+            .holds(&input)
+            .map_err(|e| format!("{}: {e}", condition.text))?
+        {
+            return Ok(Some(condition));
+        }
+    }
+    Ok(None)
+}
+
+fn truthy(value: &Value) -> bool {
+    !matches!(value, Value::Null | Value::Bool(false))
+}
+
+fn eval(expr: &Expr, input: &Value) -> Result<Value, String> {
+    Ok(match expr {
+        Expr::Path(steps) => {
+            let mut value = input.clone();
+            for step in steps {
+                value = match (step, &value) {
+                    (_, Value::Null) => Value::Null,
+                    (Step::Key(key), Value::Object(map)) => {
+                        map.get(key).cloned().unwrap_or(Value::Null)
+                    }
+                    (Step::Index(i), Value::Array(items)) => {
+                        let i = if *i < 0 { items.len() as i64 + i } else { *i };
+                        usize::try_from(i)
+                            .ok()
+                            .and_then(|i| items.get(i))
+                            .cloned()
+                            .unwrap_or(Value::Null)
+                    }
+                    (Step::Key(key), other) => {
+                        return Err(format!("cannot index {} with {key:?}", type_name(other)));
+                    }
+                    (Step::Index(_), other) => {
+                        return Err(format!("cannot index {} with a number", type_name(other)));
+                    }
+                };
+            }
+            value
+        }
+        Expr::Literal(value) => value.clone(),
+        Expr::Compare(left, op, right) => {
+            let ordering = compare(&eval(left, input)?, &eval(right, input)?);
+            Value::Bool(match op {
+                Op::Eq => ordering == Ordering::Equal,
+                Op::Ne => ordering != Ordering::Equal,
+                Op::Lt => ordering == Ordering::Less,
+                Op::Le => ordering != Ordering::Greater,
+                Op::Gt => ordering == Ordering::Greater,
+                Op::Ge => ordering != Ordering::Less,
+            })
+        }
+        Expr::And(left, right) => {
+            Value::Bool(truthy(&eval(left, input)?) && truthy(&eval(right, input)?))
+        }
+        Expr::Or(left, right) => {
+            Value::Bool(truthy(&eval(left, input)?) || truthy(&eval(right, input)?))
+        }
+        Expr::Pipe(left, right) => eval(right, &eval(left, input)?)?,
+        Expr::Length => match input {
+            Value::Null => Value::from(0),
+            Value::String(s) => Value::from(s.chars().count()),
+            Value::Array(items) => Value::from(items.len()),
+            Value::Object(map) => Value::from(map.len()),
+            Value::Number(n) => Value::from(n.as_f64().unwrap_or_default().abs()),
+            Value::Bool(_) => return Err("a boolean has no length".to_string()),
+        },
+        Expr::Not => Value::Bool(!truthy(input)),
+    })
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+/// jq's order: null, false, true, numbers, strings, arrays, objects.
+fn compare(a: &Value, b: &Value) -> Ordering {
+    let rank = |value: &Value| match value {
+        Value::Null => 0,
+        Value::Bool(false) => 1,
+        Value::Bool(true) => 2,
+        Value::Number(_) => 3,
+        Value::String(_) => 4,
+        Value::Array(_) => 5,
+        Value::Object(_) => 6,
+    };
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => {
+            let (a, b) = (
+                a.as_f64().unwrap_or_default(),
+                b.as_f64().unwrap_or_default(),
+            );
+            a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+        }
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Array(a), Value::Array(b)) => a
+            .iter()
+            .zip(b)
+            .map(|(a, b)| compare(a, b))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| a.len().cmp(&b.len())),
+        (Value::Object(_), Value::Object(_)) if a == b => Ordering::Equal,
+        (Value::Object(_), Value::Object(_)) => a.to_string().cmp(&b.to_string()),
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    /// `.name`
+    Field(String),
+    Dot,
+    Word(String),
+    Number(f64),
+    Str(String),
+    Op(Op),
+    Pipe,
+    Open,
+    Close,
+    OpenBracket,
+    CloseBracket,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Field(name) => format!("`.{name}`"),
+            Token::Dot => "`.`".to_string(),
+            Token::Word(word) => format!("`{word}`"),
+            Token::Number(n) => format!("`{n}`"),
+            Token::Str(s) => format!("{s:?}"),
+            Token::Op(_) => "a comparison".to_string(),
+            Token::Pipe => "`|`".to_string(),
+            Token::Open => "`(`".to_string(),
+            Token::Close => "`)`".to_string(),
+            Token::OpenBracket => "`[`".to_string(),
+            Token::CloseBracket => "`]`".to_string(),
+        }
+    }
+}
+
+fn lex(text: &str) -> Result<Vec<Token>, String> {
+    let word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '.' => match chars.peek() {
+                Some(&next) if next.is_ascii_alphabetic() || next == '_' => {
+                    let mut name = String::new();
+                    while let Some(c) = chars.next_if(|&c| word(c)) {
+                        name.push(c);
+                    }
+                    Token::Field(name)
+                }
+                _ => Token::Dot,
+            },
+            '"' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => s.push('\n'),
+                            Some('t') => s.push('\t'),
+                            Some(c) => s.push(c),
+                            None => return Err("unterminated string".to_string()),
+                        },
+                        Some(c) => s.push(c),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+                Token::Str(s)
+            }
+            c if c.is_ascii_digit()
+                || (c == '-' && chars.peek().is_some_and(char::is_ascii_digit)) =>
+            {
+                let mut number = c.to_string();
+                while let Some(c) = chars.next_if(|&c| c.is_ascii_digit() || c == '.') {
+                    number.push(c);
+                }
+                Token::Number(
+                    number
+                        .parse()
+                        .map_err(|_| format!("{number:?} is not a number"))?,
+                )
+            }
+            c if word(c) => {
+                let mut name = c.to_string();
+                while let Some(c) = chars.next_if(|&c| word(c)) {
+                    name.push(c);
+                }
+                Token::Word(name)
+            }
+            '=' | '!' | '<' | '>' => {
+                let equals = chars.next_if_eq(&'=').is_some();
+                Token::Op(match (c, equals) {
+                    ('=', true) => Op::Eq,
+                    ('!', true) => Op::Ne,
+                    ('<', false) => Op::Lt,
+                    ('<', true) => Op::Le,
+                    ('>', false) => Op::Gt,
+                    ('>', true) => Op::Ge,
+                    _ => return Err(format!("unexpected `{c}` (compare with `==`)")),
+                })
+            }
+            '|' => Token::Pipe,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '[' => Token::OpenBracket,
+            ']' => Token::CloseBracket,
+            c => return Err(format!("unexpected `{c}`")),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    at: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.at)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.at).cloned();
+        self.at += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        let found = self.peek() == Some(token);
+        if found {
+            self.at += 1;
+        }
+        found
+    }
+
+    fn pipe(&mut self) -> Result<Expr, String> {
+        let mut expr = self.or()?;
+        while self.eat(&Token::Pipe) {
+            expr = Expr::Pipe(Box::new(expr), Box::new(self.or()?));
+        }
+        Ok(expr)
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.eat(&Token::Word("or".to_string())) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.compare()?;
+        while self.eat(&Token::Word("and".to_string())) {
+            expr = Expr::And(Box::new(expr), Box::new(self.compare()?));
+        }
+        Ok(expr)
+    }
+
+    fn compare(&mut self) -> Result<Expr, String> {
+        let left = self.term()?;
+        match self.peek() {
+            Some(&Token::Op(op)) => {
+                self.at += 1;
+                Ok(Expr::Compare(Box::new(left), op, Box::new(self.term()?)))
+            }
+            _ => Ok(left),
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        let token = self.next().ok_or("unexpected end of the condition")?;
+        Ok(match token {
+            Token::Field(name) => self.path(vec![Step::Key(name)])?,
+            Token::Dot => self.path(Vec::new())?,
+            Token::Number(n) => Expr::Literal(Value::from(n)),
+            Token::Str(s) => Expr::Literal(Value::String(s)),
+            Token::Word(word) => match word.as_str() {
+                "true" => Expr::Literal(Value::Bool(true)),
+                "false" => Expr::Literal(Value::Bool(false)),
+                "null" => Expr::Literal(Value::Null),
+                "length" => Expr::Length,
+                "not" => Expr::Not,
+                _ => return Err(format!("unknown function `{word}`")),
+            },
+            Token::Open => {
+                let expr = self.pipe()?;
+                if !self.eat(&Token::Close) {
+                    return Err("missing `)`".to_string());
+                }
+                expr
+            }
+            token => return Err(format!("unexpected {}", token.describe())),
+        })
+    }
+
+    /// The rest of a path after its first step.
+    fn path(&mut self, mut steps: Vec<Step>) -> Result<Expr, String> {
+        loop {
+            match self.peek() {
+                Some(Token::Field(name)) => {
+                    steps.push(Step::Key(name.clone()));
+                    self.at += 1;
+                }
+                Some(Token::Dot)
+                    if matches!(
+                        self.tokens.get(self.at + 1),
+                        Some(Token::Str(_) | Token::OpenBracket)
+                    ) =>
+                {
+                    self.at += 1;
+                }
+                Some(Token::OpenBracket) => {
+                    self.at += 1;
+                    steps.push(match self.next() {
+                        Some(Token::Str(key)) => Step::Key(key),
+                        Some(Token::Number(n)) if n.fract() == 0.0 => Step::Index(n as i64),
+                        _ => return Err("expected a key or an index in `[]`".to_string()),
+                    });
+                    if !self.eat(&Token::CloseBracket) {
+                        return Err("missing `]`".to_string());
+                    }
+                }
+                Some(Token::Str(key)) if self.tokens[self.at - 1] == Token::Dot => {
+                    steps.push(Step::Key(key.clone()));
+                    self.at += 1;
+                }
+                _ => return Ok(Expr::Path(steps)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn evaluates_conditions_over_json() {
+        let output = json!({
+            "errors": 2,
+            "status": "degraded",
+            "results": [{"name": "db", "ok": true}, {"name": "cache", "ok": false}],
+            "a key": null,
+        });
+        let holds = |text: &str| parse_condition(text).unwrap().holds(&output).unwrap();
+        assert!(holds(".errors > 0"));
+        assert!(!holds(".errors >= 3"));
+        assert!(holds(".status != \"ok\" and (.results | length) == 2"));
+        assert!(holds(".results[-1].ok == false"));
+        assert!(holds(".results[1] | .ok | not"));
+        assert!(holds(".[\"a key\"] == null and .missing.deeper == null"));
+        assert!(holds("(.errors < 1) or .status == \"degraded\""));
+        assert!(!holds(".results[0].ok and .warnings"));
+
+        let condition = parse_condition("critical: .errors > 100").unwrap();
+        assert_eq!(condition.severity, Some(Severity::Critical));
+        assert_eq!(condition.text, ".errors > 100");
+        assert_eq!(parse_condition(".status: 1").unwrap_err(), "unexpected `:`");
+        assert_eq!(
+            parse_condition(".errors = 1").unwrap_err(),
+            "unexpected `=` (compare with `==`)"
+        );
+        assert_eq!(
+            parse_condition(".status.name > 1")
+                .unwrap()
+                .holds(&output)
+                .unwrap_err(),
+            "cannot index a string with \"name\""
+        );
+    }
+
+    #[test]
+    fn reads_the_last_line_of_chatty_output() {
+        let conditions = [
+            parse_condition("critical: .failed > 10").unwrap(),
+            parse_condition(".failed > 0").unwrap(),
+        ];
+        let stdout = b"scanning...\n{\"failed\": 3}\n";
+        let matched = first_holding(&conditions, stdout).unwrap().unwrap();
+        assert_eq!(matched.text, ".failed > 0");
+        assert!(
+            first_holding(&conditions, b"{\"failed\": 0}")
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(
+            first_holding(&conditions, b"done").unwrap_err(),
+            "the output is not JSON"
+        );
+    }
+}
@@ -0,0 +1,664 @@
+use crate::auth;
+use crate::escalate;
+use crate::event::Severity;
+use crate::fit::Overflow;
+use crate::maintenance;
+use crate::mute;
+use crate::notifier::Backpressure;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Clone)]
+pub struct TgConfig {
+    pub bot_token: String,
+    pub chat_id: String,
+    pub api_base: String,
+    pub http: HttpOptions,
+    /// How long to wait at exit for queued notifications
+    /// (`SENTINEL_SHUTDOWN_TIMEOUT`, in seconds).
+    pub shutdown_timeout: Duration,
+    /// What to do with messages too long for Telegram
+    /// (`SENTINEL_TELEGRAM_OVERFLOW`).
+    pub overflow: Overflow,
+    /// How many notifications may wait to be sent (`SENTINEL_QUEUE_SIZE`).
+    pub queue_size: usize,
+    /// What to do with another one when that many are waiting
+    /// (`SENTINEL_QUEUE_POLICY`).
+    pub backpressure: Backpressure,
+    /// Where failures nobody acknowledges go (`SENTINEL_ESCALATE_*`).
+    pub escalation: Option<escalate::Policy>,
+    /// The chats notifications of a severity and above go to instead of
+    /// `chat_id` (`TG_CHAT_ID_<SEVERITY>`), least severe first.
+    pub severity_chats: Vec<(Severity, String)>,
+    /// Notifications less severe than this are sent without a sound
+    /// (`SENTINEL_SILENT_BELOW`).
+    pub silent_below: Option<Severity>,
+    /// How notifications are handled while `SENTINEL_MAINTENANCE` is set,
+    /// besides any [maintenance](crate::maintenance) started on the host.
+    pub maintenance: Option<maintenance::Handling>,
+    /// Runs not to notify about (`SENTINEL_MUTE`), besides those
+    /// [muted](crate::mute) on the host.
+    pub mutes: Vec<mute::Rule>,
+}
+
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub const DEFAULT_QUEUE_SIZE: usize = 1000;
+
+/// How notification traffic (Telegram and plugin requests) is sent.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HttpOptions {
+    /// Proxy overriding `HTTPS_PROXY` and friends (`--proxy`, else
+    /// `SENTINEL_PROXY`).
+    pub proxy: Option<String>,
+    /// PEM bundle of extra root certificates to trust
+    /// (`SENTINEL_CA_BUNDLE`), e.g. for an internal CA.
+    pub ca_bundle: Option<PathBuf>,
+    /// PEM client certificate for mutual TLS (`SENTINEL_CLIENT_CERT`).
+    pub client_cert: Option<PathBuf>,
+    /// Its private key (`SENTINEL_CLIENT_KEY`), unless it is in
+    /// `client_cert` too.
+    pub client_key: Option<PathBuf>,
+    /// How long a request may take (`SENTINEL_HTTP_TIMEOUT`, in seconds;
+    /// default 10).
+    pub timeout: Option<Duration>,
+    /// How long an idle connection is kept open for the next request
+    /// (`SENTINEL_HTTP_IDLE_TIMEOUT`, in seconds; default 90).
+    pub idle_timeout: Option<Duration>,
+    /// How many idle connections are kept open per host
+    /// (`SENTINEL_HTTP_MAX_IDLE`; default no limit).
+    pub max_idle: Option<usize>,
+}
+
+pub const DEFAULT_HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+impl HttpOptions {
+    pub fn from_env() -> Result<Self, String> {
+        let path = |key: &str| {
+            env::var_os(key)
+                .filter(|p| !p.is_empty())
+                .map(PathBuf::from)
+        };
+        Ok(HttpOptions {
+            proxy: env_required("SENTINEL_PROXY").ok(),
+            ca_bundle: path("SENTINEL_CA_BUNDLE"),
+            client_cert: path("SENTINEL_CLIENT_CERT"),
+            client_key: path("SENTINEL_CLIENT_KEY"),
+            timeout: seconds("SENTINEL_HTTP_TIMEOUT")?,
+            idle_timeout: seconds("SENTINEL_HTTP_IDLE_TIMEOUT")?,
+            max_idle: max_idle()?,
+        })
+    }
+}
+
+fn max_idle() -> Result<Option<usize>, String> {
+    match env_required("SENTINEL_HTTP_MAX_IDLE") {
+        Ok(max) => Ok(Some(max.trim().parse().map_err(|_| {
+            format!("SENTINEL_HTTP_MAX_IDLE: expected a number, got {max:?}")
+        })?)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// The number of seconds in `key`, if it is set.
+fn seconds(key: &str) -> Result<Option<Duration>, String> {
+    let Ok(secs) = env_required(key) else {
+        return Ok(None);
+    };
+    secs.trim()
+        .parse()
+        .ok()
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        .map(Some)
+        .ok_or_else(|| format!("{key}: expected seconds, got {secs:?}"))
+}
+
+impl TgConfig {
+    pub fn new(bot_token: &str, chat_id: &str, api_base: &str) -> Self {
+        TgConfig {
+            bot_token: bot_token.trim().to_string(),
+            chat_id: chat_id.trim().to_string(),
+            api_base: api_base.trim_end_matches('/').to_string(),
+            http: HttpOptions::default(),
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            overflow: Overflow::default(),
+            queue_size: DEFAULT_QUEUE_SIZE,
+            backpressure: Backpressure::default(),
+            escalation: None,
+            severity_chats: Vec::new(),
+            silent_below: None,
+            maintenance: None,
+            mutes: Vec::new(),
+        }
+    }
+
+    /// The chat notifications of `severity` go to: that of the most severe
+    /// of `severity_chats` up to it, else `chat_id`.
+    pub fn chat_for(&self, severity: Severity) -> &str {
+        self.severity_chats
+            .iter()
+            .rev()
+            .find(|(from, _)| *from <= severity)
+            .map_or(&self.chat_id, |(_, chat)| chat)
+    }
+
+    /// Whether notifications of `severity` are sent without a sound.
+    pub fn silent(&self, severity: Severity) -> bool {
+        self.silent_below.is_some_and(|below| severity < below)
+    }
+}
+
+pub const DEFAULT_API_BASE: &str = "https://api.telegram.org/";
+
+/// `url` checked as a Bot API server to send to: an `http(s)://` URL, the
+/// server only, not the `/bot<token>/` a method URL adds.
+pub fn parse_api_base(url: &str) -> Result<String, String> {
+    let url = url.trim();
+    let parsed = reqwest::Url::parse(url).map_err(|e| format!("{url:?}: {e}"))?;
+    if !matches!(parsed.scheme(), "http" | "https") || !parsed.has_host() {
+        return Err(format!("{url:?}: expected an http:// or https:// URL"));
+    }
+    if parsed.query().is_some() || parsed.fragment().is_some() {
+        return Err(format!("{url:?}: expected no ?query or #fragment"));
+    }
+    if parsed
+        .path_segments()
+        .into_iter()
+        .flatten()
+        .any(|s| s.starts_with("bot") && s.contains(':'))
+    {
+        return Err(format!(
+            "{url:?}: give the server only; sentinel-rs adds /bot<token>/ itself"
+        ));
+    }
+    Ok(url.to_string())
+}
+
+/// The Bot API server: `TG_API_BASE` (which `--api-base` sets), else
+/// [`DEFAULT_API_BASE`].
+pub fn api_base() -> Result<String, String> {
+    match env_required("TG_API_BASE") {
+        Ok(url) => parse_api_base(&url).map_err(|e| format!("TG_API_BASE {e}")),
+        Err(_) => Ok(DEFAULT_API_BASE.to_string()),
+    }
+}
+
+/// `$SENTINEL_CONFIG_DIR`, else `$XDG_CONFIG_HOME/sentinel-rs`, else
+/// `~/.config/sentinel-rs`.
+pub fn config_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("SENTINEL_CONFIG_DIR").filter(|d| !d.is_empty()) {
+        return Some(PathBuf::from(dir));
+    }
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("sentinel-rs"))
+}
+
+/// Where sentinel-rs keeps what it remembers between runs:
+/// `$SENTINEL_STATE_DIR`, else `$XDG_STATE_HOME/sentinel-rs`, else
+/// `~/.local/state/sentinel-rs`.
+pub fn state_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("SENTINEL_STATE_DIR").filter(|d| !d.is_empty()) {
+        return Some(PathBuf::from(dir));
+    }
+    let base = env::var_os("XDG_STATE_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state"))
+        })?;
+    Some(base.join("sentinel-rs"))
+}
+
+/// What the state file `path` holds, if it is there and can be read.
+pub fn read_state<T: DeserializeOwned>(path: &Path) -> Option<T> {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+}
+
+/// Writes `state` to the state file `path`, whole and renamed into place, so
+/// that a run reading it meanwhile cannot see half a file.
+pub fn write_state<T: Serialize + ?Sized>(path: &Path, state: &T) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+    std::fs::write(&tmp, serde_json::to_string(state)?)?;
+    std::fs::rename(&tmp, path)
+}
+
+/// Takes the lock on the state file `path`, a `.lock` file beside it, held
+/// until the file returned is dropped: around reading the state, changing
+/// it and writing it back, so that runs doing so at the same time do not
+/// lose each other's changes.
+pub fn lock_state(path: &Path) -> std::io::Result<std::fs::File> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let lock = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path.with_extension("lock"))?;
+    lock.lock()?;
+    Ok(lock)
+}
+
+/// The profile selected with `SENTINEL_PROFILE`, if any.
+pub fn profile() -> Result<Option<String>, String> {
+    match env::var("SENTINEL_PROFILE") {
+        Ok(name) if name.trim().is_empty() => Ok(None),
+        Ok(name) if name.contains(['/', '\\']) || name.starts_with('.') => {
+            Err(format!("invalid profile name {name:?}"))
+        }
+        Ok(name) => Ok(Some(name)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Directory holding a profile's overrides within the config dir.
+pub fn profile_dir(config_dir: &Path, profile: &str) -> PathBuf {
+    config_dir.join("profiles").join(profile)
+}
+
+/// Name of the settings file in the config dir and in a profile's
+/// directory, as written by `sentinel-rs init`.
+pub const SETTINGS_FILE: &str = "env";
+
+/// The `KEY=VALUE` lines of a settings file. Blank lines and `#` comments
+/// are skipped, and a value may be in single or double quotes.
+pub fn parse_settings(text: &str) -> Result<Vec<(String, String)>, String> {
+    let mut settings = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected KEY=VALUE", n + 1))?;
+        let key = key.trim();
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("line {}: invalid name {key:?}", n + 1));
+        }
+        let value = value.trim();
+        let value = ['"', '\'']
+            .into_iter()
+            .find_map(|quote| value.strip_prefix(quote)?.strip_suffix(quote))
+            .unwrap_or(value);
+        settings.push((key.to_string(), value.to_string()));
+    }
+    Ok(settings)
+}
+
+/// The directories configuration files are looked for in, the one that
+/// wins first: the selected profile's, then the config dir itself.
+pub fn config_dirs() -> Result<Vec<PathBuf>, String> {
+    let Some(dir) = config_dir() else {
+        return Ok(Vec::new());
+    };
+    let mut dirs = Vec::new();
+    if let Some(profile) = profile()? {
+        dirs.push(profile_dir(&dir, &profile));
+    }
+    dirs.push(dir);
+    Ok(dirs)
+}
+
+/// The settings from the [`SETTINGS_FILE`]s in the [`config_dirs`], the
+/// profile's first so that they win. Either may be missing.
+pub fn settings() -> Result<Vec<(String, String)>, String> {
+    let mut settings = Vec::new();
+    for dir in config_dirs()? {
+        let file = dir.join(SETTINGS_FILE);
+        let text = match std::fs::read_to_string(&file) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(format!("cannot read {}: {e}", file.display())),
+        };
+        settings.extend(parse_settings(&text).map_err(|e| format!("{}: {e}", file.display()))?);
+    }
+    Ok(settings)
+}
+
+pub fn env_required(key: &str) -> Result<String, std::env::VarError> {
+    let value = std::env::var(key)?;
+    if value.trim().is_empty() {
+        return Err(std::env::VarError::NotPresent);
+    }
+    Ok(value)
+}
+
+/// Name of the systemd credential (`LoadCredential=tg_bot_token:…`) the bot
+/// token is read from.
+pub const TOKEN_CREDENTIAL: &str = "tg_bot_token";
+
+fn read_token_file(path: &Path) -> Result<String, String> {
+    let token = std::fs::read_to_string(path)
+        .map_err(|e| format!("cannot read bot token from {}: {e}", path.display()))?;
+    if token.trim().is_empty() {
+        return Err(format!("bot token file {} is empty", path.display()));
+    }
+    Ok(token)
+}
+
+/// The bot token from, in order: `token_file` (`--token-file`),
+/// `TG_BOT_TOKEN`, the file named by `TG_BOT_TOKEN_FILE`, the
+/// `tg_bot_token` systemd credential in `$CREDENTIALS_DIRECTORY`, or the OS
+/// keyring (`sentinel-rs auth set telegram`).
+pub fn bot_token(token_file: Option<&Path>) -> Result<String, String> {
+    if let Some(path) = token_file {
+        tracing::info!("Bot token from --token-file {}", path.display());
+        return read_token_file(path);
+    }
+    if let Ok(token) = env_required("TG_BOT_TOKEN") {
+        tracing::info!("Bot token from TG_BOT_TOKEN");
+        return Ok(token);
+    }
+    if let Some(path) = env::var_os("TG_BOT_TOKEN_FILE").filter(|p| !p.is_empty()) {
+        tracing::info!("Bot token from TG_BOT_TOKEN_FILE {}", path.display());
+        return read_token_file(Path::new(&path));
+    }
+    if let Some(dir) = env::var_os("CREDENTIALS_DIRECTORY").filter(|d| !d.is_empty()) {
+        let path = Path::new(&dir).join(TOKEN_CREDENTIAL);
+        if path.is_file() {
+            tracing::info!("Bot token from credential {}", path.display());
+            return read_token_file(&path);
+        }
+    }
+    tracing::info!("Looking up the bot token in the keyring");
+    auth::lookup(auth::BOT_TOKEN).ok_or_else(|| {
+        "TG_BOT_TOKEN is not set (nor TG_BOT_TOKEN_FILE, --token-file, a tg_bot_token credential or a keyring entry)"
+            .to_string()
+    })
+}
+
+/// `TG_CHAT_ID`, else the chat id stored in the OS keyring.
+pub fn chat_id() -> Result<String, String> {
+    env_required("TG_CHAT_ID")
+        .ok()
+        .or_else(|| auth::lookup(auth::CHAT_ID))
+        .ok_or_else(|| "TG_CHAT_ID is not set (nor is there a keyring entry)".to_string())
+}
+
+pub fn load_tg_config() -> Result<TgConfig, Box<dyn std::error::Error>> {
+    load_tg_config_with(None)
+}
+
+/// Like [`load_tg_config`], reading the bot token from `token_file` if given.
+pub fn load_tg_config_with(
+    token_file: Option<&Path>,
+) -> Result<TgConfig, Box<dyn std::error::Error>> {
+    let bot_token = bot_token(token_file)?;
+    let chat_id = chat_id()?;
+    let api_base = api_base()?;
+    Ok(TgConfig {
+        http: HttpOptions::from_env()?,
+        shutdown_timeout: seconds("SENTINEL_SHUTDOWN_TIMEOUT")?.unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT),
+        overflow: overflow()?,
+        queue_size: queue_size()?,
+        backpressure: backpressure()?,
+        escalation: escalate::from_env()?,
+        severity_chats: severity_chats(),
+        silent_below: silent_below()?,
+        maintenance: maintenance::from_env()?,
+        mutes: mute::from_env()?,
+        ..TgConfig::new(&bot_token, &chat_id, &api_base)
+    })
+}
+
+fn overflow() -> Result<Overflow, String> {
+    match env_required("SENTINEL_TELEGRAM_OVERFLOW") {
+        Ok(policy) => policy
+            .parse()
+            .map_err(|e| format!("SENTINEL_TELEGRAM_OVERFLOW: {e}")),
+        Err(_) => Ok(Overflow::default()),
+    }
+}
+
+/// `TG_CHAT_ID_WARNING`, `TG_CHAT_ID_ERROR` and `TG_CHAT_ID_CRITICAL`, those
+/// set.
+fn severity_chats() -> Vec<(Severity, String)> {
+    Severity::ALL
+        .into_iter()
+        .skip(1)
+        .filter_map(|severity| {
+            let key = format!("TG_CHAT_ID_{}", severity.name().to_uppercase());
+            env_required(&key)
+                .ok()
+                .map(|chat| (severity, chat.trim().to_string()))
+        })
+        .collect()
+}
+
+fn silent_below() -> Result<Option<Severity>, String> {
+    match env_required("SENTINEL_SILENT_BELOW") {
+        Ok(name) => name
+            .parse()
+            .map(Some)
+            .map_err(|e| format!("SENTINEL_SILENT_BELOW: {e}")),
+        Err(_) => Ok(None),
+    }
+}
+
+fn queue_size() -> Result<usize, String> {
+    match env_required("SENTINEL_QUEUE_SIZE") {
+        Ok(size) => size
+            .trim()
+            .parse()
+            .ok()
+            .filter(|&size| size > 0)
+            .ok_or_else(|| {
+                format!("SENTINEL_QUEUE_SIZE: expected a positive number, got {size:?}")
+            }),
+        Err(_) => Ok(DEFAULT_QUEUE_SIZE),
+    }
+}
+
+fn backpressure() -> Result<Backpressure, String> {
+    match env_required("SENTINEL_QUEUE_POLICY") {
+        Ok(policy) => policy
+            .parse()
+            .map_err(|e| format!("SENTINEL_QUEUE_POLICY: {e}")),
+        Err(_) => Ok(Backpressure::default()),
+    }
+}
+
+/// What is wrong with the settings besides the bot token and chat id: all
+/// of it, where [`load_tg_config_with`] stops at the first problem.
+pub fn setting_problems() -> Vec<String> {
+    let mut problems: Vec<String> = [
+        "SENTINEL_SHUTDOWN_TIMEOUT",
+        "SENTINEL_HTTP_TIMEOUT",
+        "SENTINEL_HTTP_IDLE_TIMEOUT",
+    ]
+    .into_iter()
+    .filter_map(|key| seconds(key).err())
+    .collect();
+    problems.extend(max_idle().err());
+    problems.extend(overflow().err());
+    problems.extend(queue_size().err());
+    problems.extend(backpressure().err());
+    problems.extend(escalate::from_env().err());
+    problems.extend(silent_below().err());
+    problems.extend(maintenance::from_env().err());
+    problems.extend(mute::from_env().err());
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn env_required_present_returns_value() {
+        let key = "SENTINEL_RS_TEST_ENV";
+        let value = "test_value".to_string();
+        let prior = std::env::var(key).ok();
+        unsafe {
+            std::env::set_var(key, &value);
+        }
+        let result = env_required(key).unwrap();
+        unsafe {
+            if let Some(prior) = prior {
+                std::env::set_var(key, prior);
+            } else {
+                std::env::remove_var(key);
+            }
+        }
+        assert_eq!(result, value);
+    }
+
+    #[test]
+    fn env_required_missing_returns_err() {
+        let key = "SENTINEL_RS_TEST_MISSING_ENV";
+        unsafe {
+            std::env::remove_var(key);
+        }
+        let result = env_required(key);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn load_tg_config_rejects_empty_values() {
+        let token_key = "TG_BOT_TOKEN";
+        let chat_key = "TG_CHAT_ID";
+        let prior_token = std::env::var(token_key).ok();
+        let prior_chat = std::env::var(chat_key).ok();
+        unsafe {
+            std::env::set_var(token_key, "   ");
+            std::env::set_var(chat_key, "123");
+        }
+        let result = load_tg_config();
+        unsafe {
+            if let Some(prior) = prior_token {
+                std::env::set_var(token_key, prior);
+            } else {
+                std::env::remove_var(token_key);
+            }
+            if let Some(prior) = prior_chat {
+                std::env::set_var(chat_key, prior);
+            } else {
+                std::env::remove_var(chat_key);
+            }
+        }
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn validate_tg_tokens_set_accepts_non_empty_values() {
+        let token_key = "TG_BOT_TOKEN";
+        let chat_key = "TG_CHAT_ID";
+        let prior_token = std::env::var(token_key).ok();
+        let prior_chat = std::env::var(chat_key).ok();
+        unsafe {
+            std::env::set_var(token_key, "token");
+            std::env::set_var(chat_key, "123");
+        }
+        let result = load_tg_config();
+        unsafe {
+            if let Some(prior) = prior_token {
+                std::env::set_var(token_key, prior);
+            } else {
+                std::env::remove_var(token_key);
+            }
+            if let Some(prior) = prior_chat {
+                std::env::set_var(chat_key, prior);
+            } else {
+                std::env::remove_var(chat_key);
+            }
+        }
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn bot_token_is_read_from_token_file_before_env() {
+        let path = std::env::temp_dir().join(format!("sentinel-token-{}", std::process::id()));
+        std::fs::write(&path, "file-token\n").unwrap();
+        let token = bot_token(Some(&path));
+        std::fs::write(&path, " \n").unwrap();
+        let empty = bot_token(Some(&path));
+        std::fs::remove_file(&path).ok();
+        assert_eq!(token.unwrap(), "file-token\n");
+        assert!(empty.unwrap_err().contains("is empty"));
+        assert!(bot_token(Some(Path::new("/nonexistent/token"))).is_err());
+    }
+
+    #[test]
+    fn api_base_must_be_a_server_url() {
+        assert_eq!(
+            parse_api_base(" http://localhost:8081/ ").unwrap(),
+            "http://localhost:8081/"
+        );
+        assert!(parse_api_base("https://tg.example.com/telegram").is_ok());
+        assert!(
+            parse_api_base("localhost:8081")
+                .unwrap_err()
+                .contains("http://")
+        );
+        assert!(parse_api_base("api.telegram.org").is_err());
+        assert!(
+            parse_api_base("https://api.telegram.org/bot123:abc/")
+                .unwrap_err()
+                .contains("/bot<token>/")
+        );
+        assert!(parse_api_base("https://tg.example.com/?key=1").is_err());
+    }
+
+    #[test]
+    fn tg_config_new_normalizes_values() {
+        let cfg = TgConfig::new(" token ", " 123\n", "http://localhost:8081/");
+        assert_eq!(cfg.bot_token, "token");
+        assert_eq!(cfg.chat_id, "123");
+        assert_eq!(cfg.api_base, "http://localhost:8081");
+    }
+
+    #[test]
+    fn severities_pick_the_chat_and_the_sound() {
+        let cfg = TgConfig {
+            severity_chats: vec![
+                (Severity::Warning, "-1".to_string()),
+                (Severity::Critical, "-3".to_string()),
+            ],
+            silent_below: Some(Severity::Error),
+            ..TgConfig::new("t", "123", DEFAULT_API_BASE)
+        };
+        assert_eq!(cfg.chat_for(Severity::Info), "123");
+        assert_eq!(cfg.chat_for(Severity::Warning), "-1");
+        assert_eq!(cfg.chat_for(Severity::Error), "-1");
+        assert_eq!(cfg.chat_for(Severity::Critical), "-3");
+        assert!(cfg.silent(Severity::Warning));
+        assert!(!cfg.silent(Severity::Error));
+        assert!(!TgConfig::new("t", "123", DEFAULT_API_BASE).silent(Severity::Info));
+    }
+
+    #[test]
+    fn settings_lines_are_parsed() {
+        let text = "# Telegram\nTG_BOT_TOKEN=123:abc\n\nexport TG_CHAT_ID = \"-100 5\"\n  # SENTINEL_PROXY=\nGRAFANA_TOKEN='x=y'\n";
+        let settings = parse_settings(text).unwrap();
+        assert_eq!(
+            settings,
+            [
+                ("TG_BOT_TOKEN".to_string(), "123:abc".to_string()),
+                ("TG_CHAT_ID".to_string(), "-100 5".to_string()),
+                ("GRAFANA_TOKEN".to_string(), "x=y".to_string()),
+            ]
+        );
+        assert_eq!(
+            parse_settings("TG_CHAT_ID\n").unwrap_err(),
+            "line 1: expected KEY=VALUE"
+        );
+        assert!(
+            parse_settings("A B=1")
+                .unwrap_err()
+                .contains("invalid name")
+        );
+    }
+}
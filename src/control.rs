@@ -0,0 +1,460 @@
+//! `--control`: talking to a run from another shell. While the command
+//! runs, sentinel-rs listens on a Unix socket of its own, named after the
+//! run id in a directory only we can enter (see [`dir`]), for one request
+//! per connection, a line each:
+//!
+//! - `status`: the run as JSON (see [`crate::status`])
+//! - `tail [N]`: its last N lines of output (100 by default)
+//! - `signal SIG`: sends SIG (`TERM`, `SIGINT`, `9`, …) to the command
+//! - `pause`, `resume`: stops the command and everything it started with
+//!   SIGSTOP, and lets them go on with SIGCONT
+//! - `stdin TEXT`: writes TEXT and a newline to the command's stdin, which
+//!   is then a pipe of ours rather than the terminal's
+//! - `eof`: closes the command's stdin
+//! - `attach [stdin]`: `ok`, the last lines of output and then every line
+//!   as it comes, until the run is over; with `stdin`, what is sent after
+//!   the request goes on to the command's stdin
+//!
+//! Replies are text, and start with `error: ` if the request failed.
+//! `sentinel-rs ctl` and `sentinel-rs attach` are the clients.
+
+use crate::runner::{parse_signal, signal_name};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process::ChildStdin;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// The longest request read; `stdin` lines included.
+const MAX_REQUEST: usize = 64 * 1024;
+
+/// How many lines of output `attach` starts with.
+const ATTACH_LINES: usize = 10;
+
+/// The command the requests are about, while it runs.
+struct Child {
+    pid: u32,
+    stdin: Option<Arc<File>>,
+}
+
+static CHILD: Mutex<Option<Child>> = Mutex::new(None);
+
+/// How many `attach` clients are still being sent the output.
+static ATTACHED: (Mutex<usize>, Condvar) = (Mutex::new(0), Condvar::new());
+
+/// How long the end of a run waits for them to be sent the last of it.
+const DRAIN: Duration = Duration::from_secs(1);
+
+fn child() -> std::sync::MutexGuard<'static, Option<Child>> {
+    CHILD.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Where the sockets are: `$XDG_RUNTIME_DIR/sentinel-rs`, else
+/// `sentinel-rs-UID` in the temporary directory.
+pub fn dir() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR").filter(|d| !d.is_empty()) {
+        Some(runtime) => PathBuf::from(runtime).join("sentinel-rs"),
+        // SAFETY: getuid cannot fail.
+        None => std::env::temp_dir().join(format!("sentinel-rs-{}", unsafe { libc::getuid() })),
+    }
+}
+
+/// Removes the socket when dropped, once `attach` clients have what
+/// there is for them (or after [`DRAIN`]).
+pub struct Listening(PathBuf);
+
+impl Listening {
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for Listening {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.0).ok();
+        let (count, drained) = &ATTACHED;
+        let count = count.lock().unwrap_or_else(|e| e.into_inner());
+        drained.wait_timeout_while(count, DRAIN, |n| *n > 0).ok();
+    }
+}
+
+/// Takes requests about run `run_id` from a background thread until the
+/// returned guard is dropped.
+pub fn serve(run_id: &str) -> std::io::Result<Listening> {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+    let dir = dir();
+    std::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&dir)?;
+    // Others could read the command's output and send it signals.
+    std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700))?;
+    let path = dir.join(format!("{run_id}.sock"));
+    let listener = UnixListener::bind(&path)?;
+    crate::status::track();
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // A slow `stdin` write must not hold up a `status`.
+            std::thread::spawn(move || handle(stream));
+        }
+    });
+    Ok(Listening(path))
+}
+
+/// Lets requests reach the command with pid `pid`, and its stdin, if it is
+/// a pipe of ours.
+pub fn attach(pid: u32, stdin: Option<ChildStdin>) {
+    *child() = Some(Child {
+        pid,
+        stdin: stdin.map(|stdin| Arc::new(File::from(std::os::fd::OwnedFd::from(stdin)))),
+    });
+}
+
+/// Ends what [`attach`] started, once the command has exited.
+pub fn detach() {
+    *child() = None;
+}
+
+fn handle(stream: UnixStream) {
+    let reply = match read_request(&stream) {
+        Ok(request) => match request.split_once(' ').unwrap_or((&request, "")) {
+            ("attach", mode) => match follow_output(&stream, mode) {
+                Ok(()) => return,
+                Err(e) => Err(e),
+            },
+            _ => answer(&request),
+        },
+        Err(e) => Err(format!("unreadable request: {e}")),
+    };
+    let reply = reply.unwrap_or_else(|e| format!("error: {e}\n"));
+    (&stream).write_all(reply.as_bytes()).ok();
+}
+
+/// The first line, read a byte at a time so that what follows it is left
+/// for `attach stdin`.
+fn read_request(mut stream: &UnixStream) -> std::io::Result<String> {
+    let mut request = Vec::new();
+    let mut byte = [0];
+    while request.len() < MAX_REQUEST && stream.read(&mut byte)? == 1 && byte[0] != b'\n' {
+        request.push(byte[0]);
+    }
+    Ok(String::from_utf8_lossy(&request).into_owned())
+}
+
+/// Answers `attach`, for as long as the run goes on or the client stays.
+fn follow_output(stream: &UnixStream, mode: &str) -> Result<(), String> {
+    let forward = match mode.trim() {
+        "" => false,
+        "stdin" => true,
+        other => return Err(format!("unknown attach mode {other:?} (expected stdin)")),
+    };
+    if forward {
+        let stdin = command_stdin()?;
+        let mut from = stream.try_clone().map_err(|e| e.to_string())?;
+        // Not io::copy, whose splice(2) would hold the pipe, and the
+        // command's reads from it, while waiting for the client.
+        std::thread::spawn(move || {
+            let mut buf = [0; 4096];
+            while let Ok(n @ 1..) = from.read(&mut buf) {
+                if (&*stdin).write_all(&buf[..n]).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    let (tail, lines) = crate::status::follow(ATTACH_LINES);
+    *ATTACHED.0.lock().unwrap_or_else(|e| e.into_inner()) += 1;
+    let mut to = stream;
+    if to.write_all(format!("ok\n{tail}").as_bytes()).is_ok() {
+        for line in lines {
+            if to.write_all(format!("{line}\n").as_bytes()).is_err() {
+                break;
+            }
+        }
+    }
+    // Also ends the copying to stdin.
+    stream.shutdown(Shutdown::Both).ok();
+    *ATTACHED.0.lock().unwrap_or_else(|e| e.into_inner()) -= 1;
+    ATTACHED.1.notify_all();
+    Ok(())
+}
+
+/// The reply to `request`, or what went wrong.
+fn answer(request: &str) -> Result<String, String> {
+    let (verb, arg) = request.split_once(' ').unwrap_or((request, ""));
+    match verb {
+        "status" => Ok(crate::status::status().to_string() + "\n"),
+        "tail" => {
+            let lines = match arg.trim() {
+                "" => crate::status::DEFAULT_LINES,
+                n => n
+                    .parse()
+                    .map_err(|_| format!("not a number of lines: {n:?}"))?,
+            };
+            Ok(crate::status::tail(lines.min(crate::status::MAX_LINES)))
+        }
+        "signal" => {
+            let signal = parse_signal(arg).ok_or_else(|| format!("unknown signal {arg:?}"))?;
+            let pid = running()?;
+            kill(pid, signal)?;
+            Ok(format!("sent {} to {pid}\n", signal_name(signal)))
+        }
+        "pause" => {
+            let pid = running()?;
+            // Stopped top down, so none is left to start more; again for
+            // any started meanwhile.
+            let mut stopped = Vec::new();
+            loop {
+                let more: Vec<i32> = tree(pid)
+                    .into_iter()
+                    .filter(|p| !stopped.contains(p))
+                    .collect();
+                if more.is_empty() {
+                    break;
+                }
+                for &p in &more {
+                    // It may have exited since.
+                    kill(p, libc::SIGSTOP).ok();
+                }
+                stopped.extend(more);
+            }
+            Ok(format!("paused {} processes\n", stopped.len()))
+        }
+        "resume" => {
+            let tree = tree(running()?);
+            for &p in &tree {
+                kill(p, libc::SIGCONT).ok();
+            }
+            Ok(format!("resumed {} processes\n", tree.len()))
+        }
+        "stdin" => {
+            let stdin = command_stdin()?;
+            (&*stdin)
+                .write_all(format!("{arg}\n").as_bytes())
+                .map_err(|e| format!("failed to write to the command's stdin: {e}"))?;
+            Ok(format!("wrote {} bytes\n", arg.len() + 1))
+        }
+        "eof" => {
+            let mut child = child();
+            let child = child.as_mut().ok_or("the command is not running")?;
+            child.stdin = None;
+            Ok("closed the command's stdin\n".to_string())
+        }
+        _ => Err(format!(
+            "unknown request {verb:?} (expected status, tail, signal, pause, resume, stdin or eof)"
+        )),
+    }
+}
+
+/// The command's stdin, if it is running and that is still open.
+fn command_stdin() -> Result<Arc<File>, String> {
+    child()
+        .as_ref()
+        .ok_or("the command is not running")?
+        .stdin
+        .clone()
+        .ok_or_else(|| "the command's stdin is closed".to_string())
+}
+
+/// The pid of the command, if it is running.
+fn running() -> Result<i32, String> {
+    let pid = child()
+        .as_ref()
+        .map(|c| c.pid)
+        .ok_or("the command is not running")?;
+    libc::pid_t::try_from(pid).map_err(|e| e.to_string())
+}
+
+fn kill(pid: i32, signal: i32) -> Result<(), String> {
+    // SAFETY: kill takes no pointers.
+    if unsafe { libc::kill(pid, signal) } != 0 {
+        return Err(std::io::Error::last_os_error().to_string());
+    }
+    Ok(())
+}
+
+/// `pid` and the processes it started, parents before their children, as
+/// `/proc` has them now.
+fn tree(pid: i32) -> Vec<i32> {
+    let parents: Vec<(i32, i32)> = std::fs::read_dir("/proc")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let child = entry.file_name().to_str()?.parse().ok()?;
+            let stat = std::fs::read_to_string(entry.path().join("stat")).ok()?;
+            Some((child, parent_pid(&stat)?))
+        })
+        .collect();
+    let mut tree = vec![pid];
+    let mut i = 0;
+    while let Some(&parent) = tree.get(i) {
+        tree.extend(
+            parents
+                .iter()
+                .filter(|&&(_, p)| p == parent)
+                .map(|&(child, _)| child),
+        );
+        i += 1;
+    }
+    tree
+}
+
+/// The parent's pid from a `/proc/PID/stat` line, past the command name
+/// (which may hold spaces and parentheses).
+fn parent_pid(stat: &str) -> Option<i32> {
+    stat.rsplit_once(')')?
+        .1
+        .split_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()
+}
+
+/// The ids of the runs taking requests, in order. Sockets left behind by
+/// runs that were killed refuse, and are left out.
+pub fn live() -> Vec<String> {
+    let dir = dir();
+    let mut ids: Vec<String> = std::fs::read_dir(&dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let run_id = name.strip_suffix(".sock")?.to_string();
+            UnixStream::connect(entry.path()).ok()?;
+            Some(run_id)
+        })
+        .collect();
+    ids.sort();
+    ids
+}
+
+/// Connects to run `run_id`, or to the only run taking requests.
+fn connect(run_id: Option<&str>) -> Result<UnixStream, String> {
+    let run_id = match run_id {
+        Some(run_id) => run_id.to_string(),
+        None => {
+            let mut live = live();
+            match live.len() {
+                0 => return Err(format!("No run takes requests in {}.", dir().display())),
+                1 => live.remove(0),
+                _ => {
+                    return Err(format!(
+                        "Several runs take requests ({}); name the one you mean.",
+                        live.join(", ")
+                    ));
+                }
+            }
+        }
+    };
+    let path = dir().join(format!("{run_id}.sock"));
+    UnixStream::connect(&path)
+        .map_err(|e| format!("Failed to reach run {run_id} at {}: {e}", path.display()))
+}
+
+/// Sends `request` to run `run_id`, or the only run taking requests, and
+/// returns its reply.
+pub fn request(run_id: Option<&str>, request: &str) -> Result<String, String> {
+    if request.contains('\n') {
+        return Err("A request is one line; send more stdin with several.".to_string());
+    }
+    let mut stream = connect(run_id)?;
+    stream
+        .write_all(format!("{request}\n").as_bytes())
+        .map_err(|e| format!("Failed to send the request: {e}"))?;
+    let mut reply = String::new();
+    stream
+        .read_to_string(&mut reply)
+        .map_err(|e| format!("Failed to read the reply: {e}"))?;
+    match reply.strip_prefix("error: ") {
+        Some(error) => Err(error.trim_end().to_string()),
+        None => Ok(reply),
+    }
+}
+
+/// Copies the output of run `run_id`, or the only run taking requests, to
+/// stdout as it comes, until the run is over; with `forward_stdin`, passes
+/// what is typed on to the command.
+pub fn attach_to(run_id: Option<&str>, forward_stdin: bool) -> Result<(), String> {
+    let mut stream = connect(run_id)?;
+    let request = if forward_stdin {
+        "attach stdin\n"
+    } else {
+        "attach\n"
+    };
+    stream
+        .write_all(request.as_bytes())
+        .map_err(|e| format!("Failed to send the request: {e}"))?;
+    let mut reader = BufReader::new(&stream);
+    let mut answer = String::new();
+    reader
+        .read_line(&mut answer)
+        .map_err(|e| format!("Failed to read the reply: {e}"))?;
+    if answer != "ok\n" {
+        let error = answer.strip_prefix("error: ").unwrap_or(&answer);
+        return Err(error.trim_end().to_string());
+    }
+    if forward_stdin {
+        let mut to = stream
+            .try_clone()
+            .map_err(|e| format!("Failed to forward stdin: {e}"))?;
+        std::thread::spawn(move || std::io::copy(&mut std::io::stdin(), &mut to));
+    }
+    std::io::copy(&mut reader, &mut std::io::stdout())
+        .map_err(|e| format!("Failed to copy the output: {e}"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_requests_about_the_command() {
+        let mut child = std::process::Command::new("cat")
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        attach(child.id(), child.stdin.take());
+        assert_eq!(answer("stdin hello").unwrap(), "wrote 6 bytes\n");
+        answer("eof").unwrap();
+        assert!(answer("stdin again").unwrap_err().contains("closed"));
+        let mut out = String::new();
+        child
+            .stdout
+            .take()
+            .unwrap()
+            .read_to_string(&mut out)
+            .unwrap();
+        assert_eq!(out, "hello\n");
+        child.wait().unwrap();
+
+        let mut sleeper = std::process::Command::new("bash")
+            .args(["-c", "sleep 30 & wait"])
+            .spawn()
+            .unwrap();
+        attach(sleeper.id(), None);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        assert_eq!(answer("pause").unwrap(), "paused 2 processes\n");
+        assert_eq!(answer("resume").unwrap(), "resumed 2 processes\n");
+        for pid in tree(running().unwrap()).into_iter().rev() {
+            kill(pid, libc::SIGKILL).unwrap();
+        }
+        sleeper.wait().unwrap();
+        assert_eq!(parent_pid("42 (a (b) c) S 7 42 42"), Some(7));
+        detach();
+        assert!(answer("signal TERM").unwrap_err().contains("not running"));
+        assert!(
+            answer("signal SIGBOGUS")
+                .unwrap_err()
+                .contains("unknown signal")
+        );
+        assert!(answer("tail x").is_err());
+        assert!(answer("reboot").unwrap_err().contains("unknown request"));
+    }
+}
@@ -0,0 +1,230 @@
+//! Finding the core file of a command that dumped core, so a crash report
+//! says where to look. The kernel names the file after
+//! `/proc/sys/kernel/core_pattern`; when that pipes the core to a handler
+//! instead (systemd-coredump, apport, …), the report names the handler.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// What we know about the process that crashed.
+pub struct Crash<'a> {
+    pub pid: Option<u32>,
+    pub signal: i32,
+    pub uid: u32,
+    pub gid: u32,
+    /// The crashed process's working directory, for relative patterns.
+    pub cwd: &'a Path,
+    /// Only files at least this recent are taken for its core.
+    pub since: SystemTime,
+}
+
+/// Where the core went, e.g. `/var/crash/core.1234`, or
+/// `systemd-coredump (coredumpctl info 1234)`. `None` if it cannot be found.
+pub fn locate(crash: &Crash) -> Option<String> {
+    let pattern = std::fs::read_to_string("/proc/sys/kernel/core_pattern").ok()?;
+    let uses_pid = std::fs::read_to_string("/proc/sys/kernel/core_uses_pid")
+        .is_ok_and(|value| value.trim() == "1");
+    locate_with(pattern.trim_end(), uses_pid, crash)
+}
+
+fn locate_with(pattern: &str, uses_pid: bool, crash: &Crash) -> Option<String> {
+    // File times come from a coarser clock than ours and can lag behind it.
+    let since = crash
+        .since
+        .checked_sub(Duration::from_secs(1))
+        .unwrap_or(crash.since);
+    if let Some(handler) = pattern.strip_prefix('|') {
+        let program = handler.split_whitespace().next().unwrap_or(handler);
+        if program.ends_with("systemd-coredump") {
+            return Some(match crash.pid {
+                Some(pid) => format!("systemd-coredump (coredumpctl info {pid})"),
+                None => "systemd-coredump (coredumpctl list)".to_string(),
+            });
+        }
+        return Some(format!("piped to {program}"));
+    }
+    let mut pieces = expand(pattern, crash);
+    if uses_pid && !pattern.contains("%p") {
+        pieces.push(Piece::Text(".".to_string()));
+        pieces.push(match crash.pid {
+            Some(pid) => Piece::Text(pid.to_string()),
+            None => Piece::Any,
+        });
+    }
+    let (dir, name) = split_dir(&pieces)?;
+    let dir = crash.cwd.join(dir);
+    if !name.contains(&Piece::Any) {
+        let path = dir.join(concat(&name));
+        return is_recent(&path, since).then(|| path.display().to_string());
+    }
+    // Some of the name is unknown (e.g. `%t`): take the newest match.
+    std::fs::read_dir(&dir)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .is_some_and(|n| matches(&name, &n.to_string_lossy()))
+        })
+        .filter_map(|path| Some((modified(&path).filter(|t| *t >= since)?, path)))
+        .max()
+        .map(|(_, path)| path.display().to_string())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Piece {
+    Text(String),
+    /// A specifier whose value we do not know.
+    Any,
+}
+
+fn expand(pattern: &str, crash: &Crash) -> Vec<Piece> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            text.push(c);
+            continue;
+        }
+        let value = match chars.next() {
+            Some('%') => Some("%".to_string()),
+            Some('p') => crash.pid.map(|pid| pid.to_string()),
+            Some('s') => Some(crash.signal.to_string()),
+            Some('u') => Some(crash.uid.to_string()),
+            Some('g') => Some(crash.gid.to_string()),
+            Some('h') => hostname::get()
+                .ok()
+                .map(|h| h.to_string_lossy().into_owned()),
+            // Only known to the kernel: %t, %e (bash may or may not have
+            // exec'd the program), %P, %i, %E, %c, …
+            Some(_) => None,
+            None => Some(String::new()),
+        };
+        match value {
+            Some(value) => text.push_str(&value),
+            None => {
+                if !text.is_empty() {
+                    pieces.push(Piece::Text(std::mem::take(&mut text)));
+                }
+                pieces.push(Piece::Any);
+            }
+        }
+    }
+    if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+    pieces
+}
+
+/// Splits at the last `/`: a directory we know exactly, and the file name.
+fn split_dir(pieces: &[Piece]) -> Option<(PathBuf, Vec<Piece>)> {
+    let last_slash = pieces
+        .iter()
+        .enumerate()
+        .rev()
+        .find_map(|(i, piece)| match piece {
+            Piece::Text(text) => text.rfind('/').map(|pos| (i, pos)),
+            Piece::Any => None,
+        });
+    let Some((index, pos)) = last_slash else {
+        return Some((PathBuf::new(), pieces.to_vec()));
+    };
+    if pieces[..index].contains(&Piece::Any) {
+        return None;
+    }
+    let Piece::Text(split) = &pieces[index] else {
+        return None;
+    };
+    let mut dir = concat(&pieces[..index]);
+    dir.push_str(&split[..=pos]);
+    let mut name = vec![Piece::Text(split[pos + 1..].to_string())];
+    name.extend_from_slice(&pieces[index + 1..]);
+    Some((PathBuf::from(dir), name))
+}
+
+fn concat(pieces: &[Piece]) -> String {
+    pieces
+        .iter()
+        .map(|piece| match piece {
+            Piece::Text(text) => text.as_str(),
+            Piece::Any => "",
+        })
+        .collect()
+}
+
+/// Whether `name` fits `pieces`, with each `Any` standing for any text.
+fn matches(pieces: &[Piece], name: &str) -> bool {
+    match pieces.split_first() {
+        None => name.is_empty(),
+        Some((Piece::Text(text), rest)) => name
+            .strip_prefix(text.as_str())
+            .is_some_and(|name| matches(rest, name)),
+        Some((Piece::Any, rest)) => (0..=name.len())
+            .filter(|&i| name.is_char_boundary(i))
+            .any(|i| matches(rest, &name[i..])),
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn is_recent(path: &Path, since: SystemTime) -> bool {
+    modified(path).is_some_and(|t| t >= since)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crash(cwd: &Path) -> Crash<'_> {
+        Crash {
+            pid: Some(4242),
+            signal: 11,
+            uid: 1000,
+            gid: 1000,
+            cwd,
+            since: SystemTime::UNIX_EPOCH,
+        }
+    }
+
+    #[test]
+    fn names_core_handlers() {
+        let cwd = Path::new("/");
+        assert_eq!(
+            locate_with(
+                "|/usr/lib/systemd/systemd-coredump %P %u %g %s %t %c %h",
+                false,
+                &crash(cwd)
+            )
+            .as_deref(),
+            Some("systemd-coredump (coredumpctl info 4242)")
+        );
+        assert_eq!(
+            locate_with("|/usr/share/apport/apport -p%p", false, &crash(cwd)).as_deref(),
+            Some("piped to /usr/share/apport/apport")
+        );
+    }
+
+    #[test]
+    fn finds_core_files_from_the_pattern() {
+        let dir = std::env::temp_dir().join(format!("sentinel-core-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("core.4242"), "").unwrap();
+        std::fs::write(dir.join("core.segv.11.1700000000"), "").unwrap();
+        let crash = crash(&dir);
+
+        let expect = |path: &str| Some(dir.join(path).display().to_string());
+        assert_eq!(locate_with("core", true, &crash), expect("core.4242"));
+        assert_eq!(locate_with("core.%p", false, &crash), expect("core.4242"));
+        let absolute = format!("{}/core.%e.%s.%t", dir.display());
+        assert_eq!(
+            locate_with(&absolute, false, &crash),
+            expect("core.segv.11.1700000000")
+        );
+        assert_eq!(locate_with("core", false, &crash), None);
+        assert_eq!(locate_with("/nonexistent/%t/core", false, &crash), None);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,93 @@
+//! Reporting a crash of sentinel-rs itself. A panic would otherwise end the
+//! run without a finish notification, leaving the command to carry on
+//! unwatched; the panic hook sends one last message saying so, straight to
+//! Telegram since the notifier thread may be the one that died.
+
+use crate::config::TgConfig;
+use crate::event::{Event, Severity};
+use crate::notifier::http_client_with;
+use crate::runner::running_children;
+use crate::telegram::tg_send;
+use crate::template::Templates;
+use std::panic::PanicHookInfo;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Makes a panic on any thread send a crash report for the run of
+/// `command`, after the usual panic message.
+pub fn install(cfg: &TgConfig, templates: Arc<Templates>, command: &str) {
+    let Ok(client) = http_client_with(&cfg.http) else {
+        return;
+    };
+    let cfg = cfg.clone();
+    let command = command.to_string();
+    let reported = AtomicBool::new(false);
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        previous(info);
+        // Only the first panic: later ones are likely fallout from it.
+        if reported.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let mut event = Event::message(&command, &crash_message(info, &running_children()));
+        event.severity = Severity::Critical;
+        let text = templates.render(&event);
+        if let Err(e) = tg_send(&client, &cfg, &text) {
+            tracing::error!("Failed to report the crash: {e}");
+        }
+    }));
+}
+
+fn crash_message(info: &PanicHookInfo, children: &[u32]) -> String {
+    let payload = info.payload();
+    let reason = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown cause");
+    let place = info
+        .location()
+        .map(|l| format!(" at {}:{}", l.file(), l.line()))
+        .unwrap_or_default();
+    let thread = std::thread::current();
+    describe(thread.name().unwrap_or("unnamed"), reason, &place, children)
+}
+
+fn describe(thread: &str, reason: &str, place: &str, children: &[u32]) -> String {
+    let mut message = format!("sentinel-rs crashed: thread '{thread}' panicked{place}: {reason}.");
+    match children {
+        [] => {}
+        [pid] => message.push_str(&format!("\nChild pid {pid} may still be running.")),
+        pids => {
+            let pids: Vec<_> = pids.iter().map(u32::to_string).collect();
+            message.push_str(&format!(
+                "\nChild pids {} may still be running.",
+                pids.join(", ")
+            ));
+        }
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_the_children_left_running() {
+        assert_eq!(
+            describe("main", "boom", " at src/lib.rs:10", &[4242]),
+            "sentinel-rs crashed: thread 'main' panicked at src/lib.rs:10: boom.\n\
+             Child pid 4242 may still be running."
+        );
+        assert_eq!(
+            describe("notifier", "boom", "", &[1, 2]),
+            "sentinel-rs crashed: thread 'notifier' panicked: boom.\n\
+             Child pids 1, 2 may still be running."
+        );
+        assert_eq!(
+            describe("main", "boom", "", &[]),
+            "sentinel-rs crashed: thread 'main' panicked: boom."
+        );
+    }
+}
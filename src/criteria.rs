@@ -0,0 +1,151 @@
+//! What counts as a successful run. `--ok-codes` accepts exit codes other
+//! than 0, for tools like rsync (24: files vanished) or grep (1: no match).
+//! `--success-regex` and `--failure-regex` judge a run by its output as
+//! well, for tools that exit 0 after doing nothing at all ("0 rows
+//! exported"); every line of stdout and stderr is checked as it is
+//! captured, not just the tail that goes into the notification.
+
+use regex::Regex;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The exit codes and output of a successful run.
+#[derive(Clone, Debug, Default)]
+pub struct Criteria {
+    /// Exit codes that mean success; just 0 if empty.
+    pub ok_codes: Vec<i32>,
+    /// Some line has to match for the run to count as a success.
+    pub success: Option<Regex>,
+    /// A line matching this fails the run.
+    pub failure: Option<Regex>,
+}
+
+pub fn parse_regex(s: &str) -> Result<Regex, String> {
+    Regex::new(s).map_err(|e| e.to_string())
+}
+
+/// Parses a list of exit codes such as `0,3,4`.
+pub fn parse_ok_codes(s: &str) -> Result<Vec<i32>, String> {
+    s.split(',')
+        .map(|code| {
+            code.trim()
+                .parse()
+                .ok()
+                .filter(|code| (0..=255).contains(code))
+                .ok_or_else(|| format!("expected exit codes from 0 to 255, got {code:?}"))
+        })
+        .collect()
+}
+
+impl Criteria {
+    pub fn is_ok(&self, code: i32) -> bool {
+        if self.ok_codes.is_empty() {
+            code == 0
+        } else {
+            self.ok_codes.contains(&code)
+        }
+    }
+
+    /// Whether the output has to be watched.
+    pub fn checks_output(&self) -> bool {
+        self.success.is_some() || self.failure.is_some()
+    }
+
+    pub fn watch(&self) -> Watch {
+        Watch {
+            criteria: self.clone(),
+            succeeded: AtomicBool::new(false),
+            failed: Mutex::new(None),
+        }
+    }
+}
+
+/// [`Criteria`] applied to the lines of one run.
+pub struct Watch {
+    criteria: Criteria,
+    succeeded: AtomicBool,
+    /// The first line that matched the failure pattern.
+    failed: Mutex<Option<String>>,
+}
+
+impl Watch {
+    pub fn on_line(&self, line: &str) {
+        if let Some(failure) = &self.criteria.failure
+            && failure.is_match(line)
+        {
+            let mut failed = self.failed.lock().unwrap_or_else(|e| e.into_inner());
+            failed.get_or_insert_with(|| crate::runner::tail_bytes(line.as_bytes(), 200));
+        }
+        if let Some(success) = &self.criteria.success
+            && !self.succeeded.load(Ordering::Relaxed)
+            && success.is_match(line)
+        {
+            self.succeeded.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Why a run that exited with an accepted code still failed, or `None`
+    /// if it did not.
+    pub fn verdict(&self) -> Option<String> {
+        let failed = self.failed.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(line) = &*failed {
+            return Some(format!("Output matched --failure-regex: {line}"));
+        }
+        match &self.criteria.success {
+            Some(success) if !self.succeeded.load(Ordering::Relaxed) => Some(format!(
+                "No output matched --success-regex {:?}",
+                success.as_str()
+            )),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn watch(success: Option<&str>, failure: Option<&str>, lines: &[&str]) -> Option<String> {
+        let watch = Criteria {
+            success: success.map(|s| parse_regex(s).unwrap()),
+            failure: failure.map(|s| parse_regex(s).unwrap()),
+            ..Criteria::default()
+        }
+        .watch();
+        for line in lines {
+            watch.on_line(line);
+        }
+        watch.verdict()
+    }
+
+    #[test]
+    fn judges_runs_by_their_output() {
+        let lines = ["exporting", "0 rows exported", "done"];
+        assert_eq!(
+            watch(None, Some(r"^0 rows"), &lines).as_deref(),
+            Some("Output matched --failure-regex: 0 rows exported")
+        );
+        assert_eq!(watch(None, Some("error"), &lines), None);
+        assert_eq!(watch(Some("^done$"), None, &lines), None);
+        assert_eq!(
+            watch(Some(r"\d+ files"), None, &lines).as_deref(),
+            Some(r#"No output matched --success-regex "\\d+ files""#)
+        );
+        // A failure line wins over a success line.
+        assert!(watch(Some("done"), Some("0 rows"), &lines).is_some());
+        assert!(parse_regex("(").is_err());
+    }
+
+    #[test]
+    fn accepts_the_listed_exit_codes() {
+        assert_eq!(parse_ok_codes("0, 24"), Ok(vec![0, 24]));
+        assert!(parse_ok_codes("0,256").is_err());
+        assert!(parse_ok_codes("").is_err());
+        let rsync = Criteria {
+            ok_codes: vec![0, 24],
+            ..Criteria::default()
+        };
+        assert!(rsync.is_ok(24) && rsync.is_ok(0) && !rsync.is_ok(23));
+        assert!(Criteria::default().is_ok(0) && !Criteria::default().is_ok(1));
+    }
+}
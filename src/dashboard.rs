@@ -0,0 +1,192 @@
+//! `sentinel-rs dashboard`: a small web page for the runs on this machine,
+//! for teams without Grafana. Needs the `dashboard` feature, which also has
+//! every finished run recorded in `runs.jsonl` in the
+//! [state directory](crate::config::state_dir) (the last [`KEEP`]). The
+//! page lists them with a success rate and the latest outcomes per job, and
+//! the runs going on now that take requests (`--control`), with their
+//! output as it comes. It is served on localhost by default, since the
+//! output may hold what should not leave the machine.
+
+use crate::ci::{self, Outcome};
+use crate::plugin::{Action, Plugin, PluginError};
+use serde_json::{Value, json};
+use std::path::{Path, PathBuf};
+
+/// How many finished runs are kept.
+pub const KEEP: usize = 1000;
+
+/// Where the dashboard is served unless told otherwise.
+pub const DEFAULT_ADDR: &str = "127.0.0.1:8124";
+
+#[cfg(feature = "dashboard")]
+const PAGE: &str = include_str!("dashboard.html");
+
+fn history_file() -> Option<PathBuf> {
+    crate::config::state_dir().map(|dir| dir.join("runs.jsonl"))
+}
+
+/// The plugin recording finished runs, if built with the feature and there
+/// is a state directory to keep them in.
+pub fn recorder() -> Option<Box<dyn Plugin>> {
+    if !cfg!(feature = "dashboard") {
+        return None;
+    }
+    Some(Box::new(Recorder {
+        file: history_file()?,
+    }))
+}
+
+struct Recorder {
+    file: PathBuf,
+}
+
+/// What the dashboard keeps of a finished run's event, `None` for events
+/// that are not one.
+fn record(event: &Value, now: i64) -> Option<Value> {
+    let outcome = match ci::outcome(event)?.0 {
+        Outcome::Running => return None,
+        Outcome::Success => "success",
+        Outcome::Failure => "failure",
+        Outcome::Error => "error",
+    };
+    Some(json!({
+        "finished": now,
+        "job": event["job"],
+        "host": event["host"],
+        "command": event["command"],
+        "outcome": outcome,
+        "exit_code": event["exit_code"],
+        "duration_secs": event["duration_secs"],
+    }))
+}
+
+/// The recorded runs, oldest first; none if there are none yet (or they
+/// cannot be read).
+fn load(path: &Path) -> Vec<Value> {
+    std::fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// Adds `run` to those in `path`. Written whole and renamed into place, so
+/// that runs finishing at the same time cannot leave half a file.
+fn append(path: &Path, run: Value) -> std::io::Result<()> {
+    let mut runs = load(path);
+    runs.push(run);
+    if runs.len() > KEEP {
+        runs.drain(..runs.len() - KEEP);
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let text: String = runs.iter().map(|run| format!("{run}\n")).collect();
+    let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+    std::fs::write(&tmp, text)?;
+    std::fs::rename(&tmp, path)
+}
+
+impl Plugin for Recorder {
+    fn name(&self) -> &str {
+        "dashboard"
+    }
+
+    fn on_event(&mut self, event_json: &str) -> Result<Vec<Action>, PluginError> {
+        let event: Value = serde_json::from_str(event_json)?;
+        if let Some(run) = record(&event, chrono::Utc::now().timestamp()) {
+            append(&self.file, run)?;
+        }
+        Ok(Vec::new())
+    }
+}
+
+/// Serves the dashboard on `addr` from a background thread for the rest of
+/// the process's life.
+pub fn serve(addr: &str) -> Result<std::net::SocketAddr, String> {
+    #[cfg(feature = "dashboard")]
+    {
+        crate::metrics::serve_routes(addr, route)
+            .map_err(|e| format!("Failed to serve the dashboard on {addr}: {e}"))
+    }
+    #[cfg(not(feature = "dashboard"))]
+    {
+        let _ = addr;
+        Err("sentinel-rs was built without the dashboard feature".to_string())
+    }
+}
+
+#[cfg(feature = "dashboard")]
+fn route(path: &str, query: &str) -> Option<(&'static str, String)> {
+    const JSON: &str = "application/json";
+    Some(match path {
+        "/" => ("text/html; charset=utf-8", PAGE.to_string()),
+        "/runs" => {
+            let runs = history_file().map(|file| load(&file)).unwrap_or_default();
+            (JSON, Value::from(runs).to_string())
+        }
+        "/live" => {
+            let live: Vec<Value> = crate::control::live()
+                .iter()
+                .filter_map(|run_id| crate::control::request(Some(run_id), "status").ok())
+                .filter_map(|status| serde_json::from_str(&status).ok())
+                .collect();
+            (JSON, Value::from(live).to_string())
+        }
+        "/tail" => {
+            let param = |name: &str| {
+                query
+                    .split('&')
+                    .find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))
+                    .unwrap_or_default()
+            };
+            let run_id = param("run");
+            // A run id, not a way to some other socket.
+            if run_id.is_empty() || run_id.contains(['/', '.']) {
+                return None;
+            }
+            let lines = param("lines")
+                .parse()
+                .unwrap_or(crate::status::DEFAULT_LINES);
+            let tail =
+                crate::control::request(Some(run_id), &format!("tail {lines}")).unwrap_or_default();
+            ("text/plain; charset=utf-8", tail)
+        }
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{Event, EventKind};
+
+    #[test]
+    fn finished_runs_are_recorded_and_kept_to_a_limit() {
+        let mut event = Event {
+            exit_code: Some(2),
+            job: "backup".to_string(),
+            ..Event::new(EventKind::Failure, "backup --full")
+        };
+        event.set_duration(std::time::Duration::from_secs(90));
+        let event: Value = serde_json::from_str(&event.to_json()).unwrap();
+        let run = record(&event, 1_000).unwrap();
+        assert_eq!(run["outcome"], "failure");
+        assert_eq!(run["job"], "backup");
+        assert_eq!(run["exit_code"], 2);
+        assert_eq!(run["duration_secs"], 90.0);
+        let start = serde_json::from_str(&Event::new(EventKind::Start, "x").to_json()).unwrap();
+        assert!(record(&start, 0).is_none());
+
+        let file = std::env::temp_dir().join(format!("sentinel-runs-{}.jsonl", std::process::id()));
+        let full: String = (0..=KEEP)
+            .map(|n| format!("{{\"finished\":{n}}}\n"))
+            .collect();
+        std::fs::write(&file, full).unwrap();
+        append(&file, json!({ "finished": KEEP + 1 })).unwrap();
+        let runs = load(&file);
+        assert_eq!(runs.len(), KEEP);
+        assert_eq!(runs[0]["finished"], 2);
+        std::fs::remove_file(&file).ok();
+    }
+}
@@ -0,0 +1,199 @@
+//! `sentinel-rs docker`: run the command in a throwaway container.
+//!
+//! The container runs attached (`docker run --rm -i`), so its stdout and
+//! stderr are captured like a local process's and its exit code is the
+//! run's. While it runs, a sampler polls `docker stats` so the finish
+//! notification can say how much CPU and memory the job used.
+
+use crate::event::ResourceUsage;
+use std::process::Command;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug)]
+pub struct DockerRun {
+    pub image: String,
+    /// Container name, unique per run, so `docker stats` can find it.
+    pub name: String,
+}
+
+impl DockerRun {
+    pub fn new(image: &str) -> Self {
+        DockerRun {
+            image: image.to_string(),
+            name: crate::runner::run_name(),
+        }
+    }
+
+    /// Host label for notifications.
+    pub fn label(&self) -> String {
+        format!("docker:{}", self.image)
+    }
+
+    /// `docker run` for `script`, or for the image's own command if
+    /// `script` is empty, with `env` set in the container. The values go
+    /// through the docker client's environment rather than its arguments.
+    pub fn command(&self, script: &str, env: &[(String, String)]) -> Command {
+        let mut cmd = Command::new("docker");
+        cmd.args(["run", "--rm", "-i", "--name", &self.name]);
+        for (name, _) in env {
+            cmd.args(["-e", name]);
+        }
+        cmd.envs(env.iter().cloned()).arg("--").arg(&self.image);
+        if !script.is_empty() {
+            cmd.args(["sh", "-c", script]);
+        }
+        cmd
+    }
+}
+
+#[derive(Default)]
+struct Samples {
+    cpu: Vec<f64>,
+    memory_peak: Option<u64>,
+}
+
+/// Polls `docker stats` for a container until [`StatsSampler::finish`].
+pub struct StatsSampler {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<Samples>,
+}
+
+impl StatsSampler {
+    pub fn start(container: &str) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let container = container.to_string();
+        let thread = thread::spawn({
+            let stop = stop.clone();
+            move || {
+                let mut samples = Samples::default();
+                // Sample at least once, even for containers that exit at once.
+                loop {
+                    if let Some((cpu, memory)) = sample(&container) {
+                        samples.cpu.extend(cpu);
+                        samples.memory_peak = samples.memory_peak.max(memory);
+                    }
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    thread::sleep(SAMPLE_INTERVAL);
+                }
+                samples
+            }
+        });
+        StatsSampler { stop, thread }
+    }
+
+    /// Stops sampling; `None` if `docker stats` never reported anything.
+    pub fn finish(self) -> Option<ResourceUsage> {
+        self.stop.store(true, Ordering::Relaxed);
+        let samples = self.thread.join().ok()?;
+        if samples.cpu.is_empty() && samples.memory_peak.is_none() {
+            return None;
+        }
+        let cpu_avg = (!samples.cpu.is_empty())
+            .then(|| samples.cpu.iter().sum::<f64>() / samples.cpu.len() as f64);
+        let cpu_peak = samples.cpu.iter().copied().reduce(f64::max);
+        Some(ResourceUsage::new(cpu_avg, cpu_peak, samples.memory_peak))
+    }
+}
+
+fn sample(container: &str) -> Option<(Option<f64>, Option<u64>)> {
+    let output = Command::new("docker")
+        .args([
+            "stats",
+            "--no-stream",
+            "--format",
+            "{{.CPUPerc}}|{{.MemUsage}}",
+            container,
+        ])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(parse_stats(String::from_utf8_lossy(&output.stdout).trim()))
+}
+
+/// Parses a `{{.CPUPerc}}|{{.MemUsage}}` line such as
+/// `12.50%|10.5MiB / 1.944GiB`. Either half may be unavailable (`--`).
+fn parse_stats(line: &str) -> (Option<f64>, Option<u64>) {
+    let (cpu, memory) = line.split_once('|').unwrap_or((line, ""));
+    let cpu = cpu.trim().trim_end_matches('%').parse().ok();
+    let used = memory.split('/').next().unwrap_or_default();
+    (cpu, parse_size(used))
+}
+
+/// Sizes as docker prints them: `512B`, `1.5kB`, `10.5MiB`, `2GB`, …
+fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let split = s.find(|c: char| c.is_ascii_alphabetic())?;
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number.trim().parse().ok()?;
+    let scale: f64 = match unit {
+        "B" => 1.0,
+        "kB" | "KB" => 1e3,
+        "MB" => 1e6,
+        "GB" => 1e9,
+        "TB" => 1e12,
+        "KiB" => 1024.0,
+        "MiB" => 1024.0 * 1024.0,
+        "GiB" => 1024.0 * 1024.0 * 1024.0,
+        "TiB" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    };
+    Some((number * scale) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_docker_stats_line() {
+        assert_eq!(
+            parse_stats("12.50%|10.5MiB / 1.944GiB"),
+            (Some(12.5), Some(11_010_048))
+        );
+        assert_eq!(parse_stats("--|-- / --"), (None, None));
+        assert_eq!(parse_size("1.5kB"), Some(1500));
+        assert_eq!(parse_size("512B"), Some(512));
+    }
+
+    #[test]
+    fn docker_command_runs_script_with_sh() {
+        let run = DockerRun {
+            image: "alpine:3".to_string(),
+            name: "sentinel-rs-1-2".to_string(),
+        };
+        let args: Vec<_> = run
+            .command("echo hi", &[])
+            .get_args()
+            .map(|a| a.to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(
+            args,
+            [
+                "run",
+                "--rm",
+                "-i",
+                "--name",
+                "sentinel-rs-1-2",
+                "--",
+                "alpine:3",
+                "sh",
+                "-c",
+                "echo hi"
+            ]
+        );
+        assert_eq!(run.command("", &[]).get_args().count(), 7);
+        let env = [("DB_PASSWORD".to_string(), "s3cret".to_string())];
+        let cmd = run.command("", &env);
+        let args: Vec<_> = cmd.get_args().map(|a| a.to_string_lossy()).collect();
+        assert_eq!(args[5..], ["-e", "DB_PASSWORD", "--", "alpine:3"]);
+    }
+}
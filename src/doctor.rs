@@ -0,0 +1,414 @@
+//! `sentinel-rs doctor`: checks the configuration the way a run would use
+//! it and says what to fix. The bot token and chat id are read from the
+//! same places as for runs and asked about with `getMe` and `getChat`, the
+//! URLs of the other backends are resolved, and hooks, plugins and
+//! templates are loaded. With `--send-test` a test message goes out too.
+
+use crate::RunOptions;
+use crate::check::Health;
+use crate::config::{self, HttpOptions, TgConfig};
+use crate::notifier::http_client_with;
+use crate::telegram::telegram_payload;
+use reqwest::blocking::Client;
+use serde_json::{Value, json};
+use std::net::ToSocketAddrs;
+use std::path::Path;
+
+/// What one check found, and what to do about it.
+#[derive(Debug, PartialEq)]
+pub struct Finding {
+    pub what: &'static str,
+    pub health: Health,
+    pub detail: String,
+    pub hint: Option<String>,
+}
+
+impl Finding {
+    fn ok(what: &'static str, detail: impl Into<String>) -> Self {
+        Finding {
+            what,
+            health: Health::Ok,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    pub fn problem(
+        what: &'static str,
+        health: Health,
+        detail: impl Into<String>,
+        hint: &str,
+    ) -> Self {
+        Finding {
+            what,
+            health,
+            detail: detail.into(),
+            hint: (!hint.is_empty()).then(|| hint.to_string()),
+        }
+    }
+
+    pub fn print(&self) {
+        println!("{:<8} {}: {}", self.health.name(), self.what, self.detail);
+        if let Some(hint) = &self.hint {
+            println!("{:<8} → {hint}", "");
+        }
+    }
+}
+
+/// Runs every check, printing each finding as it comes. Returns the exit
+/// code: 1 if something would keep notifications from arriving, else 0.
+pub fn run(token_file: Option<&Path>, proxy: Option<&str>, send_test: bool) -> i32 {
+    let mut worst = Health::Ok;
+    let mut report = |finding: Finding| {
+        finding.print();
+        worst = worst.max(finding.health);
+    };
+    let token = match config::bot_token(token_file) {
+        Ok(token) => {
+            report(token_format(token.trim()));
+            true
+        }
+        Err(e) => {
+            report(Finding::problem(
+                "Bot token",
+                Health::Failing,
+                e,
+                "export TG_BOT_TOKEN with the token @BotFather gave you",
+            ));
+            false
+        }
+    };
+    let chat = match config::chat_id() {
+        Ok(chat) => {
+            report(chat_format(chat.trim()));
+            true
+        }
+        Err(e) => {
+            report(Finding::problem(
+                "Chat",
+                Health::Failing,
+                e,
+                "export TG_CHAT_ID; see README.md for how to find it",
+            ));
+            false
+        }
+    };
+    let (mut http, invalid) = match HttpOptions::from_env() {
+        Ok(http) => (http, None),
+        Err(e) => (HttpOptions::default(), Some(e)),
+    };
+    if proxy.is_some() {
+        http.proxy = proxy.map(str::to_string);
+    }
+    let client = match invalid.map_or_else(|| http_client_with(&http), Err) {
+        Ok(client) => Some(client),
+        Err(e) => {
+            report(Finding::problem(
+                "HTTP client",
+                Health::Failing,
+                e,
+                "check --proxy, SENTINEL_PROXY, the SENTINEL_CA_BUNDLE/CLIENT_CERT/CLIENT_KEY files and the SENTINEL_HTTP_* settings",
+            ));
+            None
+        }
+    };
+    if token && chat {
+        match config::load_tg_config_with(token_file) {
+            Ok(cfg) => {
+                if let Some(client) = &client {
+                    telegram(client, &cfg, send_test, &mut report);
+                }
+            }
+            // Token and chat were fine, so it is one of the other settings.
+            Err(e) => report(Finding::problem(
+                "Settings",
+                Health::Failing,
+                e.to_string(),
+                "",
+            )),
+        }
+    }
+    let proxied = http.proxy.is_some()
+        || ["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"]
+            .iter()
+            .any(|key| config::env_required(key).is_ok());
+    for (what, key) in [
+        ("Grafana", "GRAFANA_URL"),
+        ("OpenTelemetry", "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT"),
+        ("OpenTelemetry", "OTEL_EXPORTER_OTLP_ENDPOINT"),
+    ] {
+        if let Ok(url) = config::env_required(key) {
+            report(resolve(what, key, url.trim(), proxied));
+        }
+    }
+    report(match RunOptions::from_env() {
+        Ok(opts) => Finding::ok(
+            "Hooks",
+            match opts.plugins.len() {
+                0 => "none configured".to_string(),
+                n => format!("{n} loaded (plugins, scripts and backends)"),
+            },
+        ),
+        Err(e) => Finding::problem(
+            "Hooks",
+            Health::Failing,
+            e.to_string(),
+            "check SENTINEL_PLUGINS, SENTINEL_SCRIPT, the templates and the backend settings",
+        ),
+    });
+    if worst == Health::Failing { 1 } else { 0 }
+}
+
+/// Asks Telegram about the bot and the chat, and sends the test message.
+fn telegram(client: &Client, cfg: &TgConfig, send_test: bool, report: &mut impl FnMut(Finding)) {
+    let bot = match call(client, cfg, "getMe", json!({})) {
+        Ok(bot) => bot,
+        Err(e) => return report(e.finding("Telegram bot", cfg)),
+    };
+    report(Finding::ok(
+        "Telegram bot",
+        format!("@{}", bot["username"].as_str().unwrap_or("?")),
+    ));
+    let chat = match call(client, cfg, "getChat", json!({ "chat_id": cfg.chat_id })) {
+        Ok(chat) => chat,
+        Err(e) => return report(e.finding("Telegram chat", cfg)),
+    };
+    let name = chat["title"]
+        .as_str()
+        .or(chat["username"].as_str())
+        .or(chat["first_name"].as_str())
+        .unwrap_or("?");
+    report(Finding::ok(
+        "Telegram chat",
+        format!("{name} ({})", chat["type"].as_str().unwrap_or("?")),
+    ));
+    if send_test {
+        let text = format!(
+            "sentinel-rs doctor: test message from {}",
+            crate::host::name()
+        );
+        report(
+            match call(
+                client,
+                cfg,
+                "sendMessage",
+                telegram_payload(&cfg.chat_id, &text),
+            ) {
+                Ok(_) => Finding::ok("Test message", "sent"),
+                Err(e) => e.finding("Test message", cfg),
+            },
+        );
+    }
+}
+
+/// Why a Bot API call failed.
+pub enum ApiError {
+    /// No answer from Telegram, or not one from the Bot API.
+    Unreachable(String),
+    /// The Bot API's `error_code` and `description`.
+    Api(i64, String),
+}
+
+impl ApiError {
+    /// The finding saying so, with what to do about it.
+    pub fn finding(self, what: &'static str, cfg: &TgConfig) -> Finding {
+        match self {
+            ApiError::Unreachable(e) => Finding::problem(
+                what,
+                Health::Failing,
+                format!("cannot reach {}: {e}", cfg.api_base),
+                "check the network, the proxy (--proxy, SENTINEL_PROXY, HTTPS_PROXY) and TG_API_BASE",
+            ),
+            ApiError::Api(code, description) => {
+                Finding::problem(what, Health::Failing, description, api_hint(code))
+            }
+        }
+    }
+}
+
+/// What to do about the Bot API answering with error `code`.
+fn api_hint(code: i64) -> &'static str {
+    match code {
+        401 | 404 => "Telegram does not know this bot token; copy it again from @BotFather",
+        400 => {
+            "the bot cannot see this chat: send the bot a message (or add it to the group) and check TG_CHAT_ID"
+        }
+        403 => "the bot was blocked, or removed from the chat; add it again",
+        429 => "Telegram is rate limiting the bot; try again in a minute",
+        _ => "",
+    }
+}
+
+/// Calls the Bot API's `method`, returning its `result`.
+pub fn call(
+    client: &Client,
+    cfg: &TgConfig,
+    method: &str,
+    params: Value,
+) -> Result<Value, ApiError> {
+    let url = format!("{}/bot{}/{method}", cfg.api_base, cfg.bot_token);
+    let response = client
+        .post(&url)
+        .json(&params)
+        .send()
+        // The error names the URL, and with it the token.
+        .map_err(|e| ApiError::Unreachable(with_causes(&e.without_url())))?;
+    let status = response.status();
+    let body: Value = response
+        .json()
+        .map_err(|_| ApiError::Unreachable(format!("unexpected answer (HTTP {status})")))?;
+    if body["ok"] == Value::Bool(true) {
+        return Ok(body["result"].clone());
+    }
+    Err(ApiError::Api(
+        body["error_code"]
+            .as_i64()
+            .unwrap_or(i64::from(status.as_u16())),
+        body["description"]
+            .as_str()
+            .unwrap_or("no description")
+            .to_string(),
+    ))
+}
+
+/// `e` and what caused it: reqwest's own message is just "error sending
+/// request".
+fn with_causes(e: &dyn std::error::Error) -> String {
+    let mut text = e.to_string();
+    let mut cause = e.source();
+    while let Some(e) = cause {
+        text.push_str(&format!(": {e}"));
+        cause = e.source();
+    }
+    text
+}
+
+/// Bot tokens are the bot's id, a colon and 35 characters.
+pub fn token_format(token: &str) -> Finding {
+    match token.split_once(':') {
+        Some((id, secret))
+            if !id.is_empty()
+                && id.bytes().all(|b| b.is_ascii_digit())
+                && secret.len() >= 30
+                && secret
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-') =>
+        {
+            Finding::ok("Bot token", format!("bot {id}"))
+        }
+        _ => Finding::problem(
+            "Bot token",
+            Health::Warning,
+            "does not look like a bot token (123456789:AA…)",
+            "copy the whole token @BotFather gave you, without quotes or spaces",
+        ),
+    }
+}
+
+/// Chat ids are numbers (negative for groups and channels), or a public
+/// channel's `@username`.
+pub fn chat_format(chat: &str) -> Finding {
+    let digits = chat.strip_prefix('-').unwrap_or(chat);
+    let numeric = !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit());
+    let username = chat.strip_prefix('@').is_some_and(|name| {
+        name.len() >= 5 && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+    });
+    if numeric || username {
+        Finding::ok("Chat", chat)
+    } else {
+        Finding::problem(
+            "Chat",
+            Health::Warning,
+            format!("{chat:?} does not look like a chat id"),
+            "use the numeric id (e.g. -1001234567890 for a group) or @channelname",
+        )
+    }
+}
+
+/// Whether the host of `url`, from `key`, resolves. Behind a proxy it may
+/// only resolve there, so that is not a failure.
+fn resolve(what: &'static str, key: &str, url: &str, proxied: bool) -> Finding {
+    let parsed = match reqwest::Url::parse(url) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            return Finding::problem(
+                what,
+                Health::Failing,
+                format!("{key}: {url:?} is not a URL: {e}"),
+                "use a full URL such as https://grafana.example.com",
+            );
+        }
+    };
+    let (Some(host), Some(port)) = (parsed.host_str(), parsed.port_or_known_default()) else {
+        return Finding::problem(
+            what,
+            Health::Failing,
+            format!("{key}: {url} has no host"),
+            "",
+        );
+    };
+    let resolved = (host.trim_matches(['[', ']']), port)
+        .to_socket_addrs()
+        .map(|mut addrs| addrs.next().is_some());
+    match resolved {
+        Ok(true) => Finding::ok(what, format!("{key} {host} resolves")),
+        _ => {
+            let e = resolved.err().map(|e| e.to_string());
+            let health = if proxied {
+                Health::Warning
+            } else {
+                Health::Failing
+            };
+            Finding::problem(
+                what,
+                health,
+                format!("{key}: cannot resolve {host}: {}", e.unwrap_or_default()),
+                "check the host name for typos",
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_token_and_chat_formats() {
+        let token = "123456789:AAHdqTcvCH1vGWJxfSeofSAs0K5PALDsaw";
+        assert_eq!(
+            token_format(token),
+            Finding::ok("Bot token", "bot 123456789")
+        );
+        assert_eq!(token_format("123456789").health, Health::Warning);
+        assert_eq!(
+            token_format("\"123456789:AAHdqTcvCH1vGWJxfSeofSAs0K5PALDsaw\"").health,
+            Health::Warning
+        );
+        for chat in ["123456", "-1001234567890", "@my_channel"] {
+            assert_eq!(chat_format(chat).health, Health::Ok, "{chat}");
+        }
+        for chat in ["", "-", "my_channel", "123 456"] {
+            assert_eq!(chat_format(chat).health, Health::Warning, "{chat}");
+        }
+    }
+
+    #[test]
+    fn resolves_backend_urls() {
+        assert_eq!(
+            resolve("Grafana", "GRAFANA_URL", "http://localhost:3000", false).health,
+            Health::Ok
+        );
+        let relative = resolve("Grafana", "GRAFANA_URL", "grafana:3000/api", false);
+        assert_eq!(relative.health, Health::Failing);
+        let unknown = "http://no-such-host.invalid";
+        assert_eq!(
+            resolve("Grafana", "GRAFANA_URL", unknown, false).health,
+            Health::Failing
+        );
+        assert_eq!(
+            resolve("Grafana", "GRAFANA_URL", unknown, true).health,
+            Health::Warning
+        );
+    }
+}
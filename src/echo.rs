@@ -0,0 +1,95 @@
+//! How notifications show the command, for commands with credentials or
+//! customer identifiers in them that must not end up in a chat: in full
+//! (the default), with what `--redact REGEX` matches replaced by
+//! `<redacted>` (only the first group, if the pattern has one), cut to
+//! `--command-max-chars N`, or not at all (`--hide-command`). Applies to
+//! everything a notification or plugin sees of it, including `--step`
+//! commands in reports and the job name taken from its program (unless
+//! `SENTINEL_JOB` names the job). Chosen once, at startup, for the whole
+//! process.
+
+use regex::Regex;
+use std::sync::OnceLock;
+
+const REDACTED: &str = "<redacted>";
+pub const HIDDEN: &str = "(command hidden)";
+
+#[derive(Clone, Debug, Default)]
+pub struct Policy {
+    pub hide: bool,
+    pub redact: Vec<Regex>,
+    pub max_chars: Option<usize>,
+}
+
+static POLICY: OnceLock<Policy> = OnceLock::new();
+
+/// Shows commands as `policy` says from now on. Only the first call counts.
+pub fn set(policy: Policy) {
+    POLICY.set(policy).ok();
+}
+
+/// `command` as notifications show it.
+pub fn command(command: &str) -> String {
+    POLICY.get_or_init(Policy::default).apply(command)
+}
+
+impl Policy {
+    fn apply(&self, command: &str) -> String {
+        if self.hide {
+            return HIDDEN.to_string();
+        }
+        let mut text = command.to_string();
+        for pattern in &self.redact {
+            text = pattern
+                .replace_all(&text, |caps: &regex::Captures| match caps.get(1) {
+                    Some(group) => {
+                        let whole = caps.get(0).expect("group 0 is the match");
+                        format!(
+                            "{}{REDACTED}{}",
+                            &whole.as_str()[..group.start() - whole.start()],
+                            &whole.as_str()[group.end() - whole.start()..]
+                        )
+                    }
+                    None => REDACTED.to_string(),
+                })
+                .into_owned();
+        }
+        match self.max_chars {
+            Some(max) if text.chars().count() > max => {
+                format!("{}…", text.chars().take(max).collect::<String>())
+            }
+            _ => text,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_truncates_or_hides_the_command() {
+        let policy = Policy {
+            redact: vec![
+                Regex::new(r"--password[= ](\S+)").unwrap(),
+                Regex::new(r"cust-[0-9]+").unwrap(),
+            ],
+            ..Policy::default()
+        };
+        assert_eq!(
+            policy.apply("export --password=hunter2 --customer cust-4211 --password x"),
+            "export --password=<redacted> --customer <redacted> --password <redacted>"
+        );
+        let short = Policy {
+            max_chars: Some(10),
+            ..policy
+        };
+        assert_eq!(short.apply("pg_dump --password=hunter2 db"), "pg_dump --…");
+        let hidden = Policy {
+            hide: true,
+            ..Policy::default()
+        };
+        assert_eq!(hidden.apply("curl -u admin:s3cret"), "(command hidden)");
+        assert_eq!(Policy::default().apply("make"), "make");
+    }
+}
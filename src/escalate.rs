@@ -0,0 +1,506 @@
+//! Escalation (`SENTINEL_ESCALATE_AFTER=MINUTES`): a failure notification
+//! nobody acknowledges in that many minutes is sent on, to a second chat
+//! (`SENTINEL_ESCALATE_CHAT_ID`) and/or a webhook (`SENTINEL_ESCALATE_URL`),
+//! e.g. an SMS gateway's, and again every that many minutes, up to
+//! `SENTINEL_ESCALATE_TIMES` times (default 1). Each failure sent has an
+//! Ack button, and says how to acknowledge it otherwise: `/ack ID` in the
+//! chat, or `sentinel-rs escalate --ack ID`. Failures waiting for that are
+//! kept in `escalations.json` in the [state
+//! directory](crate::config::state_dir), even those Telegram did not take,
+//! and `sentinel-rs escalate`, run from cron, reads the acknowledgements
+//! sent to the bot and escalates what is due. A message acknowledged with
+//! its button gets the name of whoever pressed it, and an acknowledged
+//! failure is no longer [repeated](crate::repeat) either.
+
+use crate::ci::{self, Outcome};
+use crate::config::{self, TgConfig};
+use crate::doctor;
+use crate::event::Event;
+use crate::fit;
+use crate::locale;
+use crate::notifier::http_client_with;
+use crate::repeat;
+use crate::telegram::{Delivery, MAX_MESSAGE, tg_send_with};
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::path::{Path, PathBuf};
+
+/// Where and how often failures are escalated.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Policy {
+    /// Minutes to wait for an acknowledgement, each time.
+    pub after: u32,
+    pub chat_id: Option<String>,
+    pub url: Option<String>,
+    pub times: u32,
+}
+
+fn positive(key: &str) -> Result<Option<u32>, String> {
+    match config::env_required(key) {
+        Ok(value) => value
+            .trim()
+            .parse()
+            .ok()
+            .filter(|&n| n > 0)
+            .map(Some)
+            .ok_or_else(|| format!("{key}: expected a positive number, got {value:?}")),
+        Err(_) => Ok(None),
+    }
+}
+
+/// The escalation policy, if `SENTINEL_ESCALATE_AFTER` is set.
+pub fn from_env() -> Result<Option<Policy>, String> {
+    let Some(after) = positive("SENTINEL_ESCALATE_AFTER")? else {
+        return Ok(None);
+    };
+    let chat_id = config::env_required("SENTINEL_ESCALATE_CHAT_ID").ok();
+    let url = match config::env_required("SENTINEL_ESCALATE_URL") {
+        Ok(url) => {
+            let parsed = reqwest::Url::parse(url.trim())
+                .map_err(|e| format!("SENTINEL_ESCALATE_URL: {url:?}: {e}"))?;
+            if !matches!(parsed.scheme(), "http" | "https") {
+                return Err(format!("SENTINEL_ESCALATE_URL: {url:?} is not http(s)"));
+            }
+            Some(url.trim().to_string())
+        }
+        Err(_) => None,
+    };
+    if chat_id.is_none() && url.is_none() {
+        return Err(
+            "SENTINEL_ESCALATE_AFTER needs SENTINEL_ESCALATE_CHAT_ID or SENTINEL_ESCALATE_URL to escalate to"
+                .to_string(),
+        );
+    }
+    if waiting_file().is_none() {
+        return Err(
+            "SENTINEL_ESCALATE_AFTER needs a state directory (set SENTINEL_STATE_DIR or HOME)"
+                .to_string(),
+        );
+    }
+    Ok(Some(Policy {
+        after,
+        chat_id,
+        url,
+        times: positive("SENTINEL_ESCALATE_TIMES")?.unwrap_or(1),
+    }))
+}
+
+/// A failure sent and not yet acknowledged.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+struct Waiting {
+    id: String,
+    text: String,
+    /// The job that failed, and where.
+    #[serde(default)]
+    job: String,
+    #[serde(default)]
+    host: String,
+    /// When it was sent, in seconds since the epoch.
+    raised: i64,
+    /// How many times it was escalated.
+    escalated: u32,
+}
+
+impl Waiting {
+    /// Whether it is time to escalate it (again).
+    fn due(&self, policy: &Policy, now: i64) -> bool {
+        let after = i64::from(policy.after) * 60;
+        self.escalated < policy.times && now >= self.raised + after * i64::from(self.escalated + 1)
+    }
+}
+
+fn waiting_file() -> Option<PathBuf> {
+    config::state_dir().map(|dir| dir.join("escalations.json"))
+}
+
+fn load(path: &Path) -> Vec<Waiting> {
+    config::read_state(path).unwrap_or_default()
+}
+
+/// Adds `waiting` to those in `path`.
+fn keep(path: &Path, waiting: Waiting) -> std::io::Result<()> {
+    let _lock = config::lock_state(path)?;
+    let mut all = load(path);
+    all.push(waiting);
+    config::write_state(path, &all)
+}
+
+/// Whether `event` is a failure to be acknowledged.
+pub fn applies(event: &Event) -> bool {
+    serde_json::to_value(event).ok().is_some_and(|event| {
+        matches!(
+            ci::outcome(&event),
+            Some((Outcome::Failure | Outcome::Error, _))
+        )
+    })
+}
+
+/// A new id to acknowledge a failure by: short, to be typed on a phone.
+fn new_id() -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u32;
+    format!(
+        "{:06x}",
+        (nanos ^ std::process::id().rotate_left(16)) & 0xff_ffff
+    )
+}
+
+/// The Ack button for the failure `id`, as `reply_markup`.
+pub fn ack_button(id: &str) -> Value {
+    json!({ "inline_keyboard": [[{ "text": "Ack", "callback_data": format!("ack:{id}") }]] })
+}
+
+/// Starts a failure's text with the line saying how to acknowledge it,
+/// where cutting a long message to fit leaves it, and keeps it to be
+/// escalated: to be called as it is sent. Returns the Ack button to send
+/// with it.
+pub fn raise(policy: &Policy, event: &mut Event) -> Value {
+    let id = new_id();
+    event.text = format!(
+        "{}\n\n{}",
+        locale::say(
+            "Send /ack {id} within {after} min, or this is escalated.",
+            &[("id", &id), ("after", &policy.after)]
+        ),
+        event.text
+    );
+    let button = ack_button(&id);
+    let waiting = Waiting {
+        id,
+        text: event.text.clone(),
+        job: event.job.clone(),
+        host: event.host.clone(),
+        raised: chrono::Utc::now().timestamp(),
+        escalated: 0,
+    };
+    // [`from_env`] made sure there is a state directory.
+    if let Some(file) = waiting_file()
+        && let Err(e) = keep(&file, waiting)
+    {
+        tracing::warn!("Failed to keep the failure for escalation: {e}");
+    }
+    button
+}
+
+/// An acknowledgement sent to the bot.
+#[derive(Debug, PartialEq)]
+struct Ack {
+    id: String,
+    /// The `callback_query` id and the message, if it came from a button.
+    button: Option<(String, Value)>,
+    /// The `@username` or name of whoever sent it.
+    by: String,
+}
+
+/// The acknowledgements in `updates`: `/ack ID` (or `/ack@bot ID`)
+/// messages and Ack buttons pressed, in one of `chats`.
+fn acks(updates: &Value, chats: &[&str]) -> Vec<Ack> {
+    let in_chats = |message: &Value| {
+        let chat = message["chat"]["id"].as_i64().map(|id| id.to_string());
+        chat.is_some_and(|chat| chats.contains(&chat.as_str()))
+    };
+    let name = |from: &Value| {
+        from["username"]
+            .as_str()
+            .map(|user| format!("@{user}"))
+            .or_else(|| from["first_name"].as_str().map(str::to_string))
+            .unwrap_or_else(|| "someone".to_string())
+    };
+    updates
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|update| {
+            let query = &update["callback_query"];
+            if query.is_object() {
+                let id = query["data"].as_str()?.strip_prefix("ack:")?;
+                let query_id = query["id"].as_str()?;
+                return in_chats(&query["message"]).then(|| Ack {
+                    id: id.to_lowercase(),
+                    button: Some((query_id.to_string(), query["message"].clone())),
+                    by: name(&query["from"]),
+                });
+            }
+            let message = ["message", "channel_post"]
+                .iter()
+                .map(|kind| &update[kind])
+                .find(|message| message.is_object())
+                .filter(|message| in_chats(message))?;
+            let mut words = message["text"].as_str()?.split_whitespace();
+            let command = words.next()?;
+            let command = command.split_once('@').map_or(command, |(c, _)| c);
+            (command == "/ack").then_some(())?;
+            Some(Ack {
+                id: words.next()?.to_lowercase(),
+                button: None,
+                by: name(&message["from"]),
+            })
+        })
+        .collect()
+}
+
+/// Answers a pressed Ack button, and adds who pressed it to its message in
+/// place of the button.
+fn answer(client: &Client, cfg: &TgConfig, query: &str, message: &Value, by: &str) {
+    let text = format!(
+        "{}\n\n{}",
+        message["text"].as_str().unwrap_or_default(),
+        locale::say("Acknowledged by {by}.", &[("by", &by)])
+    );
+    let edit = json!({
+        "chat_id": message["chat"]["id"],
+        "message_id": message["message_id"],
+        "text": fit::truncate(&text, MAX_MESSAGE),
+        "disable_web_page_preview": true,
+    });
+    let answer = json!({ "callback_query_id": query, "text": locale::say("Acknowledged", &[]) });
+    for (method, params) in [("editMessageText", edit), ("answerCallbackQuery", answer)] {
+        if let Err(e) = doctor::call(client, cfg, method, params) {
+            tracing::warn!(
+                "Failed to answer an Ack button: {}",
+                e.finding("Telegram", cfg).detail
+            );
+        }
+    }
+}
+
+/// What one `sentinel-rs escalate` did.
+#[derive(Debug, Default, PartialEq)]
+pub struct Report {
+    pub acknowledged: usize,
+    pub escalated: usize,
+    pub waiting: usize,
+}
+
+/// Sends `waiting` on as escalation `n`, to the chat and webhook of
+/// `policy`.
+fn escalate(
+    client: &Client,
+    cfg: &TgConfig,
+    policy: &Policy,
+    waiting: &Waiting,
+    n: u32,
+) -> Result<(), String> {
+    let text = format!(
+        "{}\n\n{}",
+        locale::say(
+            "Escalated ({n} of {times}): not acknowledged in {after} min.",
+            &[
+                ("n", &n),
+                ("times", &policy.times),
+                ("after", &(policy.after * n))
+            ]
+        ),
+        waiting.text
+    );
+    if let Some(chat_id) = &policy.chat_id {
+        let cfg = TgConfig {
+            chat_id: chat_id.clone(),
+            ..cfg.clone()
+        };
+        let delivery = Delivery {
+            markup: Some(ack_button(&waiting.id)),
+            ..Delivery::default()
+        };
+        tg_send_with(client, &cfg, &text, &delivery)
+            .map_err(|e| format!("cannot send to {chat_id}: {e}"))?;
+    }
+    if let Some(url) = &policy.url {
+        let body = json!({ "id": waiting.id, "text": text, "escalation": n, "of": policy.times });
+        client
+            .post(url)
+            .json(&body)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("cannot post to SENTINEL_ESCALATE_URL: {}", e.without_url()))?;
+    }
+    Ok(())
+}
+
+/// Brings the failures in `path` up to date with what [`run`] did: drops
+/// those `acked`, records the escalations sent, by id and number, and drops
+/// those escalated `times` times. They are read again for this, rather than
+/// taken from before the round-trips to Telegram, so that failures raised
+/// meanwhile are kept. Returns those acknowledged, and how many are left.
+fn settle(
+    path: &Path,
+    acked: &[Ack],
+    escalated: &[(String, u32)],
+    times: u32,
+) -> std::io::Result<(Vec<Waiting>, usize)> {
+    let _lock = config::lock_state(path)?;
+    let (acknowledged, mut waiting): (Vec<Waiting>, Vec<Waiting>) = load(path)
+        .into_iter()
+        .partition(|w| acked.iter().any(|ack| ack.id == w.id));
+    for w in &mut waiting {
+        if let Some((_, n)) = escalated.iter().find(|(id, _)| *id == w.id) {
+            w.escalated = w.escalated.max(*n);
+        }
+    }
+    waiting.retain(|w| w.escalated < times);
+    config::write_state(path, &waiting)?;
+    Ok((acknowledged, waiting.len()))
+}
+
+/// Marks `ack` acknowledged, if given; otherwise reads the acknowledgements
+/// sent to the bot, and escalates the failures that are due. The updates
+/// read are marked as handled once what they acknowledged is saved, so
+/// none is lost if that fails.
+pub fn run(cfg: &TgConfig, policy: &Policy, ack: Option<&str>) -> Result<Report, String> {
+    let file = waiting_file().ok_or("no state directory (set SENTINEL_STATE_DIR or HOME)")?;
+    let waiting = load(&file);
+    let client = http_client_with(&cfg.http)?;
+    let (acked, seen) = match ack {
+        Some(id) => {
+            let id = id.trim().to_lowercase();
+            if !waiting.iter().any(|w| w.id == id) {
+                return Err(format!("no failure {id} is waiting to be acknowledged"));
+            }
+            let acked = vec![Ack {
+                id,
+                button: None,
+                by: String::new(),
+            }];
+            (acked, None)
+        }
+        None => {
+            let updates = doctor::call(&client, cfg, "getUpdates", json!({ "timeout": 0 }))
+                .map_err(|e| e.finding("Telegram", cfg).detail)?;
+            let mut chats = vec![cfg.chat_id.as_str()];
+            chats.extend(cfg.severity_chats.iter().map(|(_, chat)| chat.as_str()));
+            chats.extend(policy.chat_id.as_deref());
+            let acked = acks(&updates, &chats);
+            let seen = updates
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|update| update["update_id"].as_i64())
+                .max();
+            (acked, seen)
+        }
+    };
+    for ack in &acked {
+        if let Some((query, message)) = &ack.button {
+            answer(&client, cfg, query, message, &ack.by);
+        }
+    }
+    let mut escalated = Vec::new();
+    if ack.is_none() {
+        let now = chrono::Utc::now().timestamp();
+        let due = waiting
+            .iter()
+            .filter(|w| w.due(policy, now) && !acked.iter().any(|ack| ack.id == w.id));
+        for w in due {
+            match escalate(&client, cfg, policy, w, w.escalated + 1) {
+                Ok(()) => escalated.push((w.id.clone(), w.escalated + 1)),
+                Err(e) => tracing::warn!("Failed to escalate {}: {e}", w.id),
+            }
+        }
+    }
+    let (acknowledged, left) = settle(&file, &acked, &escalated, policy.times)
+        .map_err(|e| format!("cannot write {}: {e}", file.display()))?;
+    for w in &acknowledged {
+        repeat::acknowledge(&w.job, &w.host);
+    }
+    let report = Report {
+        acknowledged: acknowledged.len(),
+        escalated: escalated.len(),
+        waiting: left,
+    };
+    if let Some(last) = seen {
+        let done = json!({ "offset": last + 1, "timeout": 0 });
+        doctor::call(&client, cfg, "getUpdates", done)
+            .map_err(|e| e.finding("Telegram", cfg).detail)?;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failures_are_escalated_every_interval_up_to_the_limit() {
+        let policy = Policy {
+            after: 15,
+            chat_id: Some("-100".to_string()),
+            url: None,
+            times: 2,
+        };
+        let mut waiting = Waiting {
+            id: "3fa9c1".to_string(),
+            text: "Failed".to_string(),
+            job: "backup".to_string(),
+            host: "vm".to_string(),
+            raised: 1_000,
+            escalated: 0,
+        };
+        assert!(!waiting.due(&policy, 1_000 + 14 * 60));
+        assert!(waiting.due(&policy, 1_000 + 15 * 60));
+        waiting.escalated = 1;
+        assert!(!waiting.due(&policy, 1_000 + 29 * 60));
+        assert!(waiting.due(&policy, 1_000 + 30 * 60));
+        waiting.escalated = 2;
+        assert!(!waiting.due(&policy, 1_000 + 90 * 60));
+    }
+
+    #[test]
+    fn acknowledgements_come_from_the_chats_only() {
+        let updates = json!([
+            { "update_id": 1, "message": { "chat": { "id": 123 }, "text": "/ack 3FA9C1" } },
+            { "update_id": 2, "message": { "chat": { "id": 999 }, "text": "/ack 000001" } },
+            { "update_id": 3, "channel_post": { "chat": { "id": -100 }, "text": "/ack@bot 0a0b0c" } },
+            { "update_id": 4, "message": { "chat": { "id": 123 }, "text": "ack 111111" } },
+            { "update_id": 5, "message": { "chat": { "id": 123 }, "text": "/ack" } },
+            { "update_id": 6, "callback_query": {
+                "id": "42", "data": "ack:abcdef", "from": { "first_name": "Ana" },
+                "message": { "message_id": 9, "chat": { "id": 123 }, "text": "Failed" },
+            } },
+        ]);
+        let acks = acks(&updates, &["123", "-100"]);
+        let ids: Vec<&str> = acks.iter().map(|ack| ack.id.as_str()).collect();
+        assert_eq!(ids, ["3fa9c1", "0a0b0c", "abcdef"]);
+        assert_eq!(acks[2].by, "Ana");
+        assert_eq!(acks[2].button.as_ref().unwrap().0, "42");
+        assert!(acks[0].button.is_none());
+    }
+
+    #[test]
+    fn failures_raised_during_a_run_are_kept() {
+        let file =
+            std::env::temp_dir().join(format!("sentinel-escalations-{}.json", std::process::id()));
+        let failure = |id: &str| Waiting {
+            id: id.to_string(),
+            text: "Failed".to_string(),
+            job: "backup".to_string(),
+            host: "vm".to_string(),
+            raised: 1_000,
+            escalated: 0,
+        };
+        keep(&file, failure("aaaaaa")).unwrap();
+        keep(&file, failure("bbbbbb")).unwrap();
+        // A run reads these, and another raises a failure while it talks to
+        // Telegram.
+        assert_eq!(load(&file).len(), 2);
+        keep(&file, failure("cccccc")).unwrap();
+        let acked = [Ack {
+            id: "aaaaaa".to_string(),
+            button: None,
+            by: String::new(),
+        }];
+        let (acknowledged, left) = settle(&file, &acked, &[("bbbbbb".to_string(), 1)], 2).unwrap();
+        assert_eq!(acknowledged, [failure("aaaaaa")]);
+        assert_eq!(left, 2);
+        let waiting = load(&file);
+        let ids: Vec<(&str, u32)> = waiting
+            .iter()
+            .map(|w| (w.id.as_str(), w.escalated))
+            .collect();
+        assert_eq!(ids, [("bbbbbb", 1), ("cccccc", 0)]);
+        std::fs::remove_file(&file).ok();
+        std::fs::remove_file(file.with_extension("lock")).ok();
+    }
+}
@@ -0,0 +1,472 @@
+use serde::{Deserialize, Serialize};
+
+/// What happened to the wrapped command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Start,
+    Success,
+    Failure,
+    Signal,
+    SpawnError,
+    /// A mid-run notification raised by a hook rather than the run itself.
+    Message,
+    /// One summary of several runs (e.g. `--hosts`), listed in `steps`.
+    Report,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+    Critical,
+}
+
+impl Severity {
+    pub const ALL: [Severity; 4] = [
+        Severity::Info,
+        Severity::Warning,
+        Severity::Error,
+        Severity::Critical,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+impl std::str::FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Severity::ALL
+            .into_iter()
+            .find(|severity| severity.name() == s.trim())
+            .ok_or_else(|| {
+                format!("unknown severity {s:?} (expected info, warning, error or critical)")
+            })
+    }
+}
+
+impl EventKind {
+    pub const ALL: [EventKind; 7] = [
+        EventKind::Start,
+        EventKind::Success,
+        EventKind::Failure,
+        EventKind::Signal,
+        EventKind::SpawnError,
+        EventKind::Message,
+        EventKind::Report,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EventKind::Start => "start",
+            EventKind::Success => "success",
+            EventKind::Failure => "failure",
+            EventKind::Signal => "signal",
+            EventKind::SpawnError => "spawn_error",
+            EventKind::Message => "message",
+            EventKind::Report => "report",
+        }
+    }
+
+    pub fn default_severity(self) -> Severity {
+        match self {
+            EventKind::Start | EventKind::Success | EventKind::Message | EventKind::Report => {
+                Severity::Info
+            }
+            EventKind::Failure | EventKind::Signal | EventKind::SpawnError => Severity::Error,
+        }
+    }
+}
+
+/// A notification-worthy moment in a run. The fields are the variables
+/// available to message templates and plugins; `text` is the rendered
+/// message the backends deliver.
+#[derive(Clone, Debug, Serialize)]
+pub struct Event {
+    pub kind: EventKind,
+    pub severity: Severity,
+    /// Marks the outcome at a glance, e.g. "✅" (see [`crate::icon`]).
+    pub icon: Option<String>,
+    pub timestamp: String,
+    /// Where the command runs.
+    pub host: String,
+    /// The machine sentinel-rs itself runs on, when that is not `host`.
+    pub via: Option<String>,
+    pub user: String,
+    pub cwd: String,
+    pub command: String,
+    /// Short name for the run: `SENTINEL_JOB`, else the command's program,
+    /// as `command` shows it (so `--hide-command` hides it too).
+    pub job: String,
+    /// How a local command is run, where that is not the default, e.g.
+    /// "nice 10, ionice idle, CPUs 0-3". Set on the start event.
+    pub settings: Option<String>,
+    pub exit_code: Option<i32>,
+    /// What a failure's exit code means, if it is well known, e.g.
+    /// "command not found" or "rsync: partial transfer due to an error".
+    pub exit_meaning: Option<String>,
+    /// Which stage of a pipeline failed, e.g. "stage 2 of 3, `gzip`,
+    /// exited 1".
+    pub failed_stage: Option<String>,
+    /// Why a run that exited 0 counts as failed, e.g. "Output matched
+    /// --failure-regex: 0 rows exported".
+    pub failure_reason: Option<String>,
+    /// A `--pre`, `--post` or `--on-failure` command that failed, e.g.
+    /// "--post `umount /mnt` exited 32: umount: /mnt: target is busy".
+    pub hook_failure: Option<String>,
+    /// Why a successful run was notified about with `--notify-if`, e.g.
+    /// "Output matched --notify-if: .errors > 0".
+    pub notify_if: Option<String>,
+    /// The signal that killed the command, e.g. "SIGKILL".
+    pub signal: Option<String>,
+    /// Whether the command dumped core when the signal killed it.
+    pub core_dumped: bool,
+    /// Where the core went: a file, or the handler that took it.
+    pub core_file: Option<String>,
+    /// Whether the OOM killer sent the signal.
+    pub oom_killed: bool,
+    /// Resident memory when the OOM killer struck, e.g. "14.2 GiB".
+    pub peak_rss: Option<String>,
+    pub duration_secs: Option<f64>,
+    /// Human-readable `duration_secs`.
+    pub duration: Option<String>,
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+    /// With `--merge-output`, both in the order they came, each line of
+    /// stderr marked `[stderr]`, in place of the two.
+    pub output: Option<String>,
+    /// Why the command could not be started.
+    pub error: Option<String>,
+    /// Body of a [`EventKind::Message`].
+    pub message: Option<String>,
+    /// Headline of a [`EventKind::Report`], e.g. "2 of 5 hosts failed".
+    pub summary: Option<String>,
+    /// What the `--parser` made of the output, in place of its tail.
+    pub output_summary: Option<String>,
+    /// The runs a [`EventKind::Report`] covers, failures first.
+    pub steps: Vec<StepResult>,
+    /// What the command used, where that is measured (e.g. in a container).
+    pub resources: Option<ResourceUsage>,
+    /// Who started the run, for a start notification with `--origin`.
+    pub origin: Option<crate::origin::Origin>,
+    /// The cloud instance the run is on, for a start notification with
+    /// `--cloud`.
+    pub instance: Option<crate::cloud::Instance>,
+    /// The repository the run worked on, with `--git`.
+    pub git: Option<crate::git::GitInfo>,
+    /// The command's environment, for a start notification with
+    /// `--show-env`.
+    pub environment: Option<crate::environ::Snapshot>,
+    /// What the `--artifact` patterns matched after the run.
+    pub artifacts: Option<Artifacts>,
+    /// Whether a [mute rule](crate::mute) holds for the run: it is then not
+    /// sent to Telegram.
+    pub muted: bool,
+    /// How [maintenance](crate::maintenance) handles it, if that is on:
+    /// quiet hours and repeats then leave it alone.
+    pub maintenance: Option<crate::maintenance::Handling>,
+    pub text: String,
+}
+
+/// The files `--artifact` patterns matched after a run.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Artifacts {
+    pub found: Vec<Artifact>,
+    /// Matches left out of `found` to keep the message short.
+    pub more: usize,
+    /// The patterns that matched no file.
+    pub missing: Vec<String>,
+}
+
+/// A file an `--artifact` pattern matched.
+#[derive(Clone, Debug, Serialize)]
+pub struct Artifact {
+    pub path: String,
+    pub bytes: u64,
+    /// `bytes` made readable, e.g. "14.2 MiB".
+    pub size: String,
+    /// When it was last written, e.g. "2026-10-14 06:57:41".
+    pub modified: String,
+    /// Last written before the run started: left over from an earlier one.
+    pub stale: bool,
+}
+
+/// CPU and memory use of a finished run.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ResourceUsage {
+    /// In percent of one core.
+    pub cpu_avg_percent: Option<f64>,
+    pub cpu_peak_percent: Option<f64>,
+    pub memory_peak_bytes: Option<u64>,
+    /// Processes the OOM killer killed, where that is counted (the run's own
+    /// cgroup).
+    pub oom_kills: Option<u64>,
+    /// Resource limits the run ran into, e.g. "memory limit 2.0 GiB hit".
+    pub limit_hits: Vec<String>,
+    /// The above as one line, e.g. "CPU avg 12.5%, peak 80.0%; memory peak 10.5 MiB".
+    pub summary: String,
+}
+
+impl ResourceUsage {
+    pub fn new(cpu_avg: Option<f64>, cpu_peak: Option<f64>, memory_peak: Option<u64>) -> Self {
+        let mut parts = Vec::new();
+        match (cpu_avg, cpu_peak) {
+            (Some(avg), Some(peak)) => parts.push(format!("CPU avg {avg:.1}%, peak {peak:.1}%")),
+            (Some(cpu), None) | (None, Some(cpu)) => parts.push(format!("CPU {cpu:.1}%")),
+            (None, None) => {}
+        }
+        if let Some(bytes) = memory_peak {
+            parts.push(format!("memory peak {}", format_bytes(bytes)));
+        }
+        ResourceUsage {
+            cpu_avg_percent: cpu_avg,
+            cpu_peak_percent: cpu_peak,
+            memory_peak_bytes: memory_peak,
+            oom_kills: None,
+            limit_hits: Vec::new(),
+            summary: parts.join("; "),
+        }
+    }
+
+    /// Adds the limits that were hit, to the summary as well.
+    pub fn with_limit_hits(mut self, hits: Vec<String>) -> Self {
+        for hit in &hits {
+            if !self.summary.is_empty() {
+                self.summary.push_str("; ");
+            }
+            self.summary.push_str(hit);
+        }
+        self.limit_hits = hits;
+        self
+    }
+}
+
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+/// `elapsed` for people: "4.2s", "3m 07s", "1h 23m 05s".
+pub fn format_duration(elapsed: std::time::Duration) -> String {
+    let secs = elapsed.as_secs();
+    match secs {
+        // Cut to tenths rather than rounded, so 59.96s is not "60.0s".
+        0..60 => format!("{:.1}s", (elapsed.as_millis() / 100) as f64 / 10.0),
+        60..3600 => format!("{}m {:02}s", secs / 60, secs % 60),
+        _ => format!(
+            "{}h {:02}m {:02}s",
+            secs / 3600,
+            secs % 3600 / 60,
+            secs % 60
+        ),
+    }
+}
+
+/// `90s`, `30m`, `2h`, or seconds.
+pub fn parse_duration(s: &str) -> Result<std::time::Duration, String> {
+    let s = s.trim();
+    let (number, unit) = match s.char_indices().last() {
+        Some((i, unit @ ('s' | 'm' | 'h'))) => (&s[..i], unit),
+        _ => (s, 's'),
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| match unit {
+            'h' => number.checked_mul(3600),
+            'm' => number.checked_mul(60),
+            _ => Some(number),
+        })
+        .map(std::time::Duration::from_secs)
+        .ok_or_else(|| format!("{s:?} is not a duration (e.g. 90s, 30m, 2h)"))
+}
+
+/// The program a command line runs, without its directory.
+fn job_name(command: &str) -> String {
+    if command == crate::echo::HIDDEN {
+        return command.to_string();
+    }
+    let program = command.split_whitespace().next().unwrap_or_default();
+    program.rsplit('/').next().unwrap_or(program).to_string()
+}
+
+/// How one of the runs in a report went.
+#[derive(Clone, Debug, Serialize)]
+pub struct StepResult {
+    pub name: String,
+    pub ok: bool,
+    /// Not run, because an earlier step failed.
+    pub skipped: bool,
+    /// "ok", "exit code 3", "killed by SIGKILL" or why it could not start.
+    pub status: String,
+    pub exit_code: Option<i32>,
+    pub duration: String,
+    /// Tail of the output of a failed run.
+    pub excerpt: Option<String>,
+}
+
+impl Event {
+    pub fn new(kind: EventKind, command: &str) -> Self {
+        let command = crate::echo::command(command);
+        Event {
+            kind,
+            severity: kind.default_severity(),
+            icon: crate::icon::for_kind(kind),
+            timestamp: crate::clock::now(),
+            host: crate::host::name(),
+            via: None,
+            user: std::env::var("USER")
+                .or_else(|_| std::env::var("LOGNAME"))
+                .unwrap_or_default(),
+            cwd: std::env::current_dir()
+                .map(|p| p.display().to_string())
+                .unwrap_or_default(),
+            job: std::env::var("SENTINEL_JOB").unwrap_or_else(|_| job_name(&command)),
+            command,
+            settings: None,
+            exit_code: None,
+            exit_meaning: None,
+            failed_stage: None,
+            failure_reason: None,
+            hook_failure: None,
+            notify_if: None,
+            signal: None,
+            core_dumped: false,
+            core_file: None,
+            oom_killed: false,
+            peak_rss: None,
+            duration_secs: None,
+            duration: None,
+            stdout: None,
+            stderr: None,
+            output: None,
+            error: None,
+            message: None,
+            summary: None,
+            output_summary: None,
+            steps: Vec::new(),
+            resources: None,
+            origin: None,
+            instance: None,
+            git: None,
+            environment: None,
+            artifacts: None,
+            muted: false,
+            maintenance: None,
+            text: String::new(),
+        }
+    }
+
+    pub fn message(command: &str, text: &str) -> Self {
+        Event {
+            message: Some(text.to_string()),
+            ..Event::new(EventKind::Message, command)
+        }
+    }
+
+    /// Marks the event as coming from a command run on `host` over SSH.
+    pub fn set_remote_host(&mut self, host: &str) {
+        self.via = Some(std::mem::replace(
+            &mut self.host,
+            crate::host::label_for(host),
+        ));
+    }
+
+    pub fn set_duration(&mut self, elapsed: std::time::Duration) {
+        self.duration_secs = Some(elapsed.as_secs_f64());
+        self.duration = Some(format_duration(elapsed));
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_json_uses_snake_case_kind() {
+        let mut event = Event::new(EventKind::SpawnError, "true");
+        event.host = "host".to_string();
+        event.text = "boom".to_string();
+        let value: serde_json::Value = serde_json::from_str(&event.to_json()).unwrap();
+        assert_eq!(value["kind"], "spawn_error");
+        assert_eq!(value["severity"], "error");
+        assert_eq!(value["command"], "true");
+        assert_eq!(value["host"], "host");
+        assert_eq!(value["exit_code"], serde_json::Value::Null);
+        assert_eq!(value["text"], "boom");
+    }
+
+    #[test]
+    fn resource_usage_summary_is_readable() {
+        let usage = ResourceUsage::new(Some(12.5), Some(80.0), Some(11_010_048));
+        assert_eq!(
+            usage.summary,
+            "CPU avg 12.5%, peak 80.0%; memory peak 10.5 MiB"
+        );
+        assert_eq!(
+            ResourceUsage::new(None, None, Some(512)).summary,
+            "memory peak 512 B"
+        );
+        let usage = ResourceUsage::new(Some(150.0), None, Some(2048))
+            .with_limit_hits(vec!["memory limit 2.0 KiB hit".to_string()]);
+        assert_eq!(
+            usage.summary,
+            "CPU 150.0%; memory peak 2.0 KiB; memory limit 2.0 KiB hit"
+        );
+    }
+
+    #[test]
+    fn durations_are_readable() {
+        let secs = std::time::Duration::from_secs_f64;
+        assert_eq!(format_duration(secs(4.21)), "4.2s");
+        assert_eq!(format_duration(secs(59.96)), "59.9s");
+        assert_eq!(format_duration(secs(187.0)), "3m 07s");
+        assert_eq!(format_duration(secs(4985.0)), "1h 23m 05s");
+        assert_eq!(parse_duration("2h").unwrap(), secs(7200.0));
+        assert_eq!(parse_duration("45").unwrap(), secs(45.0));
+        assert!(parse_duration("soon").is_err());
+        assert!(parse_duration("99999999999999999h").is_err());
+    }
+
+    #[test]
+    fn job_name_is_program_basename() {
+        assert_eq!(job_name("/usr/local/bin/backup.sh --full"), "backup.sh");
+        assert_eq!(job_name("  make test"), "make");
+        assert_eq!(job_name(""), "");
+        assert_eq!(job_name("<redacted> --full"), "<redacted>");
+        assert_eq!(job_name("(command hidden)"), "(command hidden)");
+    }
+
+    #[test]
+    fn kind_names_match_serialized_form() {
+        for kind in EventKind::ALL {
+            assert_eq!(
+                serde_json::to_value(kind).unwrap(),
+                serde_json::Value::String(kind.name().to_string())
+            );
+        }
+    }
+}
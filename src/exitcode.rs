@@ -0,0 +1,142 @@
+//! What an exit code means, for the failure notification: the shell's own
+//! (127, command not found; 128+N, killed by signal N) and those of a few
+//! tools with many of them (rsync, curl, wget, ssh, timeout), saving a trip
+//! to the man pages.
+
+use crate::runner::signal_name;
+
+/// Commands that run the next word as the program.
+const WRAPPERS: [&str; 7] = ["sudo", "env", "nice", "ionice", "nohup", "exec", "time"];
+
+const RSYNC: [(i32, &str); 19] = [
+    (1, "syntax or usage error"),
+    (2, "protocol incompatibility"),
+    (3, "errors selecting input/output files or directories"),
+    (4, "requested action not supported"),
+    (5, "error starting the client-server protocol"),
+    (6, "daemon unable to append to its log file"),
+    (10, "error in socket I/O"),
+    (11, "error in file I/O"),
+    (12, "error in the rsync protocol data stream"),
+    (13, "errors with program diagnostics"),
+    (14, "error in IPC code"),
+    (20, "received SIGUSR1 or SIGINT"),
+    (21, "some error returned by waitpid()"),
+    (22, "error allocating memory buffers"),
+    (23, "partial transfer due to an error"),
+    (24, "partial transfer, source files vanished"),
+    (25, "the --max-delete limit stopped deletions"),
+    (30, "timeout in data send/receive"),
+    (35, "timeout waiting for the daemon connection"),
+];
+
+const CURL: [(i32, &str); 20] = [
+    (1, "unsupported protocol"),
+    (3, "malformed URL"),
+    (5, "could not resolve the proxy"),
+    (6, "could not resolve the host"),
+    (7, "could not connect to the host"),
+    (18, "partial transfer"),
+    (22, "the server returned an HTTP error"),
+    (23, "error writing the output"),
+    (26, "error reading the input"),
+    (27, "out of memory"),
+    (28, "timed out"),
+    (35, "TLS handshake failed"),
+    (47, "too many redirects"),
+    (52, "the server sent nothing"),
+    (55, "error sending data"),
+    (56, "error receiving data"),
+    (60, "the server's certificate could not be verified"),
+    (67, "login denied"),
+    (77, "error reading the CA certificates"),
+    (78, "the remote file was not found"),
+];
+
+const WGET: [(i32, &str); 8] = [
+    (1, "generic error"),
+    (2, "parse error in the options or config"),
+    (3, "file I/O error"),
+    (4, "network failure"),
+    (5, "TLS verification failed"),
+    (6, "authentication failed"),
+    (7, "protocol error"),
+    (8, "the server returned an error"),
+];
+
+/// The program `command` runs, past `VAR=value` assignments and wrappers
+/// such as `sudo`, and without its directory.
+fn program(command: &str) -> &str {
+    // The last stage of a pipeline sets its exit code.
+    let stage = command.rsplit('|').next().unwrap_or(command);
+    let mut words = stage
+        .split_whitespace()
+        .filter(|word| !word.contains('=') && !word.starts_with('-'))
+        .map(|word| word.rsplit('/').next().unwrap_or(word));
+    words
+        .find(|word| !WRAPPERS.contains(word))
+        .unwrap_or_default()
+}
+
+/// What exit code `code` of `command` means, if it is well known; `remote`
+/// if the command ran over SSH.
+pub fn explain(command: &str, code: i32, remote: bool) -> Option<String> {
+    let program = program(command);
+    let table: &[(i32, &str)] = match program {
+        "rsync" => &RSYNC,
+        "curl" => &CURL,
+        "wget" => &WGET,
+        _ => &[],
+    };
+    if let Some((_, meaning)) = table.iter().find(|(c, _)| *c == code) {
+        return Some(format!("{program}: {meaning}"));
+    }
+    let meaning = match code {
+        124 if program == "timeout" => "timed out".to_string(),
+        125 if program == "timeout" => "timeout itself failed".to_string(),
+        126 => "found but not executable".to_string(),
+        127 => "command not found".to_string(),
+        130 => "interrupted by SIGINT (Ctrl-C)".to_string(),
+        137 => "killed by SIGKILL, often the OOM killer".to_string(),
+        255 if remote || program == "ssh" => {
+            "ssh failed to connect or lost the connection".to_string()
+        }
+        129..=192 => format!("killed by {}", signal_name(code - 128)),
+        _ => return None,
+    };
+    Some(meaning)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explains_shell_and_tool_exit_codes() {
+        assert_eq!(
+            explain("nosuchcmd --x", 127, false).as_deref(),
+            Some("command not found")
+        );
+        assert_eq!(
+            explain("sudo -E /usr/bin/rsync -a src/ dst/", 23, false).as_deref(),
+            Some("rsync: partial transfer due to an error")
+        );
+        assert_eq!(
+            explain("tar c . | curl -fsS -T - https://x", 22, false).as_deref(),
+            Some("curl: the server returned an HTTP error")
+        );
+        assert_eq!(
+            explain("timeout 60 make", 124, false).as_deref(),
+            Some("timed out")
+        );
+        assert_eq!(
+            explain("make", 143, false).as_deref(),
+            Some("killed by SIGTERM")
+        );
+        assert_eq!(
+            explain("make", 255, true).as_deref(),
+            Some("ssh failed to connect or lost the connection")
+        );
+        assert_eq!(explain("make", 2, false), None);
+    }
+}
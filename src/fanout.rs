@@ -0,0 +1,239 @@
+//! `--hosts`: the same command on many machines over SSH, a few at a time,
+//! summed up in a single [`EventKind::Report`] notification.
+
+use crate::config::TgConfig;
+use crate::event::{Event, EventKind, Severity, StepResult, format_duration};
+use crate::metrics::METRICS;
+use crate::notifier::{Reporter, start_notifier};
+use crate::runner::{Exec, OnLine, Stream, run_bash, signal_name, ssh_host, tail_bytes};
+use crate::{LineHook, RunOptions, exit_code};
+use std::process::Output;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Instant;
+
+pub const DEFAULT_CONCURRENCY: usize = 8;
+
+/// How much of a failed host's output makes it into the report.
+const EXCERPT_BYTES: usize = 300;
+
+/// Destinations from a hosts file: one `[user@]host` per line; blank lines
+/// and `#` comments are ignored.
+pub fn parse_hosts(text: &str) -> Vec<String> {
+    text.lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+struct HostRun {
+    /// Position in the hosts file.
+    index: usize,
+    step: StepResult,
+    exit: i32,
+}
+
+/// Runs `command` on every host in `hosts` with at most `concurrency` SSH
+/// sessions at once, then sends one report listing how each host did.
+/// Returns the exit code for the whole run: 0 if every host succeeded,
+/// otherwise the highest exit code among the failures.
+pub fn run_on_hosts(
+    command: &str,
+    hosts: &[String],
+    concurrency: usize,
+    cfg: TgConfig,
+    opts: RunOptions,
+) -> i32 {
+    let (tx, notifier) = start_notifier(cfg, opts.plugins);
+    let reporter = Reporter::new(tx, opts.templates);
+    let label = format!("{} hosts", hosts.len());
+    let started = Instant::now();
+    let mut start = Event::new(EventKind::Start, command);
+    start.set_remote_host(&label);
+    reporter.send(start);
+
+    let next = AtomicUsize::new(0);
+    let mut runs: Vec<HostRun> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..concurrency.clamp(1, hosts.len().max(1)))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(host) = hosts.get(index) else {
+                            break;
+                        };
+                        let on_line =
+                            line_hook(host, command, opts.tee, &opts.line_hooks, &reporter);
+                        done.push(run_host(index, command, host, on_line));
+                    }
+                    done
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap_or_default())
+            .collect()
+    });
+
+    // Failures first, then in hosts-file order.
+    runs.sort_by_key(|run| (run.step.ok, run.index));
+    let failed = runs.iter().filter(|run| !run.step.ok).count();
+    let mut report = Event::new(EventKind::Report, command);
+    report.set_remote_host(&label);
+    report.set_duration(started.elapsed());
+    report.summary = Some(if failed == 0 {
+        format!("All {} hosts succeeded.", runs.len())
+    } else {
+        format!("{failed} of {} hosts failed.", runs.len())
+    });
+    if failed > 0 {
+        report.severity = Severity::Error;
+    }
+    let exit = runs.iter().map(|run| run.exit).max().unwrap_or(0);
+    report.steps = runs.into_iter().map(|run| run.step).collect();
+    reporter.send(report);
+
+    drop(reporter);
+    notifier.shutdown();
+    exit
+}
+
+/// Output from the hosts is prefixed with the host name when mirrored, so
+/// interleaved lines stay attributable; hook notices are sent as coming
+/// from that host.
+fn line_hook(
+    host: &str,
+    command: &str,
+    tee: bool,
+    hooks: &[Arc<dyn LineHook>],
+    reporter: &Reporter,
+) -> Option<OnLine> {
+    if !tee && hooks.is_empty() {
+        return None;
+    }
+    let name = ssh_host(host).to_string();
+    let command = command.to_string();
+    let hooks = hooks.to_vec();
+    let reporter = reporter.clone();
+    Some(Arc::new(move |stream, line: &str| {
+        if tee {
+            match stream {
+                Stream::Stdout => println!("[{name}] {line}"),
+                Stream::Stderr => eprintln!("[{name}] {line}"),
+            }
+        }
+        for notice in hooks.iter().filter_map(|hook| hook.on_line(stream, line)) {
+            let mut event = Event::message(&command, &notice.text);
+            event.set_remote_host(&name);
+            if let Some(severity) = notice.severity {
+                event.severity = severity;
+            }
+            reporter.send(event);
+        }
+    }))
+}
+
+fn run_host(index: usize, command: &str, host: &str, on_line: Option<OnLine>) -> HostRun {
+    let exec = Exec {
+        ssh: Some(host.to_string()),
+        ..Exec::default()
+    };
+    let started = Instant::now();
+    let _running = METRICS.run_started();
+    let result = run_bash(command, &exec, false, on_line).map(|run| run.output);
+    let (step, exit) = step_result(host, started, result);
+    HostRun { index, step, exit }
+}
+
+/// How the run called `name` that began at `started` went, for a report,
+/// and the exit code to pass on for it.
+pub(crate) fn step_result(
+    name: &str,
+    started: Instant,
+    result: std::io::Result<Output>,
+) -> (StepResult, i32) {
+    let mut step = StepResult {
+        name: name.to_string(),
+        ok: false,
+        skipped: false,
+        status: String::new(),
+        exit_code: None,
+        duration: format_duration(started.elapsed()),
+        excerpt: None,
+    };
+    let exit = match result {
+        Ok(output) => {
+            step.exit_code = output.status.code();
+            step.ok = step.exit_code == Some(0);
+            step.status = match step.exit_code {
+                Some(code) => format!("exit code {code}"),
+                None => {
+                    use std::os::unix::process::ExitStatusExt;
+                    match output.status.signal() {
+                        Some(signal) => format!("killed by {}", signal_name(signal)),
+                        None => "killed by signal".to_string(),
+                    }
+                }
+            };
+            if !step.ok {
+                let tail = if output.stderr.is_empty() {
+                    &output.stdout
+                } else {
+                    &output.stderr
+                };
+                let excerpt = tail_bytes(tail, EXCERPT_BYTES).trim_end().to_string();
+                step.excerpt = Some(excerpt).filter(|e| !e.is_empty());
+            }
+            exit_code(&output)
+        }
+        Err(e) => {
+            step.status = e.to_string();
+            1
+        }
+    };
+    (step, exit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_hosts_skips_blanks_and_comments() {
+        let hosts = parse_hosts("# fleet\nweb1\n\n  deploy@web2  # canary\n#db1\nssh://db2:2222\n");
+        assert_eq!(hosts, ["web1", "deploy@web2", "ssh://db2:2222"]);
+    }
+
+    #[test]
+    fn report_template_lists_failures_with_excerpt() {
+        let step = |name: &str, ok, status: &str, excerpt: Option<&str>| StepResult {
+            name: name.to_string(),
+            ok,
+            skipped: false,
+            status: status.to_string(),
+            exit_code: None,
+            duration: "0.5s".to_string(),
+            excerpt: excerpt.map(str::to_string),
+        };
+        let report = Event {
+            timestamp: "2025-01-01 00:00:00".to_string(),
+            host: "2 hosts".to_string(),
+            summary: Some("1 of 2 hosts failed.".to_string()),
+            steps: vec![
+                step("web2", false, "exit code 3", Some("disk full")),
+                step("web1", true, "exit code 0", None),
+            ],
+            ..Event::new(EventKind::Report, "uptime")
+        };
+        assert_eq!(
+            crate::template::Templates::builtin().render(&report),
+            "[2025-01-01 00:00:00] [2 hosts]\n1 of 2 hosts failed.\nuptime\n\
+             FAILED web2: exit code 3 (0.5s)\ndisk full\n\
+             ok web1: exit code 0 (0.5s)"
+        );
+    }
+}
@@ -0,0 +1,119 @@
+//! Fitting a message into what a backend accepts. Each backend has its own
+//! maximum (Telegram takes 4096 characters per message, 1024 per file
+//! caption) and its own way of dealing with more, chosen with
+//! [`Overflow`]: cut the middle out, split the message into several, or
+//! send it whole as a file and only its start as text.
+
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Overflow {
+    /// Keep the start and the end, which has the last of the output.
+    #[default]
+    Truncate,
+    /// Send several messages, split between lines where possible.
+    Split,
+    /// Attach the whole message as a text file.
+    Document,
+}
+
+impl FromStr for Overflow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "truncate" => Ok(Overflow::Truncate),
+            "split" => Ok(Overflow::Split),
+            "document" => Ok(Overflow::Document),
+            _ => Err(format!(
+                "unknown overflow policy {s:?} (expected truncate, split or document)"
+            )),
+        }
+    }
+}
+
+/// `text` itself if it has at most `max` characters, otherwise its start
+/// and end around a note of how much was cut.
+pub fn truncate(text: &str, max: usize) -> String {
+    let len = text.chars().count();
+    if len <= max {
+        return text.to_string();
+    }
+    // The note is at most this long.
+    let room = max.saturating_sub(40);
+    let head = room / 4;
+    let tail = room - head;
+    let cut = len - head - tail;
+    let start: String = text.chars().take(head).collect();
+    let end: String = text.chars().skip(len - tail).collect();
+    format!("{start}\n[… {cut} characters cut …]\n{end}")
+}
+
+/// `text` in parts of at most `max` characters, each ending in `[i/n]`
+/// when there are several.
+pub fn split(text: &str, max: usize) -> Vec<String> {
+    if text.chars().count() <= max {
+        return vec![text.to_string()];
+    }
+    // Room for "\n[99/99]".
+    let room = max.saturating_sub(8).max(1);
+    let mut parts: Vec<String> = Vec::new();
+    let mut part = String::new();
+    let mut part_len = 0;
+    for line in text.split_inclusive('\n') {
+        let mut line = line;
+        loop {
+            let len = line.chars().count();
+            if part_len + len <= room {
+                part.push_str(line);
+                part_len += len;
+                break;
+            }
+            if part_len > 0 {
+                parts.push(std::mem::take(&mut part));
+                part_len = 0;
+                continue;
+            }
+            // A line longer than a whole part.
+            let at = line.char_indices().nth(room).map_or(line.len(), |(i, _)| i);
+            parts.push(line[..at].to_string());
+            line = &line[at..];
+        }
+    }
+    if part_len > 0 {
+        parts.push(part);
+    }
+    let count = parts.len();
+    parts
+        .into_iter()
+        .enumerate()
+        .map(|(i, part)| format!("{}\n[{}/{count}]", part.trim_end_matches('\n'), i + 1))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_messages_are_cut_or_split_to_size() {
+        assert_eq!(truncate("short", 100), "short");
+        let long = format!("HEADER\n{}\nlast line", "x".repeat(500));
+        let cut = truncate(&long, 100);
+        assert!(cut.chars().count() <= 100);
+        assert!(cut.starts_with("HEADER\n"));
+        assert!(cut.ends_with("\nlast line"));
+        assert!(cut.contains("characters cut"));
+
+        let lines: String = (1..=30).map(|n| format!("line {n:02}\n")).collect();
+        let parts = split(&lines, 60);
+        assert!(parts.iter().all(|p| p.chars().count() <= 60));
+        assert_eq!(parts.len(), 5);
+        assert!(parts[0].starts_with("line 01\n"));
+        assert!(parts[0].ends_with("line 06\n[1/5]"));
+        assert!(parts[4].ends_with("line 30\n[5/5]"));
+        assert_eq!(split(&"y".repeat(25), 10).len(), 13);
+        assert_eq!(split("a\nb", 10), ["a\nb"]);
+        assert!("staple".parse::<Overflow>().is_err());
+    }
+}
@@ -0,0 +1,91 @@
+//! `--git`: which commit a run worked on, for builds and deploys. When the
+//! current directory is in a git repository, the start and finish
+//! notifications name its branch and commit, and say if there were
+//! uncommitted changes (to tracked files, as `git describe --dirty` has
+//! it). Read once, when the run starts.
+
+use serde::Serialize;
+use std::path::Path;
+use std::process::Command;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct GitInfo {
+    /// The checked-out branch; `None` with a detached HEAD.
+    pub branch: Option<String>,
+    /// The abbreviated commit hash.
+    pub commit: String,
+    pub dirty: bool,
+    /// The above as one line, e.g. "main @ 1a2b3c4, with uncommitted changes".
+    pub summary: String,
+}
+
+/// The state of the repository `dir` is in, if it is in one and git is
+/// installed.
+pub fn describe(dir: &Path) -> Option<GitInfo> {
+    let git = |args: &[&str]| {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())?;
+        Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+    let commit = git(&["rev-parse", "--short", "HEAD"])?;
+    let branch = git(&["rev-parse", "--abbrev-ref", "HEAD"]).filter(|b| b != "HEAD");
+    let dirty = git(&["status", "--porcelain", "--untracked-files=no"])
+        .is_some_and(|status| !status.is_empty());
+    let summary = format!(
+        "{} @ {commit}{}",
+        branch.as_deref().unwrap_or("detached HEAD"),
+        if dirty {
+            ", with uncommitted changes"
+        } else {
+            ""
+        }
+    );
+    Some(GitInfo {
+        branch,
+        commit,
+        dirty,
+        summary,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes_branch_commit_and_changes() {
+        let dir = std::env::temp_dir().join(format!("sentinel-git-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        assert_eq!(describe(&dir), None);
+
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .arg("-C")
+                .arg(&dir)
+                .args(["-c", "user.name=t", "-c", "user.email=t@example.com"])
+                .args(args)
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success(), "git {args:?}");
+        };
+        git(&["init", "-q", "-b", "deploy"]);
+        std::fs::write(dir.join("app.conf"), "a").unwrap();
+        git(&["add", "app.conf"]);
+        git(&["commit", "-q", "-m", "first"]);
+        let info = describe(&dir).unwrap();
+        assert_eq!(info.branch.as_deref(), Some("deploy"));
+        assert!(!info.dirty);
+        assert_eq!(info.summary, format!("deploy @ {}", info.commit));
+
+        std::fs::write(dir.join("app.conf"), "b").unwrap();
+        assert!(describe(&dir).unwrap().dirty);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
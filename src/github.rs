@@ -0,0 +1,229 @@
+//! GitHub Actions integration, on when `GITHUB_ACTIONS=true` or with
+//! `--github`:
+//!
+//! - output lines that look like errors become `::error::` annotations;
+//! - the final result is appended to `$GITHUB_STEP_SUMMARY` as Markdown;
+//! - with `--github-status`, a commit status for `$GITHUB_SHA` goes from
+//!   pending to success/failure (needs `GITHUB_TOKEN` with `statuses: write`).
+
+use crate::ci::{self, Outcome};
+use crate::plugin::{Action, Plugin, PluginError};
+use crate::runner::Stream;
+use crate::{LineHook, LineNotice, RunOptions};
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// GitHub shows at most 10 error annotations per step.
+const MAX_ANNOTATIONS: usize = 10;
+
+pub fn detected() -> bool {
+    std::env::var("GITHUB_ACTIONS").is_ok_and(|v| v == "true")
+}
+
+/// Adds the annotator and the summary/status plugin to `opts`.
+pub fn install(opts: &mut RunOptions, commit_status: bool) -> Result<(), String> {
+    let status = if commit_status {
+        Some(StatusTarget::from_env()?)
+    } else {
+        None
+    };
+    opts.line_hooks.push(Arc::new(Annotator::default()));
+    opts.plugins.push(Box::new(GithubPlugin {
+        summary: std::env::var_os("GITHUB_STEP_SUMMARY").map(PathBuf::from),
+        status,
+    }));
+    Ok(())
+}
+
+/// Whether a line of output reads like an error message.
+pub fn looks_like_error(line: &str) -> bool {
+    let line = line.trim_start().to_lowercase();
+    line.starts_with("error")
+        || line.starts_with("fatal")
+        || line.contains("error:")
+        || line.contains("[error]")
+}
+
+/// Escapes a workflow command's message (`%`, CR and LF are special).
+fn escape_data(s: &str) -> String {
+    s.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+#[derive(Default)]
+struct Annotator {
+    emitted: AtomicUsize,
+}
+
+impl LineHook for Annotator {
+    fn on_line(&self, _stream: Stream, line: &str) -> Option<LineNotice> {
+        if looks_like_error(line) && self.emitted.fetch_add(1, Ordering::Relaxed) < MAX_ANNOTATIONS
+        {
+            // Workflow commands are read from stdout, whichever stream the line was on.
+            println!("::error::{}", escape_data(line.trim()));
+        }
+        None
+    }
+}
+
+struct StatusTarget {
+    api_url: String,
+    repository: String,
+    sha: String,
+    token: String,
+    /// Link to the workflow run, shown on the status.
+    run_url: Option<String>,
+}
+
+impl StatusTarget {
+    fn from_env() -> Result<Self, String> {
+        let var =
+            |key: &str| std::env::var(key).map_err(|_| format!("--github-status needs {key}"));
+        let repository = var("GITHUB_REPOSITORY")?;
+        let run_url = match (
+            std::env::var("GITHUB_SERVER_URL"),
+            std::env::var("GITHUB_RUN_ID"),
+        ) {
+            (Ok(server), Ok(run)) => Some(format!("{server}/{repository}/actions/runs/{run}")),
+            _ => None,
+        };
+        Ok(StatusTarget {
+            api_url: std::env::var("GITHUB_API_URL")
+                .unwrap_or_else(|_| "https://api.github.com".to_string()),
+            repository,
+            sha: var("GITHUB_SHA")?,
+            token: var("GITHUB_TOKEN")?,
+            run_url,
+        })
+    }
+
+    fn action(&self, state: &str, description: &str) -> Action {
+        let headers = BTreeMap::from([
+            (
+                "Authorization".to_string(),
+                format!("Bearer {}", self.token),
+            ),
+            (
+                "Accept".to_string(),
+                "application/vnd.github+json".to_string(),
+            ),
+            ("User-Agent".to_string(), "sentinel-rs".to_string()),
+        ]);
+        // GitHub rejects descriptions over 140 characters.
+        let description: String = description.chars().take(140).collect();
+        Action::Http {
+            url: format!(
+                "{}/repos/{}/statuses/{}",
+                self.api_url.trim_end_matches('/'),
+                self.repository,
+                self.sha
+            ),
+            method: "POST".to_string(),
+            headers,
+            body: json!({
+                "state": state,
+                "context": "sentinel-rs",
+                "description": description,
+                "target_url": self.run_url,
+            })
+            .to_string(),
+        }
+    }
+}
+
+struct GithubPlugin {
+    summary: Option<PathBuf>,
+    status: Option<StatusTarget>,
+}
+
+impl Plugin for GithubPlugin {
+    fn name(&self) -> &str {
+        "github"
+    }
+
+    fn on_event(&mut self, event_json: &str) -> Result<Vec<Action>, PluginError> {
+        let event: Value = serde_json::from_str(event_json)?;
+        let Some((outcome, headline)) = ci::outcome(&event) else {
+            return Ok(Vec::new());
+        };
+        let state = match outcome {
+            Outcome::Running => "pending",
+            Outcome::Success => "success",
+            Outcome::Failure => "failure",
+            Outcome::Error => "error",
+        };
+        if outcome != Outcome::Running
+            && let Some(path) = &self.summary
+        {
+            let mut file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?;
+            file.write_all(ci::markdown(&event, &headline).as_bytes())?;
+        }
+        Ok(self
+            .status
+            .iter()
+            .map(|s| s.action(state, &headline))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{Event, EventKind};
+
+    #[test]
+    fn recognises_error_lines() {
+        assert!(looks_like_error("error[E0308]: mismatched types"));
+        assert!(looks_like_error("  FATAL: disk full"));
+        assert!(looks_like_error("npm ERR! code 1 error: oops"));
+        assert!(looks_like_error("2025-01-01 [ERROR] boom"));
+        assert!(!looks_like_error("0 errors, 0 warnings"));
+    }
+
+    #[test]
+    fn annotation_messages_are_escaped() {
+        assert_eq!(escape_data("100% done\r\nnext"), "100%25 done%0D%0Anext");
+    }
+
+    #[test]
+    fn failure_writes_summary_and_sets_status() {
+        let summary = std::env::temp_dir().join(format!("sentinel-gh-{}.md", std::process::id()));
+        let mut plugin = GithubPlugin {
+            summary: Some(summary.clone()),
+            status: Some(StatusTarget {
+                api_url: "https://api.github.com/".to_string(),
+                repository: "o/r".to_string(),
+                sha: "abc".to_string(),
+                token: "t".to_string(),
+                run_url: None,
+            }),
+        };
+        let event = Event {
+            exit_code: Some(3),
+            stderr: Some("boom".to_string()),
+            ..Event::new(EventKind::Failure, "make test")
+        };
+        let actions = plugin.on_event(&event.to_json()).unwrap();
+        let markdown = std::fs::read_to_string(&summary).unwrap();
+        std::fs::remove_file(&summary).ok();
+
+        assert!(markdown.starts_with("### sentinel-rs: Failed with exit code 3\n"));
+        assert!(markdown.contains("| Command | `make test` |"));
+        assert!(markdown.contains("<summary>stderr</summary>\n\n```\nboom\n```"));
+        let [Action::Http { url, body, .. }] = actions.as_slice() else {
+            panic!("expected one status update, got {actions:?}");
+        };
+        assert_eq!(url, "https://api.github.com/repos/o/r/statuses/abc");
+        let body: Value = serde_json::from_str(body).unwrap();
+        assert_eq!(body["state"], "failure");
+        assert_eq!(body["context"], "sentinel-rs");
+    }
+}
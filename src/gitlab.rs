@@ -0,0 +1,174 @@
+//! GitLab CI integration: `--gitlab-status` sets a `sentinel-rs` commit
+//! status on `$CI_COMMIT_SHA` (running, then success/failed), and
+//! `--gitlab-comment` posts the result as a note on the merge request of a
+//! merge request pipeline. Both use the `CI_*` variables of the job and a
+//! `GITLAB_TOKEN` with `api` scope (`CI_JOB_TOKEN` cannot do either).
+
+use crate::RunOptions;
+use crate::ci::{self, Outcome};
+use crate::plugin::{Action, Plugin, PluginError};
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+
+/// Adds the GitLab plugin to `opts` for whichever of the two is asked for.
+pub fn install(opts: &mut RunOptions, status: bool, comment: bool) -> Result<(), String> {
+    let var = |key: &str| std::env::var(key).map_err(|_| format!("GitLab integration needs {key}"));
+    let merge_request = std::env::var("CI_MERGE_REQUEST_IID").ok();
+    if comment && merge_request.is_none() {
+        tracing::warn!(
+            "Not a merge request pipeline (no CI_MERGE_REQUEST_IID); skipping the MR comment."
+        );
+    }
+    opts.plugins.push(Box::new(GitlabPlugin {
+        api_url: var("CI_API_V4_URL")?,
+        project: var("CI_PROJECT_ID")?,
+        sha: var("CI_COMMIT_SHA")?,
+        token: var("GITLAB_TOKEN")?,
+        job_url: std::env::var("CI_JOB_URL").ok(),
+        status,
+        merge_request: merge_request.filter(|_| comment),
+    }));
+    Ok(())
+}
+
+struct GitlabPlugin {
+    api_url: String,
+    project: String,
+    sha: String,
+    token: String,
+    job_url: Option<String>,
+    status: bool,
+    /// IID of the merge request to comment on.
+    merge_request: Option<String>,
+}
+
+impl GitlabPlugin {
+    fn request(&self, path: &str, body: Value) -> Action {
+        Action::Http {
+            url: format!(
+                "{}/projects/{}/{path}",
+                self.api_url.trim_end_matches('/'),
+                self.project
+            ),
+            method: "POST".to_string(),
+            headers: BTreeMap::from([
+                ("PRIVATE-TOKEN".to_string(), self.token.clone()),
+                ("Content-Type".to_string(), "application/json".to_string()),
+            ]),
+            body: body.to_string(),
+        }
+    }
+}
+
+impl Plugin for GitlabPlugin {
+    fn name(&self) -> &str {
+        "gitlab"
+    }
+
+    fn on_event(&mut self, event_json: &str) -> Result<Vec<Action>, PluginError> {
+        let event: Value = serde_json::from_str(event_json)?;
+        let Some((outcome, headline)) = ci::outcome(&event) else {
+            return Ok(Vec::new());
+        };
+        let mut actions = Vec::new();
+        if self.status {
+            let state = match outcome {
+                Outcome::Running => "running",
+                Outcome::Success => "success",
+                Outcome::Failure | Outcome::Error => "failed",
+            };
+            actions.push(self.request(
+                &format!("statuses/{}", self.sha),
+                json!({
+                    "state": state,
+                    "name": "sentinel-rs",
+                    "description": headline,
+                    "target_url": self.job_url,
+                }),
+            ));
+        }
+        if let Some(iid) = &self.merge_request
+            && outcome != Outcome::Running
+        {
+            let mut note = ci::markdown(&event, &headline);
+            if let Some(url) = &self.job_url {
+                note.push_str(&format!("[Job log]({url})\n"));
+            }
+            actions.push(self.request(
+                &format!("merge_requests/{iid}/notes"),
+                json!({ "body": note }),
+            ));
+        }
+        Ok(actions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{Event, EventKind};
+
+    fn plugin() -> GitlabPlugin {
+        GitlabPlugin {
+            api_url: "https://gitlab.example.com/api/v4/".to_string(),
+            project: "42".to_string(),
+            sha: "abc".to_string(),
+            token: "t".to_string(),
+            job_url: Some("https://gitlab.example.com/o/r/-/jobs/7".to_string()),
+            status: true,
+            merge_request: Some("5".to_string()),
+        }
+    }
+
+    #[test]
+    fn start_only_sets_running_status() {
+        let actions = plugin()
+            .on_event(&Event::new(EventKind::Start, "deploy").to_json())
+            .unwrap();
+        let [Action::Http { url, body, .. }] = actions.as_slice() else {
+            panic!("expected one request, got {actions:?}");
+        };
+        assert_eq!(
+            url,
+            "https://gitlab.example.com/api/v4/projects/42/statuses/abc"
+        );
+        assert_eq!(
+            serde_json::from_str::<Value>(body).unwrap()["state"],
+            "running"
+        );
+    }
+
+    #[test]
+    fn failure_sets_status_and_comments_on_merge_request() {
+        let event = Event {
+            exit_code: Some(1),
+            ..Event::new(EventKind::Failure, "deploy")
+        };
+        let actions = plugin().on_event(&event.to_json()).unwrap();
+        let [
+            Action::Http { body: status, .. },
+            Action::Http {
+                url,
+                body: note,
+                headers,
+                ..
+            },
+        ] = actions.as_slice()
+        else {
+            panic!("expected status and note, got {actions:?}");
+        };
+        assert_eq!(
+            serde_json::from_str::<Value>(status).unwrap()["state"],
+            "failed"
+        );
+        assert_eq!(
+            url,
+            "https://gitlab.example.com/api/v4/projects/42/merge_requests/5/notes"
+        );
+        assert_eq!(headers["PRIVATE-TOKEN"], "t");
+        let note = serde_json::from_str::<Value>(note).unwrap();
+        let note = note["body"].as_str().unwrap();
+        assert!(note.starts_with("### sentinel-rs: Failed with exit code 1"));
+        assert!(note.ends_with("[Job log](https://gitlab.example.com/o/r/-/jobs/7)\n"));
+    }
+}
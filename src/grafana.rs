@@ -0,0 +1,133 @@
+//! Grafana annotations: with `GRAFANA_URL` and `GRAFANA_TOKEN` (a service
+//! account token with annotation write access) set, every finished run is
+//! posted as a region annotation spanning the run, tagged `sentinel-rs`,
+//! the job name and the result, so runs show up as markers on dashboards.
+//! `GRAFANA_DASHBOARD_UID` pins the annotations to one dashboard instead of
+//! the whole organization.
+
+use crate::ci::{self, Outcome};
+use crate::plugin::{Action, Plugin, PluginError};
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+
+/// The Grafana plugin, if `GRAFANA_URL` is set.
+pub fn from_env() -> Result<Option<Box<dyn Plugin>>, String> {
+    let Ok(url) = std::env::var("GRAFANA_URL") else {
+        return Ok(None);
+    };
+    let token = std::env::var("GRAFANA_TOKEN")
+        .map_err(|_| "GRAFANA_URL is set but GRAFANA_TOKEN is not".to_string())?;
+    Ok(Some(Box::new(GrafanaPlugin {
+        url,
+        token,
+        dashboard_uid: std::env::var("GRAFANA_DASHBOARD_UID").ok(),
+    })))
+}
+
+struct GrafanaPlugin {
+    url: String,
+    token: String,
+    dashboard_uid: Option<String>,
+}
+
+impl GrafanaPlugin {
+    fn annotation(&self, event: &Value, now_ms: i64) -> Option<Value> {
+        let result = match ci::outcome(event)?.0 {
+            Outcome::Running => return None,
+            Outcome::Success => "success",
+            Outcome::Failure => "failure",
+            Outcome::Error => "error",
+        };
+        let job = event["job"].as_str().unwrap_or_default();
+        let mut tags = vec!["sentinel-rs".to_string(), result.to_string()];
+        if !job.is_empty() {
+            tags.insert(1, job.to_string());
+        }
+        let took_ms = (event["duration_secs"].as_f64().unwrap_or_default() * 1000.0) as i64;
+        let mut text = format!(
+            "{job} on {}: {result}",
+            event["host"].as_str().unwrap_or_default()
+        );
+        if let Some(code) = event["exit_code"].as_i64() {
+            text.push_str(&format!(" (exit code {code})"));
+        }
+        if let Some(duration) = event["duration"].as_str() {
+            text.push_str(&format!(" after {duration}"));
+        }
+        let mut annotation = json!({
+            "time": now_ms - took_ms,
+            "timeEnd": now_ms,
+            "tags": tags,
+            "text": text,
+        });
+        if let Some(uid) = &self.dashboard_uid {
+            annotation["dashboardUID"] = json!(uid);
+        }
+        Some(annotation)
+    }
+}
+
+impl Plugin for GrafanaPlugin {
+    fn name(&self) -> &str {
+        "grafana"
+    }
+
+    fn on_event(&mut self, event_json: &str) -> Result<Vec<Action>, PluginError> {
+        let event: Value = serde_json::from_str(event_json)?;
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        let Some(annotation) = self.annotation(&event, now_ms) else {
+            return Ok(Vec::new());
+        };
+        Ok(vec![Action::Http {
+            url: format!("{}/api/annotations", self.url.trim_end_matches('/')),
+            method: "POST".to_string(),
+            headers: BTreeMap::from([
+                (
+                    "Authorization".to_string(),
+                    format!("Bearer {}", self.token),
+                ),
+                ("Content-Type".to_string(), "application/json".to_string()),
+            ]),
+            body: annotation.to_string(),
+        }])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{Event, EventKind};
+
+    #[test]
+    fn finished_run_becomes_tagged_region() {
+        let plugin = GrafanaPlugin {
+            url: "http://grafana".to_string(),
+            token: "t".to_string(),
+            dashboard_uid: Some("ops".to_string()),
+        };
+        let mut event = Event {
+            exit_code: Some(2),
+            host: "db1".to_string(),
+            job: "backup".to_string(),
+            ..Event::new(EventKind::Failure, "backup --full")
+        };
+        event.set_duration(std::time::Duration::from_secs(90));
+        let event: Value = serde_json::from_str(&event.to_json()).unwrap();
+
+        let annotation = plugin.annotation(&event, 1_000_000).unwrap();
+        assert_eq!(annotation["time"], 910_000);
+        assert_eq!(annotation["timeEnd"], 1_000_000);
+        assert_eq!(
+            annotation["tags"],
+            json!(["sentinel-rs", "backup", "failure"])
+        );
+        assert_eq!(
+            annotation["text"],
+            "backup on db1: failure (exit code 2) after 1m 30s"
+        );
+        assert_eq!(annotation["dashboardUID"], "ops");
+
+        let start = serde_json::from_str(&Event::new(EventKind::Start, "x").to_json()).unwrap();
+        assert!(plugin.annotation(&start, 0).is_none());
+    }
+}
@@ -0,0 +1,128 @@
+//! What notifications call a machine. Cloud hostnames such as
+//! `ip-10-0-1-23.ec2.internal` say little, so a run can be labelled with
+//! `--host-label db-primary`, and a `host-labels` file in the config
+//! directory maps real hostnames (of this machine and of `--ssh`/`--hosts`
+//! targets) to labels, one `HOSTNAME LABEL` pair per line:
+//!
+//! ```text
+//! ip-10-0-1-23.ec2.internal  db-primary
+//! ip-10-0-2-7.ec2.internal   db-replica  # read-only
+//! ```
+
+use crate::config;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+#[derive(Debug, Default)]
+struct Labels {
+    /// This machine's label from `--host-label`.
+    own: Option<String>,
+    aliases: HashMap<String, String>,
+}
+
+static LABELS: OnceLock<Labels> = OnceLock::new();
+
+pub fn parse_label(s: &str) -> Result<String, String> {
+    let label = s.trim();
+    if label.is_empty() {
+        return Err("the host label is empty".to_string());
+    }
+    Ok(label.to_string())
+}
+
+/// Labels this machine `label` if given, and reads the `host-labels` file
+/// from the config dir (and profile). Only the first call counts.
+pub fn set(label: Option<String>) -> Result<(), String> {
+    let mut aliases = HashMap::new();
+    if let Some(path) = aliases_file()? {
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+        aliases = parse_aliases(&text).map_err(|e| format!("{}: {e}", path.display()))?;
+    }
+    LABELS
+        .set(Labels {
+            own: label,
+            aliases,
+        })
+        .ok();
+    Ok(())
+}
+
+fn aliases_file() -> Result<Option<PathBuf>, String> {
+    Ok(config::config_dirs()?
+        .iter()
+        .map(|d| d.join("host-labels"))
+        .find(|p| p.is_file()))
+}
+
+fn parse_aliases(text: &str) -> Result<HashMap<String, String>, String> {
+    let mut aliases = HashMap::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields[..] {
+            [] => {}
+            [host, label] => {
+                aliases.insert(host.to_string(), label.to_string());
+            }
+            _ => {
+                return Err(format!(
+                    "line {}: expected `HOSTNAME LABEL`, got {:?}",
+                    n + 1,
+                    line.trim()
+                ));
+            }
+        }
+    }
+    Ok(aliases)
+}
+
+/// This machine, as notifications name it.
+pub fn name() -> String {
+    let labels = LABELS.get_or_init(Labels::default);
+    if let Some(own) = &labels.own {
+        return own.clone();
+    }
+    let real = hostname::get()
+        .map(|h| h.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    label_in(labels, &real)
+}
+
+/// The label of another machine called `host`, or `host` itself.
+pub fn label_for(host: &str) -> String {
+    label_in(LABELS.get_or_init(Labels::default), host)
+}
+
+fn label_in(labels: &Labels, host: &str) -> String {
+    labels
+        .aliases
+        .get(host)
+        .cloned()
+        .unwrap_or_else(|| host.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_hostnames_to_labels() {
+        let labels = Labels {
+            own: None,
+            aliases: parse_aliases(
+                "# fleet\nip-10-0-1-23.ec2.internal  db-primary\n\n\
+                 ip-10-0-2-7.ec2.internal\tdb-replica  # read-only\n",
+            )
+            .unwrap(),
+        };
+        assert_eq!(label_in(&labels, "ip-10-0-1-23.ec2.internal"), "db-primary");
+        assert_eq!(label_in(&labels, "ip-10-0-2-7.ec2.internal"), "db-replica");
+        assert_eq!(label_in(&labels, "web1"), "web1");
+        assert_eq!(
+            parse_aliases("a b\nc d e\n").unwrap_err(),
+            "line 2: expected `HOSTNAME LABEL`, got \"c d e\""
+        );
+    }
+}
@@ -0,0 +1,102 @@
+//! The icon in front of a finish notification, so that outcomes stand out
+//! in a busy chat: ✅ for a success, ❌ for a failure (or a command that
+//! could not be started) and 💀 for a signal. `--icon failure=🔥` picks
+//! another one, `--icon success=` drops one and `--no-icons` drops them all.
+//! Templates see it as `icon`. Chosen once, at startup, for the whole
+//! process.
+
+use crate::event::EventKind;
+use std::sync::OnceLock;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Icons {
+    pub success: Option<String>,
+    pub failure: Option<String>,
+    pub signal: Option<String>,
+}
+
+impl Default for Icons {
+    fn default() -> Self {
+        Icons {
+            success: Some("✅".to_string()),
+            failure: Some("❌".to_string()),
+            signal: Some("💀".to_string()),
+        }
+    }
+}
+
+impl Icons {
+    pub fn none() -> Self {
+        Icons {
+            success: None,
+            failure: None,
+            signal: None,
+        }
+    }
+
+    /// These icons with `outcome`'s replaced by `icon` (none if empty).
+    pub fn with(mut self, (outcome, icon): &(String, String)) -> Self {
+        let icon = (!icon.is_empty()).then(|| icon.clone());
+        match outcome.as_str() {
+            "success" => self.success = icon,
+            "failure" => self.failure = icon,
+            _ => self.signal = icon,
+        }
+        self
+    }
+
+    fn for_kind(&self, kind: EventKind) -> Option<&String> {
+        match kind {
+            EventKind::Success => self.success.as_ref(),
+            EventKind::Failure | EventKind::SpawnError => self.failure.as_ref(),
+            EventKind::Signal => self.signal.as_ref(),
+            EventKind::Start | EventKind::Message | EventKind::Report => None,
+        }
+    }
+}
+
+static ICONS: OnceLock<Icons> = OnceLock::new();
+
+/// Uses `icons` from now on. Only the first call counts.
+pub fn set(icons: Icons) {
+    ICONS.set(icons).ok();
+}
+
+/// `OUTCOME=ICON`, where the outcome is `success`, `failure` or `signal`
+/// and an empty icon means none.
+pub fn parse_icon(s: &str) -> Result<(String, String), String> {
+    let (outcome, icon) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected OUTCOME=ICON, got {s:?}"))?;
+    let outcome = outcome.trim();
+    if !["success", "failure", "signal"].contains(&outcome) {
+        return Err(format!(
+            "unknown outcome {outcome:?} (expected success, failure or signal)"
+        ));
+    }
+    Ok((outcome.to_string(), icon.trim().to_string()))
+}
+
+/// The icon for a notification about `kind`, if any.
+pub fn for_kind(kind: EventKind) -> Option<String> {
+    ICONS.get_or_init(Icons::default).for_kind(kind).cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn icons_can_be_replaced_and_dropped() {
+        let icons = Icons::default()
+            .with(&parse_icon("failure=🔥").unwrap())
+            .with(&parse_icon("success=").unwrap());
+        assert_eq!(icons.for_kind(EventKind::Failure).unwrap(), "🔥");
+        assert_eq!(icons.for_kind(EventKind::SpawnError).unwrap(), "🔥");
+        assert_eq!(icons.for_kind(EventKind::Success), None);
+        assert_eq!(icons.for_kind(EventKind::Signal).unwrap(), "💀");
+        assert_eq!(icons.for_kind(EventKind::Start), None);
+        assert!(parse_icon("timeout=⏱️").is_err());
+        assert!(parse_icon("success").is_err());
+    }
+}
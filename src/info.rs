@@ -0,0 +1,170 @@
+//! `sentinel-rs info`: what this build has in it and where it looks for
+//! its configuration, for bug reports and for finding out why a setting is
+//! not taken. Unlike `doctor` it asks nothing of the network.
+
+use crate::config;
+use crate::event::EventKind;
+use std::fmt::Write;
+use std::path::Path;
+
+/// The optional features and whether this build has them.
+pub const FEATURES: &[(&str, bool)] = &[
+    ("dashboard", cfg!(feature = "dashboard")),
+    ("keyring", cfg!(feature = "keyring")),
+    ("python", cfg!(feature = "python")),
+    ("scripting", cfg!(feature = "scripting")),
+    ("wasm-plugins", cfg!(feature = "wasm-plugins")),
+];
+
+/// The backends and hooks a run picks up, each with the variables that
+/// turn it on (the first one set is shown).
+const BACKENDS: &[(&str, &[&str])] = &[
+    ("Telegram", &["TG_BOT_TOKEN", "TG_BOT_TOKEN_FILE"]),
+    ("Grafana", &["GRAFANA_URL"]),
+    (
+        "OpenTelemetry",
+        &[
+            "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT",
+            "OTEL_EXPORTER_OTLP_ENDPOINT",
+        ],
+    ),
+    ("Hook script", &["SENTINEL_SCRIPT"]),
+    ("Plugins", &["SENTINEL_PLUGINS"]),
+    ("Quiet hours", &["SENTINEL_QUIET_HOURS"]),
+    ("Repeated failures", &["SENTINEL_REPEAT_WINDOW"]),
+    ("Escalation", &["SENTINEL_ESCALATE_AFTER"]),
+    ("Maintenance", &["SENTINEL_MAINTENANCE"]),
+    ("Mute rules", &["SENTINEL_MUTE"]),
+];
+
+/// The files looked for in each config dir besides the templates.
+const FILES: &[&str] = &[config::SETTINGS_FILE, "host-labels"];
+
+/// Where `key` is set: in the environment, which wins, or in the settings
+/// file.
+fn origin(key: &str, settings: &[(String, String)]) -> Option<&'static str> {
+    if config::env_required(key).is_ok() {
+        Some("environment")
+    } else if settings
+        .iter()
+        .any(|(k, v)| k == key && !v.trim().is_empty())
+    {
+        Some("settings file")
+    } else {
+        None
+    }
+}
+
+/// What is in `dir`, one line per file found, and a line saying so if none is.
+fn files_in(dir: &Path, out: &mut String) {
+    let mut found = false;
+    for name in FILES {
+        let path = dir.join(name);
+        if !path.is_file() {
+            continue;
+        }
+        found = true;
+        let _ = write!(out, "  {}", path.display());
+        if *name == config::SETTINGS_FILE {
+            let text = std::fs::read_to_string(&path).unwrap_or_default();
+            let keys: Vec<String> = match config::parse_settings(&text) {
+                Ok(file) => file
+                    .into_iter()
+                    .map(|(key, _)| match config::env_required(&key) {
+                        Ok(_) => format!("{key} (the environment's wins)"),
+                        Err(_) => key,
+                    })
+                    .collect(),
+                Err(e) => vec![format!("unreadable: {e}")],
+            };
+            let _ = write!(out, ": {}", keys.join(", "));
+        }
+        out.push('\n');
+    }
+    for kind in EventKind::ALL {
+        let path = dir.join(format!("{}.tmpl", kind.name()));
+        if path.is_file() {
+            found = true;
+            let _ = writeln!(out, "  {}", path.display());
+        }
+    }
+    if !found {
+        let _ = writeln!(out, "  {}: nothing there", dir.display());
+    }
+}
+
+/// The report `sentinel-rs info` prints. `settings` are those of the
+/// settings files, which the caller has not put in the environment yet, so
+/// that where each variable comes from can be told.
+pub fn report(settings: &[(String, String)]) -> String {
+    let mut out = format!("sentinel-rs {}\n\nFeatures:\n", env!("CARGO_PKG_VERSION"));
+    for (name, built) in FEATURES {
+        let _ = writeln!(out, "  {name:<14} {}", if *built { "yes" } else { "no" });
+    }
+
+    out.push_str("\nBackends and hooks:\n");
+    for (name, keys) in BACKENDS {
+        let from = keys
+            .iter()
+            .find_map(|key| Some(format!("{key}, from the {}", origin(key, settings)?)))
+            .unwrap_or_else(|| format!("not set ({})", keys.join(", ")));
+        let _ = writeln!(out, "  {name:<14} {from}");
+    }
+
+    match config::profile() {
+        Ok(Some(profile)) => {
+            let _ = write!(out, "\nConfiguration (profile {profile}, which wins):\n");
+        }
+        Ok(None) => out.push_str("\nConfiguration:\n"),
+        Err(e) => {
+            let _ = write!(out, "\nConfiguration: {e}\n");
+        }
+    }
+    match config::config_dirs() {
+        Ok(dirs) if dirs.is_empty() => out.push_str("  no config dir (HOME is not set)\n"),
+        Ok(dirs) => dirs.iter().for_each(|dir| files_in(dir, &mut out)),
+        Err(_) => {}
+    }
+    let _ = write!(
+        out,
+        "\nState: {}\n",
+        config::state_dir().map_or("none (HOME is not set)".to_string(), |dir| dir
+            .display()
+            .to_string())
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_files_found_are_listed_with_their_settings() {
+        let dir = std::env::temp_dir().join(format!("sentinel-info-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut out = String::new();
+        files_in(&dir, &mut out);
+        assert_eq!(out, format!("  {}: nothing there\n", dir.display()));
+
+        std::fs::write(dir.join("env"), "SENTINEL_INFO_TEST_UNSET=1\nPATH=/bin\n").unwrap();
+        std::fs::write(dir.join("failure.tmpl"), "{{command}} failed").unwrap();
+        let mut out = String::new();
+        files_in(&dir, &mut out);
+        assert_eq!(
+            out,
+            format!(
+                "  {0}/env: SENTINEL_INFO_TEST_UNSET, PATH (the environment's wins)\n  {0}/failure.tmpl\n",
+                dir.display()
+            )
+        );
+        std::fs::remove_dir_all(&dir).ok();
+
+        let settings = [("SENTINEL_INFO_TEST_UNSET".to_string(), "1".to_string())];
+        assert_eq!(
+            origin("SENTINEL_INFO_TEST_UNSET", &settings),
+            Some("settings file")
+        );
+        assert_eq!(origin("SENTINEL_INFO_TEST_UNSET", &[]), None);
+    }
+}
@@ -0,0 +1,198 @@
+//! `sentinel-rs init`: writes the [settings file](crate::config::SETTINGS_FILE)
+//! a first run needs. The questions are asked by the binary; this renders
+//! the answers as a commented file, with every setting it knows about listed
+//! and those not given left commented out, so that the file also says what
+//! else can go in it.
+
+use crate::config;
+use std::path::{Path, PathBuf};
+
+/// The settings `init` knows about, in sections, each with an example
+/// value for when it is left commented out.
+const SECTIONS: &[(&str, &[(&str, &str)])] = &[
+    (
+        "Telegram: the token @BotFather gave the bot, and the chat it sends to.",
+        &[
+            ("TG_BOT_TOKEN", ""),
+            ("TG_CHAT_ID", ""),
+            ("TG_API_BASE", config::DEFAULT_API_BASE),
+        ],
+    ),
+    (
+        "Messages too long for Telegram: truncate, split or document.",
+        &[("SENTINEL_TELEGRAM_OVERFLOW", "truncate")],
+    ),
+    (
+        "A proxy for notifications (default: HTTPS_PROXY and friends).",
+        &[("SENTINEL_PROXY", "http://proxy.example.com:3128")],
+    ),
+    (
+        "Grafana: an annotation for every finished run.",
+        &[
+            ("GRAFANA_URL", "https://grafana.example.com"),
+            ("GRAFANA_TOKEN", ""),
+            ("GRAFANA_DASHBOARD_UID", ""),
+        ],
+    ),
+    (
+        "OpenTelemetry: a trace for every run, over OTLP/HTTP.",
+        &[
+            ("OTEL_EXPORTER_OTLP_ENDPOINT", "http://otel-collector:4318"),
+            ("OTEL_SERVICE_NAME", "sentinel-rs"),
+        ],
+    ),
+];
+
+/// Where `init` writes: the selected profile's directory if there is one,
+/// else the config dir.
+pub fn path() -> Result<PathBuf, String> {
+    let dir = config::config_dirs()?
+        .into_iter()
+        .next()
+        .ok_or("no config directory (set SENTINEL_CONFIG_DIR or HOME)")?;
+    Ok(dir.join(config::SETTINGS_FILE))
+}
+
+fn quote(value: &str) -> String {
+    if !value.contains([' ', '\t', '#', '"', '\'']) {
+        value.to_string()
+    } else if value.contains('"') {
+        format!("'{value}'")
+    } else {
+        format!("\"{value}\"")
+    }
+}
+
+/// The settings file holding `values`.
+pub fn render(values: &[(&str, String)]) -> String {
+    let mut text = String::from(
+        "# sentinel-rs settings, written by `sentinel-rs init`.\n\
+         # KEY=VALUE lines; the same variable set in the environment wins.\n\
+         # Remove the # in front of a setting to use it.\n",
+    );
+    for (about, keys) in SECTIONS {
+        text.push_str(&format!("\n# {about}\n"));
+        for (key, example) in *keys {
+            match values.iter().find(|(k, _)| k == key) {
+                Some((_, value)) => text.push_str(&format!("{key}={}\n", quote(value))),
+                None => text.push_str(&format!("# {key}={}\n", quote(example))),
+            }
+        }
+    }
+    text
+}
+
+/// Writes `text` to `path`, readable by the owner only since it holds the
+/// bot token. An existing file is only replaced if `force`.
+pub fn write(path: &Path, text: &str, force: bool) -> Result<(), String> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("cannot create {}: {e}", dir.display()))?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(!force)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::AlreadyExists => {
+                format!("{} exists already; --force replaces it", path.display())
+            }
+            _ => format!("cannot write {}: {e}", path.display()),
+        })?;
+    file.write_all(text.as_bytes())
+        .map_err(|e| format!("cannot write {}: {e}", path.display()))
+}
+
+/// `text` with `values` set: each replaces the line setting its key, or
+/// the commented-out one, else it is added at the end.
+pub fn set(text: &str, values: &[(&str, String)]) -> String {
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+    for (key, value) in values {
+        let line = format!("{key}={}", quote(value));
+        let sets = |line: &str| {
+            let line = line.trim();
+            line.strip_prefix("export ")
+                .unwrap_or(line)
+                .starts_with(&format!("{key}="))
+        };
+        let at = lines.iter().position(|l| sets(l)).or_else(|| {
+            lines
+                .iter()
+                .position(|l| l.trim().strip_prefix('#').is_some_and(sets))
+        });
+        match at {
+            Some(at) => lines[at] = line,
+            None => lines.push(line),
+        }
+    }
+    lines.iter().map(|line| format!("{line}\n")).collect()
+}
+
+/// Sets `values` in the settings file at `path`, starting a new one if
+/// there is none.
+pub fn update(path: &Path, values: &[(&str, String)]) -> Result<(), String> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => set(&text, values),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => render(values),
+        Err(e) => return Err(format!("cannot read {}: {e}", path.display())),
+    };
+    write(path, &text, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rendered_file_reads_back_as_the_answers() {
+        let text = render(&[
+            ("TG_BOT_TOKEN", "123:abc".to_string()),
+            ("TG_CHAT_ID", "-100".to_string()),
+            ("OTEL_SERVICE_NAME", "night jobs".to_string()),
+        ]);
+        assert!(text.contains("\n# TG_API_BASE=https://api.telegram.org/\n"));
+        assert!(text.contains("\n# GRAFANA_TOKEN=\n"));
+        assert_eq!(
+            config::parse_settings(&text).unwrap(),
+            [
+                ("TG_BOT_TOKEN".to_string(), "123:abc".to_string()),
+                ("TG_CHAT_ID".to_string(), "-100".to_string()),
+                ("OTEL_SERVICE_NAME".to_string(), "night jobs".to_string()),
+            ]
+        );
+
+        let path = std::env::temp_dir()
+            .join(format!("sentinel-init-{}", std::process::id()))
+            .join("env");
+        write(&path, &text, false).unwrap();
+        assert!(write(&path, "", false).unwrap_err().contains("--force"));
+        write(&path, "", true).unwrap();
+        use std::os::unix::fs::PermissionsExt;
+        let meta = std::fs::metadata(&path).unwrap();
+        assert_eq!(meta.permissions().mode() & 0o777, 0o600);
+        assert_eq!(meta.len(), 0);
+        std::fs::remove_dir_all(path.parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn setting_replaces_the_line_or_the_commented_one() {
+        let text = "export TG_BOT_TOKEN=old\n# TG_CHAT_ID=\nSENTINEL_JOB=x\n";
+        assert_eq!(
+            set(
+                text,
+                &[
+                    ("TG_CHAT_ID", "42".to_string()),
+                    ("TG_BOT_TOKEN", "new".to_string()),
+                    ("TG_API_BASE", "http://localhost:8081".to_string()),
+                ]
+            ),
+            "TG_BOT_TOKEN=new\nTG_CHAT_ID=42\nSENTINEL_JOB=x\nTG_API_BASE=http://localhost:8081\n"
+        );
+    }
+}
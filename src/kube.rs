@@ -0,0 +1,148 @@
+//! `--k8s`: run the command as a Kubernetes Job instead of a local process.
+//!
+//! Everything goes through `kubectl` (and so through the current kubeconfig
+//! context): a small bash driver creates the Job, waits for its pod to start,
+//! follows its logs, exits with the container's exit code and deletes the Job
+//! on the way out. Running the driver like any other command means capture,
+//! tee, hooks and notifications work exactly as they do for local runs.
+//! Pod logs interleave the container's stdout and stderr, so both arrive on
+//! stdout.
+
+use crate::runner::shell_quote;
+use serde_json::json;
+
+/// Exit code of the driver when the Job could not be created or its pod
+/// never started (as opposed to the container exiting non-zero).
+pub const SETUP_FAILED: i32 = 125;
+
+/// How long to wait for the pod to leave `Pending`, in seconds.
+const START_TIMEOUT_SECS: u32 = 600;
+
+#[derive(Clone, Debug)]
+pub struct KubeJob {
+    pub image: String,
+    pub namespace: Option<String>,
+    /// Name of the Job object, unique per run.
+    pub name: String,
+}
+
+impl KubeJob {
+    pub fn new(image: &str, namespace: Option<String>) -> Self {
+        KubeJob {
+            image: image.to_string(),
+            namespace,
+            name: crate::runner::run_name(),
+        }
+    }
+
+    /// Host label for notifications, e.g. `batch/job/sentinel-rs-…`.
+    pub fn label(&self) -> String {
+        match &self.namespace {
+            Some(ns) => format!("{ns}/job/{}", self.name),
+            None => format!("job/{}", self.name),
+        }
+    }
+
+    /// A single-attempt Job running `command` with `sh -c` in the image.
+    pub fn manifest(&self, command: &str) -> serde_json::Value {
+        json!({
+            "apiVersion": "batch/v1",
+            "kind": "Job",
+            "metadata": {
+                "name": self.name,
+                "labels": {"app.kubernetes.io/managed-by": "sentinel-rs"},
+            },
+            "spec": {
+                "backoffLimit": 0,
+                // In case the driver is killed before it can clean up.
+                "ttlSecondsAfterFinished": 3600,
+                "template": {
+                    "spec": {
+                        "restartPolicy": "Never",
+                        "containers": [{
+                            "name": "main",
+                            "image": self.image,
+                            "command": ["sh", "-c", command],
+                        }],
+                    },
+                },
+            },
+        })
+    }
+
+    /// The bash script that runs `command` as this Job.
+    pub fn driver(&self, command: &str) -> String {
+        let namespace = self
+            .namespace
+            .as_deref()
+            .map(|ns| format!(" --namespace {}", shell_quote(ns)))
+            .unwrap_or_default();
+        format!(
+            r#"kc() {{ kubectl{namespace} "$@"; }}
+job={job}
+pods() {{ kc get pods -l job-name="$job" -o "jsonpath={{.items[0].$1}}" 2>/dev/null; }}
+printf '%s' {manifest} | kc create -f - >/dev/null || exit {SETUP_FAILED}
+trap 'kc delete job "$job" --ignore-not-found --wait=false >/dev/null 2>&1' EXIT
+trap 'exit 130' INT
+trap 'exit 143' TERM
+phase=
+for _ in $(seq {START_TIMEOUT_SECS}); do
+  phase=$(pods status.phase)
+  [ -n "$phase" ] && [ "$phase" != Pending ] && break
+  sleep 1
+done
+if [ -z "$phase" ] || [ "$phase" = Pending ]; then
+  echo "sentinel-rs: pod for job $job did not start" >&2
+  exit {SETUP_FAILED}
+fi
+kc logs -f "job/$job"
+for _ in $(seq 60); do
+  code=$(pods 'status.containerStatuses[0].state.terminated.exitCode')
+  [ -n "$code" ] && exit "$code"
+  sleep 1
+done
+echo "sentinel-rs: could not read the exit code of job $job" >&2
+exit {SETUP_FAILED}
+"#,
+            job = shell_quote(&self.name),
+            manifest = shell_quote(&self.manifest(command).to_string()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job() -> KubeJob {
+        KubeJob {
+            image: "alpine:3".to_string(),
+            namespace: Some("batch".to_string()),
+            name: "sentinel-rs-1-2".to_string(),
+        }
+    }
+
+    #[test]
+    fn manifest_runs_command_once_in_image() {
+        let manifest = job().manifest("echo 'hi' && exit 3");
+        assert_eq!(manifest["metadata"]["name"], "sentinel-rs-1-2");
+        assert_eq!(manifest["spec"]["backoffLimit"], 0);
+        let pod = &manifest["spec"]["template"]["spec"];
+        assert_eq!(pod["restartPolicy"], "Never");
+        assert_eq!(pod["containers"][0]["image"], "alpine:3");
+        assert_eq!(
+            pod["containers"][0]["command"],
+            json!(["sh", "-c", "echo 'hi' && exit 3"])
+        );
+    }
+
+    #[test]
+    fn label_includes_namespace() {
+        assert_eq!(job().label(), "batch/job/sentinel-rs-1-2");
+        let job = KubeJob {
+            namespace: None,
+            ..job()
+        };
+        assert_eq!(job.label(), "job/sentinel-rs-1-2");
+    }
+}
@@ -0,0 +1,671 @@
+//! Library half of sentinel-rs: the runner, the notifier and the glue that
+//! wraps a command with start/finish notifications. The `sentinel-rs` binary
+//! and the optional Python bindings are thin layers over [`run_and_notify`].
+
+pub mod artifact;
+pub mod auth;
+pub mod canary;
+pub mod cgroup;
+pub mod check;
+pub mod ci;
+pub mod clock;
+pub mod cloud;
+pub mod condition;
+pub mod config;
+pub mod control;
+pub mod coredump;
+pub mod crash;
+pub mod criteria;
+pub mod dashboard;
+pub mod docker;
+pub mod doctor;
+pub mod echo;
+pub mod environ;
+pub mod escalate;
+pub mod event;
+pub mod exitcode;
+pub mod fanout;
+pub mod fit;
+pub mod git;
+pub mod github;
+pub mod gitlab;
+pub mod grafana;
+pub mod host;
+pub mod icon;
+pub mod info;
+pub mod init;
+pub mod kube;
+pub mod locale;
+pub mod logging;
+pub mod maintenance;
+pub mod metrics;
+pub mod mute;
+pub mod notifier;
+pub mod oom;
+pub mod origin;
+pub mod otel;
+pub mod parse;
+pub mod pipeline;
+pub mod plugin;
+#[cfg(feature = "python")]
+mod python;
+pub mod quiet;
+pub mod repeat;
+pub mod runner;
+pub mod sandbox;
+pub mod sched;
+pub mod script;
+pub mod secrets;
+pub mod setup;
+pub mod severity;
+pub mod shell;
+pub mod status;
+pub mod steps;
+pub mod tee;
+pub mod telegram;
+pub mod template;
+pub mod ulimit;
+pub mod upload;
+pub mod user;
+pub mod validate;
+pub mod workflow;
+
+use config::TgConfig;
+use event::{Event, EventKind, Severity};
+use notifier::{Reporter, start_notifier};
+use plugin::Plugin;
+use runner::{Exec, OnLine, Stream, run_bash, tail_bytes};
+use std::process::Output;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use template::Templates;
+use tracing::info;
+
+/// A mid-run notification raised by a [`LineHook`].
+pub struct LineNotice {
+    pub text: String,
+    pub severity: Option<Severity>,
+}
+
+/// Sees every line of output while the command runs.
+pub trait LineHook: Send + Sync {
+    fn on_line(&self, stream: Stream, line: &str) -> Option<LineNotice>;
+}
+
+pub struct RunOptions {
+    /// Mirror the child's stdout/stderr to our own while capturing it.
+    pub tee: bool,
+    pub exec: Exec,
+    /// Filters/backends every event passes through before delivery.
+    pub plugins: Vec<Box<dyn Plugin>>,
+    /// Each sees every line; any notices they raise are sent as messages.
+    pub line_hooks: Vec<Arc<dyn LineHook>>,
+    pub templates: Arc<Templates>,
+    /// Limits for a local command, enforced with a cgroup made for the run.
+    pub limits: Option<cgroup::Limits>,
+    /// The exit codes that count as a success, and what the output must (not)
+    /// contain for them to.
+    pub criteria: criteria::Criteria,
+    /// Boils the output down for the finish notification (`--parser`).
+    pub parser: Option<Box<dyn parse::OutputParser>>,
+    /// Run on this machine before the command (`--pre`); the command is only
+    /// run if this succeeds.
+    pub pre: Option<String>,
+    /// Run on this machine after the command (`--post`), with its exit code
+    /// in `SENTINEL_EXIT_CODE`.
+    pub post: Option<String>,
+    /// Run on this machine, before `post`, if the command failed
+    /// (`--on-failure`), with its exit code, the run id and a file holding
+    /// all of its output in `SENTINEL_EXIT_CODE`, `SENTINEL_RUN_ID` and
+    /// `SENTINEL_LOG`.
+    pub on_failure: Option<String>,
+    /// What sentinel-rs exits with if the command cannot be started
+    /// (`--exit-on-spawn-error`), and so what `on_failure` and `post` are
+    /// given then.
+    pub spawn_error_code: i32,
+    /// If any, a successful run is only notified about when one of these
+    /// holds for its JSON output (`--notify-if`), and there is no start
+    /// notification.
+    pub notify_if: Vec<condition::Condition>,
+    /// Set the severity of the finish notification from what the run did
+    /// (`--severity`, `SENTINEL_SEVERITY_RULES`).
+    pub severity_rules: Vec<severity::Rule>,
+    /// Glob patterns for the files the run should leave behind
+    /// (`--artifact`), listed in the finish notification.
+    pub artifacts: Vec<String>,
+    /// Files on this machine to send to the chat after the finish
+    /// notification (`--attach`), e.g. the run's own log.
+    pub attach: Vec<std::path::PathBuf>,
+    /// The environment variables to show in the start notification
+    /// (`--show-env`): names, or prefixes ending in `*`.
+    pub show_env: Vec<String>,
+    /// Name the branch and commit of the repository the run is in (`--git`).
+    pub git: bool,
+    /// Name the cloud instance the run is on in the start notification
+    /// (`--cloud`).
+    pub cloud: bool,
+    /// Say who started the run in the start notification (`--origin`).
+    pub origin: bool,
+    /// Take requests about the run over a Unix socket while it runs
+    /// (`--control`).
+    pub control: bool,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        RunOptions {
+            tee: true,
+            exec: Exec::default(),
+            plugins: Vec::new(),
+            line_hooks: Vec::new(),
+            templates: Arc::new(Templates::builtin()),
+            limits: None,
+            criteria: criteria::Criteria::default(),
+            parser: None,
+            pre: None,
+            post: None,
+            on_failure: None,
+            spawn_error_code: 125,
+            notify_if: Vec::new(),
+            severity_rules: Vec::new(),
+            artifacts: Vec::new(),
+            attach: Vec::new(),
+            show_env: Vec::new(),
+            git: false,
+            cloud: false,
+            origin: false,
+            control: false,
+        }
+    }
+}
+
+impl RunOptions {
+    /// Default options plus the templates (`SENTINEL_TEMPLATE_*`), hook
+    /// script (`SENTINEL_SCRIPT`), plugins (`SENTINEL_PLUGINS`) and built-in
+    /// backends (e.g. `GRAFANA_URL`, `OTEL_EXPORTER_OTLP_ENDPOINT`) configured
+    /// in the environment, the run history for the [dashboard](dashboard) if
+    /// it is built in, the [repeated failures](repeat) window and [quiet
+    /// hours](quiet) if there are any.
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let mut opts = RunOptions {
+            plugins: plugin::load_plugins()?,
+            templates: Arc::new(Templates::from_env()?),
+            severity_rules: severity::from_env()?,
+            ..RunOptions::default()
+        };
+        if let Some(script) = script::load_script()? {
+            // Script hooks run before WASM plugins so those see the script's edits.
+            opts.plugins.insert(0, script.plugin);
+            opts.line_hooks.extend(script.line_hook);
+        }
+        opts.plugins.extend(grafana::from_env()?);
+        opts.plugins.extend(otel::from_env()?);
+        opts.plugins.extend(dashboard::recorder());
+        opts.plugins.extend(repeat::from_env()?);
+        // Last, so that what it holds is what would have been sent.
+        opts.plugins.extend(quiet::from_env()?);
+        Ok(opts)
+    }
+}
+
+/// Exit code to hand back to the caller for a finished child: its own, or
+/// 128+N if signal N killed it, as a shell would report.
+pub fn exit_code(output: &Output) -> i32 {
+    use std::os::unix::process::ExitStatusExt;
+    output
+        .status
+        .code()
+        .or_else(|| output.status.signal().map(|signal| 128 + signal))
+        .unwrap_or(128)
+}
+
+/// Sends `event` through the configured backends as a run would, and
+/// waits for it to be delivered (or given up on).
+pub fn notify(event: Event, cfg: TgConfig, opts: RunOptions) {
+    let (tx, notifier) = start_notifier(cfg, opts.plugins);
+    Reporter::new(tx, opts.templates).send(event);
+    notifier.shutdown();
+}
+
+/// Runs a `--pre` or `--post` command on this machine. Returns its output,
+/// unless it could not be started, and what went wrong if it failed.
+fn run_hook(
+    flag: &str,
+    command: &str,
+    env: Vec<(String, String)>,
+    tee: bool,
+) -> (Option<Output>, Option<String>) {
+    let exec = Exec {
+        env,
+        ..Exec::default()
+    };
+    match run_bash(command, &exec, tee, None).map(|run| run.output) {
+        Ok(output) if output.status.success() => (Some(output), None),
+        Ok(output) => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let code = exit_code(&output);
+            let failure = match stderr.lines().map(str::trim).rfind(|l| !l.is_empty()) {
+                Some(line) => format!("{flag} `{command}` exited {code}: {line}"),
+                None => format!("{flag} `{command}` exited {code}"),
+            };
+            (Some(output), Some(failure))
+        }
+        Err(e) => (
+            None,
+            Some(format!("{flag} `{command}` could not be run: {e}")),
+        ),
+    }
+}
+
+/// Writes what `output` captured, stdout then stderr, to `path` for an
+/// `--on-failure` command, readable only by us, when the command did not
+/// get to write it as it ran.
+fn write_log(path: &std::path::Path, output: Option<&Output>) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    if let Some(output) = output {
+        file.write_all(&output.stdout)?;
+        file.write_all(&output.stderr)?;
+    }
+    Ok(())
+}
+
+/// Runs `command` via `bash -c`, sending a start notification and a finish
+/// notification carrying the tail of its output. A failure to spawn is
+/// reported to Telegram before being returned. The run is judged by the
+/// [`criteria`](RunOptions::criteria) and returned with exit code 0 or 1
+/// when they overrule its own: an accepted exit code, or output that fails
+/// it. A failed [`pre`](RunOptions::pre) command stands in for the run; a
+/// failed [`post`](RunOptions::post) or
+/// [`on_failure`](RunOptions::on_failure) one is noted in the finish
+/// notification.
+pub fn run_and_notify(command: &str, cfg: TgConfig, opts: RunOptions) -> std::io::Result<Output> {
+    let run_id = runner::run_name();
+    let span = tracing::info_span!(
+        "run",
+        run_id = run_id.as_str(),
+        job = Event::new(EventKind::Start, command).job,
+    );
+    let _span = span.enter();
+    status::started(&run_id, command);
+    let listening = opts
+        .control
+        .then(|| match control::serve(&run_id) {
+            Ok(listening) => {
+                info!("Taking requests at {}", listening.path().display());
+                Some(listening)
+            }
+            Err(e) => {
+                tracing::warn!("Failed to set up the control socket: {e}");
+                None
+            }
+        })
+        .flatten();
+    let attach = (!opts.attach.is_empty()).then(|| (cfg.clone(), opts.attach));
+    let (tx, notifier) = start_notifier(cfg, opts.plugins);
+    let reporter = Reporter::new(tx, opts.templates);
+    let remote = opts.exec.remote_host();
+    let run_as = opts.exec.run_as.as_ref().map(|r| r.user.clone());
+    let tag = move |event: &mut Event| {
+        if let Some(remote) = &remote {
+            event.set_remote_host(remote);
+        }
+        if let Some(user) = &run_as {
+            event.user = user.clone();
+        }
+    };
+    let send = |mut event: Event| {
+        tag(&mut event);
+        reporter.send(event);
+    };
+    let started = Instant::now();
+    let started_at = std::time::SystemTime::now();
+    let _running = metrics::METRICS.run_started();
+    let mut settings = opts.limits.map(|l| l.describe()).unwrap_or_default();
+    settings.extend(opts.exec.ulimits.iter().map(|u| format!("ulimit {u}")));
+    settings.extend(opts.exec.sched.describe());
+    let git = opts
+        .git
+        .then(|| git::describe(std::path::Path::new(".")))
+        .flatten();
+    // With `--notify-if`, only what the output says is news.
+    if opts.notify_if.is_empty() {
+        send(Event {
+            settings: (!settings.is_empty()).then(|| settings.join(", ")),
+            origin: opts.origin.then(origin::current),
+            instance: opts.cloud.then(cloud::detect).flatten(),
+            git: git.clone(),
+            environment: (!opts.show_env.is_empty())
+                .then(|| environ::snapshot(&opts.show_env, &opts.exec)),
+            ..Event::new(EventKind::Start, command)
+        });
+    }
+    if let Some(pre) = &opts.pre
+        && let (output, Some(failure)) = run_hook("--pre", pre, Vec::new(), opts.tee)
+    {
+        info!("{failure}; not running the command");
+        let hook_failure = Some(format!("{failure}; the command was not run"));
+        let result = match output {
+            Some(output) => {
+                let mut event = Event {
+                    exit_code: Some(exit_code(&output)),
+                    hook_failure,
+                    stdout: Some(tail_bytes(&output.stdout, 1500)),
+                    stderr: Some(tail_bytes(&output.stderr, 1500)),
+                    ..Event::new(EventKind::Failure, command)
+                };
+                event.set_duration(started.elapsed());
+                send(event);
+                Ok(output)
+            }
+            None => {
+                send(Event {
+                    error: Some(failure.clone()),
+                    ..Event::new(EventKind::SpawnError, command)
+                });
+                Err(std::io::Error::other(failure))
+            }
+        };
+        drop(reporter);
+        notifier.shutdown();
+        return result;
+    }
+    // `--on-failure` if the command failed, then `--post`; what failed of
+    // them, for the finish notification.
+    // What `--on-failure` gets to read: all of the output, not only the
+    // tails kept in memory.
+    let log = opts
+        .on_failure
+        .as_ref()
+        .map(|_| std::env::temp_dir().join(format!("{run_id}.log")));
+    let after = |code: i32, failed: bool, output: Option<&Output>| {
+        let exit = ("SENTINEL_EXIT_CODE".to_string(), code.to_string());
+        let mut failures = Vec::new();
+        if let Some(handler) = opts.on_failure.as_deref().filter(|_| failed)
+            && let Some(log) = &log
+        {
+            // Written as the command ran, unless it could not be.
+            if !log.is_file()
+                && let Err(e) = write_log(log, output)
+            {
+                tracing::warn!("Failed to write the output to {}: {e}", log.display());
+            }
+            let env = vec![
+                exit.clone(),
+                ("SENTINEL_RUN_ID".to_string(), run_id.clone()),
+                ("SENTINEL_LOG".to_string(), log.display().to_string()),
+            ];
+            failures.extend(run_hook("--on-failure", handler, env, opts.tee).1);
+        }
+        if let Some(log) = &log {
+            std::fs::remove_file(log).ok();
+        }
+        if let Some(post) = opts.post.as_deref() {
+            failures.extend(run_hook("--post", post, vec![exit], opts.tee).1);
+        }
+        for failure in &failures {
+            tracing::warn!("{failure}");
+        }
+        (!failures.is_empty()).then(|| failures.join("\n"))
+    };
+
+    let hooks = opts.line_hooks;
+    let watch = opts
+        .criteria
+        .checks_output()
+        .then(|| Arc::new(opts.criteria.watch()));
+    let parser = opts.parser.map(|parser| Arc::new(Mutex::new(parser)));
+    let track = status::enabled();
+    let on_line = (!hooks.is_empty() || watch.is_some() || parser.is_some() || track).then(|| {
+        let reporter = reporter.clone();
+        let command = command.to_string();
+        let tag = tag.clone();
+        let watch = watch.clone();
+        let parser = parser.clone();
+        Arc::new(move |stream, line: &str| {
+            if track {
+                status::line(line);
+            }
+            if let Some(watch) = &watch {
+                watch.on_line(line);
+            }
+            let parsed = parser.as_ref().and_then(|parser| {
+                let mut parser = parser.lock().unwrap_or_else(|e| e.into_inner());
+                parser.on_line(stream, line)
+            });
+            let notices = hooks.iter().filter_map(|hook| hook.on_line(stream, line));
+            for notice in parsed.into_iter().chain(notices) {
+                let mut event = Event::message(&command, &notice.text);
+                tag(&mut event);
+                if let Some(severity) = notice.severity {
+                    event.severity = severity;
+                }
+                reporter.send(event);
+            }
+        }) as OnLine
+    });
+
+    let sampler = opts
+        .exec
+        .docker
+        .as_ref()
+        .map(|run| docker::StatsSampler::start(&run.name));
+    let mut exec = opts.exec;
+    exec.log = log.clone();
+    exec.control = listening.is_some();
+    let cgroup = opts.limits.as_ref().map(cgroup::Cgroup::create).transpose();
+    let result = match &cgroup {
+        Ok(cgroup) => {
+            exec.cgroup = cgroup.as_ref().map(|c| c.path().to_path_buf());
+            run_bash(command, &exec, opts.tee, on_line)
+        }
+        Err(e) => Err(std::io::Error::new(
+            e.kind(),
+            format!("Failed to set up resource limits: {e}"),
+        )),
+    };
+    let resources = match cgroup {
+        Ok(Some(cgroup)) => Some(cgroup.finish(started.elapsed())),
+        _ => sampler.and_then(docker::StatsSampler::finish),
+    };
+    status::finished(
+        result
+            .as_ref()
+            .ok()
+            .and_then(|run| run.output.status.code()),
+    );
+    let runner::Run {
+        output,
+        pid,
+        failed_stage,
+        merged,
+    } = match result {
+        Ok(run) => run,
+        Err(e) => {
+            send(Event {
+                error: Some(e.to_string()),
+                hook_failure: after(opts.spawn_error_code, true, None),
+                ..Event::new(EventKind::SpawnError, command)
+            });
+            info!("Failed to execute command: {e}");
+            drop(reporter);
+            notifier.shutdown();
+            return Err(e);
+        }
+    };
+
+    let ok = output.status.code().is_some_and(|c| opts.criteria.is_ok(c));
+    // An accepted exit code can still be a failure by the output.
+    let failure_reason = match &watch {
+        Some(watch) if ok => watch.verdict(),
+        _ => None,
+    };
+    let merged_output = merged.map(|text| tail_bytes(text.as_bytes(), 3000));
+    let (signal, core_dumped) = {
+        use std::os::unix::process::ExitStatusExt;
+        (output.status.signal(), output.status.core_dumped())
+    };
+    // Only a local command's core is on this machine.
+    let local = exec.remote_host().is_none();
+    let (output_summary, parsed) = match parser {
+        Some(parser) => {
+            let mut parser = parser.lock().unwrap_or_else(|e| e.into_inner());
+            let notice = parser.finish(&parse::Finished {
+                succeeded: ok && failure_reason.is_none(),
+                elapsed: started.elapsed(),
+                local,
+            });
+            (parser.summary(), notice)
+        }
+        None => (None, None),
+    };
+    let core_file = (core_dumped && local).then(|| {
+        let (uid, gid) = match &exec.run_as {
+            Some(run_as) => (run_as.uid, run_as.gid),
+            None => unsafe { (libc::geteuid(), libc::getegid()) },
+        };
+        coredump::locate(&coredump::Crash {
+            pid: Some(pid),
+            signal: signal.unwrap_or_default(),
+            uid,
+            gid,
+            cwd: &std::env::current_dir().unwrap_or_default(),
+            since: started_at,
+        })
+    });
+    let oom = if signal == Some(libc::SIGKILL) && local {
+        oom::check(Some(pid), resources.as_ref())
+    } else {
+        None
+    };
+    let signal = signal.map(runner::signal_name);
+    // Before `--post`, which may take them away.
+    let artifacts =
+        (!opts.artifacts.is_empty()).then(|| artifact::collect(&opts.artifacts, started_at));
+    let hook_failure = after(
+        exit_code(&output),
+        !ok || failure_reason.is_some(),
+        Some(&output),
+    );
+    let finish = |kind: EventKind| {
+        let mut event = Event {
+            exit_code: output.status.code(),
+            exit_meaning: output
+                .status
+                .code()
+                .filter(|_| kind == EventKind::Failure)
+                .and_then(|code| exitcode::explain(command, code, !local)),
+            failed_stage: failed_stage.clone(),
+            failure_reason: failure_reason.clone(),
+            signal: signal.clone(),
+            core_dumped,
+            core_file: core_file.clone().flatten(),
+            oom_killed: oom.is_some(),
+            peak_rss: oom
+                .as_ref()
+                .and_then(|kill| kill.peak_rss)
+                .map(event::format_bytes),
+            output_summary: output_summary.clone(),
+            stdout: Some(tail_bytes(&output.stdout, 1500)),
+            stderr: Some(tail_bytes(&output.stderr, 1500)),
+            output: merged_output.clone(),
+            resources: resources.clone(),
+            hook_failure: hook_failure.clone(),
+            artifacts: artifacts.clone(),
+            git: git.clone(),
+            ..Event::new(kind, command)
+        };
+        let missing = artifacts.as_ref().is_some_and(|a| !a.missing.is_empty());
+        if event.hook_failure.is_some() || missing {
+            event.severity = event.severity.max(Severity::Warning);
+        }
+        event.set_duration(started.elapsed());
+        severity::assign(&opts.severity_rules, &mut event);
+        event
+    };
+    let mut notified = true;
+    match output.status.code() {
+        Some(code) if ok && failure_reason.is_some() => {
+            send(finish(EventKind::Failure));
+            info!(
+                "Exited {code} but failed: {}",
+                failure_reason.as_deref().unwrap_or_default()
+            );
+        }
+        Some(code) if ok => {
+            let mut event = finish(EventKind::Success);
+            let quiet = !opts.notify_if.is_empty()
+                && match condition::first_holding(&opts.notify_if, &output.stdout) {
+                    Ok(Some(condition)) => {
+                        event.severity = condition.severity.unwrap_or(Severity::Warning);
+                        event.notify_if =
+                            Some(format!("Output matched --notify-if: {}", condition.text));
+                        false
+                    }
+                    Ok(None) => event.hook_failure.is_none(),
+                    Err(e) => {
+                        tracing::warn!("Failed to check --notify-if: {e}");
+                        event.notify_if = Some(format!("--notify-if could not be checked: {e}"));
+                        false
+                    }
+                };
+            notified = !quiet;
+            if quiet {
+                info!(
+                    "Command finished successfully with exit code {code}; no --notify-if condition holds"
+                );
+            } else {
+                send(event);
+                info!("Command finished successfully with exit code {code}");
+            }
+        }
+        Some(code) => {
+            send(finish(EventKind::Failure));
+            info!(
+                "Failed with exit code: {}. Stdout: {} Stderr: {}",
+                code,
+                String::from_utf8_lossy(&output.stdout),
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+        None => {
+            send(finish(EventKind::Signal));
+            info!(
+                "Process terminated by {}.",
+                signal.as_deref().unwrap_or("a signal")
+            );
+        }
+    }
+    if let Some(notice) = parsed {
+        let mut event = Event::message(command, &notice.text);
+        if let Some(severity) = notice.severity {
+            event.severity = severity;
+        }
+        send(event);
+    }
+    drop(reporter);
+    notifier.shutdown();
+    // After the finish notification, which says what they are about.
+    if let Some((cfg, paths)) = &attach
+        && notified
+    {
+        upload::send_all(cfg, paths);
+    }
+    let mut output = output;
+    {
+        use std::os::unix::process::ExitStatusExt;
+        // Exit 1 and 0, so whoever ran us sees the same outcome.
+        if failure_reason.is_some() {
+            output.status = std::process::ExitStatus::from_raw(1 << 8);
+        } else if ok {
+            output.status = std::process::ExitStatus::from_raw(0);
+        }
+    }
+    Ok(output)
+}
@@ -0,0 +1,422 @@
+//! `--locale de`: the built-in message templates in another language, for
+//! teams that would rather read alerts in their own. German (`de`), Spanish
+//! (`es`) and French (`fr`) are built in. The templates' own words are
+//! translated, and so are the lines sentinel-rs adds to messages itself
+//! (the quiet hours digest, repeat counts, escalation and maintenance
+//! notes); what comes from elsewhere (the command's output, error messages,
+//! tool summaries) is shown as is, and templates of one's own are used as
+//! written. Chosen once, at startup, for the whole process.
+
+use std::sync::OnceLock;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Locale {
+    #[default]
+    En,
+    De,
+    Es,
+    Fr,
+}
+
+static LOCALE: OnceLock<Locale> = OnceLock::new();
+
+/// Uses `locale` from now on. Only the first call counts.
+pub fn set(locale: Locale) {
+    LOCALE.set(locale).ok();
+}
+
+pub fn current() -> Locale {
+    *LOCALE.get_or_init(Locale::default)
+}
+
+/// A language code, optionally with a region and encoding as in `LANG`
+/// (`de`, `de-AT`, `de_DE.UTF-8`).
+pub fn parse_locale(s: &str) -> Result<Locale, String> {
+    let language = s
+        .split(['_', '-', '.'])
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match language.as_str() {
+        "en" | "c" | "posix" => Ok(Locale::En),
+        "de" => Ok(Locale::De),
+        "es" => Ok(Locale::Es),
+        "fr" => Ok(Locale::Fr),
+        _ => Err(format!(
+            "unsupported locale {s:?} (expected en, de, es or fr)"
+        )),
+    }
+}
+
+/// The words of the built-in templates, as written there, with their German,
+/// Spanish and French versions.
+const PHRASES: [(&str, [&str; 3]); 26] = [
+    (
+        "\nStarted\n",
+        ["\nGestartet\n", "\nIniciado\n", "\nDémarré\n"],
+    ),
+    (
+        "\nStarted by: ",
+        ["\nGestartet von: ", "\nIniciado por: ", "\nDémarré par : "],
+    ),
+    (
+        "\nSettings: ",
+        ["\nEinstellungen: ", "\nAjustes: ", "\nParamètres : "],
+    ),
+    (
+        "\nInstance: ",
+        ["\nInstanz: ", "\nInstancia: ", "\nInstance : "],
+    ),
+    (
+        "\nIn {{environment.cwd}} with {{environment.shell}}",
+        [
+            "\nIn {{environment.cwd}} mit {{environment.shell}}",
+            "\nEn {{environment.cwd}} con {{environment.shell}}",
+            "\nDans {{environment.cwd}} avec {{environment.shell}}",
+        ],
+    ),
+    (
+        "Finished successfully with exit code {{exit_code}}",
+        [
+            "Erfolgreich beendet mit Exit-Code {{exit_code}}",
+            "Finalizado correctamente con código de salida {{exit_code}}",
+            "Terminé avec succès, code de sortie {{exit_code}}",
+        ],
+    ),
+    (
+        "{{#if duration}} in {{duration}}{{/if}}",
+        [
+            "{{#if duration}} in {{duration}}{{/if}}",
+            "{{#if duration}} en {{duration}}{{/if}}",
+            "{{#if duration}} en {{duration}}{{/if}}",
+        ],
+    ),
+    (
+        "{{#if duration}} after {{duration}}{{/if}}",
+        [
+            "{{#if duration}} nach {{duration}}{{/if}}",
+            "{{#if duration}} tras {{duration}}{{/if}}",
+            "{{#if duration}} après {{duration}}{{/if}}",
+        ],
+    ),
+    (
+        "\nResources: ",
+        ["\nRessourcen: ", "\nRecursos: ", "\nRessources : "],
+    ),
+    (
+        "\nArtifacts:",
+        ["\nArtefakte:", "\nArtefactos:", "\nArtefacts :"],
+    ),
+    (
+        " (not from this run)",
+        [
+            " (nicht aus diesem Lauf)",
+            " (no es de esta ejecución)",
+            " (pas de cette exécution)",
+        ],
+    ),
+    (
+        "\nand {{artifacts.more}} more",
+        [
+            "\nund {{artifacts.more}} weitere",
+            "\ny {{artifacts.more}} más",
+            "\net {{artifacts.more}} de plus",
+        ],
+    ),
+    (
+        "\nMissing artifacts: ",
+        [
+            "\nFehlende Artefakte: ",
+            "\nArtefactos que faltan: ",
+            "\nArtefacts manquants : ",
+        ],
+    ),
+    (
+        "Failed with exit code: {{exit_code}}",
+        [
+            "Fehlgeschlagen mit Exit-Code: {{exit_code}}",
+            "Falló con código de salida: {{exit_code}}",
+            "Échec avec le code de sortie : {{exit_code}}",
+        ],
+    ),
+    (
+        "Killed by the OOM killer",
+        [
+            "Vom OOM-Killer beendet",
+            "Terminado por el OOM killer",
+            "Tué par l'OOM killer",
+        ],
+    ),
+    (
+        ", peak RSS ",
+        [", maximaler RSS ", ", RSS máximo ", ", pic de RSS "],
+    ),
+    (
+        "Process terminated by ",
+        [
+            "Prozess beendet durch ",
+            "Proceso terminado por ",
+            "Processus terminé par ",
+        ],
+    ),
+    (
+        "{{else}}signal{{/if}}",
+        [
+            "{{else}}Signal{{/if}}",
+            "{{else}}señal{{/if}}",
+            "{{else}}signal{{/if}}",
+        ],
+    ),
+    (
+        " (core dumped",
+        [
+            " (Core-Dump geschrieben",
+            " (volcado de memoria",
+            " (core dump écrit",
+        ],
+    ),
+    ("\nGit: ", ["\nGit: ", "\nGit: ", "\nGit : "]),
+    (
+        "\nStdout:\n",
+        [
+            "\nStandardausgabe:\n",
+            "\nSalida estándar:\n",
+            "\nSortie standard :\n",
+        ],
+    ),
+    (
+        "\nStderr:\n",
+        [
+            "\nFehlerausgabe:\n",
+            "\nSalida de errores:\n",
+            "\nSortie d'erreur :\n",
+        ],
+    ),
+    (
+        "{{#if output}}\nOutput:\n",
+        [
+            "{{#if output}}\nAusgabe:\n",
+            "{{#if output}}\nSalida:\n",
+            "{{#if output}}\nSortie :\n",
+        ],
+    ),
+    (
+        "Failed to execute command: ",
+        [
+            "Befehl konnte nicht ausgeführt werden: ",
+            "No se pudo ejecutar el comando: ",
+            "Impossible d'exécuter la commande : ",
+        ],
+    ),
+    (
+        "{{#if skipped}}skipped {{name}}",
+        [
+            "{{#if skipped}}übersprungen {{name}}",
+            "{{#if skipped}}omitido {{name}}",
+            "{{#if skipped}}ignoré {{name}}",
+        ],
+    ),
+    (
+        "{{else}}FAILED{{/if}}",
+        [
+            "{{else}}FEHLGESCHLAGEN{{/if}}",
+            "{{else}}FALLÓ{{/if}}",
+            "{{else}}ÉCHEC{{/if}}",
+        ],
+    ),
+];
+
+/// What sentinel-rs adds to messages itself, outside the templates, with
+/// `{name}` where [`say`] fills something in.
+const MESSAGES: [(&str, [&str; 3]); 13] = [
+    (
+        "Held during quiet hours ({n}):",
+        [
+            "Während der Ruhezeit zurückgehalten ({n}):",
+            "Retenidas durante las horas de silencio ({n}):",
+            "Retenues pendant les heures calmes ({n}) :",
+        ],
+    ),
+    (
+        "Repeated {n}× in {took} since the last notification.",
+        [
+            "{n}× wiederholt in {took} seit der letzten Benachrichtigung.",
+            "Repetido {n}× en {took} desde la última notificación.",
+            "Répété {n}× en {took} depuis la dernière notification.",
+        ],
+    ),
+    (
+        "The failure before repeated {n}× in {took} without being sent.",
+        [
+            "Der vorige Fehler wiederholte sich {n}× in {took}, ohne gesendet zu werden.",
+            "El fallo anterior se repitió {n}× en {took} sin enviarse.",
+            "L'échec précédent s'est répété {n}× en {took} sans être envoyé.",
+        ],
+    ),
+    (
+        "Send /ack {id} within {after} min, or this is escalated.",
+        [
+            "Sende /ack {id} innerhalb von {after} min, sonst wird eskaliert.",
+            "Envía /ack {id} en {after} min o se escalará.",
+            "Envoyez /ack {id} d'ici {after} min, sinon l'alerte est escaladée.",
+        ],
+    ),
+    (
+        "Acknowledged by {by}.",
+        [
+            "Bestätigt von {by}.",
+            "Confirmado por {by}.",
+            "Pris en compte par {by}.",
+        ],
+    ),
+    (
+        "Acknowledged",
+        ["Bestätigt", "Confirmado", "Pris en compte"],
+    ),
+    (
+        "Escalated ({n} of {times}): not acknowledged in {after} min.",
+        [
+            "Eskaliert ({n} von {times}): nicht bestätigt in {after} min.",
+            "Escalado ({n} de {times}): sin confirmar en {after} min.",
+            "Escaladé ({n} sur {times}) : non pris en compte en {after} min.",
+        ],
+    ),
+    (
+        "Maintenance ended after {took}; {counts}.",
+        [
+            "Wartung nach {took} beendet; {counts}.",
+            "Mantenimiento terminado tras {took}; {counts}.",
+            "Maintenance terminée après {took} ; {counts}.",
+        ],
+    ),
+    (
+        "no notifications held back",
+        [
+            "keine Benachrichtigungen zurückgehalten",
+            "ninguna notificación retenida",
+            "aucune notification retenue",
+        ],
+    ),
+    (
+        "{n} notification(s) suppressed",
+        [
+            "{n} Benachrichtigung(en) unterdrückt",
+            "{n} notificación(es) suprimida(s)",
+            "{n} notification(s) supprimée(s)",
+        ],
+    ),
+    (
+        "{n} notification(s) downgraded",
+        [
+            "{n} Benachrichtigung(en) herabgestuft",
+            "{n} notificación(es) rebajada(s)",
+            "{n} notification(s) rétrogradée(s)",
+        ],
+    ),
+    (
+        "{n} notification(s) suppressed and {m} downgraded",
+        [
+            "{n} Benachrichtigung(en) unterdrückt und {m} herabgestuft",
+            "{n} notificación(es) suprimida(s) y {m} rebajada(s)",
+            "{n} notification(s) supprimée(s) et {m} rétrogradée(s)",
+        ],
+    ),
+    (
+        "[maintenance] ",
+        ["[Wartung] ", "[mantenimiento] ", "[maintenance] "],
+    ),
+];
+
+fn column(locale: Locale) -> Option<usize> {
+    match locale {
+        Locale::En => None,
+        Locale::De => Some(0),
+        Locale::Es => Some(1),
+        Locale::Fr => Some(2),
+    }
+}
+
+/// `english`, one of the sentences sentinel-rs adds to messages itself, in
+/// the current locale, with each `{name}` of `args` filled in.
+pub fn say(english: &str, args: &[(&str, &dyn std::fmt::Display)]) -> String {
+    say_in(current(), english, args)
+}
+
+fn say_in(locale: Locale, english: &str, args: &[(&str, &dyn std::fmt::Display)]) -> String {
+    let sentence = column(locale)
+        .and_then(|column| {
+            MESSAGES
+                .iter()
+                .find(|(e, _)| *e == english)
+                .map(|(_, translations)| translations[column])
+        })
+        .unwrap_or(english);
+    args.iter()
+        .fold(sentence.to_string(), |text, (name, value)| {
+            text.replace(&format!("{{{name}}}"), &value.to_string())
+        })
+}
+
+/// The built-in `template` in `locale`.
+pub fn translate(locale: Locale, template: &str) -> String {
+    let Some(column) = column(locale) else {
+        return template.to_string();
+    };
+    PHRASES
+        .iter()
+        .fold(template.to_string(), |text, (english, translations)| {
+            text.replace(english, translations[column])
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::EventKind;
+    use crate::template;
+
+    #[test]
+    fn every_phrase_is_in_a_builtin_template() {
+        let all: String = EventKind::ALL
+            .iter()
+            .map(|&kind| template::builtin(kind))
+            .collect();
+        for (english, _) in PHRASES {
+            assert!(all.contains(english), "{english:?}");
+        }
+        assert_eq!(parse_locale("de_DE.UTF-8"), Ok(Locale::De));
+        assert_eq!(parse_locale("fr-CA"), Ok(Locale::Fr));
+        assert!(parse_locale("tlh").is_err());
+        assert_eq!(
+            translate(Locale::De, "{{#if duration}} after {{duration}}{{/if}}."),
+            "{{#if duration}} nach {{duration}}{{/if}}."
+        );
+    }
+
+    #[test]
+    fn messages_keep_their_placeholders() {
+        let placeholders = |s: &str| -> Vec<String> {
+            s.split('{')
+                .skip(1)
+                .filter_map(|rest| rest.split_once('}').map(|(name, _)| name.to_string()))
+                .collect()
+        };
+        for (english, translations) in MESSAGES {
+            for translation in translations {
+                assert_eq!(
+                    placeholders(english),
+                    placeholders(translation),
+                    "{translation:?}"
+                );
+            }
+        }
+        assert_eq!(
+            say_in(Locale::Fr, "Acknowledged by {by}.", &[("by", &"@ana")]),
+            "Pris en compte par @ana."
+        );
+        assert_eq!(
+            say_in(Locale::En, "Held during quiet hours ({n}):", &[("n", &2)]),
+            "Held during quiet hours (2):"
+        );
+    }
+}
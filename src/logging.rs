@@ -0,0 +1,91 @@
+//! sentinel-rs's own diagnostics (notification attempts and failures, where
+//! its configuration came from, …), as opposed to the command's output.
+//! They go through `tracing` to stderr, or to the `--self-log` file so they
+//! do not mix with the command's stderr. `-q`/`-v`/`-vv` pick what is logged
+//! (warnings by default, or what `RUST_LOG` says), and `--log-format json`
+//! writes one JSON object per line for journald or Loki. Records made during
+//! a run carry its `run_id` and `job`.
+
+use std::io::IsTerminal;
+use std::path::Path;
+use std::sync::Mutex;
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+pub fn parse_log_format(s: &str) -> Result<LogFormat, String> {
+    match s {
+        "text" => Ok(LogFormat::Text),
+        "json" => Ok(LogFormat::Json),
+        _ => Err(format!("expected text or json, got {s:?}")),
+    }
+}
+
+/// The filter for `verbosity`: the number of `-v`s, or minus the number of
+/// `-q`s. More verbose levels are for sentinel-rs's own records only, until
+/// `-vvv` lets the HTTP libraries in too.
+fn filter(verbosity: i8) -> EnvFilter {
+    let directives = match verbosity {
+        ..=-2 => "off",
+        -1 => "error",
+        0 => return EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn")),
+        1 => "sentinel_rs=info,warn",
+        2 => "sentinel_rs=debug,warn",
+        3.. => "trace",
+    };
+    EnvFilter::new(directives)
+}
+
+/// Sends log records, including those of libraries using `log`, to stderr
+/// or appends them to `file`.
+pub fn init(format: LogFormat, verbosity: i8, file: Option<&Path>) -> Result<(), String> {
+    let (writer, ansi) = match file {
+        Some(path) => {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| format!("cannot open log file {}: {e}", path.display()))?;
+            (BoxMakeWriter::new(Mutex::new(file)), false)
+        }
+        None => (
+            BoxMakeWriter::new(std::io::stderr),
+            std::io::stderr().is_terminal(),
+        ),
+    };
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter(verbosity))
+        .with_ansi(ansi)
+        .with_writer(writer);
+    // Already set up if we are a library in someone else's process.
+    let result = match format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .try_init(),
+    };
+    result.ok();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verbosity_picks_the_level() {
+        assert_eq!(filter(-2).to_string(), "off");
+        assert_eq!(filter(-1).to_string(), "error");
+        assert_eq!(filter(2).to_string(), "sentinel_rs=debug,warn");
+        assert_eq!(filter(5).to_string(), "trace");
+    }
+}
@@ -1,436 +1,1660 @@
-use chrono::Local;
-use hostname::get;
-use log::info;
-use reqwest::blocking::Client;
-use serde_json::json;
+use chrono_tz::Tz;
+use clap::error::ErrorKind;
+use clap::{ArgAction, CommandFactory, Parser, Subcommand, ValueEnum};
+use regex::Regex;
+use sentinel_rs::check::Health;
+use sentinel_rs::condition::{self, Condition};
+use sentinel_rs::config::{self, TgConfig, load_tg_config_with};
+use sentinel_rs::docker::DockerRun;
+use sentinel_rs::doctor::Finding;
+use sentinel_rs::event::{self, Event};
+use sentinel_rs::fanout::{self, DEFAULT_CONCURRENCY};
+use sentinel_rs::kube::KubeJob;
+use sentinel_rs::locale::Locale;
+use sentinel_rs::logging::{self, LogFormat};
+use sentinel_rs::notifier::{self, http_client, http_client_with};
+use sentinel_rs::parse::{self, ParserKind};
+use sentinel_rs::runner::tail_bytes;
+use sentinel_rs::sandbox::Sandbox;
+use sentinel_rs::sched::{self, IoClass, Scheduling};
+use sentinel_rs::secrets::{self, SecretRef};
+use sentinel_rs::shell::{self, Shell};
+use sentinel_rs::steps;
+use sentinel_rs::tee::Flush;
+use sentinel_rs::telegram::tg_send;
+use sentinel_rs::ulimit::{self, Ulimit};
+use sentinel_rs::user::RunAs;
+use sentinel_rs::{RunOptions, exit_code, run_and_notify};
+use sentinel_rs::{
+    artifact, auth, canary, cgroup, check, clock, control, crash, criteria, dashboard, doctor,
+    echo, escalate, github, gitlab, host, icon, info, init, locale, maintenance, metrics, mute,
+    quiet, setup, severity, status, validate, workflow,
+};
 use std::env;
-use std::io::{Read, Write};
-use std::process::{Command, Output, Stdio};
-use std::sync::mpsc;
-use std::thread;
-
-struct TgConfig {
-    bot_token: String,
-    chat_id: String,
-    api_base: String,
-}
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-fn env_required(key: &str) -> Result<String, std::env::VarError> {
-    let value = std::env::var(key)?;
-    if value.trim().is_empty() {
-        return Err(std::env::VarError::NotPresent);
-    }
-    Ok(value)
+const EXAMPLES: &str = "\
+Examples:
+  sentinel-rs -- \"echo hello\"
+  sentinel-rs -- ls -la
+  sentinel-rs -- --help   # runs a command named \"--help\"
+  sentinel-rs --ssh backup@nas -- \"restic backup /srv\"
+  sentinel-rs --pre \"mount /mnt/b\" --post \"umount /mnt/b\" -- \"restic backup /srv\"
+  sentinel-rs --on-failure \"systemctl restart app\" -- /srv/app/healthcheck
+  sentinel-rs --notify-if '.errors > 0' -- \"scanner --json\"
+  sentinel-rs --artifact 'dist/*.tar.gz' -- make release
+  sentinel-rs --show-env 'PATH,LANG,PG*' -- ./nightly-export.sh
+  sentinel-rs --git -- make deploy
+  sentinel-rs --step \"cargo build\" --step \"cargo test\"
+  sentinel-rs --parallel 4 --step \"./export acme\" --step \"./export globex\"
+  sentinel-rs pipeline run nightly.yaml
+  sentinel-rs batch --from exports.txt --parallel 4
+  sentinel-rs --hosts fleet.txt --concurrency 4 -- \"apt-get -y upgrade\"
+  sentinel-rs --k8s python:3.12 --k8s-namespace batch -- \"python -m etl\"
+  sentinel-rs docker -- alpine:3 \"apk update && apk upgrade\"
+  sentinel-rs -- docker ps   # runs the docker CLI itself
+  sentinel-rs init
+  sentinel-rs setup telegram
+  sentinel-rs telegram chats
+  sentinel-rs info
+  sentinel-rs auth set telegram
+  sentinel-rs doctor --send-test
+  sentinel-rs test   # e.g. hourly from cron
+  SENTINEL_QUIET_HOURS=23:00-07:00 sentinel-rs -- ./nightly.sh
+  sentinel-rs digest   # e.g. at 07:00 from cron
+  sentinel-rs --severity critical:exit=124 --severity 'warning:took>2h' -- timeout 3h ./backup.sh
+  SENTINEL_ESCALATE_AFTER=15 SENTINEL_ESCALATE_CHAT_ID=-1001234 sentinel-rs -- ./backup.sh
+  sentinel-rs escalate   # e.g. every minute from cron
+  sentinel-rs maintenance on --for 2h --reason \"kernel upgrade\"
+  sentinel-rs mute add --job 'backup-*' --for 12h --reason \"disk swap\"
+  df -h | sentinel-rs notify --stdin \"Disk usage on $(hostname)\"
+  eval \"$(sentinel-rs shell-hook zsh)\"   # in ~/.zshrc";
+
+/// A `--cpuset` value; an alias so clap takes it as one value, not a list.
+type CpuList = Vec<usize>;
+/// An `--ok-codes` value, likewise.
+type ExitCodes = Vec<i32>;
+
+/// Runs a command via bash -c and sends Telegram notifications.
+#[derive(Parser)]
+#[command(
+    name = "sentinel-rs",
+    version,
+    after_help = EXAMPLES,
+    args_conflicts_with_subcommands = true,
+    disable_help_subcommand = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    mode: Option<Mode>,
+
+    /// Run the command on this host over SSH (using your ssh client and config)
+    #[arg(long, value_name = "[USER@]HOST")]
+    ssh: Option<String>,
+
+    /// Run the command over SSH on every host listed in FILE (one per line)
+    /// and send a single report
+    #[arg(long, value_name = "FILE", conflicts_with = "ssh")]
+    hosts: Option<PathBuf>,
+
+    /// How many hosts to run on at once with --hosts
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = DEFAULT_CONCURRENCY,
+        requires = "hosts",
+        value_parser = parse_positive
+    )]
+    concurrency: usize,
+
+    /// Run CMD as one of several steps, in the order given, stopping at the
+    /// first that fails; one report lists how each went (repeatable,
+    /// instead of a command)
+    #[arg(
+        long = "step",
+        value_name = "CMD",
+        conflicts_with_all = [
+            "command", "hosts", "memory_max", "cpu_max", "ok_codes", "parser",
+            "success_regex", "failure_regex",
+        ]
+    )]
+    steps: Vec<String>,
+
+    /// Run the --step commands at most N at a time, all of them even when
+    /// some fail
+    #[arg(long, value_name = "N", requires = "steps", value_parser = parse_positive)]
+    parallel: Option<usize>,
+
+    /// Run the command (with sh -c) as a Kubernetes Job using this image,
+    /// via kubectl and the current context
+    #[arg(long, value_name = "IMAGE", conflicts_with_all = ["ssh", "hosts"])]
+    k8s: Option<String>,
+
+    /// Namespace for the --k8s Job
+    #[arg(long, value_name = "NAMESPACE", requires = "k8s")]
+    k8s_namespace: Option<String>,
+
+    /// Emit GitHub Actions annotations and a job summary (the default when
+    /// GITHUB_ACTIONS=true)
+    #[arg(long)]
+    github: bool,
+
+    /// Also set a commit status for GITHUB_SHA (needs GITHUB_TOKEN)
+    #[arg(long)]
+    github_status: bool,
+
+    /// Set a GitLab commit status for CI_COMMIT_SHA (needs GITLAB_TOKEN)
+    #[arg(long)]
+    gitlab_status: bool,
+
+    /// Comment the result on the merge request of a GitLab MR pipeline
+    /// (needs GITLAB_TOKEN)
+    #[arg(long)]
+    gitlab_comment: bool,
+
+    /// Set NAME in the command's environment to a secret fetched at run time:
+    /// vault:PATH#FIELD or ssm:PARAMETER (repeatable; local and docker runs)
+    #[arg(
+        long = "secret",
+        value_name = "NAME=REF",
+        value_parser = secrets::parse_secret,
+        conflicts_with_all = ["ssh", "hosts", "k8s"]
+    )]
+    secrets: Vec<SecretRef>,
+
+    /// Send to the Bot API server at URL, e.g. a local one or a proxy in
+    /// front of Telegram, instead of TG_API_BASE or https://api.telegram.org
+    #[arg(long, value_name = "URL", value_parser = config::parse_api_base)]
+    api_base: Option<String>,
+
+    /// Send notifications through this proxy (http://, socks5://, …) instead
+    /// of the one in HTTPS_PROXY/ALL_PROXY
+    #[arg(long, value_name = "URL")]
+    proxy: Option<String>,
+
+    /// Run the command with no network, no new privileges and the system
+    /// directories (/usr, /etc, …) read-only (Linux)
+    #[arg(long, conflicts_with_all = ["ssh", "hosts", "k8s"])]
+    sandbox: bool,
+
+    /// Make PATH read-only for the command (repeatable; Linux)
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["ssh", "hosts", "k8s"])]
+    read_only: Vec<PathBuf>,
+
+    /// Run the command without network access (Linux)
+    #[arg(long, conflicts_with_all = ["ssh", "hosts", "k8s"])]
+    no_network: bool,
+
+    /// Keep the command from gaining privileges via setuid binaries (Linux)
+    #[arg(long, conflicts_with_all = ["ssh", "hosts", "k8s"])]
+    no_new_privs: bool,
+
+    /// Run the command as this user (name or uid) when sentinel-rs runs as
+    /// root, with the user's groups
+    #[arg(long, value_name = "USER", conflicts_with_all = ["ssh", "hosts", "k8s"])]
+    user: Option<String>,
+
+    /// Run the command with this primary group (name or gid), as root
+    #[arg(long, value_name = "GROUP", conflicts_with_all = ["ssh", "hosts", "k8s"])]
+    group: Option<String>,
+
+    /// Limit the command's memory (e.g. 512M, 2G) with a cgroup; the finish
+    /// notification says if it was hit (Linux, cgroup v2)
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = cgroup::parse_memory,
+        conflicts_with_all = ["ssh", "hosts", "k8s"]
+    )]
+    memory_max: Option<u64>,
+
+    /// Limit the command's CPU time (e.g. 150% or 1.5 CPUs) with a cgroup
+    /// (Linux, cgroup v2)
+    #[arg(
+        long,
+        value_name = "LIMIT",
+        value_parser = cgroup::parse_cpu,
+        conflicts_with_all = ["ssh", "hosts", "k8s"]
+    )]
+    cpu_max: Option<f64>,
+
+    /// Set a resource limit for the command, as for docker run --ulimit:
+    /// NAME=SOFT[:HARD], e.g. nofile=1024:2048 or core=0 (repeatable)
+    #[arg(
+        long = "ulimit",
+        value_name = "NAME=SOFT[:HARD]",
+        value_parser = ulimit::parse_ulimit,
+        conflicts_with_all = ["ssh", "hosts", "k8s"]
+    )]
+    ulimits: Vec<Ulimit>,
+
+    /// Run the command at this niceness, from -20 to 19 (e.g. 10 for a
+    /// background batch job)
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = sched::parse_nice,
+        allow_negative_numbers = true,
+        conflicts_with_all = ["ssh", "hosts", "k8s"]
+    )]
+    nice: Option<i32>,
+
+    /// Run the command in this I/O scheduling class: idle,
+    /// best-effort[:0-7] or realtime[:0-7] (Linux)
+    #[arg(
+        long,
+        value_name = "CLASS",
+        value_parser = sched::parse_ionice,
+        conflicts_with_all = ["ssh", "hosts", "k8s"]
+    )]
+    ionice: Option<IoClass>,
+
+    /// Run the command only on these CPUs, e.g. 0-3 or 0,2,4-7 (Linux)
+    #[arg(
+        long,
+        value_name = "CPUS",
+        value_parser = sched::parse_cpuset,
+        conflicts_with_all = ["ssh", "hosts", "k8s"]
+    )]
+    cpuset: Option<CpuList>,
+
+    /// Read the command's output N bytes at a time (default 4096); more
+    /// means fewer reads for commands that print a lot
+    #[arg(long, value_name = "N", value_parser = parse_positive)]
+    read_buffer: Option<usize>,
+
+    /// When to pass the output on to the terminal: chunk (as soon as it is
+    /// read), line (in whole lines; the default on a terminal) or full (in
+    /// blocks of --read-buffer, for a file or pipe)
+    #[arg(long, value_name = "MODE")]
+    flush: Option<Flush>,
+
+    /// Show the command's stdout and stderr together in the finish
+    /// notification, in the order they came, with stderr lines marked
+    /// [stderr]
+    #[arg(long, conflicts_with_all = ["hosts", "steps"])]
+    merge_output: bool,
+
+    /// Exit codes that count as success (e.g. 0,24 for rsync); sentinel-rs
+    /// then exits 0
+    #[arg(
+        long,
+        value_name = "CODES",
+        value_parser = criteria::parse_ok_codes,
+        conflicts_with = "hosts"
+    )]
+    ok_codes: Option<ExitCodes>,
+
+    /// Exit with CODE when the command could not be started at all, e.g.
+    /// bash is missing or --pre could not run
+    #[arg(long, value_name = "CODE", default_value_t = 125)]
+    exit_on_spawn_error: u8,
+
+    /// Exit with CODE when a signal killed the command, instead of 128 plus
+    /// the signal number
+    #[arg(long, value_name = "CODE")]
+    exit_on_signal: Option<u8>,
+
+    /// Exit with CODE when the command succeeded but a notification failed,
+    /// was dropped or was given up on; a failure's own code is kept
+    #[arg(long, value_name = "CODE")]
+    exit_on_undelivered: Option<u8>,
+
+    /// Summarize the output of this tool in the finish notification instead
+    /// of showing its tail: borg, certs (certbot, acme.sh), dump (pg_dump,
+    /// mysqldump), restic, rsync, train (epochs and metrics of model
+    /// training, with progress messages) or upgrade (apt-get/dnf upgrades)
+    #[arg(
+        long,
+        value_name = "NAME",
+        value_parser = parse::parse_parser,
+        conflicts_with = "hosts"
+    )]
+    parser: Option<ParserKind>,
+
+    /// Count a successful run as failed unless some line of its output
+    /// matches REGEX
+    #[arg(
+        long,
+        value_name = "REGEX",
+        value_parser = criteria::parse_regex,
+        conflicts_with = "hosts"
+    )]
+    success_regex: Option<Regex>,
+
+    /// Count a run as failed, even when its exit code says success, if a line
+    /// of its output matches REGEX (e.g. "^0 rows exported")
+    #[arg(
+        long,
+        value_name = "REGEX",
+        value_parser = criteria::parse_regex,
+        conflicts_with = "hosts"
+    )]
+    failure_regex: Option<Regex>,
+
+    /// Run CMD on this machine before the command, e.g. to mount what it
+    /// needs; if it fails, the command is not run
+    #[arg(long, value_name = "CMD", conflicts_with_all = ["hosts", "steps"])]
+    pre: Option<String>,
+
+    /// Run CMD on this machine after the command, whatever its outcome, with
+    /// its exit code in SENTINEL_EXIT_CODE, e.g. to unmount again
+    #[arg(long, value_name = "CMD", conflicts_with_all = ["hosts", "steps"])]
+    post: Option<String>,
+
+    /// Run CMD on this machine if the command fails, e.g. to restart a
+    /// service, with its exit code, the run id and a file holding its
+    /// output in SENTINEL_EXIT_CODE, SENTINEL_RUN_ID and SENTINEL_LOG
+    #[arg(long, value_name = "CMD", conflicts_with_all = ["hosts", "steps"])]
+    on_failure: Option<String>,
+
+    /// Only notify about a successful run if EXPR, a jq-style condition,
+    /// holds for the JSON it prints (e.g. '.errors > 0'); put a severity in
+    /// front to send it as that ('critical: .errors > 100'; default
+    /// warning). Repeatable, the first that holds wins; failures are always
+    /// notified
+    #[arg(
+        long,
+        value_name = "EXPR",
+        value_parser = condition::parse_condition,
+        conflicts_with_all = ["hosts", "steps"]
+    )]
+    notify_if: Vec<Condition>,
+
+    /// Send the finish notification as LEVEL (info, warning, error or
+    /// critical) if RULE holds: exit=CODES ('critical:exit=124'),
+    /// match=REGEX on the output, or took>DURATION ('warning:took>30m').
+    /// Repeatable, the most severe that holds wins; added to
+    /// SENTINEL_SEVERITY_RULES
+    #[arg(
+        long,
+        value_name = "LEVEL:RULE",
+        value_parser = clap::value_parser!(severity::Rule),
+        conflicts_with_all = ["hosts", "steps"]
+    )]
+    severity: Vec<severity::Rule>,
+
+    /// List the files matching GLOB (e.g. 'dist/*.tar.gz') in the finish
+    /// notification, with sizes and times, and warn if there are none
+    /// (repeatable)
+    #[arg(
+        long = "artifact",
+        value_name = "GLOB",
+        value_parser = artifact::parse_pattern,
+        conflicts_with_all = ["ssh", "hosts", "k8s", "steps"]
+    )]
+    artifacts: Vec<String>,
+
+    /// Send FILE to the chat after the finish notification, e.g. the job's
+    /// log; up to 50 MiB, or 2000 MiB through a local Bot API server
+    /// (repeatable)
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["ssh", "hosts", "k8s", "steps"]
+    )]
+    attach: Vec<PathBuf>,
+
+    /// Show these environment variables of the command (comma-separated;
+    /// a trailing * matches a prefix, e.g. PATH,LANG,RUST_*), its resolved
+    /// working directory and its bash in the start notification; values
+    /// that look like credentials are redacted
+    #[arg(
+        long,
+        value_name = "NAMES",
+        value_delimiter = ',',
+        conflicts_with_all = ["ssh", "hosts", "k8s", "steps"]
+    )]
+    show_env: Vec<String>,
+
+    /// Name the branch and commit of the git repository the current
+    /// directory is in, and whether it has uncommitted changes, in the start
+    /// and finish notifications
+    #[arg(long, conflicts_with_all = ["hosts", "steps"])]
+    git: bool,
+
+    /// Name the cloud instance this runs on (EC2 or GCE: instance id,
+    /// region, zone and public IP, from the instance metadata service) in
+    /// the start notification
+    #[arg(long, conflicts_with_all = ["ssh", "hosts", "k8s", "steps"])]
+    cloud: bool,
+
+    /// Say who started the run in the start notification: the user (and
+    /// who they were before sudo), their terminal, and where their SSH
+    /// session came from
+    #[arg(long, conflicts_with_all = ["hosts", "steps"])]
+    origin: bool,
+
+    /// Write the times in notifications in UTC instead of local time
+    #[arg(long, conflicts_with = "timezone")]
+    utc: bool,
+
+    /// Write the times in notifications in this time zone (e.g.
+    /// Europe/Berlin) instead of local time
+    #[arg(long, value_name = "ZONE", value_parser = clock::parse_zone)]
+    timezone: Option<Tz>,
+
+    /// How to write the times in notifications, as a strftime format
+    /// (default "%Y-%m-%d %H:%M:%S", plus " %Z" with --utc or --timezone)
+    #[arg(long, value_name = "FORMAT", value_parser = clock::parse_format)]
+    time_format: Option<String>,
+
+    /// Call this machine LABEL in notifications instead of its hostname
+    /// (see also the host-labels file in the config directory)
+    #[arg(long, value_name = "LABEL", value_parser = host::parse_label)]
+    host_label: Option<String>,
+
+    /// Put ICON in front of the notifications for OUTCOME (success, failure
+    /// or signal) instead of ✅, ❌ or 💀; an empty ICON leaves it off
+    /// (repeatable)
+    #[arg(long = "icon", value_name = "OUTCOME=ICON", value_parser = icon::parse_icon)]
+    icons: Vec<(String, String)>,
+
+    /// Leave the outcome icons off the notifications
+    #[arg(long, conflicts_with = "icons")]
+    no_icons: bool,
+
+    /// Show the command as "(command hidden)" in notifications
+    #[arg(long, conflicts_with_all = ["redact", "command_max_chars"])]
+    hide_command: bool,
+
+    /// Replace what REGEX matches in the command (only its first group, if
+    /// it has one) with <redacted> in notifications (repeatable)
+    #[arg(long, value_name = "REGEX", allow_hyphen_values = true)]
+    redact: Vec<Regex>,
+
+    /// Cut the command to N characters in notifications
+    #[arg(long, value_name = "N", value_parser = parse_positive)]
+    command_max_chars: Option<usize>,
+
+    /// Write the built-in messages in this language: de, es or fr (default
+    /// en)
+    #[arg(long, value_name = "LANG", value_parser = locale::parse_locale)]
+    locale: Option<Locale>,
+
+    /// Read the Telegram bot token from FILE instead of TG_BOT_TOKEN
+    #[arg(long, value_name = "FILE")]
+    token_file: Option<PathBuf>,
+
+    /// Serve Prometheus metrics on ADDR (e.g. 127.0.0.1:9464) at /metrics
+    /// while the command runs
+    #[arg(long, value_name = "ADDR")]
+    metrics_addr: Option<String>,
+
+    /// Serve the run's status at /status and its last lines of output at
+    /// /tail?lines=N on 127.0.0.1:PORT while it runs
+    #[arg(long, value_name = "PORT", conflicts_with_all = ["hosts", "steps"])]
+    status_port: Option<u16>,
+
+    /// Take requests while the command runs (see sentinel-rs ctl) on a Unix
+    /// socket named after the run id; the command's stdin is then what they
+    /// send
+    #[arg(long, conflicts_with_all = ["hosts", "steps"])]
+    control: bool,
+
+    /// How sentinel-rs logs its own diagnostics: text or json
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_value = "text",
+        value_parser = logging::parse_log_format
+    )]
+    log_format: LogFormat,
+
+    /// Log more of sentinel-rs's own diagnostics: -v for what it is doing,
+    /// -vv for every notification attempt, -vvv for the HTTP libraries too
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+
+    /// Log less: -q for errors only, -qq for nothing
+    #[arg(short, long, action = ArgAction::Count, conflicts_with = "verbose")]
+    quiet: u8,
+
+    /// Append sentinel-rs's own diagnostics to FILE instead of stderr, apart
+    /// from the command's output
+    #[arg(long, value_name = "FILE")]
+    self_log: Option<PathBuf>,
+
+    /// The command to run; several words are joined with spaces
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    command: Vec<String>,
 }
 
-fn load_tg_config() -> Result<TgConfig, Box<dyn std::error::Error>> {
-    let bot_token = env_required("TG_BOT_TOKEN")?;
-    let chat_id = env_required("TG_CHAT_ID")?;
-    let api_base =
-        env::var("TG_API_BASE").unwrap_or_else(|_| "https://api.telegram.org/".to_string());
-    Ok(TgConfig {
-        bot_token: bot_token.trim().to_string(),
-        chat_id: chat_id.trim().to_string(),
-        api_base: api_base.trim_end_matches('/').to_string(),
-    })
+#[derive(Subcommand)]
+enum Mode {
+    /// Follow the output of a run started with --control as it comes, from
+    /// another terminal
+    Attach {
+        /// The run's id (default: the only run taking requests)
+        run: Option<String>,
+
+        /// Also pass what is typed here on to the command's stdin
+        #[arg(long)]
+        stdin: bool,
+    },
+    /// Manage credentials kept in the OS keyring
+    Auth {
+        #[command(subcommand)]
+        action: AuthAction,
+    },
+    /// Run each line of a file (or stdin) as a job and send one digest of
+    /// how they went
+    Batch {
+        /// The jobs, one command per line; - for stdin
+        #[arg(long, value_name = "FILE", default_value = "-")]
+        from: PathBuf,
+
+        /// How many jobs to run at once
+        #[arg(long, value_name = "N", default_value_t = 1, value_parser = parse_positive)]
+        parallel: usize,
+    },
+    /// Check the health of this machine, notifying only when it changes
+    Check {
+        #[command(subcommand)]
+        target: CheckTarget,
+    },
+    /// Print a completion script for SHELL, e.g. sentinel-rs completions
+    /// bash > /etc/bash_completion.d/sentinel-rs
+    Completions {
+        /// bash, zsh, fish, powershell or elvish
+        shell: clap_complete::Shell,
+    },
+    /// Check the configuration without contacting anything, listing every
+    /// problem at once
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Send a request to a run started with --control: status, tail [N],
+    /// signal SIG, pause, resume, stdin TEXT or eof
+    Ctl {
+        /// The run's id (default: the only run taking requests)
+        #[arg(long, value_name = "ID")]
+        run: Option<String>,
+
+        /// The request; several words are joined with spaces
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        request: Vec<String>,
+    },
+    /// Serve a web page with the runs on this machine: their history, and
+    /// those going on with --control and their output (needs the dashboard
+    /// feature)
+    Dashboard {
+        /// Where to listen
+        #[arg(long, value_name = "ADDR", default_value = sentinel_rs::dashboard::DEFAULT_ADDR)]
+        addr: String,
+    },
+    /// Send the notifications held during quiet hours now, as one message,
+    /// e.g. from cron as the quiet hours end
+    Digest,
+    /// Run the command in a fresh container and report its CPU and memory use
+    Docker {
+        /// The image to run
+        image: String,
+
+        /// The command to run in it with sh -c (default: the image's own
+        /// command); several words are joined with spaces
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// Check the configuration and that Telegram can be reached, and say
+    /// what to fix
+    Doctor {
+        /// Also send a test message
+        #[arg(long)]
+        send_test: bool,
+    },
+    /// Read the /ack messages sent to the bot and escalate the failures
+    /// not acknowledged in time, e.g. every minute from cron
+    Escalate {
+        /// Acknowledge this failure instead
+        #[arg(long, value_name = "ID")]
+        ack: Option<String>,
+    },
+    /// List the features built in, the backends turned on and the
+    /// configuration files found
+    Info,
+    /// Ask for the bot token, chat id and defaults, write them to the
+    /// settings file in the config dir and send a test message
+    Init {
+        /// Replace the settings file if there is one
+        #[arg(long)]
+        force: bool,
+    },
+    /// Hold back the host's notifications during planned maintenance, and
+    /// say how many were once it is over
+    Maintenance {
+        #[command(subcommand)]
+        action: MaintenanceAction,
+    },
+    /// Silence a noisy job or host for a while, without editing the
+    /// crontab entries that run it
+    Mute {
+        #[command(subcommand)]
+        action: MuteAction,
+    },
+    /// Send a message through the configured backends without running
+    /// anything, e.g. from a script: sentinel-rs notify "deploy finished"
+    Notify {
+        /// The message; several words are joined with spaces
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        message: Vec<String>,
+
+        /// Send what is piped in (its last few KiB), after the message if
+        /// one is given
+        #[arg(long)]
+        stdin: bool,
+    },
+    /// Run the steps described in a YAML file
+    Pipeline {
+        #[command(subcommand)]
+        action: PipelineAction,
+    },
+    /// Set up a bot: check its token, find the chat id from a message sent
+    /// to it, store both in the settings file and send a confirmation
+    Setup {
+        service: AuthService,
+
+        /// How long to wait for the message to the bot
+        #[arg(long, value_name = "SECS", default_value_t = setup::WAIT.as_secs())]
+        wait: u64,
+    },
+    /// Print shell code that notifies when a command typed at the prompt
+    /// runs for long, e.g. eval "$(sentinel-rs shell-hook zsh)" in ~/.zshrc
+    ShellHook {
+        /// bash or zsh
+        #[arg(value_parser = shell::parse_shell)]
+        shell: Shell,
+
+        /// Notify about commands that ran for at least this many seconds
+        #[arg(long, value_name = "SECS", default_value_t = 60)]
+        min_duration: u64,
+
+        /// Programs not to notify about, comma-separated
+        #[arg(long, value_name = "PROGRAMS", default_value = shell::DEFAULT_IGNORE)]
+        ignore: String,
+    },
+    /// Send the finish notification for a command the shell ran (called by
+    /// the shell-hook code)
+    #[command(hide = true)]
+    ShellDone {
+        #[arg(long, allow_negative_numbers = true)]
+        exit_code: i32,
+
+        #[arg(long)]
+        seconds: u64,
+
+        #[arg(long, default_value = shell::DEFAULT_IGNORE)]
+        ignore: String,
+
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// Send a test notification through every configured backend and
+    /// report how long each took and whether it worked, e.g. from cron as a
+    /// canary
+    Test,
+    /// Ask Telegram about the bot
+    Telegram {
+        #[command(subcommand)]
+        action: TelegramAction,
+    },
 }
 
-fn format_message(ts: &str, host: &str, text: &str) -> String {
-    format!("[{ts}] [{host}]\n{text}")
+#[derive(Subcommand)]
+enum TelegramAction {
+    /// List the chats the bot got messages from lately, with their ids
+    Chats,
 }
 
-fn telegram_payload(chat_id: &str, body: &str) -> serde_json::Value {
-    json!({
-        "chat_id": chat_id,
-        "text": body,
-        "disable_web_page_preview": true,
-    })
+#[derive(Subcommand)]
+enum PipelineAction {
+    /// Run the steps in FILE in dependency order and send one report
+    Run {
+        /// The pipeline definition
+        file: PathBuf,
+    },
 }
 
-fn tg_send(client: &Client, cfg: &TgConfig, text: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let url = format!("{}/bot{}/sendMessage", cfg.api_base, cfg.bot_token);
+#[derive(Subcommand)]
+enum CheckTarget {
+    /// SMART health of disks (smartctl) and the state of ZFS pools (zpool)
+    Storage {
+        /// Check this disk with smartctl (repeatable; default: all that
+        /// smartctl --scan finds)
+        #[arg(long = "device", value_name = "DEV")]
+        devices: Vec<String>,
 
-    let host = get().unwrap_or_default().to_string_lossy().to_string();
-    let ts = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
-    let body = format_message(&ts, &host, text);
-    client
-        .post(&url)
-        .json(&telegram_payload(&cfg.chat_id, &body))
-        .send()?;
-    Ok(())
+        /// Skip the SMART checks
+        #[arg(long)]
+        no_smart: bool,
+
+        /// Skip the ZFS pool checks
+        #[arg(long)]
+        no_zfs: bool,
+    },
 }
 
-fn start_notifier(cfg: TgConfig) -> (mpsc::Sender<String>, thread::JoinHandle<()>) {
-    let (tx, rx) = mpsc::channel::<String>();
-    let client = Client::builder()
-        .timeout(std::time::Duration::from_secs(10))
-        .build()
-        .unwrap_or_else(|_| Client::new());
-    let handle = thread::spawn(move || {
-        for msg in rx {
-            if let Err(e) = tg_send(&client, &cfg, &msg) {
-                eprintln!("Failed to send telegram message: {e}");
-            }
+impl CheckTarget {
+    fn name(&self) -> &'static str {
+        match self {
+            CheckTarget::Storage { .. } => "storage",
         }
-    });
-    (tx, handle)
+    }
 }
 
-fn read_stream<R: Read, W: Write>(
-    mut reader: R,
-    mut writer: W,
-    tee: bool,
-) -> std::io::Result<Vec<u8>> {
-    // Limit capture to ~16KB
-    const MAX_CAPTURE: usize = 16 * 1024;
-    let mut buf = Vec::new();
-    let mut chunk = [0u8; 4096];
-    loop {
-        let read = reader.read(&mut chunk)?;
-        if read == 0 {
-            break;
-        }
-        if tee {
-            writer.write_all(&chunk[..read])?;
-            writer.flush().ok();
-        }
-        buf.extend_from_slice(&chunk[..read]);
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Report missing and malformed settings, typos in variable names and
+    /// settings that cancel each other out
+    Check,
+}
 
-        // If buffer grows too large, truncate the beginning
-        if buf.len() > MAX_CAPTURE * 2 {
-            buf.drain(..buf.len() - MAX_CAPTURE);
-        }
+#[derive(Subcommand)]
+enum MaintenanceAction {
+    /// Start it (or change it, if it is on) for every run on the host
+    On {
+        /// End it by itself after this long, e.g. 90m or 2h
+        #[arg(long = "for", value_name = "DURATION", value_parser = event::parse_duration)]
+        length: Option<std::time::Duration>,
+
+        /// Send notifications as info, without a sound, instead of
+        /// suppressing them
+        #[arg(long)]
+        downgrade: bool,
+
+        /// What the maintenance is, shown by status
+        #[arg(long, value_name = "TEXT")]
+        reason: Option<String>,
+    },
+    /// End it, sending how many notifications it held back
+    Off,
+    /// Say whether it is on, since when and until when
+    Status,
+}
+
+#[derive(Subcommand)]
+enum MuteAction {
+    /// Mute the runs on the host that every condition given holds for
+    Add {
+        /// Jobs whose name matches this glob, e.g. 'backup-*'
+        #[arg(long, value_name = "GLOB")]
+        job: Option<glob::Pattern>,
+
+        /// Hosts whose name matches this glob
+        #[arg(long, value_name = "GLOB")]
+        host: Option<glob::Pattern>,
+
+        /// Only between these times of day, e.g. 09:00-17:00
+        #[arg(long, value_name = "START-END")]
+        hours: Option<quiet::Hours>,
+
+        /// Unmute by itself after this long, e.g. 90m or 2h
+        #[arg(long = "for", value_name = "DURATION", value_parser = event::parse_duration)]
+        length: Option<std::time::Duration>,
+
+        /// Why it is muted, shown by list
+        #[arg(long, value_name = "TEXT")]
+        reason: Option<String>,
+    },
+    /// List what is muted, by SENTINEL_MUTE and with add
+    List,
+    /// Unmute what add muted
+    Remove {
+        /// Its number, as list shows it
+        id: u32,
+    },
+}
+
+#[derive(Subcommand)]
+enum AuthAction {
+    /// Store the bot token and chat id (prompted for, or read from stdin)
+    Set { service: AuthService },
+    /// Remove the stored bot token and chat id
+    Delete { service: AuthService },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum AuthService {
+    Telegram,
+}
+
+/// Reads one line from stdin, prompting (without echo, if `secret`) when it
+/// is a terminal.
+fn prompt(label: &str, secret: bool) -> std::io::Result<String> {
+    let tty = std::io::stdin().is_terminal();
+    if tty {
+        eprint!("{label}: ");
     }
-    Ok(buf)
+    let stty = |arg: &str| {
+        std::process::Command::new("stty")
+            .arg(arg)
+            .stdin(std::process::Stdio::inherit())
+            .status()
+            .ok();
+    };
+    if tty && secret {
+        stty("-echo");
+    }
+    let mut line = String::new();
+    let read = std::io::stdin().read_line(&mut line);
+    if tty && secret {
+        stty("echo");
+        eprintln!();
+    }
+    read?;
+    Ok(line.trim().to_string())
 }
 
-fn run_bash_with_tee(command: &str, tee: bool) -> std::io::Result<Output> {
-    let mut child = Command::new("bash")
-        .arg("-c")
-        .arg(command)
-        .stdin(Stdio::inherit())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()?;
-
-    let stdout = child
-        .stdout
-        .take()
-        .ok_or_else(|| std::io::Error::other("Failed to capture stdout"))?;
-    let stderr = child
-        .stderr
-        .take()
-        .ok_or_else(|| std::io::Error::other("Failed to capture stderr"))?;
-
-    let stdout_handle = std::thread::spawn(move || read_stream(stdout, std::io::stdout(), tee));
-    let stderr_handle = std::thread::spawn(move || read_stream(stderr, std::io::stderr(), tee));
-
-    let status = child.wait()?;
-    let out_buf = stdout_handle
-        .join()
-        .map_err(|_| std::io::Error::other("Failed to capture stdout"))?;
-    let err_buf = stderr_handle
-        .join()
-        .map_err(|_| std::io::Error::other("Failed to capture stderr"))?;
-
-    Ok(Output {
-        status,
-        stdout: out_buf?,
-        stderr: err_buf?,
-    })
+/// Asks for what `init` writes, writes it and sends a test message with
+/// exactly those settings. Returns `doctor`'s exit code.
+fn run_init(force: bool) -> Result<i32, String> {
+    let path = init::path()?;
+    if path.exists() && !force {
+        return Err(format!(
+            "{} exists already; --force replaces it",
+            path.display()
+        ));
+    }
+    let ask = |label: &str, secret: bool| prompt(label, secret).map_err(|e| e.to_string());
+    let mut values = Vec::new();
+    let token = ask("Bot token (from @BotFather)", true)?;
+    let chat_id = ask("Chat ID", false)?;
+    if token.is_empty() || chat_id.is_empty() {
+        return Err("both the bot token and the chat id are needed".to_string());
+    }
+    values.push(("TG_BOT_TOKEN", token));
+    values.push(("TG_CHAT_ID", chat_id));
+    let overflow = ask(
+        "Long messages: truncate, split or document [truncate]",
+        false,
+    )?;
+    if !overflow.is_empty() {
+        overflow.parse::<sentinel_rs::fit::Overflow>()?;
+        values.push(("SENTINEL_TELEGRAM_OVERFLOW", overflow));
+    }
+    let proxy = ask("Proxy (blank for none)", false)?;
+    if !proxy.is_empty() {
+        values.push(("SENTINEL_PROXY", proxy));
+    }
+    let grafana = ask("Grafana URL, for annotations (blank to skip)", false)?;
+    if !grafana.is_empty() {
+        values.push(("GRAFANA_URL", grafana));
+        values.push(("GRAFANA_TOKEN", ask("Grafana token", true)?));
+        let uid = ask("Grafana dashboard UID (blank for org-wide)", false)?;
+        if !uid.is_empty() {
+            values.push(("GRAFANA_DASHBOARD_UID", uid));
+        }
+    }
+    let otlp = ask("OTLP endpoint, for traces (blank to skip)", false)?;
+    if !otlp.is_empty() {
+        values.push(("OTEL_EXPORTER_OTLP_ENDPOINT", otlp));
+    }
+    init::write(&path, &init::render(&values), force)?;
+    eprintln!("Wrote {}.", path.display());
+
+    // The variables go to doctor as well, so that ones already set in this
+    // environment cannot stand in for what was just written.
+    let exe = env::current_exe().map_err(|e| format!("cannot find sentinel-rs itself: {e}"))?;
+    let status = std::process::Command::new(exe)
+        .args(["doctor", "--send-test"])
+        .envs(values)
+        .status()
+        .map_err(|e| format!("cannot run sentinel-rs doctor: {e}"))?;
+    Ok(status.code().unwrap_or(1))
 }
 
-fn run_bash(command: &str) -> std::io::Result<Output> {
-    run_bash_with_tee(command, true).map_err(|e| {
-        std::io::Error::new(
-            e.kind(),
-            format!("Failed to run bash command '{command}': {e}"),
-        )
-    })
+/// The settings for talking to the bot before there is a chat id, and a
+/// client whose timeout leaves room for a `getUpdates` long poll.
+fn bot_config(
+    token: &str,
+    proxy: Option<&str>,
+) -> Result<(TgConfig, reqwest::blocking::Client), String> {
+    let mut cfg = TgConfig::new(token, "", &config::api_base()?);
+    cfg.http = config::HttpOptions::from_env()?;
+    if proxy.is_some() {
+        cfg.http.proxy = proxy.map(str::to_string);
+    }
+    cfg.http.timeout = Some(setup::POLL + Duration::from_secs(10));
+    let client = http_client_with(&cfg.http)?;
+    Ok((cfg, client))
 }
 
-fn tail_bytes(buf: &[u8], max: usize) -> String {
-    if buf.len() <= max {
-        String::from_utf8_lossy(buf).into_owned()
-    } else {
-        let slice = &buf[buf.len() - max..];
-        format!(
-            "… (truncated, showing last {} bytes)\n{}",
-            max,
-            String::from_utf8_lossy(slice)
-        )
+/// Prints the chats of `telegram chats`; false if there are none.
+fn run_chats(token_file: Option<&std::path::Path>, proxy: Option<&str>) -> Result<bool, String> {
+    let (cfg, client) = bot_config(&config::bot_token(token_file)?, proxy)?;
+    let chats = setup::recent_chats(&client, &cfg)?;
+    if chats.is_empty() {
+        eprintln!(
+            "No chats in the bot's recent updates. Send it a message, or add it to the group or channel, and try again. (In a group, a bot in privacy mode only sees commands and messages that mention it.)"
+        );
+        return Ok(false);
+    }
+    println!("{:<16} {:<11} NAME", "CHAT ID", "TYPE");
+    for chat in chats {
+        println!("{:<16} {:<11} {}", chat.id, chat.kind, chat.name);
     }
+    Ok(true)
 }
 
-fn print_help() {
+/// Walks through `setup telegram`, saying at each step what it found.
+fn run_setup(proxy: Option<&str>, wait: Duration) -> Result<(), String> {
+    let path = init::path()?;
+    eprintln!("Create a bot by sending /newbot to @BotFather, which then gives you its token.");
+    let token = prompt("Bot token", true).map_err(|e| e.to_string())?;
+    if token.is_empty() {
+        return Err("the bot token is needed".to_string());
+    }
+    let (mut cfg, client) = bot_config(&token, proxy)?;
+    let bot = setup::bot_name(&client, &cfg)?;
     eprintln!(
-        "Usage: sentinel-rs [--help] [--version] [-- <command>...]\n\
-Runs a command via bash -c and sends Telegram notifications.\n\n\
-Examples:\n\
-  sentinel-rs -- \"echo hello\"\n\
-  sentinel-rs -- ls -la\n\
-  sentinel-rs -- --help   # runs a command named \"--help\""
+        "Now send {bot} a message, or add it to the group or channel the notifications should go to. Waiting for up to {} seconds...",
+        wait.as_secs()
     );
+    let chat = setup::wait_for_chat(&client, &cfg, wait)?;
+    eprintln!("Found {} (chat id {}).", chat.name, chat.id);
+    init::update(
+        &path,
+        &[("TG_BOT_TOKEN", token), ("TG_CHAT_ID", chat.id.clone())],
+    )?;
+    eprintln!("Stored the bot token and chat id in {}.", path.display());
+    cfg.chat_id = chat.id;
+    setup::confirm(&client, &cfg)?;
+    eprintln!("Sent a confirmation message.");
+    Ok(())
 }
 
-fn main() {
-    env_logger::init();
-    let args: Vec<String> = env::args().skip(1).collect();
-    if args.is_empty() {
-        print_help();
-        std::process::exit(2);
+fn run_auth(action: &AuthAction) -> Result<(), String> {
+    match action {
+        AuthAction::Set {
+            service: AuthService::Telegram,
+        } => {
+            let token = prompt("Bot token", true).map_err(|e| e.to_string())?;
+            let chat_id = prompt("Chat ID", false).map_err(|e| e.to_string())?;
+            if token.is_empty() || chat_id.is_empty() {
+                return Err("both the bot token and the chat id are needed".to_string());
+            }
+            auth::store(auth::BOT_TOKEN, &token)?;
+            auth::store(auth::CHAT_ID, &chat_id)?;
+            eprintln!("Stored the Telegram credentials in the OS keyring.");
+        }
+        AuthAction::Delete {
+            service: AuthService::Telegram,
+        } => {
+            auth::delete(auth::BOT_TOKEN)?;
+            auth::delete(auth::CHAT_ID)?;
+            eprintln!("Removed the Telegram credentials from the OS keyring.");
+        }
     }
+    Ok(())
+}
 
-    if args.len() == 1 && (args[0] == "--help" || args[0] == "-h") {
-        print_help();
-        return;
+fn run_maintenance(action: &MaintenanceAction, token_file: Option<&Path>) -> Result<(), String> {
+    match action {
+        MaintenanceAction::On {
+            length,
+            downgrade,
+            reason,
+        } => {
+            let handling = if *downgrade {
+                maintenance::Handling::Downgrade
+            } else {
+                maintenance::Handling::Suppress
+            };
+            let window = maintenance::start(handling, *length, reason.clone())?;
+            match maintenance::format_until(&window) {
+                Some(until) => println!("Maintenance is on until {until}."),
+                None => println!("Maintenance is on until sentinel-rs maintenance off."),
+            }
+        }
+        MaintenanceAction::Off => {
+            let Some((window, summary)) = maintenance::stop()? else {
+                println!("Maintenance is not on.");
+                return Ok(());
+            };
+            println!("{summary}");
+            if window.handled() > 0 {
+                let cfg = load_tg_config_with(token_file).map_err(|e| e.to_string())?;
+                http_client_with(&cfg.http)
+                    .and_then(|client| tg_send(&client, &cfg, &summary).map_err(|e| e.to_string()))
+                    .map_err(|e| format!("cannot send the summary: {e}"))?;
+            }
+        }
+        MaintenanceAction::Status => match maintenance::status() {
+            Some(status) => println!("{status}"),
+            None => println!("Maintenance is not on."),
+        },
     }
+    Ok(())
+}
 
-    if args.len() == 1 && (args[0] == "--version" || args[0] == "-V") {
-        eprintln!("sentinel-rs {}", env!("CARGO_PKG_VERSION"));
-        return;
+fn run_mute(action: &MuteAction) -> Result<(), String> {
+    match action {
+        MuteAction::Add {
+            job,
+            host,
+            hours,
+            length,
+            reason,
+        } => {
+            let rule = mute::Rule {
+                job: job.clone(),
+                host: host.clone(),
+                hours: *hours,
+            };
+            if rule == mute::Rule::default() {
+                return Err("that mutes everything; give --job, --host or --hours".to_string());
+            }
+            let added = mute::add(&rule, *length, reason.clone())?;
+            println!("Muted {} as {}.", added.rule, added.id);
+        }
+        MuteAction::List => {
+            let lines = mute::list(&mute::from_env()?);
+            if lines.is_empty() {
+                println!("Nothing is muted.");
+            }
+            lines.iter().for_each(|line| println!("{line}"));
+        }
+        MuteAction::Remove { id } => {
+            if !mute::remove(*id)? {
+                return Err(format!(
+                    "nothing is muted as {id} (see sentinel-rs mute list)"
+                ));
+            }
+            println!("Unmuted {id}.");
+        }
     }
+    Ok(())
+}
 
-    let command_args = if args[0] == "--" {
-        if args.len() == 1 {
-            eprintln!("Missing command after --.");
-            print_help();
-            std::process::exit(2);
-        }
-        &args[1..]
-    } else {
-        &args[..]
+fn run_check(target: &CheckTarget, cfg: TgConfig, opts: RunOptions) -> i32 {
+    let statuses = match target {
+        CheckTarget::Storage {
+            devices,
+            no_smart,
+            no_zfs,
+        } => check::storage::check(&check::storage::Checks {
+            devices: devices.clone(),
+            smart: !no_smart,
+            zfs: !no_zfs,
+        }),
     };
-    let command = command_args.join(" ");
-
-    let tg_config = match load_tg_config() {
-        Ok(cfg) => cfg,
+    match statuses {
+        Ok(statuses) => check::report(target.name(), &statuses, cfg, opts),
         Err(e) => {
-            eprintln!("Failed to load Telegram configuration: {e}");
-            std::process::exit(2);
+            eprintln!("{e}");
+            2
         }
+    }
+}
+
+/// The jobs listed in `from`, or on stdin for `-`.
+fn read_jobs(from: &std::path::Path) -> Result<Vec<String>, String> {
+    let text = if from.as_os_str() == "-" {
+        std::io::read_to_string(std::io::stdin())
+            .map_err(|e| format!("Failed to read stdin: {e}"))?
+    } else {
+        std::fs::read_to_string(from)
+            .map_err(|e| format!("Failed to read jobs file {}: {e}", from.display()))?
     };
+    let jobs = steps::parse_jobs(&text);
+    if jobs.is_empty() {
+        return Err(format!("No jobs listed in {}.", from.display()));
+    }
+    Ok(jobs)
+}
 
-    let (notifier, handle) = start_notifier(tg_config);
-    notifier.send(format!("Started\n{command}")).ok();
+/// Most of a message piped to `notify --stdin` that is sent; like command
+/// output, it is the end that matters.
+const MAX_STDIN: usize = 3000;
 
-    let output = match run_bash(&command) {
-        Ok(output) => output,
-        Err(e) => {
-            notifier
-                .send(format!("Failed to execute command: {e}"))
-                .ok();
-            info!("Failed to execute command: {e}");
-            drop(notifier);
-            handle.join().ok();
-            std::process::exit(1);
+/// The text of a `notify` message: `message` and, with `stdin`, what is
+/// piped in.
+fn notify_text(message: &[String], stdin: bool) -> Result<String, String> {
+    let mut parts = Vec::new();
+    if !message.is_empty() {
+        parts.push(message.join(" "));
+    }
+    if stdin {
+        let mut input = Vec::new();
+        std::io::Read::read_to_end(&mut std::io::stdin(), &mut input)
+            .map_err(|e| format!("Failed to read stdin: {e}"))?;
+        let input = tail_bytes(&input, MAX_STDIN);
+        let input = input.trim_end();
+        if !input.is_empty() {
+            parts.push(input.to_string());
         }
-    };
+    }
+    if parts.is_empty() {
+        return Err("Nothing to send: give a message or pipe one in with --stdin".to_string());
+    }
+    Ok(parts.join("\n"))
+}
 
-    let exit_code = match output.status.code() {
-        Some(0) => {
-            notifier
-                .send(format!(
-                    "Finished successfully with exit code 0.\nStdout:\n{}\nStderr:\n{}",
-                    tail_bytes(&output.stdout, 1500),
-                    tail_bytes(&output.stderr, 1500)
-                ))
-                .ok();
-            info!("Command finished successfully with exit code 0");
-            0
-        }
-        Some(code) => {
-            notifier
-                .send(format!(
-                    "Failed with exit code: {}.\nStdout:\n{}\nStderr:\n{}",
-                    code,
-                    tail_bytes(&output.stdout, 1500),
-                    tail_bytes(&output.stderr, 1500)
-                ))
-                .ok();
-            info!(
-                "Failed with exit code: {}. Stdout: {} Stderr: {}",
-                code,
-                String::from_utf8_lossy(&output.stdout),
-                String::from_utf8_lossy(&output.stderr)
-            );
-            code
-        }
-        None => {
-            notifier
-                .send(format!(
-                    "Process terminated by signal.\nStdout:\n{}\nStderr:\n{}",
-                    tail_bytes(&output.stdout, 1500),
-                    tail_bytes(&output.stderr, 1500)
-                ))
-                .ok();
-            info!("Process terminated by signal.");
-            128
+fn parse_positive(s: &str) -> Result<usize, String> {
+    match s.parse() {
+        Ok(0) | Err(_) => Err("expected a whole number of at least 1".to_string()),
+        Ok(n) => Ok(n),
+    }
+}
+
+fn parse_cli() -> Cli {
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) if matches!(e.kind(), ErrorKind::DisplayHelp | ErrorKind::DisplayVersion) => {
+            // stdout belongs to the wrapped command, so help goes to stderr.
+            eprint!("{e}");
+            std::process::exit(0);
         }
+        Err(e) => e.exit(),
     };
-    drop(notifier);
-    handle.join().ok();
-    std::process::exit(exit_code);
+    if cli.mode.is_none() && cli.command.is_empty() && cli.steps.is_empty() {
+        if env::args().skip(1).any(|arg| arg == "--") {
+            eprintln!("Missing command after --.");
+        }
+        eprint!("{}", Cli::command().render_help());
+        std::process::exit(2);
+    }
+    cli
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn env_required_present_returns_value() {
-        let key = "SENTINEL_RS_TEST_ENV";
-        let value = "test_value".to_string();
-        let prior = std::env::var(key).ok();
-        unsafe {
-            std::env::set_var(key, &value);
-        }
-        let result = env_required(key).unwrap();
-        unsafe {
-            if let Some(prior) = prior {
-                std::env::set_var(key, prior);
-            } else {
-                std::env::remove_var(key);
-            }
+/// What sentinel-rs exits with when that is not simply the command's exit
+/// code (`--exit-on-*`).
+struct ExitPolicy {
+    spawn_error: i32,
+    signal: Option<i32>,
+    undelivered: Option<i32>,
+}
+
+impl ExitPolicy {
+    fn new(cli: &Cli) -> Self {
+        ExitPolicy {
+            spawn_error: cli.exit_on_spawn_error.into(),
+            signal: cli.exit_on_signal.map(i32::from),
+            undelivered: cli.exit_on_undelivered.map(i32::from),
         }
-        assert_eq!(result, value);
     }
 
-    #[test]
-    fn env_required_missing_returns_err() {
-        let key = "SENTINEL_RS_TEST_MISSING_ENV";
-        unsafe {
-            std::env::remove_var(key);
-        }
-        let result = env_required(key);
-        assert!(result.is_err());
+    /// The exit code for a run that ended with `result`.
+    fn run(&self, result: &std::io::Result<std::process::Output>) -> i32 {
+        use std::os::unix::process::ExitStatusExt;
+        let code = match result {
+            Ok(output) => match (output.status.signal(), self.signal) {
+                (Some(_), Some(code)) => code,
+                _ => exit_code(output),
+            },
+            Err(_) => self.spawn_error,
+        };
+        self.delivered(code)
     }
 
-    #[test]
-    fn load_tg_config_rejects_empty_values() {
-        let token_key = "TG_BOT_TOKEN";
-        let chat_key = "TG_CHAT_ID";
-        let prior_token = std::env::var(token_key).ok();
-        let prior_chat = std::env::var(chat_key).ok();
-        unsafe {
-            std::env::set_var(token_key, "   ");
-            std::env::set_var(chat_key, "123");
+    /// `code`, unless it is 0 and a notification did not go out.
+    fn delivered(&self, code: i32) -> i32 {
+        match self.undelivered {
+            Some(undelivered) if code == 0 && !notifier::all_delivered() => undelivered,
+            _ => code,
         }
-        let result = load_tg_config();
-        unsafe {
-            if let Some(prior) = prior_token {
-                std::env::set_var(token_key, prior);
-            } else {
-                std::env::remove_var(token_key);
+    }
+}
+
+fn main() {
+    let cli = parse_cli();
+    if let Some(url) = &cli.api_base {
+        // Set rather than passed on, so that every way of loading the
+        // config sees it; the settings file only fills in what is not set.
+        // SAFETY: no other thread has been started yet.
+        unsafe { env::set_var("TG_API_BASE", url) };
+    }
+    if let Some(Mode::Info) = &cli.mode {
+        let settings = config::settings().unwrap_or_else(|e| {
+            eprintln!("{e}");
+            Vec::new()
+        });
+        print!("{}", info::report(&settings));
+        return;
+    }
+    if !matches!(cli.mode, Some(Mode::Init { .. })) {
+        match config::settings() {
+            Ok(settings) => {
+                for (key, value) in settings {
+                    if env::var_os(&key).is_none() {
+                        // SAFETY: no other thread has been started yet.
+                        unsafe { env::set_var(key, value) };
+                    }
+                }
             }
-            if let Some(prior) = prior_chat {
-                std::env::set_var(chat_key, prior);
-            } else {
-                std::env::remove_var(chat_key);
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(2);
             }
         }
-        assert!(result.is_err());
     }
-
-    #[test]
-    fn validate_tg_tokens_set_accepts_non_empty_values() {
-        let token_key = "TG_BOT_TOKEN";
-        let chat_key = "TG_CHAT_ID";
-        let prior_token = std::env::var(token_key).ok();
-        let prior_chat = std::env::var(chat_key).ok();
-        unsafe {
-            std::env::set_var(token_key, "token");
-            std::env::set_var(chat_key, "123");
+    let verbosity = cli.verbose.min(10) as i8 - cli.quiet.min(10) as i8;
+    if let Err(e) = logging::init(cli.log_format, verbosity, cli.self_log.as_deref()) {
+        eprintln!("{e}");
+        std::process::exit(2);
+    }
+    let zone = match (cli.utc, cli.timezone) {
+        (true, _) => clock::Zone::Utc,
+        (false, Some(tz)) => clock::Zone::Named(tz),
+        (false, None) => clock::Zone::Local,
+    };
+    clock::set(zone, cli.time_format.clone());
+    let icons = if cli.no_icons {
+        icon::Icons::none()
+    } else {
+        cli.icons
+            .iter()
+            .fold(icon::Icons::default(), icon::Icons::with)
+    };
+    icon::set(icons);
+    locale::set(cli.locale.unwrap_or_default());
+    echo::set(echo::Policy {
+        hide: cli.hide_command,
+        redact: cli.redact.clone(),
+        max_chars: cli.command_max_chars,
+    });
+    if let Err(e) = host::set(cli.host_label.clone()) {
+        eprintln!("{e}");
+        std::process::exit(2);
+    }
+    if let Some(Mode::Auth { action }) = &cli.mode {
+        if let Err(e) = run_auth(action) {
+            eprintln!("{e}");
+            std::process::exit(2);
         }
-        let result = load_tg_config();
-        unsafe {
-            if let Some(prior) = prior_token {
-                std::env::set_var(token_key, prior);
-            } else {
-                std::env::remove_var(token_key);
+        return;
+    }
+    if let Some(Mode::Maintenance { action }) = &cli.mode {
+        if let Err(e) = run_maintenance(action, cli.token_file.as_deref()) {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Some(Mode::Mute { action }) = &cli.mode {
+        if let Err(e) = run_mute(action) {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Some(Mode::Attach { run, stdin }) = &cli.mode {
+        if let Err(e) = control::attach_to(run.as_deref(), *stdin) {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Some(Mode::Completions { shell }) = &cli.mode {
+        clap_complete::generate(
+            *shell,
+            &mut Cli::command(),
+            "sentinel-rs",
+            &mut std::io::stdout(),
+        );
+        return;
+    }
+    if let Some(Mode::Config {
+        action: ConfigAction::Check,
+    }) = &cli.mode
+    {
+        let problems = validate::check(cli.token_file.as_deref());
+        problems.iter().for_each(Finding::print);
+        if problems.is_empty() {
+            println!("No problems found.");
+        }
+        let failing = problems.iter().any(|p| p.health == Health::Failing);
+        std::process::exit(if failing { 1 } else { 0 });
+    }
+    if let Some(Mode::Ctl { run, request }) = &cli.mode {
+        match control::request(run.as_deref(), &request.join(" ")) {
+            Ok(reply) => print!("{reply}"),
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(1);
             }
-            if let Some(prior) = prior_chat {
-                std::env::set_var(chat_key, prior);
-            } else {
-                std::env::remove_var(chat_key);
+        }
+        return;
+    }
+    if let Some(Mode::Dashboard { addr }) = &cli.mode {
+        match dashboard::serve(addr) {
+            Ok(addr) => eprintln!("Serving the dashboard on http://{addr}/"),
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(2);
             }
         }
-        assert!(result.is_ok());
+        loop {
+            std::thread::park();
+        }
     }
-
-    #[test]
-    fn format_message_includes_fields() {
-        let body = format_message("2025-01-01 00:00:00", "host", "hello");
-        assert_eq!(body, "[2025-01-01 00:00:00] [host]\nhello");
+    if let Some(Mode::Init { force }) = &cli.mode {
+        match run_init(*force) {
+            Ok(code) => std::process::exit(code),
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(2);
+            }
+        }
     }
-
-    #[test]
-    fn telegram_payload_is_expected_shape() {
-        let payload = telegram_payload("123", "body");
-        assert_eq!(payload["chat_id"], "123");
-        assert_eq!(payload["text"], "body");
-        assert_eq!(payload["disable_web_page_preview"], true);
+    if let Some(Mode::Setup {
+        service: AuthService::Telegram,
+        wait,
+    }) = &cli.mode
+    {
+        if let Err(e) = run_setup(cli.proxy.as_deref(), Duration::from_secs(*wait)) {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+        return;
     }
-
-    #[test]
-    fn tail_bytes_truncates_correctly() {
-        let data = b"abcdefghijklmnopqrstuvwxyz";
-        let result = tail_bytes(data, 10);
-        assert_eq!(result, "… (truncated, showing last 10 bytes)\nqrstuvwxyz");
+    if let Some(Mode::Telegram {
+        action: TelegramAction::Chats,
+    }) = &cli.mode
+    {
+        match run_chats(cli.token_file.as_deref(), cli.proxy.as_deref()) {
+            Ok(true) => return,
+            Ok(false) => std::process::exit(1),
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(2);
+            }
+        }
     }
-
-    #[test]
-    fn tail_bytes_no_truncation() {
-        let data = b"hello";
-        let result = tail_bytes(data, 10);
-        assert_eq!(result, "hello");
+    if let Some(Mode::Doctor { send_test }) = &cli.mode {
+        let code = doctor::run(cli.token_file.as_deref(), cli.proxy.as_deref(), *send_test);
+        std::process::exit(code);
     }
-
-    #[test]
-    fn tail_bytes_exact_boundary() {
-        let data = b"exact10!!";
-        let result = tail_bytes(data, 9);
-        assert_eq!(result, "exact10!!");
+    if let Some(Mode::ShellHook {
+        shell,
+        min_duration,
+        ignore,
+    }) = &cli.mode
+    {
+        let exe = env::current_exe().unwrap_or_else(|_| PathBuf::from("sentinel-rs"));
+        print!("{}", shell::hook(*shell, &exe, *min_duration, ignore));
+        return;
     }
-
-    #[test]
-    fn tail_bytes_handles_non_utf8() {
-        let data = [0x66, 0xff, 0x6f];
-        let result = tail_bytes(&data, 10);
-        assert_eq!(result, String::from_utf8_lossy(&data));
+    if let Some(Mode::ShellDone {
+        exit_code,
+        ignore,
+        command,
+        ..
+    }) = &cli.mode
+        && !shell::worth_notifying(&command.join(" "), *exit_code, ignore)
+    {
+        return;
     }
+    let notify_text = match &cli.mode {
+        Some(Mode::Notify { message, stdin }) => match notify_text(message, *stdin) {
+            Ok(text) => Some(text),
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(2);
+            }
+        },
+        _ => None,
+    };
+    let jobs = match &cli.mode {
+        Some(Mode::Batch { from, .. }) => match read_jobs(from) {
+            Ok(jobs) => Some(jobs),
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(2);
+            }
+        },
+        _ => None,
+    };
+    let workflow = match &cli.mode {
+        Some(Mode::Pipeline {
+            action: PipelineAction::Run { file },
+        }) => match workflow::load(file) {
+            Ok(workflow) => Some(workflow),
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(2);
+            }
+        },
+        _ => None,
+    };
+    let (command, docker) = match &cli.mode {
+        Some(Mode::Docker { image, command }) => (command.join(" "), Some(DockerRun::new(image))),
+        Some(Mode::Batch { from, .. }) => {
+            (format!("sentinel-rs batch --from {}", from.display()), None)
+        }
+        Some(Mode::Check { target }) => (format!("sentinel-rs check {}", target.name()), None),
+        Some(Mode::ShellDone { command, .. }) => (command.join(" "), None),
+        Some(Mode::Notify { .. }) => ("sentinel-rs notify".to_string(), None),
+        Some(Mode::Test) => ("sentinel-rs test".to_string(), None),
+        Some(Mode::Digest) => ("sentinel-rs digest".to_string(), None),
+        Some(Mode::Escalate { .. }) => ("sentinel-rs escalate".to_string(), None),
+        Some(Mode::Pipeline { .. }) => (
+            workflow
+                .as_ref()
+                .and_then(|w| w.name.clone())
+                .unwrap_or_default(),
+            None,
+        ),
+        Some(
+            Mode::Attach { .. }
+            | Mode::Auth { .. }
+            | Mode::Completions { .. }
+            | Mode::Config { .. }
+            | Mode::Ctl { .. }
+            | Mode::Dashboard { .. }
+            | Mode::Doctor { .. }
+            | Mode::Info
+            | Mode::Init { .. }
+            | Mode::Maintenance { .. }
+            | Mode::Mute { .. }
+            | Mode::Setup { .. }
+            | Mode::Telegram { .. }
+            | Mode::ShellHook { .. },
+        ) => {
+            unreachable!("handled above")
+        }
+        None if !cli.steps.is_empty() => (cli.steps.join(" && "), None),
+        None => (cli.command.join(" "), None),
+    };
 
-    #[test]
-    fn run_bash_captures_stdout_and_stderr() {
-        let output = run_bash_with_tee("printf 'out'; printf 'err' 1>&2", false).unwrap();
-        assert!(output.status.success());
-        assert_eq!(output.stdout, b"out");
-        assert_eq!(output.stderr, b"err");
+    let mut tg_config = match load_tg_config_with(cli.token_file.as_deref()) {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            eprintln!("Failed to load Telegram configuration: {e}");
+            let others: Vec<_> = validate::check(cli.token_file.as_deref())
+                .into_iter()
+                .filter(|p| p.health == Health::Failing && p.detail != e.to_string())
+                .collect();
+            if !others.is_empty() {
+                eprintln!("Also:");
+                for problem in others {
+                    eprintln!("  {}: {}", problem.what, problem.detail);
+                }
+            }
+            eprintln!("sentinel-rs config check lists every problem.");
+            std::process::exit(2);
+        }
+    };
+    if cli.proxy.is_some() {
+        tg_config.http.proxy = cli.proxy.clone();
     }
-
-    #[test]
-    fn run_bash_captures_non_zero_exit() {
-        let output = run_bash_with_tee("exit 7", false).unwrap();
-        assert_eq!(output.status.code(), Some(7));
+    if let Err(e) = http_client_with(&tg_config.http) {
+        eprintln!("Failed to set up the HTTP client: {e}");
+        std::process::exit(2);
     }
 
-    #[test]
-    fn read_stream_no_tee_keeps_writer_empty() {
-        use std::io::Cursor;
-        let input_data = Cursor::new(b"hello world");
-        let mut output = Vec::new();
-        let buf = read_stream(input_data, &mut output, false).expect("Failed to read stream");
-        assert_eq!(buf, b"hello world");
-        assert!(output.is_empty());
+    if let Some(Mode::Digest) = &cli.mode {
+        match quiet::send_digest(&tg_config) {
+            Ok(0) => println!("Nothing is held."),
+            Ok(n) => println!("Sent the {n} notification(s) held."),
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+        }
+        return;
     }
-
-    #[test]
-    fn read_stream_copies_when_tee_true() {
-        use std::io::Cursor;
-        let input_data = Cursor::new(b"hello world");
-        let mut output = Vec::new();
-        let buf = read_stream(input_data, &mut output, true).expect("Failed to read stream");
-        assert_eq!(buf, b"hello world");
-        assert_eq!(output, b"hello world");
+    if let Some(Mode::Escalate { ack }) = &cli.mode {
+        let Some(policy) = &tg_config.escalation else {
+            eprintln!("Escalation is off: set SENTINEL_ESCALATE_AFTER.");
+            std::process::exit(2);
+        };
+        match escalate::run(&tg_config, policy, ack.as_deref()) {
+            Ok(report) => println!(
+                "Acknowledged {}, escalated {}, {} waiting.",
+                report.acknowledged, report.escalated, report.waiting
+            ),
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    let mut opts = match RunOptions::from_env() {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("Failed to load hooks: {e}");
+            std::process::exit(2);
+        }
+    };
+    crash::install(&tg_config, opts.templates.clone(), &command);
+    if let Some(Mode::Check { target }) = &cli.mode {
+        std::process::exit(run_check(target, tg_config, opts));
+    }
+    if let Some(Mode::Test) = &cli.mode {
+        let outcomes = canary::run(&tg_config, opts);
+        for outcome in &outcomes {
+            println!("{}", outcome.line());
+        }
+        std::process::exit(i32::from(outcomes.iter().any(|o| o.result.is_err())));
+    }
+    if let Some(Mode::ShellDone {
+        exit_code, seconds, ..
+    }) = &cli.mode
+    {
+        let elapsed = Duration::from_secs(*seconds);
+        shell::finished(&command, *exit_code, elapsed, tg_config, opts);
+        return;
+    }
+    let exits = ExitPolicy::new(&cli);
+    if let (Some(jobs), Some(Mode::Batch { parallel, .. })) = (&jobs, &cli.mode) {
+        let exit = steps::run_steps(&command, jobs, "jobs", Some(*parallel), tg_config, opts);
+        std::process::exit(exits.delivered(exit));
+    }
+    if let Some(workflow) = &workflow {
+        let exit = workflow::run(workflow, tg_config, opts);
+        std::process::exit(exits.delivered(exit));
+    }
+    if let Some(text) = notify_text {
+        sentinel_rs::notify(Event::message(&command, &text), tg_config, opts);
+        return;
+    }
+    let github = cli.github || cli.github_status || github::detected();
+    if github && let Err(e) = github::install(&mut opts, cli.github_status) {
+        eprintln!("Failed to set up GitHub integration: {e}");
+        std::process::exit(2);
+    }
+    let gitlab = cli.gitlab_status || cli.gitlab_comment;
+    if gitlab && let Err(e) = gitlab::install(&mut opts, cli.gitlab_status, cli.gitlab_comment) {
+        eprintln!("Failed to set up GitLab integration: {e}");
+        std::process::exit(2);
+    }
+    if let Some(addr) = &cli.metrics_addr
+        && let Err(e) = metrics::serve(addr.as_str())
+    {
+        eprintln!("Failed to serve metrics on {addr}: {e}");
+        std::process::exit(2);
+    }
+    if let Some(port) = cli.status_port
+        && let Err(e) = status::serve(port)
+    {
+        eprintln!("Failed to serve the status on port {port}: {e}");
+        std::process::exit(2);
+    }
+    if let Some(path) = cli.hosts {
+        let hosts = match std::fs::read_to_string(&path) {
+            Ok(text) => fanout::parse_hosts(&text),
+            Err(e) => {
+                eprintln!("Failed to read hosts file {}: {e}", path.display());
+                std::process::exit(2);
+            }
+        };
+        if hosts.is_empty() {
+            eprintln!("No hosts listed in {}.", path.display());
+            std::process::exit(2);
+        }
+        let exit = fanout::run_on_hosts(&command, &hosts, cli.concurrency, tg_config, opts);
+        std::process::exit(exits.delivered(exit));
+    }
+    opts.exec.ssh = cli.ssh;
+    opts.exec.k8s = cli.k8s.map(|image| KubeJob::new(&image, cli.k8s_namespace));
+    opts.exec.docker = docker;
+    let mut sandbox = if cli.sandbox {
+        Sandbox::strict()
+    } else {
+        Sandbox::default()
+    };
+    sandbox.read_only.extend(cli.read_only);
+    sandbox.no_network |= cli.no_network;
+    sandbox.no_new_privs |= cli.no_new_privs;
+    opts.exec.sandbox = sandbox;
+    opts.exec.ulimits = cli.ulimits;
+    opts.exec.read_buffer = cli.read_buffer;
+    opts.exec.flush = cli.flush;
+    opts.exec.merge_output = cli.merge_output;
+    opts.exec.sched = Scheduling {
+        nice: cli.nice,
+        ionice: cli.ionice,
+        cpuset: cli.cpuset.unwrap_or_default(),
+    };
+    let limits = cgroup::Limits {
+        memory_max: cli.memory_max,
+        cpu_max_percent: cli.cpu_max,
+    };
+    opts.limits = (!limits.is_empty()).then_some(limits);
+    opts.parser = cli.parser.map(|kind| kind.build(&command));
+    opts.criteria = criteria::Criteria {
+        ok_codes: cli.ok_codes.unwrap_or_default(),
+        success: cli.success_regex,
+        failure: cli.failure_regex,
+    };
+    opts.pre = cli.pre;
+    opts.post = cli.post;
+    opts.on_failure = cli.on_failure;
+    opts.spawn_error_code = cli.exit_on_spawn_error.into();
+    opts.notify_if = cli.notify_if;
+    opts.severity_rules.extend(cli.severity);
+    opts.artifacts = cli.artifacts;
+    opts.attach = cli.attach;
+    opts.show_env = cli.show_env;
+    opts.git = cli.git;
+    opts.cloud = cli.cloud;
+    opts.origin = cli.origin;
+    opts.control = cli.control;
+    if cli.user.is_some() || cli.group.is_some() {
+        match RunAs::resolve(cli.user.as_deref(), cli.group.as_deref()) {
+            Ok(run_as) => opts.exec.run_as = Some(run_as),
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(2);
+            }
+        }
+    }
+    opts.exec.env = match secrets::resolve(&cli.secrets, &http_client()) {
+        Ok(env) => env,
+        Err(e) => {
+            eprintln!("Failed to fetch secrets: {e}");
+            std::process::exit(2);
+        }
+    };
+    if !cli.steps.is_empty() {
+        let exit = steps::run_steps(&command, &cli.steps, "steps", cli.parallel, tg_config, opts);
+        std::process::exit(exits.delivered(exit));
     }
+    let result = run_and_notify(&command, tg_config, opts);
+    std::process::exit(exits.run(&result));
 }
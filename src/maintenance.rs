@@ -0,0 +1,376 @@
+//! Maintenance (`sentinel-rs maintenance on`, `SENTINEL_MAINTENANCE`): while
+//! a host is being worked on, its notifications are not sent to Telegram
+//! but counted, or with `--downgrade` sent as `info`, without a sound and
+//! not [escalated](crate::escalate). `sentinel-rs maintenance on` starts it
+//! for every run on the host, kept in `maintenance.json` in the [state
+//! directory](crate::config::state_dir), until `sentinel-rs maintenance
+//! off` or, with `--for`, until the time is up. `SENTINEL_MAINTENANCE=MODE`
+//! (`suppress` or `downgrade`), e.g. in the settings file, does the same
+//! while it is set. Once it is over, the first notification sent starts
+//! with how many there were, or `maintenance off` sends that itself. It is
+//! decided before the plugins run, so [quiet hours](crate::quiet) and
+//! [repeats](crate::repeat) leave those notifications alone. The other
+//! backends are not held back.
+
+use crate::clock;
+use crate::config;
+use crate::event::{Event, Severity, format_duration};
+use crate::locale;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// What happens to notifications during maintenance.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Handling {
+    /// They are not sent, only counted.
+    #[default]
+    Suppress,
+    /// They are sent as `info`, without a sound.
+    Downgrade,
+}
+
+impl FromStr for Handling {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "suppress" => Ok(Handling::Suppress),
+            "downgrade" => Ok(Handling::Downgrade),
+            _ => Err(format!(
+                "unknown maintenance mode {s:?} (expected suppress or downgrade)"
+            )),
+        }
+    }
+}
+
+/// A stretch of maintenance, started or going on.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct Window {
+    pub handling: Handling,
+    /// When it began, in seconds since the epoch.
+    pub since: i64,
+    /// When it ends by itself, if it does.
+    pub until: Option<i64>,
+    pub reason: Option<String>,
+    /// How many notifications it has not sent.
+    #[serde(default)]
+    pub suppressed: u32,
+    /// How many it has sent as `info`.
+    #[serde(default)]
+    pub downgraded: u32,
+    /// Whether `SENTINEL_MAINTENANCE` began it rather than `maintenance
+    /// on`: it then ends once that is unset.
+    #[serde(default)]
+    pub by_env: bool,
+}
+
+impl Window {
+    fn over(&self, now: i64, env: Option<Handling>) -> bool {
+        self.until.is_some_and(|until| until <= now) || (self.by_env && env.is_none())
+    }
+
+    /// How many notifications it suppressed or downgraded.
+    pub fn handled(&self) -> u32 {
+        self.suppressed + self.downgraded
+    }
+
+    fn counts(&self) -> String {
+        match (self.suppressed, self.downgraded) {
+            (0, 0) => locale::say("no notifications held back", &[]),
+            (n, 0) => locale::say("{n} notification(s) suppressed", &[("n", &n)]),
+            (0, n) => locale::say("{n} notification(s) downgraded", &[("n", &n)]),
+            (n, m) => locale::say(
+                "{n} notification(s) suppressed and {m} downgraded",
+                &[("n", &n), ("m", &m)],
+            ),
+        }
+    }
+
+    /// What is said once it is over, at `now`.
+    pub fn summary(&self, now: i64) -> String {
+        locale::say(
+            "Maintenance ended after {took}; {counts}.",
+            &[
+                ("took", &took(now - self.since)),
+                ("counts", &self.counts()),
+            ],
+        )
+    }
+
+    /// What `maintenance status` says about it at `now`.
+    pub fn status(&self, now: i64) -> String {
+        let mut status = format!(
+            "Maintenance ({}) has been on for {}; {} so far.",
+            match self.handling {
+                Handling::Suppress => "suppress",
+                Handling::Downgrade => "downgrade",
+            },
+            took(now - self.since),
+            self.counts()
+        );
+        match self.until {
+            Some(until) if until <= now => {
+                status.push_str(" It is over, and the next notification says so.")
+            }
+            Some(until) => status.push_str(&format!(" It ends in {}.", took(until - now))),
+            None if self.by_env => status.push_str(" It ends once SENTINEL_MAINTENANCE is unset."),
+            None => status.push_str(" It ends with sentinel-rs maintenance off."),
+        }
+        if let Some(reason) = &self.reason {
+            status.push_str(&format!("\nReason: {reason}"));
+        }
+        status
+    }
+}
+
+fn took(secs: i64) -> String {
+    format_duration(Duration::from_secs(secs.max(0) as u64))
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+/// `SENTINEL_MAINTENANCE`, if it is set.
+pub fn from_env() -> Result<Option<Handling>, String> {
+    match config::env_required("SENTINEL_MAINTENANCE") {
+        Ok(mode) => mode
+            .parse()
+            .map(Some)
+            .map_err(|e| format!("SENTINEL_MAINTENANCE: {e}")),
+        Err(_) => Ok(None),
+    }
+}
+
+fn maintenance_file() -> Option<PathBuf> {
+    config::state_dir().map(|dir| dir.join("maintenance.json"))
+}
+
+fn load(path: &Path) -> Option<Window> {
+    config::read_state(path)
+}
+
+/// Removed if there is no `window`.
+fn save(path: &Path, window: Option<&Window>) -> std::io::Result<()> {
+    match window {
+        Some(window) => config::write_state(path, window),
+        None => match std::fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        },
+    }
+}
+
+fn state_file() -> Result<PathBuf, String> {
+    maintenance_file()
+        .ok_or_else(|| "no state directory (set SENTINEL_STATE_DIR or HOME)".to_string())
+}
+
+/// Starts maintenance for every run on the host, for `length` if given. If
+/// it is on already, it goes on as this says, keeping what it counted.
+pub fn start(
+    handling: Handling,
+    length: Option<Duration>,
+    reason: Option<String>,
+) -> Result<Window, String> {
+    let file = state_file()?;
+    let _lock =
+        config::lock_state(&file).map_err(|e| format!("cannot lock {}: {e}", file.display()))?;
+    let now = now();
+    let (since, suppressed, downgraded) = load(&file)
+        .filter(|w| !w.over(now, None))
+        .map_or((now, 0, 0), |w| (w.since, w.suppressed, w.downgraded));
+    let window = Window {
+        handling,
+        since,
+        until: length
+            .map(|length| clock::seconds_after(now, length))
+            .transpose()?,
+        reason,
+        suppressed,
+        downgraded,
+        by_env: false,
+    };
+    save(&file, Some(&window)).map_err(|e| format!("cannot write {}: {e}", file.display()))?;
+    Ok(window)
+}
+
+/// Ends maintenance, returning it with what is to be said about it, if it
+/// was on.
+pub fn stop() -> Result<Option<(Window, String)>, String> {
+    let file = state_file()?;
+    let _lock =
+        config::lock_state(&file).map_err(|e| format!("cannot lock {}: {e}", file.display()))?;
+    let Some(window) = load(&file) else {
+        return Ok(None);
+    };
+    save(&file, None).map_err(|e| format!("cannot remove {}: {e}", file.display()))?;
+    let summary = window.summary(now());
+    Ok(Some((window, summary)))
+}
+
+/// What `maintenance status` says, if maintenance is on.
+pub fn status() -> Option<String> {
+    load(&maintenance_file()?).map(|window| window.status(now()))
+}
+
+/// When `window` ends by itself, written as notifications write times.
+pub fn format_until(window: &Window) -> Option<String> {
+    window
+        .until
+        .map(|until| clock::format(UNIX_EPOCH + Duration::from_secs(until.max(0) as u64)))
+}
+
+/// Brings `window` up to `now`, given `SENTINEL_MAINTENANCE`'s `env`, for a
+/// notification: the window it ended, if it did, and how the notification
+/// is handled, counting it.
+fn check(
+    window: &mut Option<Window>,
+    env: Option<Handling>,
+    now: i64,
+) -> (Option<Window>, Option<Handling>) {
+    let ended = window.take_if(|w| w.over(now, env));
+    if window.is_none()
+        && let Some(handling) = env
+    {
+        *window = Some(Window {
+            handling,
+            since: now,
+            until: None,
+            reason: None,
+            suppressed: 0,
+            downgraded: 0,
+            by_env: true,
+        });
+    }
+    let handling = window.as_mut().map(|w| {
+        match w.handling {
+            Handling::Suppress => w.suppressed += 1,
+            Handling::Downgrade => w.downgraded += 1,
+        }
+        w.handling
+    });
+    (ended, handling)
+}
+
+/// [Checks](check) the window in `file` for a notification at `now`, under
+/// its lock, and writes it back.
+fn update(file: &Path, env: Option<Handling>, now: i64) -> (Option<Window>, Option<Handling>) {
+    // Handled without the lock too; only the counts may then be off.
+    let _lock = config::lock_state(file)
+        .inspect_err(|e| tracing::warn!("Failed to lock {}: {e}", file.display()))
+        .ok();
+    let mut window = load(file);
+    let was = window.is_some();
+    let (ended, handling) = check(&mut window, env, now);
+    if (was || window.is_some())
+        && let Err(e) = save(file, window.as_ref())
+    {
+        tracing::warn!("Failed to update {}: {e}", file.display());
+    }
+    (ended, handling)
+}
+
+/// Decides how maintenance handles `event`, before the plugins see it, and
+/// marks it so; starts it with the summary of maintenance that has just
+/// ended. `env` is `SENTINEL_MAINTENANCE`'s.
+pub fn handle(env: Option<Handling>, event: &mut Event) {
+    let Some(file) = maintenance_file() else {
+        event.maintenance = env;
+        return;
+    };
+    let now = now();
+    let (ended, handling) = update(&file, env, now);
+    if let Some(ended) = ended.filter(|w| w.handled() > 0) {
+        event.text = format!("{}\n\n{}", ended.summary(now), event.text);
+    }
+    event.maintenance = handling;
+}
+
+/// Makes `event` what is sent to Telegram instead of it with `--downgrade`.
+pub fn downgrade(event: &mut Event) {
+    event.severity = Severity::Info;
+    event.text = format!("{}{}", locale::say("[maintenance] ", &[]), event.text);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(until: Option<i64>, by_env: bool) -> Option<Window> {
+        Some(Window {
+            handling: Handling::Suppress,
+            since: 0,
+            until,
+            reason: None,
+            suppressed: 0,
+            downgraded: 0,
+            by_env,
+        })
+    }
+
+    #[test]
+    fn maintenance_counts_until_it_is_over() {
+        let mut on = window(Some(7200), false);
+        for now in [60, 600, 7199] {
+            assert_eq!(check(&mut on, None, now), (None, Some(Handling::Suppress)));
+        }
+        let (ended, handling) = check(&mut on, None, 7200);
+        assert_eq!(handling, None);
+        assert!(on.is_none());
+        assert_eq!(
+            ended.unwrap().summary(7200),
+            "Maintenance ended after 2h 00m 00s; 3 notification(s) suppressed."
+        );
+
+        // SENTINEL_MAINTENANCE starts one of its own, over once it is unset.
+        let downgrade = Some(Handling::Downgrade);
+        assert_eq!(check(&mut on, downgrade, 10), (None, downgrade));
+        assert!(on.as_ref().is_some_and(|w| w.by_env && w.downgraded == 1));
+        let (ended, handling) = check(&mut on, None, 70);
+        assert_eq!((ended.unwrap().handled(), handling), (1, None));
+        assert!(on.is_none());
+
+        // `maintenance on` goes on however SENTINEL_MAINTENANCE is.
+        let mut open = window(None, false);
+        check(&mut open, None, 1_000_000);
+        assert_eq!(
+            check(&mut open, downgrade, 1_000_001).1,
+            Some(Handling::Suppress)
+        );
+        assert!(
+            open.unwrap()
+                .status(3600)
+                .contains("on for 1h 00m 00s; 2 notification(s) suppressed so far.")
+        );
+        assert!("off".parse::<Handling>().is_err());
+    }
+
+    #[test]
+    fn notifications_at_once_are_all_counted() {
+        let file =
+            std::env::temp_dir().join(format!("sentinel-maintenance-{}.json", std::process::id()));
+        save(&file, window(None, false).as_ref()).unwrap();
+        let runs: Vec<_> = (0..8)
+            .map(|_| {
+                let file = file.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..10 {
+                        update(&file, None, 60);
+                    }
+                })
+            })
+            .collect();
+        for run in runs {
+            run.join().unwrap();
+        }
+        assert_eq!(load(&file).unwrap().suppressed, 80);
+        std::fs::remove_file(&file).ok();
+        std::fs::remove_file(file.with_extension("lock")).ok();
+    }
+}
@@ -0,0 +1,224 @@
+//! `--metrics-addr`: a small Prometheus endpoint for scraping a run while it
+//! is in progress. The counters live in [`METRICS`] and are updated by the
+//! runner and the notifier whether or not anything serves them.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::thread;
+
+pub struct Metrics {
+    /// Commands currently running (several with `--hosts`).
+    pub running: AtomicI64,
+    /// Unix time the current run started, 0 before the first one.
+    pub started_unix_secs: AtomicU64,
+    pub stdout_bytes: AtomicU64,
+    pub stderr_bytes: AtomicU64,
+    pub notifications_sent: AtomicU64,
+    /// Deliveries (Telegram or plugin HTTP actions) that failed.
+    pub notifier_errors: AtomicU64,
+    /// Notifications dropped or coalesced away because the queue was full.
+    pub notifications_dropped: AtomicU64,
+}
+
+pub static METRICS: Metrics = Metrics {
+    running: AtomicI64::new(0),
+    started_unix_secs: AtomicU64::new(0),
+    stdout_bytes: AtomicU64::new(0),
+    stderr_bytes: AtomicU64::new(0),
+    notifications_sent: AtomicU64::new(0),
+    notifier_errors: AtomicU64::new(0),
+    notifications_dropped: AtomicU64::new(0),
+};
+
+fn unix_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+impl Metrics {
+    /// Counts a command as running until the guard is dropped.
+    pub fn run_started(&'static self) -> RunGuard {
+        if self.running.fetch_add(1, Ordering::Relaxed) == 0 {
+            self.started_unix_secs.store(unix_secs(), Ordering::Relaxed);
+        }
+        RunGuard(self)
+    }
+
+    /// The metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let running = self.running.load(Ordering::Relaxed).max(0);
+        let started = self.started_unix_secs.load(Ordering::Relaxed);
+        let elapsed = if running > 0 && started > 0 {
+            unix_secs().saturating_sub(started)
+        } else {
+            0
+        };
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(&str, u64)]| {
+            out.push_str(&format!("# HELP {name} {help}\n# TYPE {name} {kind}\n"));
+            for (labels, value) in samples {
+                out.push_str(&format!("{name}{labels} {value}\n"));
+            }
+        };
+        metric(
+            "sentinel_running_jobs",
+            "gauge",
+            "Commands currently running.",
+            &[("", running as u64)],
+        );
+        metric(
+            "sentinel_run_elapsed_seconds",
+            "gauge",
+            "Seconds since the current run started.",
+            &[("", elapsed)],
+        );
+        metric(
+            "sentinel_output_bytes_total",
+            "counter",
+            "Bytes of output read from the command.",
+            &[
+                (
+                    "{stream=\"stdout\"}",
+                    self.stdout_bytes.load(Ordering::Relaxed),
+                ),
+                (
+                    "{stream=\"stderr\"}",
+                    self.stderr_bytes.load(Ordering::Relaxed),
+                ),
+            ],
+        );
+        metric(
+            "sentinel_notifications_sent_total",
+            "counter",
+            "Notifications delivered to Telegram.",
+            &[("", self.notifications_sent.load(Ordering::Relaxed))],
+        );
+        metric(
+            "sentinel_notifier_errors_total",
+            "counter",
+            "Notification deliveries and plugin requests that failed.",
+            &[("", self.notifier_errors.load(Ordering::Relaxed))],
+        );
+        metric(
+            "sentinel_notifications_dropped_total",
+            "counter",
+            "Notifications dropped or coalesced because the queue was full.",
+            &[("", self.notifications_dropped.load(Ordering::Relaxed))],
+        );
+        out
+    }
+}
+
+pub struct RunGuard(&'static Metrics);
+
+impl Drop for RunGuard {
+    fn drop(&mut self) {
+        self.0.running.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Serves `GET /metrics` on `addr` from a background thread for the rest of
+/// the process's life.
+pub fn serve(addr: impl ToSocketAddrs) -> std::io::Result<std::net::SocketAddr> {
+    serve_routes(addr, |path, _| {
+        (path == "/metrics").then(|| ("text/plain; version=0.0.4", METRICS.render()))
+    })
+}
+
+/// What a small endpoint answers for a path and its query string: a
+/// content type and body, or `None` for a 404.
+pub type Route = fn(path: &str, query: &str) -> Option<(&'static str, String)>;
+
+/// Serves GET requests on `addr` with `route` from a background thread for
+/// the rest of the process's life.
+pub fn serve_routes(
+    addr: impl ToSocketAddrs,
+    route: Route,
+) -> std::io::Result<std::net::SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let local = listener.local_addr()?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            if let Err(e) = respond(stream, route) {
+                tracing::info!("HTTP request to {local} failed: {e}");
+            }
+        }
+    });
+    Ok(local)
+}
+
+fn respond(mut stream: TcpStream, route: Route) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(std::time::Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Drain the headers so the client sees a clean response.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+    let target = request_line.split_whitespace().nth(1).unwrap_or_default();
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let (status, content_type, body) = match route(path, query) {
+        Some((content_type, body)) => ("200 OK", content_type, body),
+        None => ("404 Not Found", "text/plain", "not found\n".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_uses_exposition_format() {
+        let metrics = Metrics {
+            running: AtomicI64::new(2),
+            started_unix_secs: AtomicU64::new(unix_secs() - 30),
+            stdout_bytes: AtomicU64::new(1024),
+            stderr_bytes: AtomicU64::new(0),
+            notifications_sent: AtomicU64::new(1),
+            notifier_errors: AtomicU64::new(3),
+            notifications_dropped: AtomicU64::new(0),
+        };
+        let text = metrics.render();
+        assert!(text.contains("# TYPE sentinel_running_jobs gauge\nsentinel_running_jobs 2\n"));
+        assert!(text.contains("sentinel_output_bytes_total{stream=\"stdout\"} 1024\n"));
+        assert!(text.contains("sentinel_notifier_errors_total 3\n"));
+        let elapsed: u64 = text
+            .lines()
+            .find_map(|l| l.strip_prefix("sentinel_run_elapsed_seconds "))
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((30..35).contains(&elapsed));
+    }
+
+    #[test]
+    fn serves_metrics_over_http() {
+        use std::io::Read;
+        let addr = serve("127.0.0.1:0").unwrap();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: x\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("sentinel_running_jobs"));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 404"));
+    }
+}
@@ -0,0 +1,244 @@
+//! Mute rules (`SENTINEL_MUTE`, `sentinel-rs mute`): a known-noisy job can
+//! be silenced without editing every crontab entry that runs it. A rule is
+//! one or more of
+//!
+//! - `job=GLOB`: the job's name matches `GLOB`, e.g. `job=backup-*`
+//! - `host=GLOB`: so does the host's, e.g. `host=staging-*`
+//! - `hours=START-END`: it is between the two times of day, e.g.
+//!   `hours=09:00-17:00`, in the zone notifications are written in
+//!
+//! separated by commas, all of which must hold. Those in `SENTINEL_MUTE`,
+//! separated by `;`, stay until it is changed; `sentinel-rs mute add` keeps
+//! one in `mutes.json` in the [state directory](crate::config::state_dir),
+//! for every run on the host, until `sentinel-rs mute remove` or, with
+//! `--for`, until the time is up. A muted run's notifications are not sent
+//! to Telegram, nor [held](crate::quiet) or [counted](crate::repeat) for
+//! it; the other backends still get them, with `muted` set.
+
+use crate::clock;
+use crate::config;
+use crate::event::{Event, format_duration};
+use crate::quiet::Hours;
+use glob::Pattern;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Rule {
+    pub job: Option<Pattern>,
+    pub host: Option<Pattern>,
+    pub hours: Option<Hours>,
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rule = Rule::default();
+        for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let glob = |g: &str| Pattern::new(g).map_err(|e| format!("{g:?}: {e}"));
+            match part.split_once('=') {
+                Some(("job", pattern)) => rule.job = Some(glob(pattern)?),
+                Some(("host", pattern)) => rule.host = Some(glob(pattern)?),
+                Some(("hours", hours)) => rule.hours = Some(hours.parse()?),
+                _ => {
+                    return Err(format!(
+                        "unknown condition {part:?} (expected job=GLOB, host=GLOB or hours=START-END)"
+                    ));
+                }
+            }
+        }
+        if rule == Rule::default() {
+            return Err(format!(
+                "{s:?} mutes everything; give job=GLOB, host=GLOB or hours=START-END"
+            ));
+        }
+        Ok(rule)
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = [
+            self.job.as_ref().map(|p| format!("job={p}")),
+            self.host.as_ref().map(|p| format!("host={p}")),
+            self.hours.map(|h| format!("hours={h}")),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        f.write_str(&parts.join(","))
+    }
+}
+
+impl Rule {
+    /// Whether it holds for a run of `job` on `host`, `minute` minutes into
+    /// the day.
+    pub fn holds(&self, job: &str, host: &str, minute: u32) -> bool {
+        self.job.as_ref().is_none_or(|p| p.matches(job))
+            && self.host.as_ref().is_none_or(|p| p.matches(host))
+            && self.hours.is_none_or(|h| h.contains(minute))
+    }
+}
+
+/// The rules in `SENTINEL_MUTE`, separated by `;`.
+pub fn from_env() -> Result<Vec<Rule>, String> {
+    let Ok(rules) = config::env_required("SENTINEL_MUTE") else {
+        return Ok(Vec::new());
+    };
+    rules
+        .split(';')
+        .filter(|rule| !rule.trim().is_empty())
+        .map(|rule| rule.parse().map_err(|e| format!("SENTINEL_MUTE: {e}")))
+        .collect()
+}
+
+/// A rule [added](add) with `sentinel-rs mute add`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Mute {
+    /// What `sentinel-rs mute remove` takes.
+    pub id: u32,
+    /// The [rule](Rule), as written.
+    pub rule: String,
+    /// When it stops holding, in seconds since the epoch, if it does.
+    pub until: Option<i64>,
+    pub reason: Option<String>,
+}
+
+impl Mute {
+    fn expired(&self, now: i64) -> bool {
+        self.until.is_some_and(|until| until <= now)
+    }
+
+    /// A line of `sentinel-rs mute list` at `now`.
+    pub fn line(&self, now: i64) -> String {
+        let mut line = format!("{:>3}  {}", self.id, self.rule);
+        if let Some(until) = self.until {
+            let left = Duration::from_secs((until - now).max(0) as u64);
+            line.push_str(&format!(" (another {})", format_duration(left)));
+        }
+        if let Some(reason) = &self.reason {
+            line.push_str(&format!(": {reason}"));
+        }
+        line
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+fn mutes_file() -> Option<PathBuf> {
+    config::state_dir().map(|dir| dir.join("mutes.json"))
+}
+
+fn load(path: &Path) -> Vec<Mute> {
+    config::read_state(path).unwrap_or_default()
+}
+
+fn state_file() -> Result<PathBuf, String> {
+    mutes_file().ok_or_else(|| "no state directory (set SENTINEL_STATE_DIR or HOME)".to_string())
+}
+
+/// Mutes runs on the host that `rule` holds for, for `length` if given.
+/// Those that have expired are dropped from the file meanwhile.
+pub fn add(rule: &Rule, length: Option<Duration>, reason: Option<String>) -> Result<Mute, String> {
+    let file = state_file()?;
+    let _lock =
+        config::lock_state(&file).map_err(|e| format!("cannot lock {}: {e}", file.display()))?;
+    let now = now();
+    let mut mutes = load(&file);
+    mutes.retain(|m| !m.expired(now));
+    let mute = Mute {
+        id: mutes.iter().map(|m| m.id).max().unwrap_or(0) + 1,
+        rule: rule.to_string(),
+        until: length
+            .map(|length| clock::seconds_after(now, length))
+            .transpose()?,
+        reason,
+    };
+    mutes.push(mute.clone());
+    config::write_state(&file, &mutes)
+        .map_err(|e| format!("cannot write {}: {e}", file.display()))?;
+    Ok(mute)
+}
+
+/// Unmutes what [`add`] muted as `id`, returning whether there was one.
+pub fn remove(id: u32) -> Result<bool, String> {
+    let file = state_file()?;
+    let _lock =
+        config::lock_state(&file).map_err(|e| format!("cannot lock {}: {e}", file.display()))?;
+    let mut mutes = load(&file);
+    let before = mutes.len();
+    mutes.retain(|m| m.id != id);
+    if mutes.len() == before {
+        return Ok(false);
+    }
+    config::write_state(&file, &mutes)
+        .map_err(|e| format!("cannot write {}: {e}", file.display()))?;
+    Ok(true)
+}
+
+/// What `sentinel-rs mute list` shows: the rules in `SENTINEL_MUTE`, then
+/// those added that have not expired.
+pub fn list(rules: &[Rule]) -> Vec<String> {
+    let now = now();
+    let added = mutes_file().map(|file| load(&file)).unwrap_or_default();
+    rules
+        .iter()
+        .map(|rule| format!("  -  {rule} (SENTINEL_MUTE)"))
+        .chain(
+            added
+                .iter()
+                .filter(|m| !m.expired(now))
+                .map(|m| m.line(now)),
+        )
+        .collect()
+}
+
+/// Whether `rules`, or a rule added that has not expired, holds for the run
+/// `event` is about now.
+pub fn muted(rules: &[Rule], event: &Event) -> bool {
+    let minute = clock::minute_of_day(SystemTime::now());
+    let holds = |rule: &Rule| rule.holds(&event.job, &event.host, minute);
+    if rules.iter().any(holds) {
+        return true;
+    }
+    let now = now();
+    let Some(file) = mutes_file() else {
+        return false;
+    };
+    load(&file)
+        .iter()
+        .filter(|m| !m.expired(now))
+        .filter_map(|m| m.rule.parse::<Rule>().ok())
+        .any(|rule| holds(&rule))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_hold_when_every_condition_does() {
+        let rule: Rule = "job=backup-*, host=db?,hours=23:00-07:00".parse().unwrap();
+        assert_eq!(rule.to_string(), "job=backup-*,host=db?,hours=23:00-07:00");
+        assert!(rule.holds("backup-nightly", "db1", 2 * 60));
+        assert!(!rule.holds("backup-nightly", "db1", 12 * 60));
+        assert!(!rule.holds("backup-nightly", "web1", 2 * 60));
+        assert!(!rule.holds("export", "db1", 2 * 60));
+        let job: Rule = "job=flaky".parse().unwrap();
+        assert!(job.holds("flaky", "anywhere", 0));
+        assert!(!job.holds("flaky-too", "anywhere", 0));
+
+        assert!("".parse::<Rule>().unwrap_err().contains("mutes everything"));
+        assert!("user=root".parse::<Rule>().is_err());
+        assert!("job=[".parse::<Rule>().is_err());
+        assert!("hours=9-17".parse::<Rule>().is_err());
+    }
+}
@@ -0,0 +1,429 @@
+use crate::config::{DEFAULT_HTTP_TIMEOUT, HttpOptions, TgConfig};
+use crate::escalate;
+use crate::event::{Event, Severity};
+use crate::maintenance::{self, Handling};
+use crate::metrics::METRICS;
+use crate::mute;
+use crate::plugin::{Plugin, apply_plugins};
+use crate::telegram::{Delivery, tg_send_with};
+use crate::template::Templates;
+use reqwest::blocking::Client;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Condvar, Mutex, mpsc};
+use std::thread;
+use std::time::Duration;
+
+pub fn http_client() -> Client {
+    http_client_with(&HttpOptions::default()).unwrap_or_else(|_| Client::new())
+}
+
+fn read_pem(path: &Path) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|e| format!("cannot read {}: {e}", path.display()))
+}
+
+/// Clients made so far, with the options they were made with. Each keeps a
+/// pool of open connections, so notifications sent with the same options
+/// reuse them instead of connecting (and handshaking) anew.
+static CLIENTS: Mutex<Vec<(HttpOptions, Client)>> = Mutex::new(Vec::new());
+
+/// Like [`http_client`], configured by `options`: an explicit proxy
+/// (`http://`, `https://`, `socks5://` or `socks5h://`) instead of the ones
+/// named by `HTTPS_PROXY`/`HTTP_PROXY`/`ALL_PROXY`, extra root certificates
+/// next to the system's, a client certificate for mutual TLS, and timeouts
+/// and connection pool limits. The client is made once for each `options`
+/// and shared.
+pub fn http_client_with(options: &HttpOptions) -> Result<Client, String> {
+    let mut clients = CLIENTS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((_, client)) = clients.iter().find(|(o, _)| o == options) {
+        return Ok(client.clone());
+    }
+    let client = build_client(options)?;
+    clients.push((options.clone(), client.clone()));
+    Ok(client)
+}
+
+fn build_client(options: &HttpOptions) -> Result<Client, String> {
+    let mut builder = Client::builder()
+        .timeout(options.timeout.unwrap_or(DEFAULT_HTTP_TIMEOUT))
+        .pool_max_idle_per_host(options.max_idle.unwrap_or(usize::MAX));
+    if let Some(idle) = options.idle_timeout {
+        builder = builder.pool_idle_timeout(idle);
+    }
+    if let Some(url) = &options.proxy {
+        let proxy = reqwest::Proxy::all(url).map_err(|e| format!("invalid proxy {url}: {e}"))?;
+        builder = builder.proxy(proxy);
+    }
+    if let Some(path) = &options.ca_bundle {
+        let certs = reqwest::Certificate::from_pem_bundle(&read_pem(path)?)
+            .map_err(|e| format!("invalid CA bundle {}: {e}", path.display()))?;
+        builder = builder.tls_certs_merge(certs);
+    }
+    match (&options.client_cert, &options.client_key) {
+        (Some(cert), key) => {
+            let mut pem = read_pem(cert)?;
+            if let Some(key) = key {
+                pem.push(b'\n');
+                pem.extend(read_pem(key)?);
+            }
+            let identity = reqwest::Identity::from_pem(&pem)
+                .map_err(|e| format!("invalid client certificate {}: {e}", cert.display()))?;
+            builder = builder.identity(identity);
+        }
+        (None, Some(_)) => return Err("a client key needs a client certificate".to_string()),
+        (None, None) => {}
+    }
+    builder.build().map_err(|e| e.to_string())
+}
+
+/// What happens to another notification when the queue is full, e.g. with
+/// a flood of messages raised by line hooks.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Backpressure {
+    /// Wait for room, slowing down whatever sends them (and so, for line
+    /// hooks, the command).
+    Block,
+    /// Drop the oldest waiting one, sparing failures.
+    #[default]
+    DropOldest,
+    /// Drop the one it supersedes, the oldest waiting one of the same kind
+    /// from the same host, or else the oldest.
+    Coalesce,
+}
+
+impl FromStr for Backpressure {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "block" => Ok(Backpressure::Block),
+            "drop-oldest" => Ok(Backpressure::DropOldest),
+            "coalesce" => Ok(Backpressure::Coalesce),
+            _ => Err(format!(
+                "unknown queue policy {s:?} (expected block, drop-oldest or coalesce)"
+            )),
+        }
+    }
+}
+
+/// Events waiting for the notifier thread. A collector thread moves them
+/// here from the channel as they arrive, so what is still undelivered can
+/// be seen (and reported) while a delivery hangs.
+#[derive(Default)]
+struct Queue {
+    events: VecDeque<Event>,
+    in_flight: bool,
+    /// Every sender is gone; the collector has queued all there will be.
+    closed: bool,
+    /// Shutdown gave up; deliver nothing more.
+    abandoned: bool,
+}
+
+impl Queue {
+    /// Makes room for `event` in a queue holding `size` by dropping one as
+    /// `policy` says.
+    fn make_room(&mut self, event: &Event, size: usize, policy: Backpressure) {
+        if self.events.len() < size {
+            return;
+        }
+        // Failures are dropped only when there is nothing else.
+        let droppable = |e: &Event| e.severity < Severity::Error;
+        let same = |e: &Event| droppable(e) && e.kind == event.kind && e.host == event.host;
+        let at = match policy {
+            Backpressure::Block => return,
+            Backpressure::DropOldest => self.events.iter().position(droppable),
+            Backpressure::Coalesce => self
+                .events
+                .iter()
+                .position(same)
+                .or_else(|| self.events.iter().position(droppable)),
+        };
+        let dropped = self.events.remove(at.unwrap_or(0));
+        if let Some(dropped) = dropped {
+            tracing::warn!(
+                "Notification queue full; dropped a {} notification",
+                dropped.kind.name()
+            );
+            METRICS
+                .notifications_dropped
+                .fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+type Shared = Arc<(Mutex<Queue>, Condvar)>;
+
+/// The notifier thread started by [`start_notifier`]. Call
+/// [`Notifier::shutdown`] once every [`Reporter`] is dropped; dropping it
+/// (e.g. while unwinding from a panic) does the same.
+pub struct Notifier {
+    shared: Shared,
+    timeout: Duration,
+    threads: Vec<thread::JoinHandle<()>>,
+}
+
+pub fn start_notifier(
+    cfg: TgConfig,
+    mut plugins: Vec<Box<dyn Plugin>>,
+) -> (mpsc::SyncSender<Event>, Notifier) {
+    let (size, policy) = (cfg.queue_size.max(1), cfg.backpressure);
+    let (tx, rx) = mpsc::sync_channel::<Event>(size);
+    let client = http_client_with(&cfg.http).unwrap_or_else(|e| {
+        tracing::warn!("Ignoring HTTP client settings: {e}");
+        http_client()
+    });
+    let shared: Shared = Arc::default();
+    let collector = thread::spawn({
+        let shared = shared.clone();
+        move || {
+            let (queue, changed) = &*shared;
+            for event in rx {
+                let mut state = changed
+                    .wait_while(lock(queue), |q| {
+                        policy == Backpressure::Block && q.events.len() >= size && !q.abandoned
+                    })
+                    .unwrap_or_else(|e| e.into_inner());
+                state.make_room(&event, size, policy);
+                state.events.push_back(event);
+                drop(state);
+                changed.notify_all();
+            }
+            lock(queue).closed = true;
+            changed.notify_all();
+        }
+    });
+    let timeout = cfg.shutdown_timeout;
+    let sender = thread::spawn({
+        let shared = shared.clone();
+        move || {
+            let (queue, changed) = &*shared;
+            loop {
+                let event = {
+                    let mut state = changed
+                        .wait_while(lock(queue), |q| {
+                            q.events.is_empty() && !q.closed && !q.abandoned
+                        })
+                        .unwrap_or_else(|e| e.into_inner());
+                    if state.abandoned {
+                        break;
+                    }
+                    let Some(event) = state.events.pop_front() else {
+                        break;
+                    };
+                    state.in_flight = true;
+                    event
+                };
+                deliver(&mut plugins, &client, &cfg, &event);
+                lock(queue).in_flight = false;
+                changed.notify_all();
+            }
+        }
+    });
+    let notifier = Notifier {
+        shared,
+        timeout,
+        threads: vec![collector, sender],
+    };
+    (tx, notifier)
+}
+
+fn lock(queue: &Mutex<Queue>) -> std::sync::MutexGuard<'_, Queue> {
+    queue.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn deliver(plugins: &mut [Box<dyn Plugin>], client: &Client, cfg: &TgConfig, event: &Event) {
+    let mut event = event.clone();
+    event.muted = mute::muted(&cfg.mutes, &event);
+    if !event.muted {
+        maintenance::handle(cfg.maintenance, &mut event);
+    }
+    let Some(mut event) = apply_plugins(plugins, client, &event) else {
+        return;
+    };
+    if event.muted {
+        tracing::debug!("Not sending muted {} notification", event.kind.name());
+        return;
+    }
+    let maintenance = event.maintenance;
+    match maintenance {
+        Some(Handling::Suppress) => {
+            tracing::debug!(
+                "Suppressed {} notification for maintenance",
+                event.kind.name()
+            );
+            return;
+        }
+        Some(Handling::Downgrade) => maintenance::downgrade(&mut event),
+        None => {}
+    }
+    let mut delivery = Delivery {
+        markup: None,
+        silent: maintenance.is_some() || cfg.silent(event.severity),
+    };
+    if let Some(policy) = &cfg.escalation
+        && maintenance.is_none()
+        && escalate::applies(&event)
+    {
+        // Kept whether or not Telegram takes it: then it is all the more
+        // for the escalation to get through.
+        delivery.markup = Some(escalate::raise(policy, &mut event));
+    }
+    let routed;
+    let cfg = match cfg.chat_for(event.severity) {
+        chat if chat == cfg.chat_id => cfg,
+        chat => {
+            routed = TgConfig {
+                chat_id: chat.to_string(),
+                ..cfg.clone()
+            };
+            &routed
+        }
+    };
+    tracing::debug!("Sending {} notification to Telegram", event.kind.name());
+    let counter = match tg_send_with(client, cfg, &event.text, &delivery) {
+        Ok(()) => {
+            tracing::debug!("Sent {} notification", event.kind.name());
+            &METRICS.notifications_sent
+        }
+        Err(e) => {
+            tracing::warn!("Failed to send telegram message: {e}");
+            &METRICS.notifier_errors
+        }
+    };
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Whether every notification so far went out: none failed, was dropped
+/// from a full queue or was given up on at exit.
+pub fn all_delivered() -> bool {
+    METRICS.notifier_errors.load(Ordering::Relaxed) == 0
+        && METRICS.notifications_dropped.load(Ordering::Relaxed) == 0
+}
+
+impl Notifier {
+    /// Waits, at most the configured shutdown timeout, for the queued
+    /// notifications to go out. Failures and other error-level events are
+    /// moved to the front so they are the likeliest to make it; whatever
+    /// is left when time runs out is reported on stderr instead.
+    pub fn shutdown(mut self) {
+        self.finish();
+    }
+
+    fn finish(&mut self) {
+        if self.threads.is_empty() {
+            return;
+        }
+        let (queue, changed) = &*self.shared;
+        let mut state = lock(queue);
+        state
+            .events
+            .make_contiguous()
+            .sort_by_key(|event| std::cmp::Reverse(event.severity >= Severity::Error));
+        let (mut state, waited) = changed
+            .wait_timeout_while(state, self.timeout, |q| {
+                !(q.closed && q.events.is_empty() && !q.in_flight)
+            })
+            .unwrap_or_else(|e| e.into_inner());
+        if !waited.timed_out() {
+            drop(state);
+            for thread in self.threads.drain(..) {
+                thread.join().ok();
+            }
+            return;
+        }
+        // Leave the threads behind: one may be stuck in a request.
+        self.threads.clear();
+        state.abandoned = true;
+        changed.notify_all();
+        let undelivered: Vec<Event> = state.events.drain(..).collect();
+        let pending = undelivered.len() + usize::from(state.in_flight);
+        drop(state);
+        tracing::error!(
+            "Gave up on {pending} notification(s) still pending after {}s.",
+            self.timeout.as_secs_f64()
+        );
+        for event in undelivered.iter().filter(|e| e.severity >= Severity::Error) {
+            tracing::error!(
+                "Undelivered {} notification:\n{}",
+                event.kind.name(),
+                event.text
+            );
+        }
+        METRICS
+            .notifier_errors
+            .fetch_add(pending as u64, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Notifier {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+/// Renders events with the message templates and queues them for the
+/// notifier thread, at most [`TgConfig::queue_size`] of them. Cheap to
+/// clone; the thread drains the queue and exits once every clone has been
+/// dropped.
+#[derive(Clone)]
+pub struct Reporter {
+    tx: mpsc::SyncSender<Event>,
+    templates: Arc<Templates>,
+}
+
+impl Reporter {
+    pub fn new(tx: mpsc::SyncSender<Event>, templates: Arc<Templates>) -> Self {
+        Reporter { tx, templates }
+    }
+
+    pub fn send(&self, mut event: Event) {
+        event.text = self.templates.render(&event);
+        self.tx.send(event).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::EventKind;
+
+    #[test]
+    fn a_full_queue_drops_as_the_policy_says() {
+        let message = |text: &str| Event {
+            text: text.to_string(),
+            ..Event::new(EventKind::Message, "make")
+        };
+        let queue = |events: Vec<Event>| Queue {
+            events: events.into(),
+            ..Queue::default()
+        };
+        let texts = |q: &Queue| q.events.iter().map(|e| e.text.clone()).collect::<Vec<_>>();
+        let start = Event {
+            text: "start".to_string(),
+            ..Event::new(EventKind::Start, "make")
+        };
+        let failure = Event {
+            text: "failure".to_string(),
+            ..Event::new(EventKind::Failure, "make")
+        };
+
+        let mut q = queue(vec![failure.clone(), start.clone(), message("1")]);
+        q.make_room(&message("2"), 3, Backpressure::DropOldest);
+        assert_eq!(texts(&q), ["failure", "1"]);
+
+        let mut q = queue(vec![failure.clone(), start.clone(), message("1")]);
+        q.make_room(&message("2"), 3, Backpressure::Coalesce);
+        assert_eq!(texts(&q), ["failure", "start"]);
+
+        let mut q = queue(vec![failure.clone(), start, message("1")]);
+        q.make_room(&message("2"), 4, Backpressure::DropOldest);
+        assert_eq!(q.events.len(), 3);
+
+        let mut q = queue(vec![failure.clone(), failure]);
+        q.make_room(&message("2"), 2, Backpressure::Coalesce);
+        assert_eq!(texts(&q), ["failure"]);
+        assert!("newest".parse::<Backpressure>().is_err());
+    }
+}
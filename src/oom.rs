@@ -0,0 +1,128 @@
+//! Telling an OOM kill apart from any other `SIGKILL`. The kernel logs
+//! every kill (`Out of memory: Killed process 1234 (java) … anon-rss:…`),
+//! which is readable from `/dev/kmsg` unless `dmesg_restrict` hides it from
+//! us; a run's own cgroup (`--memory-max`) also counts the kills it saw.
+
+use crate::event::ResourceUsage;
+use std::io::Read;
+
+/// An OOM kill of the command.
+#[derive(Debug, PartialEq)]
+pub struct OomKill {
+    /// Resident memory of the killed process, in bytes.
+    pub peak_rss: Option<u64>,
+}
+
+/// Whether the command that `pid` ran was killed by the OOM killer, from
+/// the kernel log or the run's `resources`.
+pub fn check(pid: Option<u32>, resources: Option<&ResourceUsage>) -> Option<OomKill> {
+    if let Some(kill) = pid.and_then(|pid| find_kill(&kernel_log(), pid)) {
+        return Some(kill);
+    }
+    let resources = resources.filter(|r| r.oom_kills.is_some_and(|n| n > 0))?;
+    Some(OomKill {
+        peak_rss: resources.memory_peak_bytes.or_else(max_child_rss),
+    })
+}
+
+/// The kernel log records still in the ring buffer.
+fn kernel_log() -> String {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open("/dev/kmsg");
+    let Ok(mut file) = file else {
+        return String::new();
+    };
+    let mut log = String::new();
+    // Every read returns one record, until there are no more.
+    let mut record = vec![0; 8192];
+    loop {
+        match file.read(&mut record) {
+            Ok(0) => break,
+            Ok(n) => {
+                let record = String::from_utf8_lossy(&record[..n]);
+                // `priority,sequence,timestamp,flags;message`
+                log.push_str(record.split_once(';').map_or(&*record, |(_, m)| m));
+            }
+            // Records overwritten while we read; carry on with the next.
+            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => {}
+            Err(_) => break,
+        }
+    }
+    log
+}
+
+/// The last `Killed process <pid>` report in `log`.
+fn find_kill(log: &str, pid: u32) -> Option<OomKill> {
+    let marker = format!("Killed process {pid} (");
+    let line = log.lines().rev().find(|line| line.contains(&marker))?;
+    let kb = |field: &str| -> Option<u64> {
+        let value = line.split(&format!("{field}:")).nth(1)?;
+        value
+            .split(|c: char| !c.is_ascii_digit())
+            .next()?
+            .parse()
+            .ok()
+    };
+    let rss = ["anon-rss", "file-rss", "shmem-rss"]
+        .iter()
+        .filter_map(|field| kb(field))
+        .reduce(|a, b| a + b);
+    Some(OomKill {
+        peak_rss: rss.map(|kb| kb * 1024).or_else(max_child_rss),
+    })
+}
+
+/// The largest resident set of any child we have waited for.
+fn max_child_rss() -> Option<u64> {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_CHILDREN, &mut usage) } < 0 {
+        return None;
+    }
+    // Kilobytes on Linux.
+    u64::try_from(usage.ru_maxrss)
+        .ok()
+        .filter(|kb| *kb > 0)
+        .map(|kb| kb * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_kill_in_the_kernel_log() {
+        let log = "\
+oom-kill:constraint=CONSTRAINT_NONE,nodemask=(null),task=java,pid=4242,uid=1000
+Out of memory: Killed process 4242 (java) total-vm:20000000kB, anon-rss:14889000kB, file-rss:1000kB, shmem-rss:0kB, UID:1000 pgtables:30000kB oom_score_adj:0
+Out of memory: Killed process 77 (python) total-vm:10kB, anon-rss:8kB, file-rss:0kB, shmem-rss:0kB, UID:0
+";
+        assert_eq!(
+            find_kill(log, 4242),
+            Some(OomKill {
+                peak_rss: Some(14_890_000 * 1024)
+            })
+        );
+        assert_eq!(find_kill(log, 424), None);
+        assert_eq!(find_kill("", 4242), None);
+    }
+
+    #[test]
+    fn cgroup_oom_kills_count_without_the_kernel_log() {
+        let resources = ResourceUsage {
+            oom_kills: Some(1),
+            ..ResourceUsage::new(None, None, Some(2 << 30))
+        };
+        assert_eq!(
+            check(None, Some(&resources)),
+            Some(OomKill {
+                peak_rss: Some(2 << 30)
+            })
+        );
+        assert_eq!(check(None, Some(&ResourceUsage::default())), None);
+        assert_eq!(check(None, None), None);
+    }
+}
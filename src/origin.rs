@@ -0,0 +1,100 @@
+//! `--origin`: who started a run, for manual runs on shared machines. The
+//! start notification names the user (and who they were before `sudo`),
+//! the terminal they typed on, if any, and where their SSH session came
+//! from.
+
+use serde::Serialize;
+use std::io::IsTerminal;
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Origin {
+    pub user: String,
+    /// The user who ran `sudo`, from `SUDO_USER`.
+    pub sudo_user: Option<String>,
+    /// Whether stdin is a terminal, i.e. someone typed the command.
+    pub interactive: bool,
+    /// The terminal stdin is, e.g. "/dev/pts/3".
+    pub tty: Option<String>,
+    /// The address an SSH session came from, from `SSH_CONNECTION`.
+    pub ssh_from: Option<String>,
+    /// The above as one line, e.g.
+    /// "root (sudo from alice) on /dev/pts/3, over SSH from 203.0.113.7".
+    pub summary: String,
+}
+
+/// Who started this process.
+pub fn current() -> Origin {
+    let var = |key: &str| std::env::var(key).ok().filter(|v| !v.trim().is_empty());
+    let interactive = std::io::stdin().is_terminal();
+    let tty = interactive
+        .then(|| std::fs::read_link("/proc/self/fd/0").ok())
+        .flatten()
+        .map(|path| path.display().to_string());
+    describe(
+        var("USER").or_else(|| var("LOGNAME")).unwrap_or_default(),
+        var("SUDO_USER"),
+        interactive,
+        tty,
+        var("SSH_CONNECTION").as_deref(),
+    )
+}
+
+fn describe(
+    user: String,
+    sudo_user: Option<String>,
+    interactive: bool,
+    tty: Option<String>,
+    ssh_connection: Option<&str>,
+) -> Origin {
+    // "CLIENT_IP CLIENT_PORT SERVER_IP SERVER_PORT"
+    let ssh_from = ssh_connection
+        .and_then(|c| c.split_whitespace().next())
+        .map(str::to_string);
+    let mut summary = if user.is_empty() {
+        "unknown user".to_string()
+    } else {
+        user.clone()
+    };
+    if let Some(sudo_user) = &sudo_user {
+        summary.push_str(&format!(" (sudo from {sudo_user})"));
+    }
+    match (&tty, interactive) {
+        (Some(tty), _) => summary.push_str(&format!(" on {tty}")),
+        (None, true) => summary.push_str(" on a terminal"),
+        (None, false) => summary.push_str(", not interactive"),
+    }
+    if let Some(from) = &ssh_from {
+        summary.push_str(&format!(", over SSH from {from}"));
+    }
+    Origin {
+        user,
+        sudo_user,
+        interactive,
+        tty,
+        ssh_from,
+        summary,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_user_terminal_and_ssh_client() {
+        let origin = describe(
+            "root".to_string(),
+            Some("alice".to_string()),
+            true,
+            Some("/dev/pts/3".to_string()),
+            Some("203.0.113.7 51234 10.0.0.5 22"),
+        );
+        assert_eq!(origin.ssh_from.as_deref(), Some("203.0.113.7"));
+        assert_eq!(
+            origin.summary,
+            "root (sudo from alice) on /dev/pts/3, over SSH from 203.0.113.7"
+        );
+        let cron = describe("backup".to_string(), None, false, None, None);
+        assert_eq!(cron.summary, "backup, not interactive");
+    }
+}
@@ -0,0 +1,313 @@
+//! OpenTelemetry traces: with `OTEL_EXPORTER_OTLP_ENDPOINT` (or
+//! `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`) set, every run is exported over
+//! OTLP/HTTP as one span covering the run, with the command, job, host, exit
+//! code and duration as attributes and a child span for each notification
+//! sent along the way. `OTEL_EXPORTER_OTLP_HEADERS` (`key=value,…`) and
+//! `OTEL_SERVICE_NAME` are honoured as in the OTel SDKs, and a W3C
+//! `TRACEPARENT` in the environment makes the run a child of that trace.
+//!
+//! The spans are sent as OTLP JSON once the run has finished, so any
+//! collector with the OTLP/HTTP receiver enabled can take them.
+
+use crate::ci::{self, Outcome};
+use crate::plugin::{Action, Plugin, PluginError};
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::hash::{BuildHasher, Hasher, RandomState};
+
+/// The tracing plugin, if an OTLP endpoint is configured.
+pub fn from_env() -> Result<Option<Box<dyn Plugin>>, String> {
+    let var = |key: &str| std::env::var(key).ok().filter(|v| !v.trim().is_empty());
+    let url = match (
+        var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT"),
+        var("OTEL_EXPORTER_OTLP_ENDPOINT"),
+    ) {
+        (Some(url), _) => url,
+        (None, Some(base)) => format!("{}/v1/traces", base.trim_end_matches('/')),
+        (None, None) => return Ok(None),
+    };
+    let mut headers = parse_headers(&var("OTEL_EXPORTER_OTLP_HEADERS").unwrap_or_default())?;
+    headers.insert("Content-Type".to_string(), "application/json".to_string());
+    let parent = var("TRACEPARENT").and_then(|tp| parse_traceparent(&tp));
+    Ok(Some(Box::new(OtelPlugin {
+        url,
+        headers,
+        service: var("OTEL_SERVICE_NAME").unwrap_or_else(|| "sentinel-rs".to_string()),
+        trace_id: parent
+            .as_ref()
+            .map(|(trace, _)| trace.clone())
+            .unwrap_or_else(|| random_hex(2)),
+        parent_span_id: parent.map(|(_, span)| span),
+        span_id: random_hex(1),
+        started_ns: None,
+        notifications: Vec::new(),
+    })))
+}
+
+/// `OTEL_EXPORTER_OTLP_HEADERS`: comma-separated `key=value` pairs.
+fn parse_headers(s: &str) -> Result<BTreeMap<String, String>, String> {
+    s.split(',')
+        .filter(|pair| !pair.trim().is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => {
+                Ok((key.trim().to_string(), value.trim().to_string()))
+            }
+            _ => Err(format!(
+                "OTEL_EXPORTER_OTLP_HEADERS: expected key=value, got {pair:?}"
+            )),
+        })
+        .collect()
+}
+
+/// Trace and span id of a `00-<trace>-<span>-<flags>` traceparent.
+fn parse_traceparent(s: &str) -> Option<(String, String)> {
+    let mut parts = s.trim().split('-');
+    let (_version, trace, span) = (parts.next()?, parts.next()?, parts.next()?);
+    let hex = |id: &str, len| id.len() == len && id.bytes().all(|b| b.is_ascii_hexdigit());
+    (hex(trace, 32) && hex(span, 16)).then(|| (trace.to_lowercase(), span.to_lowercase()))
+}
+
+/// `words` random 64-bit words as lowercase hex.
+fn random_hex(words: usize) -> String {
+    (0..words)
+        .map(|_| {
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u128(now_ns());
+            format!("{:016x}", hasher.finish())
+        })
+        .collect()
+}
+
+fn now_ns() -> u128 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default()
+}
+
+/// A notification seen on its way to delivery.
+struct Notification {
+    kind: String,
+    severity: String,
+    at_ns: u128,
+}
+
+struct OtelPlugin {
+    url: String,
+    headers: BTreeMap<String, String>,
+    service: String,
+    trace_id: String,
+    parent_span_id: Option<String>,
+    span_id: String,
+    /// When the start notification came by.
+    started_ns: Option<u128>,
+    notifications: Vec<Notification>,
+}
+
+fn attribute(key: &str, value: Value) -> Value {
+    let value = match value {
+        Value::String(s) => json!({ "stringValue": s }),
+        // OTLP JSON carries 64-bit integers as strings.
+        Value::Number(n) if n.is_i64() => json!({ "intValue": n.to_string() }),
+        Value::Number(n) => json!({ "doubleValue": n }),
+        Value::Bool(b) => json!({ "boolValue": b }),
+        other => json!({ "stringValue": other.to_string() }),
+    };
+    json!({ "key": key, "value": value })
+}
+
+impl OtelPlugin {
+    /// The OTLP export request for a finished run.
+    fn export(&self, event: &Value, outcome: Outcome, headline: &str, now_ns: u128) -> Value {
+        let took_ns = (event["duration_secs"].as_f64().unwrap_or_default() * 1e9) as u128;
+        let start_ns = self
+            .started_ns
+            .unwrap_or_else(|| now_ns.saturating_sub(took_ns));
+        let field = |key: &str| event[key].as_str().unwrap_or_default().to_string();
+        let mut attributes = vec![
+            attribute("process.command_line", json!(field("command"))),
+            attribute("host.name", json!(field("host"))),
+            attribute("sentinel.job", json!(field("job"))),
+            attribute("sentinel.result", json!(event["kind"])),
+        ];
+        if let Some(via) = event["via"].as_str() {
+            attributes.push(attribute("sentinel.via", json!(via)));
+        }
+        if let Some(code) = event["exit_code"].as_i64() {
+            attributes.push(attribute("process.exit.code", json!(code)));
+        }
+        if let Some(secs) = event["duration_secs"].as_f64() {
+            attributes.push(attribute("sentinel.duration_secs", json!(secs)));
+        }
+        let status = match outcome {
+            Outcome::Success | Outcome::Running => json!({ "code": 1 }),
+            Outcome::Failure | Outcome::Error => json!({ "code": 2, "message": headline }),
+        };
+        let mut root = json!({
+            "traceId": self.trace_id,
+            "spanId": self.span_id,
+            "name": format!("run {}", field("job")),
+            "kind": 1,
+            "startTimeUnixNano": start_ns.to_string(),
+            "endTimeUnixNano": now_ns.to_string(),
+            "attributes": attributes,
+            "status": status,
+        });
+        if let Some(parent) = &self.parent_span_id {
+            root["parentSpanId"] = json!(parent);
+        }
+        let mut spans = vec![root];
+        let finished = Notification {
+            kind: field("kind"),
+            severity: field("severity"),
+            at_ns: now_ns,
+        };
+        for notification in self.notifications.iter().chain([&finished]) {
+            spans.push(json!({
+                "traceId": self.trace_id,
+                "spanId": random_hex(1),
+                "parentSpanId": self.span_id,
+                "name": format!("notify {}", notification.kind),
+                "kind": 3,
+                "startTimeUnixNano": notification.at_ns.to_string(),
+                "endTimeUnixNano": notification.at_ns.to_string(),
+                "attributes": [
+                    attribute("sentinel.event", json!(notification.kind)),
+                    attribute("sentinel.severity", json!(notification.severity)),
+                ],
+            }));
+        }
+        json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [attribute("service.name", json!(self.service))],
+                },
+                "scopeSpans": [{
+                    "scope": { "name": "sentinel-rs", "version": env!("CARGO_PKG_VERSION") },
+                    "spans": spans,
+                }],
+            }],
+        })
+    }
+}
+
+impl Plugin for OtelPlugin {
+    fn name(&self) -> &str {
+        "otel"
+    }
+
+    fn on_event(&mut self, event_json: &str) -> Result<Vec<Action>, PluginError> {
+        let event: Value = serde_json::from_str(event_json)?;
+        let now = now_ns();
+        match ci::outcome(&event) {
+            Some((Outcome::Running, _)) => self.started_ns = Some(now),
+            Some((outcome, headline)) => {
+                let body = self.export(&event, outcome, &headline, now);
+                return Ok(vec![Action::Http {
+                    url: self.url.clone(),
+                    method: "POST".to_string(),
+                    headers: self.headers.clone(),
+                    body: body.to_string(),
+                }]);
+            }
+            None => {}
+        }
+        self.notifications.push(Notification {
+            kind: event["kind"].as_str().unwrap_or_default().to_string(),
+            severity: event["severity"].as_str().unwrap_or_default().to_string(),
+            at_ns: now,
+        });
+        Ok(Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{Event, EventKind};
+
+    fn plugin() -> OtelPlugin {
+        OtelPlugin {
+            url: "http://collector:4318/v1/traces".to_string(),
+            headers: BTreeMap::new(),
+            service: "batch".to_string(),
+            trace_id: "0af7651916cd43dd8448eb211c80319c".to_string(),
+            parent_span_id: Some("b7ad6b7169203331".to_string()),
+            span_id: "00f067aa0ba902b7".to_string(),
+            started_ns: None,
+            notifications: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn reads_env_style_headers_and_traceparent() {
+        let headers = parse_headers("api-key=abc, x-team = ops").unwrap();
+        assert_eq!(headers["api-key"], "abc");
+        assert_eq!(headers["x-team"], "ops");
+        assert!(parse_headers("nonsense").is_err());
+        assert_eq!(
+            parse_traceparent("00-0AF7651916CD43DD8448EB211C80319C-b7ad6b7169203331-01"),
+            Some((
+                "0af7651916cd43dd8448eb211c80319c".to_string(),
+                "b7ad6b7169203331".to_string()
+            ))
+        );
+        assert_eq!(parse_traceparent("00-abc-def-01"), None);
+        assert_eq!(random_hex(2).len(), 32);
+    }
+
+    #[test]
+    fn finished_run_is_exported_as_span_with_notification_children() {
+        let mut plugin = plugin();
+        let start = Event::new(EventKind::Start, "backup --full");
+        assert!(plugin.on_event(&start.to_json()).unwrap().is_empty());
+        assert!(
+            plugin
+                .on_event(&Event::message("backup --full", "half way").to_json())
+                .unwrap()
+                .is_empty()
+        );
+
+        let mut event = Event {
+            exit_code: Some(2),
+            host: "db1".to_string(),
+            job: "backup".to_string(),
+            ..Event::new(EventKind::Failure, "backup --full")
+        };
+        event.set_duration(std::time::Duration::from_secs(90));
+        let actions = plugin.on_event(&event.to_json()).unwrap();
+        let [Action::Http { url, body, .. }] = actions.as_slice() else {
+            panic!("expected one export, got {actions:?}");
+        };
+        assert_eq!(url, "http://collector:4318/v1/traces");
+
+        let body: Value = serde_json::from_str(body).unwrap();
+        let resource = &body["resourceSpans"][0];
+        assert_eq!(
+            resource["resource"]["attributes"][0],
+            json!({"key": "service.name", "value": {"stringValue": "batch"}})
+        );
+        let spans = resource["scopeSpans"][0]["spans"].as_array().unwrap();
+        let root = &spans[0];
+        assert_eq!(root["name"], "run backup");
+        assert_eq!(root["traceId"], "0af7651916cd43dd8448eb211c80319c");
+        assert_eq!(root["parentSpanId"], "b7ad6b7169203331");
+        assert_eq!(root["status"]["code"], 2);
+        assert!(
+            root["attributes"]
+                .as_array()
+                .unwrap()
+                .contains(&json!({"key": "process.exit.code", "value": {"intValue": "2"}}))
+        );
+        let children: Vec<_> = spans[1..].iter().map(|s| s["name"].clone()).collect();
+        assert_eq!(
+            children,
+            ["notify start", "notify message", "notify failure"]
+        );
+        assert!(
+            spans[1..]
+                .iter()
+                .all(|s| s["parentSpanId"] == "00f067aa0ba902b7")
+        );
+    }
+}
@@ -0,0 +1,115 @@
+//! `--parser NAME`: summaries that know the tool being run. A parser reads
+//! the command's output as it is captured and boils it down to a few lines
+//! for the finish notification (snapshot id, sizes, …), in place of the raw
+//! tail of a verbose tool's output. It may also send notices while the
+//! command runs, or once it has finished.
+
+mod borg;
+mod certs;
+mod dump;
+mod restic;
+mod rsync;
+mod train;
+mod upgrade;
+
+use crate::LineNotice;
+use crate::runner::Stream;
+use std::time::Duration;
+
+/// Reads one run's output.
+pub trait OutputParser: Send {
+    /// Sees every line of stdout and stderr; a notice it returns is sent as
+    /// a message.
+    fn on_line(&mut self, stream: Stream, line: &str) -> Option<LineNotice>;
+
+    /// The summary for the finish notification, if the output had what it
+    /// looks for.
+    fn summary(&self) -> Option<String>;
+
+    /// Called once the command has exited, before [`summary`](Self::summary);
+    /// a notice it returns is sent as a message after the finish
+    /// notification.
+    fn finish(&mut self, _run: &Finished) -> Option<LineNotice> {
+        None
+    }
+}
+
+/// How the parsed run ended.
+pub struct Finished {
+    /// Whether the run counts as a success.
+    pub succeeded: bool,
+    pub elapsed: Duration,
+    /// Whether the command ran on this machine, so that what it left
+    /// behind (files, flags) can be looked at.
+    pub local: bool,
+}
+
+/// Makes a parser for a run of the given command line.
+type Make = fn(&str) -> Box<dyn OutputParser>;
+
+const PARSERS: [(&str, Make); 7] = [
+    ("borg", |_| Box::<borg::Borg>::default()),
+    ("certs", |_| Box::<certs::Certs>::default()),
+    ("dump", |command| Box::new(dump::Dump::new(command))),
+    ("restic", |_| Box::<restic::Restic>::default()),
+    ("rsync", |_| Box::<rsync::Rsync>::default()),
+    ("train", |_| Box::<train::Train>::default()),
+    ("upgrade", |_| Box::<upgrade::Upgrade>::default()),
+];
+
+/// A parser chosen with `--parser`, made fresh for each run.
+#[derive(Clone, Copy, Debug)]
+pub struct ParserKind {
+    pub name: &'static str,
+    make: Make,
+}
+
+impl ParserKind {
+    pub fn build(self, command: &str) -> Box<dyn OutputParser> {
+        (self.make)(command)
+    }
+}
+
+pub fn parse_parser(s: &str) -> Result<ParserKind, String> {
+    PARSERS
+        .iter()
+        .find(|(name, _)| *name == s)
+        .map(|&(name, make)| ParserKind { name, make })
+        .ok_or_else(|| {
+            let names: Vec<_> = PARSERS.iter().map(|(n, _)| *n).collect();
+            format!(
+                "unknown parser {s:?} (expected one of {})",
+                names.join(", ")
+            )
+        })
+}
+
+/// The text after `label` if `line` starts with it, trimmed.
+fn field<'a>(line: &'a str, label: &str) -> Option<&'a str> {
+    line.trim_start().strip_prefix(label).map(str::trim)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `parser` over `output` as if it all came from `stream`.
+    pub(super) fn summarize(name: &str, stream: Stream, output: &str) -> Option<String> {
+        let mut parser = parse_parser(name).unwrap().build("");
+        for line in output.lines() {
+            parser.on_line(stream, line);
+        }
+        parser.summary()
+    }
+
+    #[test]
+    fn parsers_are_found_by_name() {
+        assert_eq!(parse_parser("restic").unwrap().name, "restic");
+        assert!(
+            parse_parser("tar")
+                .unwrap_err()
+                .contains("borg, certs, dump, restic, rsync, train, upgrade")
+        );
+        assert_eq!(summarize("borg", Stream::Stderr, "nothing here"), None);
+    }
+}
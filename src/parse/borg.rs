@@ -0,0 +1,111 @@
+//! `borg create --stats`: the archive, its size before and after
+//! compression and deduplication, and the size of the whole repository.
+//! Borg prints the statistics on stderr.
+
+use super::{OutputParser, field};
+use crate::LineNotice;
+use crate::runner::Stream;
+
+#[derive(Default)]
+pub struct Borg {
+    archive: Option<String>,
+    fingerprint: Option<String>,
+    duration: Option<String>,
+    files: Option<String>,
+    /// Original, compressed and deduplicated size.
+    this_archive: Option<[String; 3]>,
+    all_archives: Option<[String; 3]>,
+}
+
+impl OutputParser for Borg {
+    fn on_line(&mut self, _stream: Stream, line: &str) -> Option<LineNotice> {
+        if let Some(name) = field(line, "Archive name:") {
+            self.archive = Some(name.to_string());
+        } else if let Some(fingerprint) = field(line, "Archive fingerprint:") {
+            self.fingerprint = Some(fingerprint.chars().take(8).collect());
+        } else if let Some(duration) = field(line, "Duration:") {
+            self.duration = Some(duration.to_string());
+        } else if let Some(files) = field(line, "Number of files:") {
+            self.files = Some(files.to_string());
+        } else if let Some(sizes) = field(line, "This archive:") {
+            self.this_archive = sizes_of(sizes);
+        } else if let Some(sizes) = field(line, "All archives:") {
+            self.all_archives = sizes_of(sizes);
+        }
+        None
+    }
+
+    fn summary(&self) -> Option<String> {
+        let mut headline = format!("borg archive {}", self.archive.as_ref()?);
+        if let Some(fingerprint) = &self.fingerprint {
+            headline.push_str(&format!(" ({fingerprint})"));
+        }
+        if let Some(files) = &self.files {
+            headline.push_str(&format!(", {files} files"));
+        }
+        if let Some(duration) = &self.duration {
+            headline.push_str(&format!(" in {duration}"));
+        }
+        let mut lines = vec![headline];
+        if let Some([original, compressed, deduplicated]) = &self.this_archive {
+            lines.push(format!(
+                "This archive: {original} original, {compressed} compressed, {deduplicated} deduplicated"
+            ));
+        }
+        if let Some([original, _, deduplicated]) = &self.all_archives {
+            lines.push(format!(
+                "All archives: {original} original, {deduplicated} deduplicated"
+            ));
+        }
+        Some(lines.join("\n"))
+    }
+}
+
+/// The three sizes of a statistics row, e.g. `12.34 GB  10.00 GB  1.23 GB`.
+fn sizes_of(row: &str) -> Option<[String; 3]> {
+    match row.split_whitespace().collect::<Vec<_>>()[..] {
+        [a, a_unit, b, b_unit, c, c_unit] => Some([
+            format!("{a} {a_unit}"),
+            format!("{b} {b_unit}"),
+            format!("{c} {c_unit}"),
+        ]),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse::tests::summarize;
+    use crate::runner::Stream;
+
+    #[test]
+    fn summarizes_create_stats() {
+        let output = "\
+------------------------------------------------------------------------------
+Repository: /mnt/backup/borg
+Archive name: web1-2024-01-01
+Archive fingerprint: 7f3c1a2b9d0e4f5a6b7c8d9e0f1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d7e8f9
+Time (start): Mon, 2024-01-01 03:00:00
+Time (end):   Mon, 2024-01-01 03:05:12
+Duration: 5 minutes 12.34 seconds
+Number of files: 12345
+Utilization of max. archive size: 0%
+------------------------------------------------------------------------------
+                       Original size      Compressed size    Deduplicated size
+This archive:               12.34 GB             10.00 GB              1.23 GB
+All archives:              123.45 GB            100.00 GB             20.00 GB
+
+                       Unique chunks         Total chunks
+Chunk index:                   12345               234567
+------------------------------------------------------------------------------
+";
+        assert_eq!(
+            summarize("borg", Stream::Stderr, output).as_deref(),
+            Some(
+                "borg archive web1-2024-01-01 (7f3c1a2b), 12345 files in 5 minutes 12.34 seconds\n\
+                 This archive: 12.34 GB original, 10.00 GB compressed, 1.23 GB deduplicated\n\
+                 All archives: 123.45 GB original, 20.00 GB deduplicated"
+            )
+        );
+    }
+}
@@ -0,0 +1,275 @@
+//! `certbot renew` and `acme.sh --cron`: which certificates were renewed,
+//! which were not due yet and which failed (and why), with their expiry
+//! dates. Neither tool prints the new expiry of a renewed certificate, so
+//! after a local run it is read from the certificate with `openssl`.
+
+use super::{Finished, OutputParser, field};
+use crate::LineNotice;
+use crate::runner::Stream;
+use std::path::PathBuf;
+
+#[derive(Default)]
+pub struct Certs {
+    certs: Vec<Cert>,
+    /// The certificate the lines being read are about.
+    current: Option<usize>,
+}
+
+struct Cert {
+    name: String,
+    outcome: Option<Outcome>,
+    /// e.g. "2025-06-01".
+    expires: Option<String>,
+    /// Why renewal failed, or when acme.sh will next try.
+    note: Option<String>,
+    file: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Outcome {
+    Renewed,
+    Skipped,
+    Failed,
+}
+
+impl OutputParser for Certs {
+    fn on_line(&mut self, _stream: Stream, line: &str) -> Option<LineNotice> {
+        // acme.sh starts its lines with `[Mon Jan  6 00:00:01 UTC 2025] `.
+        let line = match line.trim().strip_prefix('[') {
+            Some(rest) => rest.split_once("] ").map_or(rest, |(_, line)| line),
+            None => line.trim(),
+        };
+        // certbot
+        if let Some(conf) = field(line, "Processing ") {
+            let name = conf.rsplit('/').next().unwrap_or(conf);
+            self.select(name.strip_suffix(".conf").unwrap_or(name));
+        } else if line.starts_with("Certificate not yet due for renewal") {
+            self.set_outcome(Outcome::Skipped);
+        } else if let Some(expiry) = field(line, "This certificate expires on ") {
+            if let Some(cert) = self.current_cert() {
+                cert.expires = Some(expiry.trim_end_matches('.').to_string());
+            }
+        } else if let Some(rest) = field(line, "Failed to renew certificate ") {
+            let (name, error) = rest.split_once(" with error: ").unwrap_or((rest, ""));
+            self.select(name);
+            self.set_outcome(Outcome::Failed);
+            self.set_note(error);
+        } else if line.starts_with('/') && line.ends_with(')') {
+            self.list_entry(line);
+        }
+        // acme.sh
+        else if let Some(name) = field(line, "Renew:") {
+            self.select(name.trim_matches('\''));
+        } else if let Some(next) = field(line, "Skip, Next renewal time is:") {
+            self.set_outcome(Outcome::Skipped);
+            self.set_note(&format!("next renewal {next}"));
+        } else if line == "Cert success." {
+            self.set_outcome(Outcome::Renewed);
+        } else if let Some(file) = field(line, "Your cert is in:") {
+            if let Some(cert) = self.current_cert() {
+                cert.file = Some(PathBuf::from(file));
+            }
+        } else if let Some(name) = field(line, "Error renew") {
+            self.select(name.trim_end_matches('.'));
+            self.set_outcome(Outcome::Failed);
+        } else if let Some(error) = field(line, "Verify error:") {
+            self.set_note(error);
+        }
+        None
+    }
+
+    fn finish(&mut self, run: &Finished) -> Option<LineNotice> {
+        if run.local {
+            for cert in &mut self.certs {
+                if cert.outcome == Some(Outcome::Renewed)
+                    && cert.expires.is_none()
+                    && let Some(file) = &cert.file
+                {
+                    cert.expires = expiry_of(file);
+                }
+            }
+        }
+        None
+    }
+
+    fn summary(&self) -> Option<String> {
+        let mut lines = Vec::new();
+        for (outcome, label) in [
+            (Outcome::Failed, "Failed"),
+            (Outcome::Renewed, "Renewed"),
+            (Outcome::Skipped, "Not due"),
+        ] {
+            let certs: Vec<_> = self
+                .certs
+                .iter()
+                .filter(|cert| cert.outcome == Some(outcome))
+                .map(Cert::describe)
+                .collect();
+            if !certs.is_empty() {
+                lines.push(format!("{label}: {}", certs.join(", ")));
+            }
+        }
+        (!lines.is_empty()).then(|| lines.join("\n"))
+    }
+}
+
+impl Certs {
+    fn select(&mut self, name: &str) {
+        let index = match self.certs.iter().position(|cert| cert.name == name) {
+            Some(index) => index,
+            None => {
+                self.certs.push(Cert {
+                    name: name.to_string(),
+                    outcome: None,
+                    expires: None,
+                    note: None,
+                    file: None,
+                });
+                self.certs.len() - 1
+            }
+        };
+        self.current = Some(index);
+    }
+
+    fn current_cert(&mut self) -> Option<&mut Cert> {
+        self.certs.get_mut(self.current?)
+    }
+
+    fn set_outcome(&mut self, outcome: Outcome) {
+        if let Some(cert) = self.current_cert() {
+            cert.outcome = Some(outcome);
+        }
+    }
+
+    fn set_note(&mut self, note: &str) {
+        if let Some(cert) = self.current_cert()
+            && !note.is_empty()
+        {
+            cert.note.get_or_insert_with(|| note.to_string());
+        }
+    }
+
+    /// A line of certbot's closing lists:
+    /// `/etc/letsencrypt/live/example.com/fullchain.pem expires on 2025-03-01 (skipped)`.
+    fn list_entry(&mut self, line: &str) {
+        let Some((rest, outcome)) = line.rsplit_once(" (") else {
+            return;
+        };
+        let outcome = match outcome.trim_end_matches(')') {
+            "success" => Outcome::Renewed,
+            "skipped" => Outcome::Skipped,
+            "failure" => Outcome::Failed,
+            _ => return,
+        };
+        let (file, expires) = match rest.split_once(" expires on ") {
+            Some((file, expires)) => (file, Some(expires)),
+            None => (rest, None),
+        };
+        // `/etc/letsencrypt/live/<name>/fullchain.pem`
+        let mut parts = file.rsplit('/');
+        let name = parts.nth(1).unwrap_or(file);
+        self.select(name);
+        self.set_outcome(outcome);
+        if let Some(cert) = self.current_cert() {
+            cert.file = Some(PathBuf::from(file));
+            if let Some(expires) = expires {
+                cert.expires = Some(expires.to_string());
+            }
+        }
+    }
+}
+
+impl Cert {
+    fn describe(&self) -> String {
+        let mut text = self.name.clone();
+        if let Some(expires) = &self.expires {
+            text.push_str(&format!(" (expires {expires})"));
+        } else if let Some(note) = &self.note {
+            text.push_str(&format!(" ({note})"));
+        }
+        text
+    }
+}
+
+/// The expiry date of the certificate in `file`, from `openssl x509`.
+fn expiry_of(file: &std::path::Path) -> Option<String> {
+    let output = std::process::Command::new("openssl")
+        .args(["x509", "-noout", "-enddate", "-in"])
+        .arg(file)
+        .output()
+        .ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    parse_enddate(field(&text, "notAfter=")?)
+}
+
+/// `Jun  1 12:00:00 2025 GMT` as `2025-06-01`.
+fn parse_enddate(date: &str) -> Option<String> {
+    let words: Vec<_> = date.split_whitespace().take(4).collect();
+    let date = chrono::NaiveDateTime::parse_from_str(&words.join(" "), "%b %d %H:%M:%S %Y").ok()?;
+    Some(date.format("%Y-%m-%d").to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::tests::summarize;
+
+    #[test]
+    fn summarizes_certbot_renew() {
+        let output = "\
+Saving debug log to /var/log/letsencrypt/letsencrypt.log
+Processing /etc/letsencrypt/renewal/example.com.conf
+Certificate not yet due for renewal
+Processing /etc/letsencrypt/renewal/example.org.conf
+Renewing an existing certificate for example.org and www.example.org
+Processing /etc/letsencrypt/renewal/bad.net.conf
+Failed to renew certificate bad.net with error: Some challenges have failed.
+The following certificates are not due for renewal yet:
+  /etc/letsencrypt/live/example.com/fullchain.pem expires on 2025-03-01 (skipped)
+The following renewals succeeded:
+  /etc/letsencrypt/live/example.org/fullchain.pem (success)
+The following renewals failed:
+  /etc/letsencrypt/live/bad.net/fullchain.pem (failure)
+1 renew failure(s), 0 parse failure(s)";
+        assert_eq!(
+            summarize("certs", Stream::Stdout, output).as_deref(),
+            Some(
+                "Failed: bad.net (Some challenges have failed.)\n\
+                 Renewed: example.org\n\
+                 Not due: example.com (expires 2025-03-01)"
+            )
+        );
+        assert_eq!(
+            summarize("certs", Stream::Stdout, "No renewals were attempted."),
+            None
+        );
+    }
+
+    #[test]
+    fn summarizes_acme_sh_cron() {
+        let output = "\
+[Mon Jan  6 00:00:01 UTC 2025] ===Starting cron===
+[Mon Jan  6 00:00:01 UTC 2025] Renew: 'example.com'
+[Mon Jan  6 00:00:01 UTC 2025] Skip, Next renewal time is: 2025-02-28T00:00:00Z
+[Mon Jan  6 00:00:01 UTC 2025] Add '--force' to force to renew.
+[Mon Jan  6 00:00:02 UTC 2025] Renew: 'example.org'
+[Mon Jan  6 00:00:09 UTC 2025] Cert success.
+[Mon Jan  6 00:00:09 UTC 2025] Your cert is in: /root/.acme.sh/example.org/example.org.cer
+[Mon Jan  6 00:00:10 UTC 2025] Renew: 'bad.net'
+[Mon Jan  6 00:00:15 UTC 2025] Verify error:Invalid response from http://bad.net/.well-known/acme-challenge/x
+[Mon Jan  6 00:00:15 UTC 2025] Error renew bad.net.
+[Mon Jan  6 00:00:15 UTC 2025] ===End cron===";
+        assert_eq!(
+            summarize("certs", Stream::Stdout, output).as_deref(),
+            Some(
+                "Failed: bad.net (Invalid response from http://bad.net/.well-known/acme-challenge/x)\n\
+                 Renewed: example.org\n\
+                 Not due: example.com (next renewal 2025-02-28T00:00:00Z)"
+            )
+        );
+        assert_eq!(
+            parse_enddate("Jun  1 12:00:00 2025 GMT").as_deref(),
+            Some("2025-06-01")
+        );
+    }
+}
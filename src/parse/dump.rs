@@ -0,0 +1,414 @@
+//! `pg_dump`, `mysqldump` and scripts wrapping them: where the dump went,
+//! its size, its compression ratio (gzip) and how long it took. The file is
+//! `SENTINEL_DUMP_FILE` if set (for wrappers that pick the path
+//! themselves), else what the command line's `-f`/`--file` (pg_dump) or
+//! `-r`/`--result-file` (mysqldump) option or last `>` redirect names.
+//! Only local runs are looked at: the dump of a run over SSH or in a
+//! container is not on this machine.
+//!
+//! The sizes of successful dumps are kept in the state dir, and one that is
+//! less than half or more than one and a half times the average of the
+//! last few sends a warning: a dump that shrank overnight usually means
+//! missing tables rather than a leaner database. Dated file names
+//! (`db-2025-01-31.sql.gz`) share one history.
+
+use super::{Finished, OutputParser};
+use crate::LineNotice;
+use crate::config;
+use crate::event::{Severity, format_bytes};
+use crate::runner::Stream;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Sizes kept per dump.
+const KEEP: usize = 10;
+/// Earlier dumps needed before a size counts as unusual.
+const MIN_HISTORY: usize = 3;
+/// How far from the average a size may be, as a fraction of it.
+const DEVIATION: f64 = 0.5;
+
+pub struct Dump {
+    path: Option<PathBuf>,
+    /// Where the sizes of earlier dumps are kept.
+    history: Option<PathBuf>,
+    /// The first error the tool reported.
+    error: Option<String>,
+    written: Option<Written>,
+}
+
+/// The dump file as found after the run.
+struct Written {
+    size: Option<u64>,
+    ratio: Option<f64>,
+    elapsed: Duration,
+    /// Average size of the earlier dumps, and how many there were.
+    average: Option<(u64, usize)>,
+}
+
+impl Dump {
+    pub fn new(command: &str) -> Self {
+        let path = std::env::var_os("SENTINEL_DUMP_FILE")
+            .filter(|p| !p.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| dump_file(command));
+        Dump {
+            path: path.map(|p| std::path::absolute(&p).unwrap_or(p)),
+            history: config::state_dir().map(|dir| dir.join("dumps.json")),
+            error: None,
+            written: None,
+        }
+    }
+}
+
+impl OutputParser for Dump {
+    fn on_line(&mut self, stream: Stream, line: &str) -> Option<LineNotice> {
+        if stream == Stream::Stderr && self.error.is_none() && line.contains("error:") {
+            self.error = Some(crate::runner::tail_bytes(line.as_bytes(), 200));
+        }
+        None
+    }
+
+    fn finish(&mut self, run: &Finished) -> Option<LineNotice> {
+        let path = self.path.as_ref().filter(|_| run.local)?;
+        let size = size_of(path);
+        let key = history_key(path);
+        let mut history = self.history.as_deref().map(History::load);
+        let average = history.as_ref().and_then(|h| h.average(&key));
+        self.written = Some(Written {
+            size,
+            ratio: size.and_then(|size| gzip_ratio(path, size)),
+            elapsed: run.elapsed,
+            average,
+        });
+        if !run.succeeded {
+            return None;
+        }
+        let Some(size) = size else {
+            return Some(LineNotice {
+                text: format!("Dump {} was not written", path.display()),
+                severity: Some(Severity::Warning),
+            });
+        };
+        if let (Some(history), Some(file)) = (&mut history, &self.history) {
+            history.push(&key, size);
+            if let Err(e) = history.save(file) {
+                tracing::warn!("Failed to save dump sizes to {}: {e}", file.display());
+            }
+        }
+        match average {
+            Some((average, dumps)) if dumps >= MIN_HISTORY && deviates(size, average) => {
+                Some(LineNotice {
+                    text: deviation(path, size, average, dumps),
+                    severity: Some(Severity::Warning),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn summary(&self) -> Option<String> {
+        let path = self.path.as_ref()?;
+        let written = self.written.as_ref()?;
+        let mut lines = Vec::new();
+        match written.size {
+            Some(size) => {
+                let mut line = format!("Dump {}: {}", path.display(), format_bytes(size));
+                if let Some(ratio) = written.ratio {
+                    line.push_str(&format!(", compressed {ratio:.1}:1"));
+                }
+                line.push_str(&format!(" in {:.1}s", written.elapsed.as_secs_f64()));
+                lines.push(line);
+            }
+            None => lines.push(format!("Dump {} was not written", path.display())),
+        }
+        if let Some((average, dumps)) = written.average {
+            lines.push(match dumps {
+                1 => format!("Previous dump: {}", format_bytes(average)),
+                n => format!("Last {n} dumps averaged {}", format_bytes(average)),
+            });
+        }
+        lines.extend(self.error.clone());
+        Some(lines.join("\n"))
+    }
+}
+
+/// Programs whose `-f`/`-r` options name the dump file.
+const DUMPERS: [&str; 4] = ["pg_dump", "pg_dumpall", "mysqldump", "mariadb-dump"];
+
+/// The file a dump command line writes to, if it says: a dump program's
+/// file option, else the last redirect of stdout.
+fn dump_file(command: &str) -> Option<PathBuf> {
+    let (mut option, mut redirect) = (None, None);
+    let mut dumping = false;
+    let mut words = words(command).into_iter().peekable();
+    while let Some(word) = words.next() {
+        if matches!(word.as_str(), "|" | ";" | "(" | ")" | "&&" | "||" | "&") {
+            dumping = false;
+            continue;
+        }
+        dumping |= DUMPERS.contains(&word.rsplit('/').next().unwrap_or_default());
+        let next_unless_option = |words: &mut std::iter::Peekable<_>| {
+            words.next_if(|next: &String| !next.starts_with(['-', '>', '&']))
+        };
+        match word.as_str() {
+            ">" | ">>" | "1>" | "1>>" | "&>" => redirect = next_unless_option(&mut words),
+            "-f" | "--file" | "-r" | "--result-file" if dumping => {
+                option = next_unless_option(&mut words)
+            }
+            _ => {
+                if let Some(file) = ["--file=", "--result-file=", "-f", "-r"]
+                    .iter()
+                    .filter(|_| dumping)
+                    .find_map(|prefix| word.strip_prefix(prefix))
+                {
+                    option = Some(file.to_string());
+                } else if let Some(file) = ["1>", "&>", ">"]
+                    .iter()
+                    .find_map(|prefix| word.strip_prefix(prefix))
+                    .map(|rest| rest.trim_start_matches('>'))
+                    .filter(|file| !file.is_empty() && !file.starts_with('&'))
+                {
+                    redirect = Some(file.to_string());
+                }
+            }
+        }
+    }
+    let file = option.or(redirect)?;
+    match file.strip_prefix("~/") {
+        Some(rest) => Some(PathBuf::from(std::env::var_os("HOME")?).join(rest)),
+        None => Some(PathBuf::from(file)),
+    }
+}
+
+/// The command line split into words as the shell would, near enough:
+/// quotes and backslashes are honoured, expansions are not, and `|`, `;`
+/// and parentheses are words of their own.
+fn words(command: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = command.chars();
+    let mut quote = None;
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"') | None, '\\') => word.extend(chars.next()),
+            (Some(_), c) => word.push(c),
+            (None, '\'' | '"') => quote = Some(c),
+            (None, c) if c.is_whitespace() || matches!(c, ';' | '|' | '(' | ')') => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                }
+                if !c.is_whitespace() {
+                    words.push(c.to_string());
+                }
+                in_word = false;
+                continue;
+            }
+            (None, c) => word.push(c),
+        }
+        in_word = true;
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+/// The size of a dump file, or of the files in a directory-format dump
+/// (`pg_dump -Fd`).
+fn size_of(path: &Path) -> Option<u64> {
+    let meta = std::fs::metadata(path).ok()?;
+    if !meta.is_dir() {
+        return Some(meta.len());
+    }
+    let entries = std::fs::read_dir(path).ok()?;
+    Some(
+        entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| entry.metadata().ok())
+            .filter(|meta| meta.is_file())
+            .map(|meta| meta.len())
+            .sum(),
+    )
+}
+
+/// How much a gzip file shrank its contents, from the uncompressed size in
+/// its trailer. That size is modulo 4 GiB, so it is only believed when it
+/// is larger than the file.
+fn gzip_ratio(path: &Path, size: u64) -> Option<f64> {
+    if !(18..1 << 32).contains(&size) {
+        return None;
+    }
+    let mut file = std::fs::File::open(path).ok()?;
+    let mut magic = [0; 2];
+    file.read_exact(&mut magic).ok()?;
+    if magic != [0x1f, 0x8b] {
+        return None;
+    }
+    let mut trailer = [0; 4];
+    file.seek(SeekFrom::End(-4)).ok()?;
+    file.read_exact(&mut trailer).ok()?;
+    let uncompressed = u32::from_le_bytes(trailer) as u64;
+    (uncompressed > size).then(|| uncompressed as f64 / size as f64)
+}
+
+/// The history a dump's size is compared with: its path, with runs of
+/// digits (dates, sequence numbers) made alike.
+fn history_key(path: &Path) -> String {
+    let mut key = String::new();
+    for c in path.to_string_lossy().chars() {
+        if !c.is_ascii_digit() {
+            key.push(c);
+        } else if !key.ends_with('#') {
+            key.push('#');
+        }
+    }
+    key
+}
+
+fn deviates(size: u64, average: u64) -> bool {
+    let (size, average) = (size as f64, average as f64);
+    size < average * (1.0 - DEVIATION) || size > average * (1.0 + DEVIATION)
+}
+
+fn deviation(path: &Path, size: u64, average: u64, dumps: usize) -> String {
+    let change = (size as f64 - average as f64) / average as f64 * 100.0;
+    format!(
+        "Dump {} is {}, {:.0}% {} than the average of the last {dumps} ({})",
+        path.display(),
+        format_bytes(size),
+        change.abs(),
+        if change < 0.0 { "smaller" } else { "larger" },
+        format_bytes(average)
+    )
+}
+
+/// The sizes of recent successful dumps, by [`history_key`].
+#[derive(Default, Serialize, Deserialize)]
+struct History(BTreeMap<String, Vec<u64>>);
+
+impl History {
+    /// The history in `path`, or an empty one if there is none yet (or it
+    /// cannot be read).
+    fn load(path: &Path) -> Self {
+        config::read_state(path).unwrap_or_default()
+    }
+
+    fn save(&self, path: &Path) -> std::io::Result<()> {
+        config::write_state(path, self)
+    }
+
+    fn average(&self, key: &str) -> Option<(u64, usize)> {
+        let sizes = self.0.get(key).filter(|sizes| !sizes.is_empty())?;
+        Some((sizes.iter().sum::<u64>() / sizes.len() as u64, sizes.len()))
+    }
+
+    fn push(&mut self, key: &str, size: u64) {
+        let sizes = self.0.entry(key.to_string()).or_default();
+        sizes.push(size);
+        if sizes.len() > KEEP {
+            sizes.drain(..sizes.len() - KEEP);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dump_file_is_found_in_the_command_line() {
+        let file = |command| dump_file(command).map(|p| p.display().to_string());
+        assert_eq!(
+            file("pg_dump -Fc -f /srv/db.dump app").as_deref(),
+            Some("/srv/db.dump")
+        );
+        assert_eq!(
+            file("pg_dump app 2>/dev/null | gzip >'/srv/my dumps/db.sql.gz'").as_deref(),
+            Some("/srv/my dumps/db.sql.gz")
+        );
+        assert_eq!(
+            file("mysqldump --result-file=/srv/db.sql app").as_deref(),
+            Some("/srv/db.sql")
+        );
+        assert_eq!(
+            file("sudo -u postgres pg_dump --file out.sql app >> log 2>&1").as_deref(),
+            Some("out.sql")
+        );
+        assert_eq!(
+            file("pg_dump app | gzip -f > db.sql.gz").as_deref(),
+            Some("db.sql.gz")
+        );
+        assert_eq!(file("pg_dump app 2>&1"), None);
+        assert_eq!(file("/usr/local/bin/backup-db"), None);
+    }
+
+    #[test]
+    fn reports_size_ratio_and_history() {
+        let dir = std::env::temp_dir().join(format!("sentinel-dump-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // Gzip magic, filler, and a trailer saying 4000 bytes went in.
+        let mut gzip = vec![0x1f, 0x8b];
+        gzip.resize(996, 0);
+        gzip.extend(4000u32.to_le_bytes());
+        let path = dir.join("db-2025-01-31.sql.gz");
+        std::fs::write(&path, &gzip).unwrap();
+        let history = dir.join("state").join("dumps.json");
+        let mut earlier = History::default();
+        for _ in 0..3 {
+            earlier.push(&history_key(&dir.join("db-2025-01-30.sql.gz")), 3000);
+        }
+        earlier.save(&history).unwrap();
+
+        let mut dump = Dump {
+            path: Some(path.clone()),
+            history: Some(history.clone()),
+            error: None,
+            written: None,
+        };
+        dump.on_line(Stream::Stderr, "pg_dump: warning: nothing serious");
+        let notice = dump.finish(&Finished {
+            succeeded: true,
+            elapsed: Duration::from_millis(2500),
+            local: true,
+        });
+        let summary = dump.summary().unwrap();
+        let saved = History::load(&history).average(&history_key(&path));
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(
+            summary,
+            format!(
+                "Dump {}: 1000 B, compressed 4.0:1 in 2.5s\nLast 3 dumps averaged 2.9 KiB",
+                path.display()
+            )
+        );
+        let notice = notice.unwrap();
+        assert_eq!(notice.severity, Some(Severity::Warning));
+        assert!(
+            notice
+                .text
+                .ends_with("is 1000 B, 67% smaller than the average of the last 3 (2.9 KiB)")
+        );
+        assert_eq!(saved, Some((2500, 4)));
+    }
+
+    #[test]
+    fn only_sharp_changes_are_unusual() {
+        assert!(deviates(40, 100) && deviates(151, 100));
+        assert!(!deviates(60, 100) && !deviates(140, 100));
+        let mut history = History::default();
+        for size in 0..15 {
+            history.push("db", size);
+        }
+        assert_eq!(history.average("db"), Some((9, KEEP)));
+        assert_eq!(
+            history_key(Path::new("/srv/db-2025-01-31.sql.gz")),
+            "/srv/db-#-#-#.sql.gz"
+        );
+    }
+}
@@ -0,0 +1,112 @@
+//! `restic backup`: the snapshot it saved, how many files were new or
+//! changed, and how much was added to the repository.
+
+use super::{OutputParser, field};
+use crate::LineNotice;
+use crate::runner::Stream;
+
+#[derive(Default)]
+pub struct Restic {
+    snapshot: Option<String>,
+    files: Option<String>,
+    dirs: Option<String>,
+    added: Option<String>,
+    processed: Option<String>,
+    /// Files restic could not read, e.g. `error: open /srv/x: permission denied`.
+    unreadable: usize,
+}
+
+impl OutputParser for Restic {
+    fn on_line(&mut self, _stream: Stream, line: &str) -> Option<LineNotice> {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("snapshot ")
+            && let Some(id) = rest.strip_suffix(" saved")
+        {
+            self.snapshot = Some(id.to_string());
+        } else if let Some(files) = field(line, "Files:") {
+            self.files = Some(squeeze(files));
+        } else if let Some(dirs) = field(line, "Dirs:") {
+            self.dirs = Some(squeeze(dirs));
+        } else if let Some(added) =
+            field(line, "Added to the repository:").or_else(|| field(line, "Added to the repo:"))
+        {
+            self.added = Some(added.to_string());
+        } else if let Some(processed) = field(line, "processed ") {
+            self.processed = Some(processed.to_string());
+        } else if line.starts_with("error: ") {
+            self.unreadable += 1;
+        }
+        None
+    }
+
+    fn summary(&self) -> Option<String> {
+        let mut lines = Vec::new();
+        match &self.snapshot {
+            Some(id) => lines.push(format!("restic snapshot {id} saved")),
+            None if self.files.is_some() => lines.push("restic saved no snapshot".to_string()),
+            None => return None,
+        }
+        if let Some(files) = &self.files {
+            lines.push(format!("Files: {files}"));
+        }
+        if let Some(dirs) = &self.dirs {
+            lines.push(format!("Dirs: {dirs}"));
+        }
+        if let Some(added) = &self.added {
+            lines.push(format!("Added: {added}"));
+        }
+        if let Some(processed) = &self.processed {
+            lines.push(format!("Processed {processed}"));
+        }
+        if self.unreadable > 0 {
+            lines.push(format!("{} file(s) could not be read", self.unreadable));
+        }
+        Some(lines.join("\n"))
+    }
+}
+
+/// Collapses the column padding restic lines up its numbers with.
+fn squeeze(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse::tests::summarize;
+    use crate::runner::Stream;
+
+    #[test]
+    fn summarizes_a_backup() {
+        let output = "\
+repository 5ab0ee53 opened (version 2, compression level auto)
+using parent snapshot 0ec9ce0a
+error: open /srv/private: permission denied
+
+Files:         123 new,    45 changed,  6789 unmodified
+Dirs:            3 new,     2 changed,   100 unmodified
+Added to the repository: 1.234 GiB (600.123 MiB stored)
+
+processed 6957 files, 12.345 GiB in 1:23
+snapshot 1a2b3c4d saved
+";
+        assert_eq!(
+            summarize("restic", Stream::Stdout, output).as_deref(),
+            Some(
+                "restic snapshot 1a2b3c4d saved\n\
+                 Files: 123 new, 45 changed, 6789 unmodified\n\
+                 Dirs: 3 new, 2 changed, 100 unmodified\n\
+                 Added: 1.234 GiB (600.123 MiB stored)\n\
+                 Processed 6957 files, 12.345 GiB in 1:23\n\
+                 1 file(s) could not be read"
+            )
+        );
+        assert_eq!(
+            summarize(
+                "restic",
+                Stream::Stdout,
+                "Fatal: unable to open config file"
+            ),
+            None
+        );
+    }
+}
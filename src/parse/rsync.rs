@@ -0,0 +1,145 @@
+//! `rsync --stats`: how many files and bytes were transferred out of how
+//! many, how fast, and whether files vanished while rsync ran (its exit
+//! code 24, often harmless for live directories).
+
+use super::{OutputParser, field};
+use crate::LineNotice;
+use crate::event::format_bytes;
+use crate::runner::Stream;
+
+#[derive(Default)]
+pub struct Rsync {
+    files: Option<String>,
+    transferred: Option<String>,
+    total_size: Option<String>,
+    transferred_size: Option<String>,
+    speed: Option<String>,
+    speedup: Option<String>,
+    vanished: usize,
+}
+
+impl OutputParser for Rsync {
+    fn on_line(&mut self, _stream: Stream, line: &str) -> Option<LineNotice> {
+        if let Some(files) = field(line, "Number of files:") {
+            // `1,234 (reg: 1,000, dir: 234)`
+            self.files = files.split_whitespace().next().map(str::to_string);
+        } else if let Some(files) = field(line, "Number of regular files transferred:")
+            .or_else(|| field(line, "Number of files transferred:"))
+        {
+            self.transferred = Some(files.to_string());
+        } else if let Some(size) = field(line, "Total file size:") {
+            self.total_size = Some(size_of(size));
+        } else if let Some(size) = field(line, "Total transferred file size:") {
+            self.transferred_size = Some(size_of(size));
+        } else if let Some(totals) = field(line, "sent ") {
+            // `sent 1,240,000 bytes  received 1,234 bytes  82,682.27 bytes/sec`
+            self.speed = totals
+                .split("  ")
+                .map(str::trim)
+                .find(|part| part.ends_with("bytes/sec"))
+                .map(speed_of);
+        } else if let Some(rest) = field(line, "total size is ") {
+            self.speedup = field(rest.split("  ").nth(1).unwrap_or_default(), "speedup is")
+                .map(str::to_string);
+        } else if line.starts_with("file has vanished: ") {
+            self.vanished += 1;
+        } else if line.contains("some files vanished before they could be transferred") {
+            self.vanished = self.vanished.max(1);
+        }
+        None
+    }
+
+    fn summary(&self) -> Option<String> {
+        let transferred = self.transferred.as_ref()?;
+        let mut headline = format!("rsync transferred {transferred}");
+        if let Some(files) = &self.files {
+            headline.push_str(&format!(" of {files}"));
+        }
+        headline.push_str(" files");
+        match (&self.transferred_size, &self.total_size) {
+            (Some(sent), Some(total)) => headline.push_str(&format!(" ({sent} of {total})")),
+            (None, Some(total)) => headline.push_str(&format!(" (total {total})")),
+            _ => {}
+        }
+        let mut lines = vec![headline];
+        if let Some(speed) = &self.speed {
+            let mut line = format!("Speed: {speed}");
+            if let Some(speedup) = &self.speedup {
+                line.push_str(&format!(" (speedup {speedup})"));
+            }
+            lines.push(line);
+        }
+        match self.vanished {
+            0 => {}
+            1 => lines.push("Warning: a file vanished during the transfer".to_string()),
+            n => lines.push(format!("Warning: {n} files vanished during the transfer")),
+        }
+        Some(lines.join("\n"))
+    }
+}
+
+/// `12,345,678 bytes` as `11.8 MiB`; `-h` sizes such as `12.35M bytes` as
+/// they are.
+fn size_of(field: &str) -> String {
+    let number = field.trim_end_matches("bytes").trim();
+    match number.replace(',', "").parse::<u64>() {
+        Ok(bytes) => format_bytes(bytes),
+        Err(_) => number.to_string(),
+    }
+}
+
+fn speed_of(field: &str) -> String {
+    let number = field.trim_end_matches("bytes/sec").trim();
+    match number.replace(',', "").parse::<f64>() {
+        Ok(rate) => format!("{}/s", format_bytes(rate as u64)),
+        Err(_) => format!("{number}/s"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse::tests::summarize;
+    use crate::runner::Stream;
+
+    #[test]
+    fn summarizes_stats_and_vanished_files() {
+        let output = "\
+file has vanished: \"/srv/tmp/session.lock\"
+
+Number of files: 1,234 (reg: 1,000, dir: 234)
+Number of created files: 10
+Number of deleted files: 0
+Number of regular files transferred: 56
+Total file size: 12,345,678 bytes
+Total transferred file size: 1,234,567 bytes
+Literal data: 1,234,567 bytes
+Matched data: 0 bytes
+
+sent 1,240,000 bytes  received 1,234 bytes  82,682.27 bytes/sec
+total size is 12,345,678  speedup is 9.96
+rsync warning: some files vanished before they could be transferred (code 24) at main.c(1865) [sender=3.2.7]
+";
+        assert_eq!(
+            summarize("rsync", Stream::Stdout, output).as_deref(),
+            Some(
+                "rsync transferred 56 of 1,234 files (1.2 MiB of 11.8 MiB)\n\
+                 Speed: 80.7 KiB/s (speedup 9.96)\n\
+                 Warning: a file vanished during the transfer"
+            )
+        );
+    }
+
+    #[test]
+    fn keeps_human_readable_sizes() {
+        let output = "\
+Number of files: 3 (reg: 2, dir: 1)
+Number of regular files transferred: 0
+Total file size: 12.35M bytes
+sent 120 bytes  received 12 bytes  264.00 bytes/sec
+";
+        assert_eq!(
+            summarize("rsync", Stream::Stdout, output).as_deref(),
+            Some("rsync transferred 0 of 3 files (total 12.35M)\nSpeed: 264 B/s")
+        );
+    }
+}
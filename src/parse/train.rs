@@ -0,0 +1,289 @@
+//! Model training logs: Keras (`Epoch 3/10`, `loss: 0.29 - accuracy:
+//! 0.91`), tqdm progress bars as PyTorch Lightning draws them (`Epoch 3:
+//! 45%|… loss=0.123`), Hugging Face `Trainer` dicts (`{'loss': 0.45,
+//! 'epoch': 1.0}`) and logs that print something similar. When an epoch
+//! ends a progress message is sent with its metrics and a guess at the time
+//! left, at most every `SENTINEL_PROGRESS_INTERVAL` seconds (default 600;
+//! 0 for every epoch). The summary has the last metrics and the best loss.
+
+use super::OutputParser;
+use crate::LineNotice;
+use crate::runner::Stream;
+use regex::Regex;
+use std::time::{Duration, Instant};
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(600);
+
+pub struct Train {
+    epoch_re: Regex,
+    total_re: Regex,
+    metric_re: Regex,
+    /// The epoch being trained, as the log numbers it.
+    epoch: Option<u64>,
+    total: Option<u64>,
+    /// The latest value of each metric, in the order they first showed up.
+    metrics: Vec<(String, String)>,
+    /// The lowest loss seen (`val_loss` if there is one).
+    best: Option<Best>,
+    started: Instant,
+    /// When the last progress message went out.
+    reported: Option<Instant>,
+    interval: Duration,
+}
+
+impl Default for Train {
+    fn default() -> Self {
+        let interval = match std::env::var("SENTINEL_PROGRESS_INTERVAL") {
+            Ok(secs) => secs
+                .trim()
+                .parse()
+                .ok()
+                .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                .unwrap_or_else(|| {
+                    tracing::warn!(
+                        "SENTINEL_PROGRESS_INTERVAL: expected seconds, got {secs:?}; using {}",
+                        DEFAULT_INTERVAL.as_secs()
+                    );
+                    DEFAULT_INTERVAL
+                }),
+            Err(_) => DEFAULT_INTERVAL,
+        };
+        Train {
+            epoch_re: Regex::new(r#"(?i)\bepoch\b['"]?\s*[:=]?\s*(\d+(?:\.\d+)?)(?:\s*(?:/|of)\s*(\d+))?"#)
+                .expect("valid regex"),
+            total_re: Regex::new(r"(?i)\bnum[_ ]epochs\s*[=:]\s*(\d+)").expect("valid regex"),
+            metric_re: Regex::new(
+                r#"(?i)\b((?:train|val|valid|eval|test)[_ ]?)?(loss|acc|accuracy|top1|top5|f1|auc|precision|recall|mae|mse|rmse|perplexity|ppl)\b['"]?\s*[:=]\s*([-+]?(?:\d+\.?\d*|\.\d+)(?:e[-+]?\d+)?)"#,
+            )
+            .expect("valid regex"),
+            epoch: None,
+            total: None,
+            metrics: Vec::new(),
+            best: None,
+            started: Instant::now(),
+            reported: None,
+            interval,
+        }
+    }
+}
+
+impl OutputParser for Train {
+    fn on_line(&mut self, _stream: Stream, line: &str) -> Option<LineNotice> {
+        // A progress bar redraws itself with carriage returns; the last
+        // drawing is the current one.
+        let line = line.rsplit('\r').find(|l| !l.trim().is_empty())?;
+        if let Some(total) = self.total_re.captures(line) {
+            self.total = total[1].parse().ok();
+            return None;
+        }
+        let mut notice = None;
+        if let Some(caps) = self.epoch_re.captures(line) {
+            // Hugging Face counts in fractions of an epoch.
+            let epoch = caps[1].parse::<f64>().map(|e| e as u64).ok();
+            if let Some(total) = caps.get(2) {
+                self.total = total.as_str().parse().ok();
+            }
+            if let (Some(done), Some(epoch)) = (self.epoch, epoch)
+                && epoch > done
+            {
+                notice = self.progress(done);
+            }
+            self.epoch = epoch.or(self.epoch);
+        }
+        let metrics: Vec<_> = self
+            .metric_re
+            .captures_iter(line)
+            .map(|caps| {
+                let prefix = caps.get(1).map_or(String::new(), |p| {
+                    p.as_str().trim_end_matches([' ', '_']).to_lowercase() + "_"
+                });
+                let name = format!("{prefix}{}", caps[2].to_lowercase());
+                (name, caps[3].to_string())
+            })
+            .collect();
+        for (name, value) in metrics {
+            self.record(name, &value);
+        }
+        notice
+    }
+
+    fn summary(&self) -> Option<String> {
+        if self.metrics.is_empty() {
+            return None;
+        }
+        let mut lines = Vec::new();
+        if let Some(epoch) = self.epoch {
+            lines.push(format!("Training reached {}", self.epoch_name(epoch)));
+        }
+        lines.push(self.metric_list());
+        if let Some(best) = &self.best
+            && let Some((_, last)) = self.metrics.iter().find(|(n, _)| *n == best.name)
+            && *last != best.printed
+        {
+            let mut line = format!("Best {} {}", best.name, best.printed);
+            if let Some(epoch) = best.epoch {
+                line.push_str(&format!(" ({})", self.epoch_name(epoch)));
+            }
+            lines.push(line);
+        }
+        Some(lines.join("\n"))
+    }
+}
+
+impl Train {
+    fn record(&mut self, name: String, value: &str) {
+        if let Ok(number) = value.parse::<f64>()
+            && name.ends_with("loss")
+        {
+            // Validation loss says more than training loss, when there is one.
+            let better = match &self.best {
+                None => true,
+                Some(best) if best.name == name => number < best.value,
+                Some(best) => !is_validation(&best.name) && is_validation(&name),
+            };
+            if better {
+                self.best = Some(Best {
+                    name: name.clone(),
+                    value: number,
+                    printed: value.to_string(),
+                    epoch: self.epoch,
+                });
+            }
+        }
+        match self.metrics.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, last)) => *last = value.to_string(),
+            None => self.metrics.push((name, value.to_string())),
+        }
+    }
+
+    /// The progress message for the end of epoch `done`, unless one went
+    /// out less than the interval ago.
+    fn progress(&mut self, done: u64) -> Option<LineNotice> {
+        let now = Instant::now();
+        let since = self.reported.unwrap_or(self.started);
+        if self.metrics.is_empty() || now.duration_since(since) < self.interval {
+            return None;
+        }
+        self.reported = Some(now);
+        let mut text = format!("{} done: {}", self.epoch_name(done), self.metric_list());
+        if let Some(left) = self.time_left(done, now.duration_since(self.started)) {
+            text.push_str(&format!("\nAbout {} left", format_eta(left)));
+        }
+        Some(LineNotice {
+            text,
+            severity: None,
+        })
+    }
+
+    /// The remaining epochs at the pace of those so far. Logs that give a
+    /// total count epochs from 1.
+    fn time_left(&self, done: u64, elapsed: Duration) -> Option<Duration> {
+        let remaining = self.total?.checked_sub(done)?;
+        let per_epoch = elapsed.checked_div(u32::try_from(done).ok()?)?;
+        per_epoch.checked_mul(u32::try_from(remaining).ok()?)
+    }
+
+    fn epoch_name(&self, epoch: u64) -> String {
+        match self.total {
+            Some(total) => format!("epoch {epoch}/{total}"),
+            None => format!("epoch {epoch}"),
+        }
+    }
+
+    fn metric_list(&self) -> String {
+        let metrics: Vec<_> = self
+            .metrics
+            .iter()
+            .map(|(name, value)| format!("{name} {value}"))
+            .collect();
+        metrics.join(", ")
+    }
+}
+
+struct Best {
+    name: String,
+    value: f64,
+    /// `value` as the log printed it.
+    printed: String,
+    epoch: Option<u64>,
+}
+
+fn is_validation(metric: &str) -> bool {
+    ["val", "eval"]
+        .iter()
+        .any(|prefix| metric.starts_with(prefix))
+}
+
+fn format_eta(left: Duration) -> String {
+    let secs = left.as_secs();
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m", secs.div_ceil(60)),
+        _ => format!("{}h {:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::tests::summarize;
+
+    #[test]
+    fn summarizes_keras_training() {
+        let output = "\
+Epoch 1/3
+1875/1875 [==============================] - 5s 2ms/step - loss: 0.4912 - accuracy: 0.8640 - val_loss: 0.3100 - val_accuracy: 0.8900
+Epoch 2/3
+1875/1875 [==============================] - 4s 2ms/step - loss: 0.2954 - accuracy: 0.9140 - val_loss: 0.2500 - val_accuracy: 0.9100
+Epoch 3/3
+1875/1875 [==============================] - 4s 2ms/step - loss: 0.2101 - accuracy: 0.9390 - val_loss: 0.2700 - val_accuracy: 0.9050";
+        assert_eq!(
+            summarize("train", Stream::Stdout, output).as_deref(),
+            Some(
+                "Training reached epoch 3/3\n\
+                 loss 0.2101, accuracy 0.9390, val_loss 0.2700, val_accuracy 0.9050\n\
+                 Best val_loss 0.2500 (epoch 2/3)"
+            )
+        );
+    }
+
+    #[test]
+    fn reads_progress_bars_and_trainer_dicts() {
+        let bar = "Epoch 0:  50%|█████     | 500/1000 [00:12<00:12, 40.1it/s, loss=0.91]\r\
+                   Epoch 0: 100%|██████████| 1000/1000 [00:24<00:00, 40.3it/s, loss=0.812, v_num=0]";
+        assert_eq!(
+            summarize("train", Stream::Stderr, bar).as_deref(),
+            Some("Training reached epoch 0\nloss 0.812")
+        );
+        let trainer = "  Num Epochs = 2\n\
+                       {'loss': 0.6931, 'learning_rate': 4.5e-05, 'epoch': 0.5}\n\
+                       {'eval_loss': 0.52, 'eval_accuracy': 0.81, 'epoch': 1.0}";
+        assert_eq!(
+            summarize("train", Stream::Stdout, trainer).as_deref(),
+            Some("Training reached epoch 1/2\nloss 0.6931, eval_loss 0.52, eval_accuracy 0.81")
+        );
+        assert_eq!(summarize("train", Stream::Stdout, "Epoch 1/3"), None);
+    }
+
+    #[test]
+    fn sends_progress_as_epochs_end() {
+        let mut train = Train {
+            interval: Duration::ZERO,
+            ..Train::default()
+        };
+        assert!(train.on_line(Stream::Stdout, "Epoch 1/4").is_none());
+        train.on_line(Stream::Stdout, "100/100 - loss: 0.5 - acc: 0.8");
+        let notice = train.on_line(Stream::Stdout, "Epoch 2/4").unwrap();
+        assert!(
+            notice
+                .text
+                .starts_with("epoch 1/4 done: loss 0.5, acc 0.8\nAbout "),
+            "{}",
+            notice.text
+        );
+        train.interval = Duration::from_secs(3600);
+        assert!(train.on_line(Stream::Stdout, "Epoch 3/4").is_none());
+        assert_eq!(format_eta(Duration::from_secs(3725)), "1h 02m");
+        assert_eq!(format_eta(Duration::from_secs(61)), "2m");
+    }
+}
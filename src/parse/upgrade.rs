@@ -0,0 +1,319 @@
+//! `apt-get upgrade` and `dnf upgrade`: which packages were upgraded,
+//! installed or held back, and whether the machine needs a reboot, for which
+//! apt leaves `/var/run/reboot-required` behind (checked after local runs)
+//! and either may upgrade the kernel.
+
+use super::{Finished, OutputParser};
+use crate::LineNotice;
+use crate::runner::Stream;
+use std::path::Path;
+
+/// Packages named in a summary line before the rest are counted.
+const LISTED: usize = 10;
+
+#[derive(Default)]
+pub struct Upgrade {
+    /// The list the next indented lines belong to.
+    section: Option<(Section, Layout)>,
+    upgraded: Vec<String>,
+    installed: Vec<String>,
+    removed: Vec<String>,
+    held: Vec<String>,
+    /// apt's `12 upgraded, 0 newly installed, …` line.
+    totals: Option<String>,
+    nothing_to_do: bool,
+    /// Why a reboot is needed, e.g. "for libc6, libssl3".
+    reboot: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+enum Section {
+    Upgraded,
+    Installed,
+    Removed,
+    Held,
+    /// A section listing other things (suggested packages, …).
+    Other,
+}
+
+#[derive(Clone, Copy)]
+enum Layout {
+    /// apt: names separated by spaces.
+    Names,
+    /// dnf's transaction table: one package per row, name first.
+    Table,
+    /// dnf's closing lists: `name-version-release.arch`, several per row.
+    Nevras,
+}
+
+impl OutputParser for Upgrade {
+    fn on_line(&mut self, _stream: Stream, line: &str) -> Option<LineNotice> {
+        if line.starts_with([' ', '\t']) && !line.trim().is_empty() {
+            if let Some((section, layout)) = self.section {
+                self.add(section, layout, line);
+            }
+            return None;
+        }
+        let line = line.trim();
+        self.section = header(line);
+        match self.section {
+            Some(_) => {}
+            None if is_totals(line) => {
+                self.nothing_to_do |= line.starts_with("0 upgraded, 0 newly installed");
+                self.totals = Some(line.trim_end_matches('.').to_string());
+            }
+            None if line == "Nothing to do." => self.nothing_to_do = true,
+            None if line.contains("System restart required") => {
+                self.reboot
+                    .get_or_insert_with(|| "system restart required".to_string());
+            }
+            None => {}
+        }
+        None
+    }
+
+    fn finish(&mut self, run: &Finished) -> Option<LineNotice> {
+        if run.local && self.reboot.is_none() {
+            self.reboot = reboot_required(Path::new("/var/run/reboot-required"));
+        }
+        None
+    }
+
+    fn summary(&self) -> Option<String> {
+        let changed = [&self.upgraded, &self.installed, &self.removed, &self.held];
+        if changed.iter().all(|list| list.is_empty()) && !self.nothing_to_do {
+            return None;
+        }
+        let mut lines = Vec::new();
+        if self.upgraded.is_empty() {
+            lines.push("No packages upgraded".to_string());
+        }
+        for (list, what) in [
+            (&self.upgraded, "upgraded"),
+            (&self.installed, "newly installed"),
+            (&self.removed, "removed"),
+            (&self.held, "held back"),
+        ] {
+            if !list.is_empty() {
+                lines.push(format!("{} {what}: {}", count(list.len()), names(list)));
+            }
+        }
+        if let Some(totals) = &self.totals {
+            lines.push(format!("apt: {totals}"));
+        }
+        let kernel = self
+            .upgraded
+            .iter()
+            .chain(&self.installed)
+            .find(|name| is_kernel(name));
+        match (&self.reboot, kernel) {
+            (Some(reason), _) => lines.push(format!("Reboot required ({reason})")),
+            (None, Some(kernel)) => lines.push(format!("Reboot required (new kernel {kernel})")),
+            (None, None) => {}
+        }
+        Some(lines.join("\n"))
+    }
+}
+
+impl Upgrade {
+    fn add(&mut self, section: Section, layout: Layout, line: &str) {
+        let list = match section {
+            Section::Upgraded => &mut self.upgraded,
+            Section::Installed => &mut self.installed,
+            Section::Removed => &mut self.removed,
+            Section::Held => &mut self.held,
+            Section::Other => return,
+        };
+        let mut words = line.split_whitespace();
+        let names: Vec<&str> = match layout {
+            Layout::Names => words.collect(),
+            // dnf 5 lists what an upgrade replaces under it.
+            Layout::Table => words
+                .next()
+                .filter(|w| *w != "replacing")
+                .into_iter()
+                .collect(),
+            Layout::Nevras => words.map(package_name).collect(),
+        };
+        for name in names {
+            if !list.iter().any(|n| n == name) {
+                list.push(name.to_string());
+            }
+        }
+    }
+}
+
+/// The section a line starts, and how its packages are listed.
+fn header(line: &str) -> Option<(Section, Layout)> {
+    let section = match line {
+        "The following packages will be upgraded:" => (Section::Upgraded, Layout::Names),
+        "The following NEW packages will be installed:" => (Section::Installed, Layout::Names),
+        "The following packages will be REMOVED:" => (Section::Removed, Layout::Names),
+        "The following packages have been kept back:" => (Section::Held, Layout::Names),
+        "Upgrading:" | "Upgrading dependencies:" => (Section::Upgraded, Layout::Table),
+        "Installing:" | "Installing dependencies:" | "Installing weak dependencies:" => {
+            (Section::Installed, Layout::Table)
+        }
+        "Removing:" | "Removing dependent packages:" | "Removing unused dependencies:" => {
+            (Section::Removed, Layout::Table)
+        }
+        "Skipping packages with conflicts:" | "Skipping packages with broken dependencies:" => {
+            (Section::Held, Layout::Table)
+        }
+        "Upgraded:" => (Section::Upgraded, Layout::Nevras),
+        "Installed:" => (Section::Installed, Layout::Nevras),
+        "Removed:" => (Section::Removed, Layout::Nevras),
+        _ if line.starts_with("The following ") && line.ends_with(':') => {
+            (Section::Other, Layout::Names)
+        }
+        _ => return None,
+    };
+    Some(section)
+}
+
+fn is_totals(line: &str) -> bool {
+    line.contains(" upgraded, ") && line.contains(" newly installed, ")
+}
+
+/// `bash-5.1.8-6.el9.x86_64` is `bash`.
+fn package_name(nevra: &str) -> &str {
+    let mut parts = nevra.rsplitn(3, '-');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(_), Some(_), Some(name)) => name,
+        _ => nevra,
+    }
+}
+
+fn is_kernel(name: &str) -> bool {
+    name == "kernel" || name.starts_with("kernel-core") || name.starts_with("linux-image-")
+}
+
+/// Why `/var/run/reboot-required` (at `path`) says to reboot, naming the
+/// packages listed next to it.
+fn reboot_required(path: &Path) -> Option<String> {
+    if !path.exists() {
+        return None;
+    }
+    let packages = std::fs::read_to_string(path.with_extension("pkgs")).unwrap_or_default();
+    let packages: Vec<String> = packages.split_whitespace().map(str::to_string).collect();
+    Some(if packages.is_empty() {
+        format!("{} exists", path.display())
+    } else {
+        format!("for {}", names(&packages))
+    })
+}
+
+fn count(n: usize) -> String {
+    match n {
+        1 => "1 package".to_string(),
+        n => format!("{n} packages"),
+    }
+}
+
+/// The first few names, and how many more there are.
+fn names(list: &[String]) -> String {
+    let mut text = list[..list.len().min(LISTED)].join(", ");
+    if list.len() > LISTED {
+        text.push_str(&format!(" and {} more", list.len() - LISTED));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::tests::summarize;
+
+    #[test]
+    fn summarizes_apt_get_upgrade() {
+        let output = "\
+Reading package lists...
+Calculating upgrade...
+The following packages have been kept back:
+  linux-image-amd64
+The following packages will be upgraded:
+  curl libcurl4 openssl
+3 upgraded, 0 newly installed, 0 to remove and 1 not upgraded.
+Need to get 2,345 kB of archives.
+Setting up curl (7.88.1-10+deb12u5) ...";
+        assert_eq!(
+            summarize("upgrade", Stream::Stdout, output).as_deref(),
+            Some(
+                "3 packages upgraded: curl, libcurl4, openssl\n\
+                 1 package held back: linux-image-amd64\n\
+                 apt: 3 upgraded, 0 newly installed, 0 to remove and 1 not upgraded"
+            )
+        );
+        let nothing = "0 upgraded, 0 newly installed, 0 to remove and 0 not upgraded.";
+        assert_eq!(
+            summarize("upgrade", Stream::Stdout, nothing).as_deref(),
+            Some(
+                "No packages upgraded\napt: 0 upgraded, 0 newly installed, 0 to remove and 0 not upgraded"
+            )
+        );
+        assert_eq!(
+            summarize("upgrade", Stream::Stdout, "Reading package lists..."),
+            None
+        );
+    }
+
+    #[test]
+    fn summarizes_dnf_upgrade() {
+        let output = "\
+Dependencies resolved.
+================================================================================
+ Package          Arch     Version               Repository      Size
+================================================================================
+Installing:
+ kernel-core      x86_64   5.14.0-427.el9        baseos          20 M
+Upgrading:
+ bash             x86_64   5.1.8-9.el9           baseos         1.7 M
+ curl             x86_64   7.76.1-29.el9         baseos         294 k
+Skipping packages with conflicts:
+ systemd          x86_64   252-32.el9            baseos         4.2 M
+
+Transaction Summary
+================================================================================
+Install  1 Package
+Upgrade  2 Packages
+
+Upgraded:
+  bash-5.1.8-9.el9.x86_64                 curl-7.76.1-29.el9.x86_64
+Installed:
+  kernel-core-5.14.0-427.el9.x86_64
+
+Complete!";
+        assert_eq!(
+            summarize("upgrade", Stream::Stdout, output).as_deref(),
+            Some(
+                "2 packages upgraded: bash, curl\n\
+                 1 package newly installed: kernel-core\n\
+                 1 package held back: systemd\n\
+                 Reboot required (new kernel kernel-core)"
+            )
+        );
+        assert_eq!(
+            summarize("upgrade", Stream::Stdout, "Nothing to do.\nComplete!").as_deref(),
+            Some("No packages upgraded")
+        );
+    }
+
+    #[test]
+    fn reads_the_reboot_flag() {
+        let dir = std::env::temp_dir().join(format!("sentinel-reboot-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let flag = dir.join("reboot-required");
+        assert_eq!(reboot_required(&flag), None);
+        std::fs::write(&flag, "*** System restart required ***\n").unwrap();
+        let bare = reboot_required(&flag);
+        std::fs::write(dir.join("reboot-required.pkgs"), "libc6\nlibssl3\n").unwrap();
+        let named = reboot_required(&flag);
+        std::fs::remove_dir_all(&dir).ok();
+        assert_eq!(bare, Some(format!("{} exists", flag.display())));
+        assert_eq!(named.as_deref(), Some("for libc6, libssl3"));
+        assert_eq!(
+            package_name("kernel-core-5.14.0-427.el9.x86_64"),
+            "kernel-core"
+        );
+    }
+}
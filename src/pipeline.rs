@@ -0,0 +1,255 @@
+//! Telling which stage of a pipeline failed. A local command that is a
+//! plain pipeline (`pg_dump db | gzip > db.gz`) runs with `set -o pipefail`,
+//! so a failing stage fails the run even when it is not the last one, and
+//! bash hands its `PIPESTATUS` back on an inherited pipe so the notification
+//! can name the stage.
+
+use std::io::{PipeReader, PipeWriter, Read};
+use std::os::fd::{AsRawFd, RawFd};
+use std::process::Command;
+
+/// The stages of `command` if it is a pipeline and nothing else: no lists
+/// (`;`, `&&`, `||`, `&`, newlines) or comments at the top level, where
+/// `PIPESTATUS` would describe only part of it.
+pub fn stages(command: &str) -> Option<Vec<String>> {
+    let command = command.trim();
+    let chars: Vec<char> = command.chars().collect();
+    let mut stages = Vec::new();
+    let mut stage = String::new();
+    let mut depth = 0usize;
+    let (mut single, mut double, mut backtick) = (false, false, false);
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let prev = i.checked_sub(1).map(|p| chars[p]);
+        stage.push(c);
+        i += 1;
+        if single {
+            single = c != '\'';
+            continue;
+        }
+        match c {
+            '\\' => {
+                if let Some(next) = next {
+                    stage.push(next);
+                    i += 1;
+                }
+                continue;
+            }
+            '"' => double = !double,
+            '`' => backtick = !backtick,
+            _ if double || backtick => {}
+            '\'' => single = true,
+            '(' | '{' => depth += 1,
+            ')' | '}' => depth = depth.saturating_sub(1),
+            _ if depth > 0 => {}
+            // `>|` overrides noclobber.
+            '|' if prev == Some('>') => {}
+            '|' if next == Some('|') => return None,
+            '|' => {
+                stage.pop();
+                if next == Some('&') {
+                    i += 1;
+                }
+                stages.push(std::mem::take(&mut stage).trim().to_string());
+            }
+            // Only part of a redirection such as `2>&1` or `&>`.
+            '&' if matches!(prev, Some('>' | '<')) || next == Some('>') => {}
+            '&' | ';' | '\n' => return None,
+            '#' if prev.is_none_or(char::is_whitespace) => return None,
+            _ => {}
+        }
+    }
+    if single || double || backtick || depth > 0 {
+        return None;
+    }
+    stages.push(stage.trim().to_string());
+    (stages.len() > 1 && stages.iter().all(|s| !s.is_empty())).then_some(stages)
+}
+
+/// `command` with pipefail set, reporting its `PIPESTATUS` on `fd` and
+/// keeping its exit status.
+pub fn script(command: &str, fd: RawFd) -> String {
+    format!(
+        "set -o pipefail\n{command}\n\
+         __sentinel_status=$? __sentinel_stages=\"${{PIPESTATUS[*]}}\"\n\
+         echo \"$__sentinel_stages\" 2>/dev/null >&{fd}\n\
+         exit $__sentinel_status\n"
+    )
+}
+
+/// What the stage is called in a notification: its program, e.g. `gzip`,
+/// or its start if it is a compound command.
+fn stage_name(stage: &str) -> String {
+    let program = stage
+        .split_whitespace()
+        .find(|word| !word.contains('=') || word.starts_with(['\'', '"']));
+    match program {
+        Some(word) if !word.starts_with(['(', '{']) => {
+            word.rsplit('/').next().unwrap_or(word).to_string()
+        }
+        _ if stage.chars().count() > 30 => {
+            format!("{}…", stage.chars().take(30).collect::<String>())
+        }
+        _ => stage.to_string(),
+    }
+}
+
+/// The stages that failed, e.g. ``stage 2 of 3, `gzip`, exited 1``, or
+/// `None` if they all succeeded.
+pub fn describe_failure(stages: &[String], statuses: &[i32]) -> Option<String> {
+    if stages.len() != statuses.len() {
+        return None;
+    }
+    let failed: Vec<String> = stages
+        .iter()
+        .zip(statuses)
+        .enumerate()
+        .filter(|(_, (_, status))| **status != 0)
+        .map(|(i, (stage, status))| {
+            format!(
+                "stage {} of {}, `{}`, {}",
+                i + 1,
+                stages.len(),
+                stage_name(stage),
+                describe_status(*status)
+            )
+        })
+        .collect();
+    (!failed.is_empty()).then(|| failed.join("; "))
+}
+
+fn describe_status(status: i32) -> String {
+    // What bash reports for a stage killed by a signal.
+    if status > 128 && status < 128 + 65 {
+        format!("killed by {}", crate::runner::signal_name(status - 128))
+    } else {
+        format!("exited {status}")
+    }
+}
+
+/// The pipe bash reports `PIPESTATUS` on.
+pub struct StatusPipe {
+    read: PipeReader,
+    write: PipeWriter,
+}
+
+impl StatusPipe {
+    pub fn new() -> std::io::Result<Self> {
+        // Both ends are close-on-exec until the child clears it on its copy
+        // of the write end.
+        let (read, write) = std::io::pipe()?;
+        // Whatever bash wrote is there once it has exited; anything it left
+        // running in the background may hold the pipe open, so do not wait
+        // for the end of it.
+        if unsafe { libc::fcntl(read.as_raw_fd(), libc::F_SETFL, libc::O_NONBLOCK) } < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(StatusPipe { read, write })
+    }
+
+    pub fn fd(&self) -> RawFd {
+        self.write.as_raw_fd()
+    }
+
+    /// Has the child of `cmd` keep the write end open across exec.
+    pub fn inherit(&self, cmd: &mut Command) {
+        use std::os::unix::process::CommandExt;
+
+        let fd = self.fd();
+        // SAFETY: fcntl is async-signal-safe.
+        unsafe {
+            cmd.pre_exec(move || {
+                if libc::fcntl(fd, libc::F_SETFD, 0) < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+
+    /// The exit status of every stage, once the child has exited.
+    pub fn statuses(self) -> Vec<i32> {
+        let StatusPipe { mut read, write } = self;
+        drop(write);
+        let mut report = Vec::new();
+        let mut buf = [0; 256];
+        while let Ok(n @ 1..) = read.read(&mut buf) {
+            report.extend_from_slice(&buf[..n]);
+        }
+        String::from_utf8_lossy(&report)
+            .split_whitespace()
+            .map_while(|status| status.parse().ok())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{Exec, run_bash_with_hook};
+
+    #[test]
+    fn splits_plain_pipelines_only() {
+        let stages = |s| super::stages(s);
+        assert_eq!(
+            stages("pg_dump db | gzip -9 2>&1 |& tee 'a|b' > out"),
+            Some(vec![
+                "pg_dump db".to_string(),
+                "gzip -9 2>&1".to_string(),
+                "tee 'a|b' > out".to_string()
+            ])
+        );
+        assert_eq!(
+            stages("(cd /tmp && tar c .) | gzip").map(|s| s.len()),
+            Some(2)
+        );
+        assert_eq!(stages("echo \"a | b\" $(x | y)"), None);
+        assert_eq!(stages("a | b; c"), None);
+        assert_eq!(stages("a | b && c"), None);
+        assert_eq!(stages("a || b"), None);
+        assert_eq!(stages("a | b &"), None);
+        assert_eq!(stages("a | b # c | d"), None);
+        assert_eq!(stages("a |"), None);
+        assert_eq!(stages("a >| out"), None);
+    }
+
+    #[test]
+    fn describes_the_failed_stages() {
+        let stages: Vec<String> = ["cat data", "FOO=1 /usr/bin/gzip -9", "{ sort; }"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            describe_failure(&stages, &[0, 1, 0]).as_deref(),
+            Some("stage 2 of 3, `gzip`, exited 1")
+        );
+        assert_eq!(
+            describe_failure(&stages, &[141, 0, 2]).as_deref(),
+            Some("stage 1 of 3, `cat`, killed by SIGPIPE; stage 3 of 3, `{ sort; }`, exited 2")
+        );
+        assert_eq!(describe_failure(&stages, &[0, 0, 0]), None);
+        assert_eq!(describe_failure(&stages, &[1]), None);
+    }
+
+    #[test]
+    fn reports_the_failed_stage_of_a_local_pipeline() {
+        let run = run_bash_with_hook(
+            "echo hi | (cat; exit 3) | cat",
+            &Exec::default(),
+            false,
+            None,
+        )
+        .unwrap();
+        assert_eq!(run.output.status.code(), Some(3));
+        assert_eq!(String::from_utf8_lossy(&run.output.stdout), "hi\n");
+        assert_eq!(
+            run.failed_stage.as_deref(),
+            Some("stage 2 of 3, `(cat; exit 3)`, exited 3")
+        );
+
+        let run = run_bash_with_hook("echo hi | cat", &Exec::default(), false, None).unwrap();
+        assert!(run.output.status.success());
+        assert_eq!(run.failed_stage, None);
+    }
+}
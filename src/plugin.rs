@@ -0,0 +1,199 @@
+//! Plugins see every event before it is delivered and answer with a list of
+//! actions: drop the notification, rewrite its text, or have the host make an
+//! HTTP request on their behalf (which is how a plugin acts as a custom
+//! backend). Plugins are listed in `SENTINEL_PLUGINS`, separated by `:`.
+
+#[cfg(feature = "wasm-plugins")]
+mod wasm;
+
+use crate::event::{Event, Severity};
+use crate::metrics::METRICS;
+use reqwest::blocking::Client;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+pub type PluginError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Action {
+    /// Do not deliver this notification to the built-in backends.
+    Drop,
+    /// Replace the notification text seen by later plugins and backends.
+    Rewrite { text: String },
+    /// Override the severity assigned to the event.
+    SetSeverity { severity: Severity },
+    /// Send an HTTP request, e.g. to a backend sentinel-rs does not ship.
+    Http {
+        url: String,
+        #[serde(default = "default_method")]
+        method: String,
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+        #[serde(default)]
+        body: String,
+    },
+}
+
+fn default_method() -> String {
+    "POST".to_string()
+}
+
+pub trait Plugin: Send {
+    fn name(&self) -> &str;
+
+    /// Handles one event, given as JSON, and returns the actions to take.
+    fn on_event(&mut self, event_json: &str) -> Result<Vec<Action>, PluginError>;
+}
+
+pub fn parse_actions(json: &[u8]) -> Result<Vec<Action>, PluginError> {
+    if json.iter().all(u8::is_ascii_whitespace) {
+        return Ok(Vec::new());
+    }
+    Ok(serde_json::from_slice(json)?)
+}
+
+pub fn load_plugins() -> Result<Vec<Box<dyn Plugin>>, Box<dyn std::error::Error>> {
+    let Ok(list) = std::env::var("SENTINEL_PLUGINS") else {
+        return Ok(Vec::new());
+    };
+    let paths: Vec<&str> = list.split(':').filter(|p| !p.trim().is_empty()).collect();
+    if paths.is_empty() {
+        return Ok(Vec::new());
+    }
+    #[cfg(feature = "wasm-plugins")]
+    {
+        paths
+            .into_iter()
+            .map(|path| {
+                wasm::WasmPlugin::load(std::path::Path::new(path))
+                    .map(|p| Box::new(p) as Box<dyn Plugin>)
+                    .map_err(|e| format!("failed to load plugin {path}: {e}").into())
+            })
+            .collect()
+    }
+    #[cfg(not(feature = "wasm-plugins"))]
+    {
+        Err(format!(
+            "SENTINEL_PLUGINS is set ({}) but sentinel-rs was built without the wasm-plugins feature",
+            paths.join(", ")
+        )
+        .into())
+    }
+}
+
+/// Makes the request of an [`Action::Http`]; other actions need none.
+pub fn perform_http(client: &Client, action: &Action) -> Result<(), PluginError> {
+    let Action::Http {
+        url,
+        method,
+        headers,
+        body,
+    } = action
+    else {
+        return Ok(());
+    };
+    let method = reqwest::Method::from_bytes(method.to_uppercase().as_bytes())?;
+    let mut request = client.request(method, url).body(body.clone());
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+    request.send()?.error_for_status()?;
+    Ok(())
+}
+
+/// Runs every plugin over `event` in order. Returns the event to deliver, or
+/// `None` if a plugin dropped the notification.
+pub fn apply_plugins(
+    plugins: &mut [Box<dyn Plugin>],
+    client: &Client,
+    event: &Event,
+) -> Option<Event> {
+    let mut event = event.clone();
+    let mut dropped = false;
+    for plugin in plugins.iter_mut() {
+        let actions = match plugin.on_event(&event.to_json()) {
+            Ok(actions) => actions,
+            Err(e) => {
+                tracing::warn!("Plugin {} failed: {e}", plugin.name());
+                continue;
+            }
+        };
+        for action in actions {
+            match action {
+                Action::Drop => dropped = true,
+                Action::Rewrite { text } => event.text = text,
+                Action::SetSeverity { severity } => event.severity = severity,
+                Action::Http { .. } => {
+                    if let Err(e) = perform_http(client, &action) {
+                        tracing::warn!("Plugin {} HTTP action failed: {e}", plugin.name());
+                        METRICS
+                            .notifier_errors
+                            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    }
+                }
+            }
+        }
+    }
+    if dropped { None } else { Some(event) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::EventKind;
+
+    struct Fixed(Vec<&'static str>);
+
+    impl Plugin for Fixed {
+        fn name(&self) -> &str {
+            "fixed"
+        }
+
+        fn on_event(&mut self, _event_json: &str) -> Result<Vec<Action>, PluginError> {
+            self.0
+                .iter()
+                .map(|json| Ok(serde_json::from_str(json)?))
+                .collect()
+        }
+    }
+
+    #[test]
+    fn parse_actions_accepts_empty_output() {
+        assert_eq!(parse_actions(b"").unwrap(), Vec::new());
+        assert_eq!(parse_actions(b" \n").unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn parse_actions_reads_tagged_actions() {
+        let actions =
+            parse_actions(br#"[{"type":"drop"},{"type":"http","url":"http://x"}]"#).unwrap();
+        assert_eq!(actions[0], Action::Drop);
+        assert_eq!(
+            actions[1],
+            Action::Http {
+                url: "http://x".to_string(),
+                method: "POST".to_string(),
+                headers: BTreeMap::new(),
+                body: String::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn apply_plugins_rewrites_then_drops() {
+        let client = Client::new();
+        let event = Event::new(EventKind::Start, "true");
+
+        let mut plugins: Vec<Box<dyn Plugin>> = vec![Box::new(Fixed(vec![
+            r#"{"type":"rewrite","text":"hi"}"#,
+            r#"{"type":"set_severity","severity":"warning"}"#,
+        ]))];
+        let delivered = apply_plugins(&mut plugins, &client, &event).unwrap();
+        assert_eq!(delivered.text, "hi");
+        assert_eq!(delivered.severity, Severity::Warning);
+
+        plugins.push(Box::new(Fixed(vec![r#"{"type":"drop"}"#])));
+        assert!(apply_plugins(&mut plugins, &client, &event).is_none());
+    }
+}
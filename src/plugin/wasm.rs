@@ -0,0 +1,143 @@
+//! WASM plugin host. A plugin module exports:
+//!
+//! - `memory`
+//! - `alloc(len: i32) -> i32`, returning a buffer the host writes the event to
+//! - `on_event(ptr: i32, len: i32) -> i64`, returning `(ptr << 32) | len` of
+//!   a JSON array of actions in its memory, or 0 for no actions
+//!
+//! Modules get no imports, so a plugin can only affect the world through the
+//! actions it returns. Each call is bounded by a fuel budget.
+
+use super::{Action, Plugin, PluginError, parse_actions};
+use std::path::Path;
+use wasmi::{Config, Engine, Instance, Linker, Memory, Module, Store, TypedFunc};
+
+/// Instructions (roughly) a plugin may execute per event.
+const FUEL_PER_EVENT: u64 = 10_000_000;
+
+pub struct WasmPlugin {
+    name: String,
+    store: Store<()>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    on_event: TypedFunc<(i32, i32), i64>,
+}
+
+impl WasmPlugin {
+    pub fn load(path: &Path) -> Result<Self, PluginError> {
+        let wasm = std::fs::read(path)?;
+        let name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.display().to_string());
+        Self::from_bytes(name, &wasm)
+    }
+
+    /// Accepts both the binary and the text (`.wat`) encoding.
+    pub fn from_bytes(name: String, wasm: &[u8]) -> Result<Self, PluginError> {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, wasm)?;
+        let mut store = Store::new(&engine, ());
+        let linker = <Linker<()>>::new(&engine);
+        let instance: Instance = linker.instantiate_and_start(&mut store, &module)?;
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or("plugin does not export `memory`")?;
+        let alloc = instance.get_typed_func::<i32, i32>(&store, "alloc")?;
+        let on_event = instance.get_typed_func::<(i32, i32), i64>(&store, "on_event")?;
+        Ok(WasmPlugin {
+            name,
+            store,
+            memory,
+            alloc,
+            on_event,
+        })
+    }
+}
+
+impl Plugin for WasmPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn on_event(&mut self, event_json: &str) -> Result<Vec<Action>, PluginError> {
+        self.store.set_fuel(FUEL_PER_EVENT)?;
+        let input = event_json.as_bytes();
+        let len = i32::try_from(input.len())?;
+        let ptr = self.alloc.call(&mut self.store, len)?;
+        self.memory
+            .write(&mut self.store, usize::try_from(ptr)?, input)?;
+
+        let packed = self.on_event.call(&mut self.store, (ptr, len))? as u64;
+        if packed == 0 {
+            return Ok(Vec::new());
+        }
+        let out_ptr = (packed >> 32) as usize;
+        let out_len = (packed & 0xffff_ffff) as usize;
+        let mut output = vec![0u8; out_len];
+        self.memory.read(&self.store, out_ptr, &mut output)?;
+        parse_actions(&output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Ignores its input and always answers with the 17-byte data segment.
+    const DROP_PLUGIN: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (data (i32.const 0) "[{\"type\":\"drop\"}]")
+          (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+          (func (export "on_event") (param i32 i32) (result i64) (i64.const 17)))
+    "#;
+
+    // Echoes the event JSON back, which is not a valid action list.
+    const ECHO_PLUGIN: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "alloc") (param i32) (result i32) (i32.const 64))
+          (func (export "on_event") (param $ptr i32) (param $len i32) (result i64)
+            (i64.or
+              (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+              (i64.extend_i32_u (local.get $len)))))
+    "#;
+
+    const SPIN_PLUGIN: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "alloc") (param i32) (result i32) (i32.const 0))
+          (func (export "on_event") (param i32 i32) (result i64)
+            (loop $l (br $l))
+            (i64.const 0)))
+    "#;
+
+    #[test]
+    fn wasm_plugin_returns_actions() {
+        let mut plugin = WasmPlugin::from_bytes("drop".into(), DROP_PLUGIN.as_bytes()).unwrap();
+        let actions = plugin.on_event(r#"{"kind":"start"}"#).unwrap();
+        assert_eq!(actions, vec![Action::Drop]);
+    }
+
+    #[test]
+    fn wasm_plugin_sees_event_json() {
+        let mut plugin = WasmPlugin::from_bytes("echo".into(), ECHO_PLUGIN.as_bytes()).unwrap();
+        let err = plugin.on_event(r#"{"kind":"start"}"#).unwrap_err();
+        assert!(err.to_string().contains("invalid type"), "{err}");
+    }
+
+    #[test]
+    fn wasm_plugin_runaway_loop_runs_out_of_fuel() {
+        let mut plugin = WasmPlugin::from_bytes("spin".into(), SPIN_PLUGIN.as_bytes()).unwrap();
+        assert!(plugin.on_event("{}").is_err());
+    }
+
+    #[test]
+    fn wasm_plugin_requires_exports() {
+        let result = WasmPlugin::from_bytes("empty".into(), b"(module)");
+        assert!(result.is_err());
+    }
+}
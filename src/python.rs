@@ -0,0 +1,105 @@
+//! Optional Python bindings, built with `maturin` when the `python` feature is
+//! enabled. They expose the same runner and notifier the binary uses, so a
+//! script can wrap a long job without shelling out to `sentinel-rs`.
+//!
+//! ```python
+//! import sentinel_rs
+//! result = sentinel_rs.run_and_notify("python train.py", {"tee": True})
+//! sentinel_rs.notify("epoch 10 done")
+//! ```
+
+use crate::config::{HttpOptions, TgConfig, api_base, bot_token, chat_id, parse_api_base};
+use crate::event::Event;
+use crate::notifier::http_client_with;
+use crate::telegram::tg_send;
+use crate::template::Templates;
+use crate::{RunOptions, exit_code};
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+
+fn string_option(options: Option<&Bound<'_, PyDict>>, key: &str) -> PyResult<Option<String>> {
+    match options.map(|o| o.get_item(key)).transpose()?.flatten() {
+        Some(value) if !value.is_none() => Ok(Some(value.extract::<String>()?)),
+        _ => Ok(None),
+    }
+}
+
+fn bool_option(options: Option<&Bound<'_, PyDict>>, key: &str) -> PyResult<Option<bool>> {
+    match options.map(|o| o.get_item(key)).transpose()?.flatten() {
+        Some(value) if !value.is_none() => Ok(Some(value.extract::<bool>()?)),
+        _ => Ok(None),
+    }
+}
+
+/// Builds the Telegram config from `bot_token`, `chat_id`, `api_base` and
+/// `proxy` options, falling back to the same environment variables as the binary.
+fn tg_config(options: Option<&Bound<'_, PyDict>>) -> PyResult<TgConfig> {
+    let bot_token = match string_option(options, "bot_token")? {
+        Some(token) => token,
+        None => bot_token(None).map_err(PyRuntimeError::new_err)?,
+    };
+    let chat_id = match string_option(options, "chat_id")? {
+        Some(chat_id) => chat_id,
+        None => chat_id().map_err(PyRuntimeError::new_err)?,
+    };
+    let api_base = match string_option(options, "api_base")? {
+        Some(api_base) => parse_api_base(&api_base)
+            .map_err(|e| PyRuntimeError::new_err(format!("api_base {e}")))?,
+        None => api_base().map_err(PyRuntimeError::new_err)?,
+    };
+    let mut http = HttpOptions::from_env().map_err(PyRuntimeError::new_err)?;
+    if let Some(proxy) = string_option(options, "proxy")? {
+        http.proxy = Some(proxy);
+    }
+    Ok(TgConfig {
+        http,
+        ..TgConfig::new(&bot_token, &chat_id, &api_base)
+    })
+}
+
+/// Runs `command` via `bash -c` with start/finish notifications and returns
+/// a dict with `exit_code`, `stdout` and `stderr` (captured tails, as bytes).
+///
+/// Options: `tee` (default True), `ssh` (run on that host), `bot_token`,
+/// `chat_id`, `api_base`.
+/// Raises `OSError` if the command could not be started.
+#[pyfunction]
+#[pyo3(signature = (command, options=None))]
+fn run_and_notify<'py>(
+    py: Python<'py>,
+    command: &str,
+    options: Option<&Bound<'py, PyDict>>,
+) -> PyResult<Bound<'py, PyDict>> {
+    let cfg = tg_config(options)?;
+    let mut opts = RunOptions::from_env().map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+    opts.tee = bool_option(options, "tee")?.unwrap_or(true);
+    opts.exec.ssh = string_option(options, "ssh")?;
+    let output = py.detach(|| crate::run_and_notify(command, cfg, opts))?;
+
+    let result = PyDict::new(py);
+    result.set_item("exit_code", exit_code(&output))?;
+    result.set_item("stdout", PyBytes::new(py, &output.stdout))?;
+    result.set_item("stderr", PyBytes::new(py, &output.stderr))?;
+    Ok(result)
+}
+
+/// Sends a single message through the `message` template (by default, the
+/// usual timestamp/host header followed by `text`).
+#[pyfunction]
+#[pyo3(signature = (text, options=None))]
+fn notify(py: Python<'_>, text: &str, options: Option<&Bound<'_, PyDict>>) -> PyResult<()> {
+    let cfg = tg_config(options)?;
+    let templates = Templates::from_env().map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+    let body = templates.render(&Event::message("", text));
+    let client = http_client_with(&cfg.http).map_err(PyRuntimeError::new_err)?;
+    py.detach(|| tg_send(&client, &cfg, &body).map_err(|e| e.to_string()))
+        .map_err(PyRuntimeError::new_err)
+}
+
+#[pymodule]
+fn sentinel_rs(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(run_and_notify, m)?)?;
+    m.add_function(wrap_pyfunction!(notify, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,269 @@
+//! Quiet hours (`SENTINEL_QUIET_HOURS=23:00-07:00`): notifications raised
+//! during them are not sent to Telegram but kept in `held.jsonl` in the
+//! [state directory](crate::config::state_dir), unless they are at least as
+//! severe as `SENTINEL_QUIET_BREAKTHROUGH` (default `critical`). The held
+//! ones go out as one digest, ahead of the first notification sent once the
+//! quiet hours are over, or when `sentinel-rs digest` is run, e.g. from cron
+//! as they end. The hours are in the zone notifications are written in
+//! (`--utc`, `--timezone`). The other backends are not held back.
+
+use crate::clock;
+use crate::config::{self, TgConfig};
+use crate::event::Severity;
+use crate::locale;
+use crate::notifier::http_client_with;
+use crate::plugin::{Action, Plugin, PluginError};
+use crate::telegram::tg_send;
+use serde_json::{Value, json};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// A daily stretch of time, from `start` up to `end`, in minutes into the
+/// day; past midnight if `end` comes first.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hours {
+    start: u32,
+    end: u32,
+}
+
+impl Hours {
+    pub fn contains(&self, minute: u32) -> bool {
+        if self.start < self.end {
+            (self.start..self.end).contains(&minute)
+        } else {
+            minute >= self.start || minute < self.end
+        }
+    }
+}
+
+fn parse_time(s: &str) -> Result<u32, String> {
+    let (hour, minute) = s
+        .trim()
+        .split_once(':')
+        .ok_or_else(|| format!("{s:?} is not a time (expected HH:MM)"))?;
+    match (hour.parse::<u32>(), minute.parse::<u32>()) {
+        (Ok(h), Ok(m)) if h < 24 && m < 60 && minute.len() == 2 => Ok(h * 60 + m),
+        _ => Err(format!("{s:?} is not a time (expected HH:MM)")),
+    }
+}
+
+impl FromStr for Hours {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| format!("expected START-END, e.g. 23:00-07:00, got {s:?}"))?;
+        let hours = Hours {
+            start: parse_time(start)?,
+            end: parse_time(end)?,
+        };
+        if hours.start == hours.end {
+            return Err(format!("{s:?} starts and ends at the same time"));
+        }
+        Ok(hours)
+    }
+}
+
+impl fmt::Display for Hours {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = |m: u32| format!("{:02}:{:02}", m / 60, m % 60);
+        write!(f, "{}-{}", time(self.start), time(self.end))
+    }
+}
+
+fn severity(name: &str) -> Option<Severity> {
+    serde_json::from_value(json!(name.trim())).ok()
+}
+
+/// Where held notifications are kept, if there is a state directory.
+pub fn held_file() -> Option<PathBuf> {
+    config::state_dir().map(|dir| dir.join("held.jsonl"))
+}
+
+/// The quiet hours plugin, if `SENTINEL_QUIET_HOURS` is set.
+pub fn from_env() -> Result<Option<Box<dyn Plugin>>, String> {
+    let Ok(hours) = config::env_required("SENTINEL_QUIET_HOURS") else {
+        return Ok(None);
+    };
+    let hours = hours
+        .parse()
+        .map_err(|e| format!("SENTINEL_QUIET_HOURS: {e}"))?;
+    let breakthrough = match config::env_required("SENTINEL_QUIET_BREAKTHROUGH") {
+        Ok(name) => severity(&name).ok_or_else(|| {
+            format!(
+                "SENTINEL_QUIET_BREAKTHROUGH: unknown severity {name:?} (expected info, warning, error or critical)"
+            )
+        })?,
+        Err(_) => Severity::Critical,
+    };
+    let file = held_file()
+        .ok_or("SENTINEL_QUIET_HOURS needs a state directory (set SENTINEL_STATE_DIR or HOME)")?;
+    Ok(Some(Box::new(Quiet {
+        hours,
+        breakthrough,
+        file,
+    })))
+}
+
+/// Adds `text` to those held in `path`, in a single write so that runs
+/// holding one at the same time do not mix them up.
+fn hold(path: &Path, text: &str) -> std::io::Result<()> {
+    use std::io::Write;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let line = format!("{}\n", json!({ "text": text }));
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(line.as_bytes())
+}
+
+/// Takes the notifications held in `path` out of it, oldest first. The file
+/// is moved aside before it is read, so each is taken once.
+fn take(path: &Path) -> std::io::Result<Vec<String>> {
+    let taken = path.with_extension(format!("taken-{}", std::process::id()));
+    match std::fs::rename(path, &taken) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    }
+    let text = std::fs::read_to_string(&taken);
+    std::fs::remove_file(&taken).ok();
+    Ok(text?
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .filter_map(|held| held["text"].as_str().map(str::to_string))
+        .collect())
+}
+
+/// One message with all of `held`.
+pub fn digest(held: &[String]) -> String {
+    format!(
+        "{}\n\n{}",
+        locale::say("Held during quiet hours ({n}):", &[("n", &held.len())]),
+        held.join("\n\n")
+    )
+}
+
+/// Sends what is held now, returning how many there were. They are held
+/// again if it cannot be sent.
+pub fn send_digest(cfg: &TgConfig) -> Result<usize, String> {
+    let file = held_file().ok_or("no state directory (set SENTINEL_STATE_DIR or HOME)")?;
+    let held = take(&file).map_err(|e| format!("cannot read {}: {e}", file.display()))?;
+    if held.is_empty() {
+        return Ok(0);
+    }
+    let sent = http_client_with(&cfg.http)
+        .and_then(|client| tg_send(&client, cfg, &digest(&held)).map_err(|e| e.to_string()));
+    if let Err(e) = sent {
+        for text in &held {
+            hold(&file, text).ok();
+        }
+        return Err(format!("cannot send the digest: {e}"));
+    }
+    Ok(held.len())
+}
+
+struct Quiet {
+    hours: Hours,
+    breakthrough: Severity,
+    file: PathBuf,
+}
+
+impl Plugin for Quiet {
+    fn name(&self) -> &str {
+        "quiet-hours"
+    }
+
+    fn on_event(&mut self, event_json: &str) -> Result<Vec<Action>, PluginError> {
+        let event: Value = serde_json::from_str(event_json)?;
+        if event["muted"] == true || !event["maintenance"].is_null() {
+            return Ok(Vec::new());
+        }
+        let text = event["text"].as_str().unwrap_or_default();
+        if self.hours.contains(clock::minute_of_day(SystemTime::now())) {
+            let severity = event["severity"]
+                .as_str()
+                .and_then(severity)
+                .unwrap_or(Severity::Info);
+            if severity >= self.breakthrough {
+                return Ok(Vec::new());
+            }
+            hold(&self.file, text)?;
+            return Ok(vec![Action::Drop]);
+        }
+        let held = take(&self.file)?;
+        if held.is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(vec![Action::Rewrite {
+            text: format!("{}\n\n{text}", digest(&held)),
+        }])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hours_may_wrap_past_midnight() {
+        let night: Hours = "23:00-07:00".parse().unwrap();
+        assert!(night.contains(23 * 60));
+        assert!(night.contains(3 * 60 + 30));
+        assert!(!night.contains(7 * 60));
+        assert!(!night.contains(12 * 60));
+        let lunch: Hours = "12:00-13:30".parse().unwrap();
+        assert!(lunch.contains(13 * 60 + 29));
+        assert!(!lunch.contains(13 * 60 + 30));
+        assert_eq!(night.to_string(), "23:00-07:00");
+        assert!("23:00".parse::<Hours>().is_err());
+        assert!("23:00-24:00".parse::<Hours>().is_err());
+        assert!("7:5-8:00".parse::<Hours>().is_err());
+        assert!("07:00-07:00".parse::<Hours>().is_err());
+    }
+
+    #[test]
+    fn held_notifications_are_taken_once() {
+        let path = std::env::temp_dir()
+            .join(format!("sentinel-quiet-{}", std::process::id()))
+            .join("held.jsonl");
+        hold(&path, "first").unwrap();
+        hold(&path, "second\nline").unwrap();
+        let held = take(&path).unwrap();
+        assert_eq!(held, ["first", "second\nline"]);
+        assert!(take(&path).unwrap().is_empty());
+        assert_eq!(
+            digest(&held),
+            "Held during quiet hours (2):\n\nfirst\n\nsecond\nline"
+        );
+        std::fs::remove_dir_all(path.parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn notifications_under_maintenance_leave_the_held_ones_alone() {
+        let path = std::env::temp_dir()
+            .join(format!("sentinel-quiet-maintenance-{}", std::process::id()))
+            .join("held.jsonl");
+        hold(&path, "held overnight").unwrap();
+        // Quiet hours that ended an hour ago.
+        let now = clock::minute_of_day(SystemTime::now());
+        let mut quiet = Quiet {
+            hours: Hours {
+                start: (now + 1440 - 120) % 1440,
+                end: (now + 1440 - 60) % 1440,
+            },
+            breakthrough: Severity::Critical,
+            file: path.clone(),
+        };
+        let suppressed = json!({ "kind": "failure", "maintenance": "suppress", "text": "Failed" });
+        assert!(quiet.on_event(&suppressed.to_string()).unwrap().is_empty());
+        assert_eq!(take(&path).unwrap(), ["held overnight"]);
+        std::fs::remove_dir_all(path.parent().unwrap()).ok();
+    }
+}
@@ -0,0 +1,290 @@
+//! Repeated failures (`SENTINEL_REPEAT_WINDOW=SECS`): a job failing the same
+//! way again and again, e.g. one a supervisor restarts in a loop, sends one
+//! failure message per window rather than one per run. A failure is the same
+//! as the last when it is of the same job on the same host, with the same
+//! exit code (or signal) and the same last line of error output. Those
+//! within the window of the last one sent are not sent but counted, in
+//! `repeats.json` in the [state directory](crate::config::state_dir), and
+//! the next message about the job says how many there were: the next
+//! failure once the window is over, a different failure, or a success.
+//! Start messages of the job are not sent within the window either. Once a
+//! failure is [acknowledged](crate::escalate), its repeats are not sent
+//! even after the window, until the job fails another way or succeeds.
+
+use crate::ci::{self, Outcome};
+use crate::config;
+use crate::event::format_duration;
+use crate::locale;
+use crate::plugin::{Action, Plugin, PluginError};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// The last failure sent for a job, and the repeats of it not sent since.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+struct Last {
+    job: String,
+    host: String,
+    /// What makes a failure the same as this one.
+    failure: String,
+    /// When it was sent, in seconds since the epoch.
+    sent: i64,
+    repeats: u32,
+    /// When the last repeat came.
+    last: i64,
+    /// Whether someone acknowledged it: its repeats are then held for good.
+    #[serde(default)]
+    acknowledged: bool,
+}
+
+impl Last {
+    /// Says how many repeats there were, if any.
+    fn note(&self, same: bool) -> Option<String> {
+        if self.repeats == 0 {
+            return None;
+        }
+        let took = format_duration(Duration::from_secs((self.last - self.sent).max(0) as u64));
+        let args: [(&str, &dyn std::fmt::Display); 2] = [("n", &self.repeats), ("took", &took)];
+        Some(if same {
+            locale::say(
+                "Repeated {n}× in {took} since the last notification.",
+                &args,
+            )
+        } else {
+            locale::say(
+                "The failure before repeated {n}× in {took} without being sent.",
+                &args,
+            )
+        })
+    }
+}
+
+/// What a failure event is told apart by: how it failed and the last line
+/// of its error output. `None` for events that are not a failure.
+fn failure(event: &Value) -> Option<String> {
+    let (outcome, description) = ci::outcome(event)?;
+    if !matches!(outcome, Outcome::Failure | Outcome::Error) {
+        return None;
+    }
+    let last_line = ["error", "stderr", "stdout"]
+        .iter()
+        .filter_map(|field| event[field].as_str())
+        .flat_map(|text| text.lines().rev().map(str::trim))
+        .find(|line| !line.is_empty())
+        .unwrap_or_default();
+    Some(format!("{description}\n{last_line}"))
+}
+
+/// What to do about `event`, given the failures sent last in `lasts`,
+/// which it updates, `now` and the `window` in seconds.
+fn handle(lasts: &mut Vec<Last>, event: &Value, now: i64, window: i64) -> Vec<Action> {
+    let job = event["job"].as_str().unwrap_or_default();
+    let host = event["host"].as_str().unwrap_or_default();
+    let at = lasts.iter().position(|l| l.job == job && l.host == host);
+    match ci::outcome(event) {
+        None => return Vec::new(),
+        _ if event["kind"] == "report"
+            || event["muted"] == true
+            || !event["maintenance"].is_null() =>
+        {
+            return Vec::new();
+        }
+        // A job restarted in a loop would still send a start message a run.
+        Some((Outcome::Running, _)) => {
+            return match at {
+                Some(at) if lasts[at].acknowledged || now - lasts[at].sent < window => {
+                    vec![Action::Drop]
+                }
+                _ => Vec::new(),
+            };
+        }
+        Some(_) => {}
+    }
+    let failure = failure(event);
+    let note = match (at, &failure) {
+        (Some(at), Some(failure)) => {
+            let last = &mut lasts[at];
+            let same = last.failure == *failure;
+            if same && (last.acknowledged || now - last.sent < window) {
+                last.repeats += 1;
+                last.last = now;
+                return vec![Action::Drop];
+            }
+            let note = last.note(same);
+            lasts.remove(at);
+            note
+        }
+        (Some(at), None) => lasts.remove(at).note(false),
+        (None, _) => None,
+    };
+    if let Some(failure) = failure {
+        lasts.push(Last {
+            job: job.to_string(),
+            host: host.to_string(),
+            failure,
+            sent: now,
+            repeats: 0,
+            last: now,
+            acknowledged: false,
+        });
+    }
+    let text = event["text"].as_str().unwrap_or_default();
+    note.map(|note| Action::Rewrite {
+        text: format!("{note}\n{text}"),
+    })
+    .into_iter()
+    .collect()
+}
+
+fn repeats_file() -> Option<PathBuf> {
+    config::state_dir().map(|dir| dir.join("repeats.json"))
+}
+
+/// Marks the last failure of `job` on `host` acknowledged, if there is one.
+pub fn acknowledge(job: &str, host: &str) {
+    let Some(file) = repeats_file() else {
+        return;
+    };
+    let _lock = match config::lock_state(&file) {
+        Ok(lock) => lock,
+        Err(e) => {
+            tracing::warn!("Failed to mark {job} acknowledged: {e}");
+            return;
+        }
+    };
+    let mut lasts: Vec<Last> = config::read_state(&file).unwrap_or_default();
+    let Some(last) = lasts.iter_mut().find(|l| l.job == job && l.host == host) else {
+        return;
+    };
+    last.acknowledged = true;
+    if let Err(e) = config::write_state(&file, &lasts) {
+        tracing::warn!("Failed to mark {job} acknowledged: {e}");
+    }
+}
+
+/// The plugin holding back repeated failures, if `SENTINEL_REPEAT_WINDOW`
+/// is set.
+pub fn from_env() -> Result<Option<Box<dyn Plugin>>, String> {
+    let Ok(window) = config::env_required("SENTINEL_REPEAT_WINDOW") else {
+        return Ok(None);
+    };
+    let window = window
+        .trim()
+        .parse::<u32>()
+        .ok()
+        .filter(|secs| *secs > 0)
+        .ok_or_else(|| format!("SENTINEL_REPEAT_WINDOW: expected seconds, got {window:?}"))?;
+    let file = repeats_file()
+        .ok_or("SENTINEL_REPEAT_WINDOW needs a state directory (set SENTINEL_STATE_DIR or HOME)")?;
+    Ok(Some(Box::new(Repeats {
+        window: window.into(),
+        file,
+    })))
+}
+
+struct Repeats {
+    window: i64,
+    file: PathBuf,
+}
+
+impl Plugin for Repeats {
+    fn name(&self) -> &str {
+        "repeats"
+    }
+
+    fn on_event(&mut self, event_json: &str) -> Result<Vec<Action>, PluginError> {
+        let event: Value = serde_json::from_str(event_json)?;
+        let _lock = config::lock_state(&self.file)?;
+        let mut lasts = config::read_state(&self.file).unwrap_or_default();
+        let actions = handle(
+            &mut lasts,
+            &event,
+            chrono::Utc::now().timestamp(),
+            self.window,
+        );
+        config::write_state(&self.file, &lasts)?;
+        Ok(actions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn failed(code: i32, stderr: &str) -> Value {
+        json!({
+            "kind": "failure", "job": "worker", "host": "vm", "exit_code": code,
+            "stderr": stderr, "text": "Failed",
+        })
+    }
+
+    fn rewritten(actions: &[Action]) -> Option<&str> {
+        match actions {
+            [Action::Rewrite { text }] => Some(text),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn repeats_within_the_window_are_counted_not_sent() {
+        let mut lasts = Vec::new();
+        let boom = failed(1, "connecting\nerror: connection refused\n");
+        assert!(handle(&mut lasts, &boom, 0, 600).is_empty());
+        for now in [30, 60, 590] {
+            assert_eq!(handle(&mut lasts, &boom, now, 600), [Action::Drop]);
+        }
+        // Once the window is over the next one is sent, with the count.
+        let actions = handle(&mut lasts, &boom, 650, 600);
+        assert_eq!(
+            rewritten(&actions),
+            Some("Repeated 3× in 9m 50s since the last notification.\nFailed")
+        );
+        // A failure of another kind is sent at once.
+        let start = json!({ "kind": "start", "job": "worker", "host": "vm", "text": "Started" });
+        assert_eq!(handle(&mut lasts, &start, 700, 600), [Action::Drop]);
+        assert_eq!(handle(&mut lasts, &boom, 700, 600), [Action::Drop]);
+        let other = failed(2, "error: connection refused");
+        assert_eq!(
+            rewritten(&handle(&mut lasts, &other, 710, 600)),
+            Some("The failure before repeated 1× in 50.0s without being sent.\nFailed")
+        );
+        // Once acknowledged, it is held past the window too.
+        lasts[0].acknowledged = true;
+        assert_eq!(handle(&mut lasts, &other, 2_000, 600), [Action::Drop]);
+        let success = json!({ "kind": "success", "job": "worker", "host": "vm", "text": "Ok" });
+        assert_eq!(
+            rewritten(&handle(&mut lasts, &success, 2_060, 600)),
+            Some("The failure before repeated 1× in 21m 30s without being sent.\nOk")
+        );
+        assert!(lasts.is_empty());
+        assert!(handle(&mut lasts, &start, 730, 600).is_empty());
+        assert!(handle(&mut lasts, &boom, 730, 600).is_empty());
+    }
+
+    #[test]
+    fn runs_failing_at_once_count_every_repeat() {
+        let file =
+            std::env::temp_dir().join(format!("sentinel-repeats-{}.json", std::process::id()));
+        let boom = failed(1, "error: connection refused").to_string();
+        let runs: Vec<_> = (0..8)
+            .map(|_| {
+                let (file, boom) = (file.clone(), boom.clone());
+                std::thread::spawn(move || {
+                    let mut repeats = Repeats { window: 600, file };
+                    for _ in 0..10 {
+                        repeats.on_event(&boom).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for run in runs {
+            run.join().unwrap();
+        }
+        let lasts: Vec<Last> = config::read_state(&file).unwrap();
+        assert_eq!(lasts[0].repeats, 79);
+        std::fs::remove_file(&file).ok();
+        std::fs::remove_file(file.with_extension("lock")).ok();
+    }
+}
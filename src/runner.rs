@@ -0,0 +1,863 @@
+use crate::docker::DockerRun;
+use crate::kube::KubeJob;
+use crate::metrics::METRICS;
+use crate::pipeline;
+use crate::sandbox::Sandbox;
+use crate::sched::Scheduling;
+use crate::tee;
+use crate::ulimit::{self, Ulimit};
+use crate::user::RunAs;
+use std::io::{IsTerminal, Read, Write};
+use std::os::fd::AsFd;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    pub fn name(self) -> &'static str {
+        match self {
+            Stream::Stdout => "stdout",
+            Stream::Stderr => "stderr",
+        }
+    }
+}
+
+/// How the command is started.
+#[derive(Clone, Debug, Default)]
+pub struct Exec {
+    /// Run via `ssh` on this destination instead of locally.
+    pub ssh: Option<String>,
+    /// Run as this Kubernetes Job instead of locally.
+    pub k8s: Option<KubeJob>,
+    /// Run in this container instead of directly on the host.
+    pub docker: Option<DockerRun>,
+    /// Extra environment for the command only (e.g. fetched secrets).
+    pub env: Vec<(String, String)>,
+    /// Restrictions for a local command.
+    pub sandbox: Sandbox,
+    /// Run a local command as this user instead of ours.
+    pub run_as: Option<RunAs>,
+    /// Move a local command into this cgroup (see [`crate::cgroup`]).
+    pub cgroup: Option<PathBuf>,
+    /// setrlimit limits for a local command.
+    pub ulimits: Vec<Ulimit>,
+    /// Priority and CPU affinity for a local command.
+    pub sched: Scheduling,
+    /// Also write everything the command prints, stdout and stderr as they
+    /// come, to this file (readable only by us), where only the last
+    /// [`CAPTURE_BYTES`] of each are kept in memory.
+    pub log: Option<PathBuf>,
+    /// Read the command's output this many bytes at a time (default
+    /// [`READ_BUFFER`]).
+    pub read_buffer: Option<usize>,
+    /// When output is passed on to the terminal and the log; by default
+    /// [`tee::Flush::Line`] if stdout is a terminal, else as it is read.
+    pub flush: Option<tee::Flush>,
+    /// Also capture stdout and stderr together, in the order they came, as
+    /// [`Run::merged`].
+    pub merge_output: bool,
+    /// Let [`crate::control`] signal the command and write to its stdin,
+    /// which is then a pipe instead of ours.
+    pub control: bool,
+}
+
+impl Exec {
+    pub fn command(&self, script: &str) -> Command {
+        if let Some(run) = &self.docker {
+            return run.command(script, &self.env);
+        }
+        if let Some(job) = &self.k8s {
+            let mut cmd = Command::new("bash");
+            cmd.arg("-c").arg(job.driver(script));
+            return cmd;
+        }
+        match &self.ssh {
+            None => {
+                let mut cmd = Command::new("bash");
+                cmd.arg("-c").arg(script).envs(self.env.iter().cloned());
+                cmd
+            }
+            Some(destination) => {
+                // ssh hands the remote side a single string for its login
+                // shell to parse, so the script has to be quoted for that.
+                let mut cmd = Command::new("ssh");
+                cmd.args(["-o", "BatchMode=yes", "--", destination])
+                    .arg(format!("bash -c {}", shell_quote(script)));
+                cmd
+            }
+        }
+    }
+
+    /// Where the command runs, for notifications, when that is not here.
+    pub fn remote_host(&self) -> Option<String> {
+        if let Some(run) = &self.docker {
+            Some(run.label())
+        } else if let Some(job) = &self.k8s {
+            Some(job.label())
+        } else {
+            self.ssh.as_deref().map(|d| ssh_host(d).to_string())
+        }
+    }
+}
+
+/// A name for the Job or container of one run, unique on this machine.
+pub fn run_name() -> String {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    format!("sentinel-rs-{secs}-{}", std::process::id())
+}
+
+/// Quotes `s` as a single word for a POSIX shell.
+pub fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+/// The part of an ssh destination that names the host.
+pub fn ssh_host(destination: &str) -> &str {
+    let host = destination
+        .strip_prefix("ssh://")
+        .unwrap_or(destination)
+        .rsplit('@')
+        .next()
+        .unwrap_or(destination);
+    host.split(':').next().unwrap_or(host)
+}
+
+/// Adds the bytes read through it to one of the [`METRICS`] output counters.
+struct CountingReader<R> {
+    inner: R,
+    count: &'static AtomicU64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count.fetch_add(read as u64, Ordering::Relaxed);
+        Ok(read)
+    }
+}
+
+/// How much of the command's output is read at a time, unless
+/// [`Exec::read_buffer`] says otherwise.
+pub const READ_BUFFER: usize = 4096;
+
+/// How much of each stream is kept in memory: the last 16 KiB.
+pub const CAPTURE_BYTES: usize = 16 * 1024;
+
+/// The last `max` bytes written to it.
+struct Tail {
+    buf: std::collections::VecDeque<u8>,
+    max: usize,
+}
+
+impl Tail {
+    fn new(max: usize) -> Self {
+        Tail {
+            buf: std::collections::VecDeque::with_capacity(max),
+            max,
+        }
+    }
+
+    fn push(&mut self, data: &[u8]) {
+        let data = &data[data.len().saturating_sub(self.max)..];
+        let over = (self.buf.len() + data.len()).saturating_sub(self.max);
+        self.buf.drain(..over);
+        self.buf.extend(data);
+    }
+
+    fn into_vec(self) -> Vec<u8> {
+        self.buf.into()
+    }
+}
+
+/// Adds what it reads to a capture of both streams in the order they come.
+struct MergingReader<R> {
+    inner: R,
+    stream: Stream,
+    merged: Arc<Mutex<Merged>>,
+}
+
+impl<R: Read> Read for MergingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        let mut merged = self.merged.lock().unwrap_or_else(|e| e.into_inner());
+        merged.push(self.stream, &buf[..read]);
+        Ok(read)
+    }
+}
+
+/// The last [`CAPTURE_BYTES`] of both streams, as chunks in the order they
+/// were read.
+#[derive(Default)]
+struct Merged {
+    chunks: std::collections::VecDeque<(Stream, std::collections::VecDeque<u8>)>,
+    len: usize,
+}
+
+impl Merged {
+    fn push(&mut self, stream: Stream, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        match self.chunks.back_mut() {
+            Some((last, chunk)) if *last == stream => chunk.extend(data),
+            _ => self
+                .chunks
+                .push_back((stream, data.iter().copied().collect())),
+        }
+        self.len += data.len();
+        while self.len > CAPTURE_BYTES {
+            let Some((_, front)) = self.chunks.front_mut() else {
+                break;
+            };
+            let over = (self.len - CAPTURE_BYTES).min(front.len());
+            front.drain(..over);
+            self.len -= over;
+            if front.is_empty() {
+                self.chunks.pop_front();
+            }
+        }
+    }
+
+    /// The chunks as text, a line break where the stream changes mid-line
+    /// and each line of stderr starting with `[stderr] `.
+    fn text(&self) -> String {
+        let mut text = String::new();
+        let mut last = None;
+        for (stream, chunk) in &self.chunks {
+            if last.is_some() && !text.ends_with('\n') {
+                text.push('\n');
+            }
+            let chunk: Vec<u8> = chunk.iter().copied().collect();
+            for line in String::from_utf8_lossy(&chunk).split_inclusive('\n') {
+                let starts = text.is_empty() || text.ends_with('\n');
+                if starts && *stream == Stream::Stderr {
+                    text.push_str("[stderr] ");
+                }
+                text.push_str(line);
+            }
+            last = Some(*stream);
+        }
+        text
+    }
+}
+
+/// A command [run](run_bash_with_hook): its output, and what else was
+/// learned running it.
+#[derive(Debug)]
+pub struct Run {
+    pub output: Output,
+    /// Its pid, e.g. to find its core file after it crashed.
+    pub pid: u32,
+    /// Which stages failed if it was a local pipeline, e.g. ``stage 2 of 3,
+    /// `gzip`, exited 1`` (see [`crate::pipeline`]).
+    pub failed_stage: Option<String>,
+    /// Both streams, in the order they came, with [`Exec::merge_output`].
+    pub merged: Option<String>,
+}
+
+/// Commands started on any thread and not waited for yet.
+static RUNNING: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+/// The pids of the commands still running, e.g. for a crash report. Empty
+/// if the list is being updated at the time.
+pub fn running_children() -> Vec<u32> {
+    RUNNING
+        .try_lock()
+        .map(|pids| pids.clone())
+        .unwrap_or_default()
+}
+
+/// Listed in [`RUNNING`] until dropped.
+struct Running(u32);
+
+impl Running {
+    fn new(pid: u32) -> Self {
+        RUNNING.lock().unwrap_or_else(|e| e.into_inner()).push(pid);
+        Running(pid)
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        RUNNING
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|&pid| pid != self.0);
+    }
+}
+
+/// Called for every line of output as it is captured, from the thread
+/// reading that stream.
+pub type OnLine = Arc<dyn Fn(Stream, &str) + Send + Sync>;
+
+/// Splits a byte stream into lines for an [`OnLine`] hook. Lines longer than
+/// `MAX_LINE` are handed over in pieces so a newline-free stream can't grow
+/// the buffer without bound.
+struct LineSplitter {
+    pending: Vec<u8>,
+}
+
+impl LineSplitter {
+    const MAX_LINE: usize = 64 * 1024;
+
+    fn feed(&mut self, mut data: &[u8], emit: &mut dyn FnMut(&str)) {
+        while let Some(pos) = data.iter().position(|&b| b == b'\n') {
+            if self.pending.is_empty() {
+                // The whole line is in `data`; no need to copy it.
+                Self::emit(&data[..pos], emit);
+            } else {
+                self.pending.extend_from_slice(&data[..pos]);
+                self.flush(emit);
+            }
+            data = &data[pos + 1..];
+        }
+        self.pending.extend_from_slice(data);
+        if self.pending.len() >= Self::MAX_LINE {
+            self.flush(emit);
+        }
+    }
+
+    fn flush(&mut self, emit: &mut dyn FnMut(&str)) {
+        Self::emit(&self.pending, emit);
+        self.pending.clear();
+    }
+
+    fn emit(line: &[u8], emit: &mut dyn FnMut(&str)) {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        emit(&String::from_utf8_lossy(line));
+    }
+}
+
+pub fn read_stream<R: Read, W: Write>(reader: R, writer: W, tee: bool) -> std::io::Result<Vec<u8>> {
+    read_stream_lines(reader, writer, tee, None, READ_BUFFER)
+}
+
+pub fn read_stream_lines<R: Read, W: Write>(
+    mut reader: R,
+    mut writer: W,
+    tee: bool,
+    mut on_line: Option<&mut dyn FnMut(&str)>,
+    buffer: usize,
+) -> std::io::Result<Vec<u8>> {
+    let mut tail = Tail::new(CAPTURE_BYTES);
+    let mut chunk = vec![0u8; buffer.max(1)];
+    let mut lines = LineSplitter {
+        pending: Vec::new(),
+    };
+    loop {
+        let read = reader.read(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        if tee {
+            writer.write_all(&chunk[..read])?;
+            writer.flush().ok();
+        }
+        if let Some(emit) = on_line.as_mut() {
+            lines.feed(&chunk[..read], *emit);
+        }
+        tail.push(&chunk[..read]);
+    }
+    if let Some(emit) = on_line
+        && !lines.pending.is_empty()
+    {
+        lines.flush(emit);
+    }
+    Ok(tail.into_vec())
+}
+
+pub fn run_bash_with_tee(command: &str, tee: bool) -> std::io::Result<Output> {
+    run_bash_with_hook(command, &Exec::default(), tee, None).map(|run| run.output)
+}
+
+pub fn run_bash_with_hook(
+    command: &str,
+    exec: &Exec,
+    tee: bool,
+    on_line: Option<OnLine>,
+) -> std::io::Result<Run> {
+    let stages = exec
+        .remote_host()
+        .is_none()
+        .then(|| pipeline::stages(command))
+        .flatten();
+    let status_pipe = stages
+        .as_ref()
+        .map(|_| pipeline::StatusPipe::new())
+        .transpose()?;
+    let mut cmd = match &status_pipe {
+        Some(pipe) => {
+            let mut cmd = exec.command(&pipeline::script(command, pipe.fd()));
+            pipe.inherit(&mut cmd);
+            cmd
+        }
+        None => exec.command(command),
+    };
+    // First, while the child still has the permissions to join.
+    if let Some(dir) = &exec.cgroup {
+        crate::cgroup::join(&mut cmd, dir)?;
+    }
+    // Before the sandbox and user switch, which can take away the right to
+    // raise hard limits.
+    if !exec.ulimits.is_empty() {
+        ulimit::apply(&mut cmd, &exec.ulimits);
+    }
+    // Also before the user switch: a negative niceness and the realtime I/O
+    // class need root.
+    if !exec.sched.is_empty() {
+        exec.sched.apply(&mut cmd);
+    }
+    if !exec.sandbox.is_empty() {
+        exec.sandbox.apply(&mut cmd)?;
+    }
+    // After the sandbox, which may need root to set up.
+    if let Some(run_as) = &exec.run_as {
+        run_as.apply(&mut cmd);
+    }
+    let mut child = cmd
+        .stdin(if exec.control {
+            Stdio::piped()
+        } else {
+            Stdio::inherit()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let _running = Running::new(child.id());
+    if exec.control {
+        crate::control::attach(child.id(), child.stdin.take());
+    }
+
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| std::io::Error::other("Failed to capture stdout"))?;
+    let stderr = child
+        .stderr
+        .take()
+        .ok_or_else(|| std::io::Error::other("Failed to capture stderr"))?;
+
+    let log = match &exec.log {
+        Some(path) => match open_log(path) {
+            Ok(file) => Some(tee::sink(file)),
+            Err(e) => {
+                tracing::warn!("Failed to create the output log {}: {e}", path.display());
+                None
+            }
+        },
+        None => None,
+    };
+    // Where a stream goes besides memory: the terminal, if teeing, and the
+    // log.
+    let sinks = |terminal: std::os::fd::BorrowedFd| -> std::io::Result<Vec<tee::Sink>> {
+        let mut sinks = Vec::new();
+        if tee {
+            sinks.push(tee::sink(terminal.try_clone_to_owned()?.into()));
+        }
+        sinks.extend(log.clone());
+        Ok(sinks)
+    };
+    let buffer = exec.read_buffer.unwrap_or(READ_BUFFER);
+    let flush = exec
+        .flush
+        .unwrap_or(if tee && std::io::stdout().is_terminal() {
+            tee::Flush::Line
+        } else {
+            tee::Flush::Chunk
+        });
+    let spawn_reader = |reader: Box<dyn Read + Send>, stream: Stream| {
+        let on_line = on_line.clone();
+        std::thread::spawn(move || {
+            let mut emit = |line: &str| {
+                if let Some(hook) = &on_line {
+                    hook(stream, line);
+                }
+            };
+            let emit = on_line
+                .is_some()
+                .then_some(&mut emit as &mut dyn FnMut(&str));
+            read_stream_lines(reader, std::io::sink(), false, emit, buffer)
+        })
+    };
+    let stdout = CountingReader {
+        inner: tee::reader(stdout, sinks(std::io::stdout().as_fd())?, flush, buffer),
+        count: &METRICS.stdout_bytes,
+    };
+    let stderr = CountingReader {
+        inner: tee::reader(stderr, sinks(std::io::stderr().as_fd())?, flush, buffer),
+        count: &METRICS.stderr_bytes,
+    };
+    let merged = exec.merge_output.then(Arc::<Mutex<Merged>>::default);
+    let merge = |reader: Box<dyn Read + Send>, stream: Stream| -> Box<dyn Read + Send> {
+        match &merged {
+            Some(merged) => Box::new(MergingReader {
+                inner: reader,
+                stream,
+                merged: merged.clone(),
+            }),
+            None => reader,
+        }
+    };
+    let stdout_handle = spawn_reader(merge(Box::new(stdout), Stream::Stdout), Stream::Stdout);
+    let stderr_handle = spawn_reader(merge(Box::new(stderr), Stream::Stderr), Stream::Stderr);
+
+    let status = child.wait();
+    if exec.control {
+        crate::control::detach();
+    }
+    let status = status?;
+    let failed_stage = match (stages, status_pipe) {
+        (Some(stages), Some(pipe)) => pipeline::describe_failure(&stages, &pipe.statuses()),
+        _ => None,
+    };
+    let out_buf = stdout_handle
+        .join()
+        .map_err(|_| std::io::Error::other("Failed to capture stdout"))?;
+    let err_buf = stderr_handle
+        .join()
+        .map_err(|_| std::io::Error::other("Failed to capture stderr"))?;
+    let merged = merged.map(|merged| merged.lock().unwrap_or_else(|e| e.into_inner()).text());
+
+    Ok(Run {
+        output: Output {
+            status,
+            stdout: out_buf?,
+            stderr: err_buf?,
+        },
+        pid: child.id(),
+        failed_stage,
+        merged,
+    })
+}
+
+fn open_log(path: &std::path::Path) -> std::io::Result<std::fs::File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+}
+
+pub fn run_bash(
+    command: &str,
+    exec: &Exec,
+    tee: bool,
+    on_line: Option<OnLine>,
+) -> std::io::Result<Run> {
+    run_bash_with_hook(command, exec, tee, on_line).map_err(|e| {
+        let message = if let Some(run) = &exec.docker {
+            format!(
+                "Failed to run command '{command}' in docker image {}: {e}",
+                run.image
+            )
+        } else if let Some(job) = &exec.k8s {
+            format!(
+                "Failed to run command '{command}' as Kubernetes job {}: {e}",
+                job.name
+            )
+        } else if let Some(destination) = &exec.ssh {
+            format!("Failed to run command '{command}' over ssh on {destination}: {e}")
+        } else {
+            format!("Failed to run bash command '{command}': {e}")
+        };
+        std::io::Error::new(e.kind(), message)
+    })
+}
+
+/// Signal names in Linux numbering, from 1.
+const SIGNALS: [&str; 31] = [
+    "SIGHUP",
+    "SIGINT",
+    "SIGQUIT",
+    "SIGILL",
+    "SIGTRAP",
+    "SIGABRT",
+    "SIGBUS",
+    "SIGFPE",
+    "SIGKILL",
+    "SIGUSR1",
+    "SIGSEGV",
+    "SIGUSR2",
+    "SIGPIPE",
+    "SIGALRM",
+    "SIGTERM",
+    "SIGSTKFLT",
+    "SIGCHLD",
+    "SIGCONT",
+    "SIGSTOP",
+    "SIGTSTP",
+    "SIGTTIN",
+    "SIGTTOU",
+    "SIGURG",
+    "SIGXCPU",
+    "SIGXFSZ",
+    "SIGVTALRM",
+    "SIGPROF",
+    "SIGWINCH",
+    "SIGIO",
+    "SIGPWR",
+    "SIGSYS",
+];
+
+/// The name of a signal as `kill -l` shows it, e.g. `SIGKILL`.
+pub fn signal_name(signal: i32) -> String {
+    match usize::try_from(signal)
+        .ok()
+        .and_then(|n| SIGNALS.get(n.wrapping_sub(1)))
+    {
+        Some(name) => name.to_string(),
+        None => format!("signal {signal}"),
+    }
+}
+
+/// The signal `name` stands for: a number, or a name with or without
+/// `SIG`, in any case.
+pub fn parse_signal(name: &str) -> Option<i32> {
+    let name = name.trim();
+    if let Ok(signal) = name.parse::<i32>() {
+        return (1..=64).contains(&signal).then_some(signal);
+    }
+    let name = name.to_ascii_uppercase();
+    let name = name.strip_prefix("SIG").unwrap_or(&name);
+    SIGNALS
+        .iter()
+        .position(|known| known[3..] == *name)
+        .and_then(|i| i32::try_from(i + 1).ok())
+}
+
+pub fn tail_bytes(buf: &[u8], max: usize) -> String {
+    if buf.len() <= max {
+        String::from_utf8_lossy(buf).into_owned()
+    } else {
+        let slice = &buf[buf.len() - max..];
+        format!(
+            "… (truncated, showing last {} bytes)\n{}",
+            max,
+            String::from_utf8_lossy(slice)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tail_bytes_truncates_correctly() {
+        let data = b"abcdefghijklmnopqrstuvwxyz";
+        let result = tail_bytes(data, 10);
+        assert_eq!(result, "… (truncated, showing last 10 bytes)\nqrstuvwxyz");
+    }
+
+    #[test]
+    fn tail_bytes_no_truncation() {
+        let data = b"hello";
+        let result = tail_bytes(data, 10);
+        assert_eq!(result, "hello");
+    }
+
+    #[test]
+    fn tail_bytes_exact_boundary() {
+        let data = b"exact10!!";
+        let result = tail_bytes(data, 9);
+        assert_eq!(result, "exact10!!");
+    }
+
+    #[test]
+    fn tail_bytes_handles_non_utf8() {
+        let data = [0x66, 0xff, 0x6f];
+        let result = tail_bytes(&data, 10);
+        assert_eq!(result, String::from_utf8_lossy(&data));
+    }
+
+    #[test]
+    fn run_bash_captures_stdout_and_stderr() {
+        let output = run_bash_with_tee("printf 'out'; printf 'err' 1>&2", false).unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"out");
+        assert_eq!(output.stderr, b"err");
+    }
+
+    #[test]
+    fn run_bash_keeps_the_tail_and_spills_the_rest() {
+        let log = std::env::temp_dir().join(format!("sentinel-spill-{}.log", std::process::id()));
+        let exec = Exec {
+            log: Some(log.clone()),
+            ..Exec::default()
+        };
+        let output = run_bash_with_hook("seq 1 100000; echo done >&2", &exec, false, None)
+            .unwrap()
+            .output;
+        assert_eq!(output.stdout.len(), CAPTURE_BYTES);
+        assert!(output.stdout.ends_with(b"\n99999\n100000\n"));
+        let full = std::fs::read_to_string(&log).unwrap();
+        assert!(full.starts_with("1\n2\n3\n"));
+        assert_eq!(full.lines().count(), 100_001);
+        assert!(full.contains("done\n"));
+        std::fs::remove_file(&log).unwrap();
+    }
+
+    #[test]
+    fn merged_output_keeps_the_order_of_both_streams() {
+        let mut merged = Merged::default();
+        merged.push(Stream::Stdout, b"one\ntw");
+        merged.push(Stream::Stderr, b"oops\nagain\n");
+        merged.push(Stream::Stdout, b"o\n");
+        assert_eq!(merged.text(), "one\ntw\n[stderr] oops\n[stderr] again\no\n");
+        merged.push(Stream::Stderr, &vec![b'x'; CAPTURE_BYTES]);
+        assert_eq!(merged.len, CAPTURE_BYTES);
+        assert_eq!(merged.chunks.len(), 1);
+
+        let exec = Exec {
+            merge_output: true,
+            ..Exec::default()
+        };
+        let run = run_bash_with_hook(
+            "echo a; sleep 0.1; echo b >&2; sleep 0.1; echo c",
+            &exec,
+            false,
+            None,
+        )
+        .unwrap();
+        assert_eq!(run.merged.as_deref(), Some("a\n[stderr] b\nc\n"));
+    }
+
+    #[test]
+    fn run_bash_captures_non_zero_exit() {
+        let output = run_bash_with_tee("exit 7", false).unwrap();
+        assert_eq!(output.status.code(), Some(7));
+    }
+
+    #[test]
+    fn read_stream_no_tee_keeps_writer_empty() {
+        use std::io::Cursor;
+        let input_data = Cursor::new(b"hello world");
+        let mut output = Vec::new();
+        let buf = read_stream(input_data, &mut output, false).expect("Failed to read stream");
+        assert_eq!(buf, b"hello world");
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn read_stream_lines_splits_across_chunks() {
+        use std::io::Cursor;
+        let mut lines = Vec::new();
+        let mut emit = |line: &str| lines.push(line.to_string());
+        let mut splitter = LineSplitter {
+            pending: Vec::new(),
+        };
+        splitter.feed(b"one\r\ntw", &mut emit);
+        splitter.feed(b"o\nthree", &mut emit);
+        assert_eq!(lines, vec!["one", "two"]);
+        assert_eq!(splitter.pending, b"three");
+
+        let mut lines = Vec::new();
+        let mut emit = |line: &str| lines.push(line.to_string());
+        let buf = read_stream_lines(
+            Cursor::new(b"a\nb\nc"),
+            std::io::sink(),
+            false,
+            Some(&mut emit),
+            READ_BUFFER,
+        )
+        .unwrap();
+        assert_eq!(buf, b"a\nb\nc");
+        assert_eq!(lines, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn run_bash_hook_sees_both_streams() {
+        use std::sync::Mutex;
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        let hook: OnLine = Arc::new(move |stream, line| {
+            sink.lock().unwrap().push((stream, line.to_string()));
+        });
+        run_bash_with_hook(
+            "echo out; echo err 1>&2",
+            &Exec::default(),
+            false,
+            Some(hook),
+        )
+        .unwrap();
+        let mut seen = seen.lock().unwrap().clone();
+        seen.sort_by_key(|(stream, _)| stream.name());
+        assert_eq!(
+            seen,
+            vec![
+                (Stream::Stderr, "err".to_string()),
+                (Stream::Stdout, "out".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn shell_quote_survives_a_shell_round_trip() {
+        let script = r#"echo "it's" $HOME; printf '%s\n' a"#;
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        let output = run_bash_with_tee(&format!("bash -c {}", shell_quote(script)), false).unwrap();
+        let expected = run_bash_with_tee(script, false).unwrap();
+        assert_eq!(output.stdout, expected.stdout);
+    }
+
+    #[test]
+    fn signals_are_named() {
+        assert_eq!(signal_name(9), "SIGKILL");
+        assert_eq!(signal_name(libc::SIGSEGV), "SIGSEGV");
+        assert_eq!(signal_name(0), "signal 0");
+        assert_eq!(parse_signal("term"), Some(libc::SIGTERM));
+        assert_eq!(parse_signal("SIGUSR1"), Some(libc::SIGUSR1));
+        assert_eq!(parse_signal("9"), Some(9));
+        assert_eq!(parse_signal("SIGBOGUS"), None);
+    }
+
+    #[test]
+    fn ssh_host_strips_user_and_port() {
+        assert_eq!(ssh_host("backup@nas"), "nas");
+        assert_eq!(ssh_host("nas"), "nas");
+        assert_eq!(ssh_host("ssh://root@db1:2222"), "db1");
+    }
+
+    #[test]
+    fn ssh_exec_builds_batch_mode_invocation() {
+        let exec = Exec {
+            ssh: Some("me@box".to_string()),
+            ..Exec::default()
+        };
+        let cmd = exec.command("echo 'hi'");
+        assert_eq!(cmd.get_program(), "ssh");
+        let args: Vec<_> = cmd.get_args().map(|a| a.to_string_lossy()).collect();
+        assert_eq!(
+            args,
+            vec![
+                "-o",
+                "BatchMode=yes",
+                "--",
+                "me@box",
+                r"bash -c 'echo '\''hi'\'''"
+            ]
+        );
+    }
+
+    #[test]
+    fn read_stream_copies_when_tee_true() {
+        use std::io::Cursor;
+        let input_data = Cursor::new(b"hello world");
+        let mut output = Vec::new();
+        let buf = read_stream(input_data, &mut output, true).expect("Failed to read stream");
+        assert_eq!(buf, b"hello world");
+        assert_eq!(output, b"hello world");
+    }
+}
@@ -0,0 +1,246 @@
+//! `--read-only`, `--no-network`, `--no-new-privs` and `--sandbox`: a light
+//! sandbox around a local command, for running semi-trusted scripts.
+//!
+//! Everything is set up in the forked child just before it execs bash, with
+//! Linux namespaces rather than an external tool: read-only paths are bind
+//! mounts remounted read-only in a private mount namespace, and no network
+//! means an empty network namespace (not even loopback is up). Without
+//! root, both happen inside a user namespace that maps the caller's own
+//! uid/gid, so file ownership looks the same from inside. `no_new_privs`
+//! keeps the command (and anything it runs) from gaining privileges through
+//! setuid binaries or file capabilities.
+
+use std::path::PathBuf;
+use std::process::Command;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Sandbox {
+    /// Paths the command may read but not change.
+    pub read_only: Vec<PathBuf>,
+    pub no_network: bool,
+    pub no_new_privs: bool,
+}
+
+impl Sandbox {
+    /// The `--sandbox` profile: no network, no new privileges, and the
+    /// system directories read-only.
+    pub fn strict() -> Self {
+        Sandbox {
+            read_only: ["/usr", "/etc", "/boot", "/opt"]
+                .iter()
+                .map(PathBuf::from)
+                .filter(|p| p.is_dir())
+                .collect(),
+            no_network: true,
+            no_new_privs: true,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Sandbox::default()
+    }
+
+    /// Arranges for `cmd` to enter the sandbox before it execs.
+    #[cfg(target_os = "linux")]
+    pub fn apply(&self, cmd: &mut Command) -> std::io::Result<()> {
+        use std::os::unix::process::CommandExt;
+
+        let setup = linux::Setup::new(self)?;
+        // SAFETY: the hook only makes async-signal-safe system calls on data
+        // prepared above; it does not allocate.
+        unsafe {
+            cmd.pre_exec(move || setup.enter());
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn apply(&self, _cmd: &mut Command) -> std::io::Result<()> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "sandboxing is only supported on Linux",
+        ))
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::Sandbox;
+    use std::ffi::{CStr, CString};
+    use std::io::{Error, Result};
+    use std::os::unix::ffi::OsStrExt;
+
+    /// Everything the child needs, prepared before the fork.
+    pub struct Setup {
+        read_only: Vec<CString>,
+        no_network: bool,
+        no_new_privs: bool,
+        /// `uid_map`/`gid_map` contents when a user namespace is needed.
+        id_maps: Option<(CString, CString)>,
+    }
+
+    fn check(ret: libc::c_int) -> Result<()> {
+        if ret < 0 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Writes `data` to the file at `path` with plain system calls.
+    fn write_file(path: &CStr, data: &CStr) -> Result<()> {
+        let fd = unsafe { libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC) };
+        check(fd)?;
+        let bytes = data.to_bytes();
+        let written = unsafe { libc::write(fd, bytes.as_ptr().cast(), bytes.len()) };
+        unsafe { libc::close(fd) };
+        if written != bytes.len() as isize {
+            return Err(Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    impl Setup {
+        pub fn new(sandbox: &Sandbox) -> Result<Self> {
+            let read_only = sandbox
+                .read_only
+                .iter()
+                .map(|path| {
+                    let path = path.canonicalize().map_err(|e| {
+                        Error::new(e.kind(), format!("read-only path {}: {e}", path.display()))
+                    })?;
+                    CString::new(path.as_os_str().as_bytes()).map_err(Error::other)
+                })
+                .collect::<Result<_>>()?;
+            let unshare = !sandbox.read_only.is_empty() || sandbox.no_network;
+            let root = unsafe { libc::geteuid() } == 0;
+            let id_maps = (unshare && !root).then(|| {
+                let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+                (
+                    CString::new(format!("{uid} {uid} 1")).unwrap_or_default(),
+                    CString::new(format!("{gid} {gid} 1")).unwrap_or_default(),
+                )
+            });
+            Ok(Setup {
+                read_only,
+                no_network: sandbox.no_network,
+                no_new_privs: sandbox.no_new_privs,
+                id_maps,
+            })
+        }
+
+        /// Runs in the forked child.
+        pub fn enter(&self) -> Result<()> {
+            let mut flags = 0;
+            if !self.read_only.is_empty() {
+                flags |= libc::CLONE_NEWNS;
+            }
+            if self.no_network {
+                flags |= libc::CLONE_NEWNET;
+            }
+            if self.id_maps.is_some() {
+                flags |= libc::CLONE_NEWUSER;
+            }
+            if flags != 0 {
+                check(unsafe { libc::unshare(flags) })?;
+            }
+            if let Some((uid_map, gid_map)) = &self.id_maps {
+                write_file(c"/proc/self/setgroups", c"deny")?;
+                write_file(c"/proc/self/uid_map", uid_map)?;
+                write_file(c"/proc/self/gid_map", gid_map)?;
+            }
+            if !self.read_only.is_empty() {
+                // Keep our mounts from propagating back to the host.
+                check(unsafe {
+                    libc::mount(
+                        std::ptr::null(),
+                        c"/".as_ptr(),
+                        std::ptr::null(),
+                        libc::MS_REC | libc::MS_PRIVATE,
+                        std::ptr::null(),
+                    )
+                })?;
+                for path in &self.read_only {
+                    check(unsafe {
+                        libc::mount(
+                            path.as_ptr(),
+                            path.as_ptr(),
+                            std::ptr::null(),
+                            libc::MS_BIND | libc::MS_REC,
+                            std::ptr::null(),
+                        )
+                    })?;
+                    check(unsafe {
+                        libc::mount(
+                            std::ptr::null(),
+                            path.as_ptr(),
+                            std::ptr::null(),
+                            libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY,
+                            std::ptr::null(),
+                        )
+                    })?;
+                }
+            }
+            if self.no_new_privs {
+                check(unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) })?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{Exec, run_bash_with_hook};
+
+    fn run(sandbox: Sandbox, script: &str) -> std::io::Result<std::process::Output> {
+        let exec = Exec {
+            sandbox,
+            ..Exec::default()
+        };
+        run_bash_with_hook(script, &exec, false, None).map(|run| run.output)
+    }
+
+    #[test]
+    fn strict_profile_is_not_empty() {
+        assert!(Sandbox::default().is_empty());
+        assert!(Sandbox::strict().no_network);
+        assert!(!Sandbox::strict().is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn read_only_paths_reject_writes() {
+        let dir = std::env::temp_dir().join(format!("sentinel-ro-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let sandbox = Sandbox {
+            read_only: vec![dir.clone()],
+            ..Sandbox::default()
+        };
+        let script = format!("touch {}/x", dir.display());
+        let output = run(sandbox, &script);
+        let touched = dir.join("x").exists();
+        std::fs::remove_dir_all(&dir).ok();
+        let Ok(output) = output else {
+            // No user namespaces here (e.g. a locked-down container).
+            return;
+        };
+        assert!(!output.status.success());
+        assert!(!touched);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("Read-only file system"));
+    }
+
+    #[test]
+    fn no_new_privs_is_visible_to_the_command() {
+        let sandbox = Sandbox {
+            no_new_privs: true,
+            ..Sandbox::default()
+        };
+        let output = run(sandbox, "grep NoNewPrivs /proc/self/status").unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout).trim(),
+            "NoNewPrivs:\t1"
+        );
+    }
+}
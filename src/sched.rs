@@ -0,0 +1,269 @@
+//! `--nice`, `--ionice` and `--cpuset`: run a heavy local job at a lower
+//! CPU and I/O priority, or on some CPUs only, so it does not starve
+//! interactive work on the same machine. Set in the child before it execs,
+//! like `nice`, `ionice` and `taskset` would.
+
+use std::process::Command;
+
+/// An I/O scheduling class, as for `ionice -c`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IoClass {
+    /// Priority 0 (highest) to 7; needs root.
+    Realtime(u8),
+    BestEffort(u8),
+    /// Only gets disk time no one else wants.
+    Idle,
+}
+
+impl IoClass {
+    fn ioprio(self) -> libc::c_int {
+        const CLASS_SHIFT: libc::c_int = 13;
+        match self {
+            IoClass::Realtime(level) => (1 << CLASS_SHIFT) | level as libc::c_int,
+            IoClass::BestEffort(level) => (2 << CLASS_SHIFT) | level as libc::c_int,
+            IoClass::Idle => 3 << CLASS_SHIFT,
+        }
+    }
+}
+
+impl std::fmt::Display for IoClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IoClass::Realtime(level) => write!(f, "realtime:{level}"),
+            IoClass::BestEffort(level) => write!(f, "best-effort:{level}"),
+            IoClass::Idle => f.write_str("idle"),
+        }
+    }
+}
+
+/// Parses `idle`, `best-effort[:LEVEL]` or `realtime[:LEVEL]` (LEVEL 0-7,
+/// default 4), or the `ionice -c` class numbers 1-3.
+pub fn parse_ionice(s: &str) -> Result<IoClass, String> {
+    let (class, level) = match s.split_once(':') {
+        Some((class, level)) => {
+            let level = level
+                .parse::<u8>()
+                .ok()
+                .filter(|l| *l <= 7)
+                .ok_or_else(|| {
+                    format!("expected an I/O priority level from 0 to 7, got {level:?}")
+                })?;
+            (class, Some(level))
+        }
+        None => (s, None),
+    };
+    match (class, level) {
+        ("realtime" | "1", level) => Ok(IoClass::Realtime(level.unwrap_or(4))),
+        ("best-effort" | "2", level) => Ok(IoClass::BestEffort(level.unwrap_or(4))),
+        ("idle" | "3", None) => Ok(IoClass::Idle),
+        ("idle" | "3", Some(_)) => Err("the idle I/O class has no levels".to_string()),
+        _ => Err(format!(
+            "unknown I/O class {class:?} (expected idle, best-effort or realtime)"
+        )),
+    }
+}
+
+/// Parses a niceness from -20 (highest priority) to 19 (lowest).
+pub fn parse_nice(s: &str) -> Result<i32, String> {
+    s.parse()
+        .ok()
+        .filter(|n| (-20..=19).contains(n))
+        .ok_or_else(|| format!("expected a niceness from -20 to 19, got {s:?}"))
+}
+
+/// CPUs a `cpu_set_t` can hold (`CPU_SETSIZE`).
+const MAX_CPUS: usize = 1024;
+
+/// Parses a CPU list such as `0-3` or `0,2,4-7`, as for `taskset -c`.
+pub fn parse_cpuset(s: &str) -> Result<Vec<usize>, String> {
+    let invalid = || format!("expected a CPU list like 0-3 or 0,2,4-7, got {s:?}");
+    let mut cpus = Vec::new();
+    for part in s.split(',') {
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (first, last),
+            None => (part, part),
+        };
+        let first: usize = first.trim().parse().map_err(|_| invalid())?;
+        let last: usize = last.trim().parse().map_err(|_| invalid())?;
+        if first > last {
+            return Err(invalid());
+        }
+        cpus.extend(first..=last);
+    }
+    cpus.sort_unstable();
+    cpus.dedup();
+    if cpus.last().is_some_and(|&cpu| cpu >= MAX_CPUS) {
+        return Err(format!("CPU numbers must be below {MAX_CPUS}"));
+    }
+    Ok(cpus)
+}
+
+/// `cpus` written back as a compact list, e.g. `0-3,6`.
+fn format_cpus(cpus: &[usize]) -> String {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &cpu in cpus {
+        match ranges.last_mut() {
+            Some((_, last)) if *last + 1 == cpu => *last = cpu,
+            _ => ranges.push((cpu, cpu)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|&(first, last)| {
+            if first == last {
+                first.to_string()
+            } else {
+                format!("{first}-{last}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// CPU and I/O priority and CPU affinity for a local command.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Scheduling {
+    pub nice: Option<i32>,
+    pub ionice: Option<IoClass>,
+    /// CPUs the command may run on; all of them if empty.
+    pub cpuset: Vec<usize>,
+}
+
+impl Scheduling {
+    pub fn is_empty(&self) -> bool {
+        *self == Scheduling::default()
+    }
+
+    /// The settings for the start message, e.g. `["nice 10", "CPUs 0-3"]`.
+    pub fn describe(&self) -> Vec<String> {
+        let mut settings = Vec::new();
+        if let Some(nice) = self.nice {
+            settings.push(format!("nice {nice}"));
+        }
+        if let Some(class) = self.ionice {
+            settings.push(format!("ionice {class}"));
+        }
+        if !self.cpuset.is_empty() {
+            settings.push(format!("CPUs {}", format_cpus(&self.cpuset)));
+        }
+        settings
+    }
+
+    /// Has `cmd` apply these settings to itself before it execs.
+    #[cfg(target_os = "linux")]
+    pub fn apply(&self, cmd: &mut Command) {
+        use std::os::unix::process::CommandExt;
+
+        let (nice, ionice) = (self.nice, self.ionice.map(IoClass::ioprio));
+        let cpuset = (!self.cpuset.is_empty()).then(|| {
+            // SAFETY: cpu_set_t is a plain bitmask; all zeroes is the empty set.
+            let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+            for &cpu in &self.cpuset {
+                unsafe { libc::CPU_SET(cpu, &mut set) };
+            }
+            set
+        });
+        // SAFETY: only async-signal-safe system calls on prepared data.
+        unsafe {
+            cmd.pre_exec(move || {
+                let check = |ret: libc::c_long| {
+                    if ret < 0 {
+                        Err(std::io::Error::last_os_error())
+                    } else {
+                        Ok(())
+                    }
+                };
+                if let Some(nice) = nice {
+                    check(libc::setpriority(libc::PRIO_PROCESS, 0, nice).into())?;
+                }
+                if let Some(ioprio) = ionice {
+                    const IOPRIO_WHO_PROCESS: libc::c_long = 1;
+                    check(libc::syscall(
+                        libc::SYS_ioprio_set,
+                        IOPRIO_WHO_PROCESS,
+                        0,
+                        ioprio,
+                    ))?;
+                }
+                if let Some(set) = &cpuset {
+                    check(
+                        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), set)
+                            .into(),
+                    )?;
+                }
+                Ok(())
+            });
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn apply(&self, cmd: &mut Command) {
+        use std::os::unix::process::CommandExt;
+
+        let nice = self.nice;
+        // SAFETY: as above.
+        unsafe {
+            cmd.pre_exec(move || {
+                if let Some(nice) = nice
+                    && libc::setpriority(libc::PRIO_PROCESS, 0, nice) < 0
+                {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{Exec, run_bash_with_hook};
+
+    #[test]
+    fn parses_priorities_and_cpu_lists() {
+        assert_eq!(parse_nice("10"), Ok(10));
+        assert!(parse_nice("20").is_err());
+        assert_eq!(parse_ionice("idle"), Ok(IoClass::Idle));
+        assert_eq!(parse_ionice("best-effort:7"), Ok(IoClass::BestEffort(7)));
+        assert_eq!(parse_ionice("2"), Ok(IoClass::BestEffort(4)));
+        assert!(parse_ionice("idle:3").is_err());
+        assert!(parse_ionice("realtime:9").is_err());
+        assert!(parse_ionice("lazy").is_err());
+        assert_eq!(parse_cpuset("0-3"), Ok(vec![0, 1, 2, 3]));
+        assert_eq!(parse_cpuset("6,0-2,1"), Ok(vec![0, 1, 2, 6]));
+        assert!(parse_cpuset("3-1").is_err());
+        assert!(parse_cpuset("a").is_err());
+    }
+
+    #[test]
+    fn describes_the_settings() {
+        let sched = Scheduling {
+            nice: Some(10),
+            ionice: Some(IoClass::Idle),
+            cpuset: vec![0, 1, 2, 3, 6],
+        };
+        assert_eq!(sched.describe(), ["nice 10", "ionice idle", "CPUs 0-3,6"]);
+        assert!(Scheduling::default().describe().is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn command_runs_with_the_settings() {
+        let exec = Exec {
+            sched: Scheduling {
+                nice: Some(5),
+                ionice: Some(IoClass::Idle),
+                cpuset: vec![0],
+            },
+            ..Exec::default()
+        };
+        let script = "nice; grep Cpus_allowed_list /proc/self/status";
+        let output = run_bash_with_hook(script, &exec, false, None)
+            .unwrap()
+            .output;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{output:?}");
+        assert_eq!(stdout, "5\nCpus_allowed_list:\t0\n");
+    }
+}
@@ -0,0 +1,48 @@
+//! Hook scripts: a Rhai script named by `SENTINEL_SCRIPT` may define
+//! `on_start(ctx)`, `on_line(line, stream)` and `on_finish(ctx)`.
+//!
+//! `on_start`/`on_finish` receive the event as a map and may return `false`
+//! to suppress the notification, a string to replace its text, or a map with
+//! any of `suppress`, `text` and `severity`. `on_line` may return a string (or
+//! a map with `text` and `severity`) to send a notification mid-run.
+
+#[cfg(feature = "scripting")]
+mod engine;
+
+use crate::LineHook;
+use crate::plugin::Plugin;
+use std::sync::Arc;
+
+pub struct ScriptHooks {
+    /// Runs `on_start`/`on_finish` as part of the plugin chain.
+    pub plugin: Box<dyn Plugin>,
+    pub line_hook: Option<Arc<dyn LineHook>>,
+}
+
+pub fn load_script() -> Result<Option<ScriptHooks>, Box<dyn std::error::Error>> {
+    let path = match std::env::var("SENTINEL_SCRIPT") {
+        Ok(path) if !path.trim().is_empty() => path,
+        _ => return Ok(None),
+    };
+    #[cfg(feature = "scripting")]
+    {
+        let script = Arc::new(
+            engine::Script::load(std::path::Path::new(&path))
+                .map_err(|e| format!("failed to load script {path}: {e}"))?,
+        );
+        let line_hook = script
+            .has_on_line()
+            .then(|| script.clone() as Arc<dyn LineHook>);
+        Ok(Some(ScriptHooks {
+            plugin: Box::new(engine::ScriptPlugin(script)),
+            line_hook,
+        }))
+    }
+    #[cfg(not(feature = "scripting"))]
+    {
+        Err(format!(
+            "SENTINEL_SCRIPT is set ({path}) but sentinel-rs was built without the scripting feature"
+        )
+        .into())
+    }
+}
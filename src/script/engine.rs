@@ -0,0 +1,244 @@
+use crate::event::Severity;
+use crate::plugin::{Action, Plugin, PluginError};
+use crate::runner::Stream;
+use crate::{LineHook, LineNotice};
+use rhai::{AST, Dynamic, Engine, FuncArgs, Map, Scope};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Upper bound on the work a single hook call may do.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Self, PluginError> {
+        Self::compile(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn compile(source: &str) -> Result<Self, PluginError> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        // stdout belongs to the wrapped command.
+        engine.on_print(|text| eprintln!("{text}"));
+        let ast = engine.compile(source)?;
+        Ok(Script { engine, ast })
+    }
+
+    fn defines(&self, name: &str, arity: usize) -> bool {
+        self.ast
+            .iter_functions()
+            .any(|f| f.name == name && f.params.len() == arity)
+    }
+
+    pub fn has_on_line(&self) -> bool {
+        self.defines("on_line", 2)
+    }
+
+    fn call(&self, name: &str, args: impl FuncArgs) -> Result<Dynamic, PluginError> {
+        let mut scope = Scope::new();
+        Ok(self
+            .engine
+            .call_fn::<Dynamic>(&mut scope, &self.ast, name, args)?)
+    }
+}
+
+fn severity(value: Dynamic) -> Result<Severity, PluginError> {
+    let name = value
+        .into_string()
+        .map_err(|t| format!("severity must be a string, got {t}"))?;
+    Ok(serde_json::from_value(serde_json::Value::String(name))?)
+}
+
+fn text(value: Dynamic) -> Result<String, PluginError> {
+    value
+        .into_string()
+        .map_err(|t| format!("text must be a string, got {t}").into())
+}
+
+fn actions_from(result: Dynamic) -> Result<Vec<Action>, PluginError> {
+    if result.is_unit() {
+        return Ok(Vec::new());
+    }
+    if let Some(keep) = result.clone().try_cast::<bool>() {
+        return Ok(if keep { Vec::new() } else { vec![Action::Drop] });
+    }
+    if result.is_string() {
+        return Ok(vec![Action::Rewrite {
+            text: text(result)?,
+        }]);
+    }
+    let Some(map) = result.clone().try_cast::<Map>() else {
+        return Err(format!("unsupported hook return value: {}", result.type_name()).into());
+    };
+    let mut actions = Vec::new();
+    for (key, value) in map {
+        match key.as_str() {
+            "suppress" => {
+                if value
+                    .as_bool()
+                    .map_err(|t| format!("suppress must be a bool, got {t}"))?
+                {
+                    actions.push(Action::Drop);
+                }
+            }
+            "text" => actions.push(Action::Rewrite { text: text(value)? }),
+            "severity" => actions.push(Action::SetSeverity {
+                severity: severity(value)?,
+            }),
+            other => return Err(format!("unknown key `{other}` in hook result").into()),
+        }
+    }
+    Ok(actions)
+}
+
+pub struct ScriptPlugin(pub Arc<Script>);
+
+impl Plugin for ScriptPlugin {
+    fn name(&self) -> &str {
+        "script"
+    }
+
+    fn on_event(&mut self, event_json: &str) -> Result<Vec<Action>, PluginError> {
+        let event: serde_json::Value = serde_json::from_str(event_json)?;
+        let hook = match event["kind"].as_str() {
+            Some("start") => "on_start",
+            Some("message") => return Ok(Vec::new()),
+            _ => "on_finish",
+        };
+        if !self.0.defines(hook, 1) {
+            return Ok(Vec::new());
+        }
+        let ctx = rhai::serde::to_dynamic(&event)?;
+        actions_from(self.0.call(hook, (ctx,))?)
+    }
+}
+
+impl LineHook for Script {
+    fn on_line(&self, stream: Stream, line: &str) -> Option<LineNotice> {
+        let result = match self.call("on_line", (line.to_string(), stream.name().to_string())) {
+            Ok(result) => result,
+            Err(e) => {
+                tracing::warn!("Script on_line failed: {e}");
+                return None;
+            }
+        };
+        if result.is_unit() {
+            return None;
+        }
+        let notice = if result.is_string() {
+            text(result).map(|text| LineNotice {
+                text,
+                severity: None,
+            })
+        } else {
+            actions_from(result).map(|actions| {
+                let mut notice = LineNotice {
+                    text: String::new(),
+                    severity: None,
+                };
+                for action in actions {
+                    match action {
+                        Action::Rewrite { text } => notice.text = text,
+                        Action::SetSeverity { severity } => notice.severity = Some(severity),
+                        _ => {}
+                    }
+                }
+                notice
+            })
+        };
+        match notice {
+            Ok(notice) if !notice.text.is_empty() => Some(notice),
+            Ok(_) => None,
+            Err(e) => {
+                tracing::warn!("Script on_line failed: {e}");
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plugin(source: &str) -> ScriptPlugin {
+        ScriptPlugin(Arc::new(Script::compile(source).unwrap()))
+    }
+
+    #[test]
+    fn missing_hooks_are_no_ops() {
+        let mut plugin = plugin("let x = 1;");
+        assert!(plugin.on_event(r#"{"kind":"start"}"#).unwrap().is_empty());
+        assert!(!plugin.0.has_on_line());
+    }
+
+    #[test]
+    fn on_finish_can_suppress_rewrite_and_set_severity() {
+        let mut plugin = plugin(
+            r#"
+            fn on_start(ctx) { false }
+            fn on_finish(ctx) {
+                if ctx.exit_code == 3 {
+                    #{ text: "partial: " + ctx.command, severity: "warning" }
+                }
+            }
+            "#,
+        );
+        assert_eq!(
+            plugin.on_event(r#"{"kind":"start"}"#).unwrap(),
+            vec![Action::Drop]
+        );
+        assert!(
+            plugin
+                .on_event(r#"{"kind":"success","exit_code":0,"command":"x"}"#)
+                .unwrap()
+                .is_empty()
+        );
+        let mut actions = plugin
+            .on_event(r#"{"kind":"failure","exit_code":3,"command":"sync"}"#)
+            .unwrap();
+        actions.sort_by_key(|a| matches!(a, Action::SetSeverity { .. }));
+        assert_eq!(
+            actions,
+            vec![
+                Action::Rewrite {
+                    text: "partial: sync".to_string()
+                },
+                Action::SetSeverity {
+                    severity: Severity::Warning
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn on_line_raises_notices() {
+        let script = Script::compile(
+            r#"
+            fn on_line(line, stream) {
+                if line.contains("ERROR") { #{ text: stream + ": " + line, severity: "critical" } }
+                else if line.starts_with("note") { line }
+            }
+            "#,
+        )
+        .unwrap();
+        assert!(script.has_on_line());
+        assert!(script.on_line(Stream::Stdout, "all good").is_none());
+        let notice = script.on_line(Stream::Stdout, "note this").unwrap();
+        assert_eq!(notice.text, "note this");
+        assert_eq!(notice.severity, None);
+        let notice = script.on_line(Stream::Stderr, "ERROR disk").unwrap();
+        assert_eq!(notice.text, "stderr: ERROR disk");
+        assert_eq!(notice.severity, Some(Severity::Critical));
+    }
+
+    #[test]
+    fn runaway_script_is_stopped() {
+        let mut plugin = plugin("fn on_start(ctx) { loop {} }");
+        assert!(plugin.on_event(r#"{"kind":"start"}"#).is_err());
+    }
+}
@@ -0,0 +1,178 @@
+//! `--secret NAME=REF`: environment variables for the command whose values
+//! are fetched at run time instead of living in a unit file or crontab.
+//! They are only set on the child (and, for `docker`, passed into the
+//! container), never in sentinel-rs's own environment or its messages.
+//!
+//! - `vault:<path>#<field>` reads `<field>` of the secret at `<path>` from
+//!   HashiCorp Vault (`VAULT_ADDR`, and `VAULT_TOKEN` or `~/.vault-token`;
+//!   `VAULT_NAMESPACE` if set). KV v2 paths include `data/`, e.g.
+//!   `vault:kv/data/app#db_password`.
+//! - `ssm:<name>` reads an AWS SSM parameter, decrypting SecureStrings, via
+//!   the `aws` CLI and its usual credentials and region.
+
+use reqwest::blocking::Client;
+use serde_json::Value;
+use std::process::Command;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+    Vault { path: String, field: String },
+    Ssm { name: String },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SecretRef {
+    /// Variable to set in the child's environment.
+    pub var: String,
+    pub source: Source,
+}
+
+/// Parses a `NAME=vault:path#field` or `NAME=ssm:name` argument.
+pub fn parse_secret(s: &str) -> Result<SecretRef, String> {
+    let (var, reference) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=REF, got {s:?}"))?;
+    let valid_name = !var.is_empty()
+        && !var.starts_with(|c: char| c.is_ascii_digit())
+        && var.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid_name {
+        return Err(format!("{var:?} is not a valid environment variable name"));
+    }
+    let source = if let Some(rest) = reference.strip_prefix("vault:") {
+        match rest.rsplit_once('#') {
+            Some((path, field)) if !path.is_empty() && !field.is_empty() => Source::Vault {
+                path: path.trim_matches('/').to_string(),
+                field: field.to_string(),
+            },
+            _ => return Err(format!("expected vault:<path>#<field>, got {reference:?}")),
+        }
+    } else if let Some(name) = reference.strip_prefix("ssm:").filter(|n| !n.is_empty()) {
+        Source::Ssm {
+            name: name.to_string(),
+        }
+    } else {
+        return Err(format!(
+            "unknown secret reference {reference:?} (expected vault:… or ssm:…)"
+        ));
+    };
+    Ok(SecretRef {
+        var: var.to_string(),
+        source,
+    })
+}
+
+/// Fetches every secret, returning the variables to set on the child.
+pub fn resolve(secrets: &[SecretRef], client: &Client) -> Result<Vec<(String, String)>, String> {
+    secrets
+        .iter()
+        .map(|secret| {
+            let value = match &secret.source {
+                Source::Vault { path, field } => vault_read(client, path, field),
+                Source::Ssm { name } => ssm_read(name),
+            }
+            .map_err(|e| format!("{}: {e}", secret.var))?;
+            Ok((secret.var.clone(), value))
+        })
+        .collect()
+}
+
+fn vault_token() -> Result<String, String> {
+    if let Ok(token) = std::env::var("VAULT_TOKEN")
+        && !token.trim().is_empty()
+    {
+        return Ok(token.trim().to_string());
+    }
+    let file = std::env::var_os("HOME")
+        .map(|home| std::path::Path::new(&home).join(".vault-token"))
+        .ok_or("VAULT_TOKEN is not set")?;
+    std::fs::read_to_string(&file)
+        .map(|token| token.trim().to_string())
+        .map_err(|_| "VAULT_TOKEN is not set and there is no ~/.vault-token".to_string())
+}
+
+fn vault_read(client: &Client, path: &str, field: &str) -> Result<String, String> {
+    let addr = std::env::var("VAULT_ADDR").map_err(|_| "VAULT_ADDR is not set".to_string())?;
+    let mut request = client
+        .get(format!("{}/v1/{path}", addr.trim_end_matches('/')))
+        .header("X-Vault-Token", vault_token()?);
+    if let Ok(namespace) = std::env::var("VAULT_NAMESPACE") {
+        request = request.header("X-Vault-Namespace", namespace);
+    }
+    let response = request
+        .send()
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("cannot read {path} from Vault: {e}"))?;
+    let body: Value = response
+        .json()
+        .map_err(|e| format!("unexpected response from Vault for {path}: {e}"))?;
+    vault_field(&body, field).ok_or_else(|| format!("Vault secret {path} has no field {field:?}"))
+}
+
+/// `field` of a Vault read response: under `data.data` for KV v2, `data`
+/// otherwise.
+fn vault_field(body: &Value, field: &str) -> Option<String> {
+    let data = &body["data"];
+    let value = match &data["data"] {
+        Value::Object(inner) => inner.get(field)?,
+        _ => data.get(field)?,
+    };
+    Some(match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    })
+}
+
+fn ssm_read(name: &str) -> Result<String, String> {
+    let output = Command::new("aws")
+        .args(["ssm", "get-parameter", "--with-decryption", "--name", name])
+        .args(["--query", "Parameter.Value", "--output", "text"])
+        .output()
+        .map_err(|e| format!("cannot run the aws CLI: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "aws ssm get-parameter {name} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let value = String::from_utf8_lossy(&output.stdout);
+    Ok(value.strip_suffix('\n').unwrap_or(&value).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parses_vault_and_ssm_references() {
+        assert_eq!(
+            parse_secret("DB_PASSWORD=vault:kv/data/app#db_password").unwrap(),
+            SecretRef {
+                var: "DB_PASSWORD".to_string(),
+                source: Source::Vault {
+                    path: "kv/data/app".to_string(),
+                    field: "db_password".to_string(),
+                },
+            }
+        );
+        assert_eq!(
+            parse_secret("API_KEY=ssm:/prod/api-key").unwrap().source,
+            Source::Ssm {
+                name: "/prod/api-key".to_string()
+            }
+        );
+        assert!(parse_secret("DB_PASSWORD").is_err());
+        assert!(parse_secret("1X=ssm:/a").is_err());
+        assert!(parse_secret("X=vault:kv/data/app").is_err());
+        assert!(parse_secret("X=env:HOME").is_err());
+    }
+
+    #[test]
+    fn vault_field_handles_kv_v1_and_v2() {
+        let v2 = json!({"data": {"data": {"db_password": "s3cret"}, "metadata": {"version": 3}}});
+        assert_eq!(vault_field(&v2, "db_password").as_deref(), Some("s3cret"));
+        let v1 = json!({"data": {"port": 5432}});
+        assert_eq!(vault_field(&v1, "port").as_deref(), Some("5432"));
+        assert_eq!(vault_field(&v1, "missing"), None);
+    }
+}
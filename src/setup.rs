@@ -0,0 +1,191 @@
+//! `sentinel-rs setup telegram`: from a bot token to a chat that gets the
+//! notifications. The token is checked with `getMe`, and the chat id is
+//! found by waiting, with `getUpdates`, for a message to the bot, or for the
+//! bot to be added to a group or channel. That wait is the only time
+//! sentinel-rs waits for what is sent to the bot; it ends with the first
+//! chat found, or after the time it is given. `sentinel-rs telegram chats`
+//! lists the chats in the updates Telegram holds for the bot without
+//! waiting.
+
+use crate::config::TgConfig;
+use crate::doctor::{self, ApiError};
+use crate::telegram::telegram_payload;
+use reqwest::blocking::Client;
+use serde_json::{Value, json};
+use std::time::{Duration, Instant};
+
+/// How long to wait for the message to the bot unless told otherwise.
+pub const WAIT: Duration = Duration::from_secs(180);
+
+/// How long each `getUpdates` call may wait for an update; the HTTP client
+/// needs a timeout beyond it.
+pub const POLL: Duration = Duration::from_secs(20);
+
+/// A chat the bot was sent a message in.
+#[derive(Debug, PartialEq)]
+pub struct Chat {
+    pub id: String,
+    /// `private`, `group`, `supergroup` or `channel`.
+    pub kind: String,
+    /// Its title, or the `@username` or name of whoever sent the message.
+    pub name: String,
+}
+
+/// The chat `update` comes from: a message or channel post, or the bot
+/// being added to a group or channel.
+pub fn chat_of(update: &Value) -> Option<Chat> {
+    let chat = ["message", "channel_post", "my_chat_member"]
+        .iter()
+        .map(|kind| &update[kind]["chat"])
+        .find(|chat| chat.is_object())?;
+    let name = chat["title"]
+        .as_str()
+        .map(str::to_string)
+        .or_else(|| chat["username"].as_str().map(|user| format!("@{user}")))
+        .or_else(|| chat["first_name"].as_str().map(str::to_string))
+        .unwrap_or_else(|| "a chat".to_string());
+    Some(Chat {
+        id: chat["id"].as_i64()?.to_string(),
+        kind: chat["type"].as_str().unwrap_or("?").to_string(),
+        name,
+    })
+}
+
+fn call(client: &Client, cfg: &TgConfig, method: &str, params: Value) -> Result<Value, String> {
+    doctor::call(client, cfg, method, params).map_err(|e| match e {
+        ApiError::Api(409, description) => format!(
+            "{description}; the bot has a webhook, and its chat id cannot be found this way"
+        ),
+        e => {
+            let finding = e.finding("Telegram", cfg);
+            match finding.hint {
+                Some(hint) => format!("{} ({hint})", finding.detail),
+                None => finding.detail,
+            }
+        }
+    })
+}
+
+/// The bot's `@username`, which also says that the token works.
+pub fn bot_name(client: &Client, cfg: &TgConfig) -> Result<String, String> {
+    let bot = call(client, cfg, "getMe", json!({}))?;
+    Ok(format!("@{}", bot["username"].as_str().unwrap_or("?")))
+}
+
+/// Waits up to `wait` for a message to the bot, skipping those sent before
+/// it was called, and returns the chat of the first one. The updates seen
+/// are marked as handled, so the bot's queue is left empty.
+pub fn wait_for_chat(client: &Client, cfg: &TgConfig, wait: Duration) -> Result<Chat, String> {
+    let next = |updates: &Value, offset: i64| {
+        updates
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|update| update["update_id"].as_i64())
+            .map(|id| id + 1)
+            .fold(offset, i64::max)
+    };
+    // Offset -1 is the latest update only, and drops those before it.
+    let pending = call(
+        client,
+        cfg,
+        "getUpdates",
+        json!({ "offset": -1, "timeout": 0 }),
+    )?;
+    let mut offset = next(&pending, 0);
+    let deadline = Instant::now() + wait;
+    while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+        let timeout = left.min(POLL).as_secs();
+        let params = json!({ "offset": offset, "timeout": timeout });
+        let updates = call(client, cfg, "getUpdates", params)?;
+        offset = next(&updates, offset);
+        let chat = updates.as_array().into_iter().flatten().find_map(chat_of);
+        if let Some(chat) = chat {
+            call(
+                client,
+                cfg,
+                "getUpdates",
+                json!({ "offset": offset, "timeout": 0 }),
+            )?;
+            return Ok(chat);
+        }
+        if timeout == 0 {
+            break;
+        }
+    }
+    Err(format!(
+        "no message to the bot within {} seconds",
+        wait.as_secs()
+    ))
+}
+
+/// The chats in the updates waiting for the bot, the latest first and each
+/// once. Telegram keeps updates for a day; they are left in place.
+pub fn recent_chats(client: &Client, cfg: &TgConfig) -> Result<Vec<Chat>, String> {
+    let updates = call(client, cfg, "getUpdates", json!({ "timeout": 0 }))?;
+    let mut chats: Vec<Chat> = Vec::new();
+    for chat in updates
+        .as_array()
+        .into_iter()
+        .flatten()
+        .rev()
+        .filter_map(chat_of)
+    {
+        if !chats.iter().any(|seen| seen.id == chat.id) {
+            chats.push(chat);
+        }
+    }
+    Ok(chats)
+}
+
+/// Tells the chat that notifications will come there.
+pub fn confirm(client: &Client, cfg: &TgConfig) -> Result<(), String> {
+    let text = format!(
+        "sentinel-rs is set up: notifications from {} will come to this chat.",
+        crate::host::name()
+    );
+    call(
+        client,
+        cfg,
+        "sendMessage",
+        telegram_payload(&cfg.chat_id, &text),
+    )
+    .map(drop)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chats_are_found_in_messages_posts_and_membership_changes() {
+        let message = json!({
+            "update_id": 7,
+            "message": { "chat": { "id": 42, "type": "private", "first_name": "Ada", "username": "ada" } },
+        });
+        assert_eq!(
+            chat_of(&message),
+            Some(Chat {
+                id: "42".to_string(),
+                kind: "private".to_string(),
+                name: "@ada".to_string()
+            })
+        );
+        let added = json!({
+            "update_id": 8,
+            "my_chat_member": { "chat": { "id": -1001234, "type": "supergroup", "title": "Ops" } },
+        });
+        assert_eq!(
+            chat_of(&added),
+            Some(Chat {
+                id: "-1001234".to_string(),
+                kind: "supergroup".to_string(),
+                name: "Ops".to_string()
+            })
+        );
+        assert_eq!(
+            chat_of(&json!({ "update_id": 9, "callback_query": {} })),
+            None
+        );
+    }
+}
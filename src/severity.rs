@@ -0,0 +1,182 @@
+//! Severity rules (`--severity LEVEL:RULE`, `SENTINEL_SEVERITY_RULES`): the
+//! severity of a finished run, which by default is `info` for a success and
+//! `error` for a failure, set from what the run did instead. A rule is one
+//! of
+//!
+//! - `exit=CODES`: it exited with one of `CODES`, e.g. `exit=1,3-5` (124 is
+//!   what `timeout(1)` exits with when the time is up)
+//! - `match=REGEX`: its output has a match for `REGEX`
+//! - `took>DURATION`: it took longer than `DURATION`, e.g. `took>90s`,
+//!   `took>30m` or `took>2h`
+//!
+//! When several hold, the most severe wins. The severity picks a message's
+//! [template](crate::template), whether it is [sent
+//! silently](crate::config::TgConfig::silent_below) and [which
+//! chat](crate::config::TgConfig::chat_for) gets it.
+
+use crate::config;
+use crate::event::{Event, Severity, parse_duration};
+use regex::Regex;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::time::Duration;
+
+#[derive(Clone, Debug)]
+pub struct Rule {
+    pub severity: Severity,
+    test: Test,
+}
+
+#[derive(Clone, Debug)]
+enum Test {
+    Exit(Vec<RangeInclusive<i32>>),
+    Match(Regex),
+    Took(Duration),
+}
+
+fn codes(s: &str) -> Result<Vec<RangeInclusive<i32>>, String> {
+    s.split(',')
+        .map(|part| {
+            let part = part.trim();
+            let code = |n: &str| {
+                n.trim()
+                    .parse::<i32>()
+                    .map_err(|_| format!("{part:?} is not an exit code or a range of them"))
+            };
+            match part.split_once('-') {
+                Some((from, to)) => Ok(code(from)?..=code(to)?),
+                None => code(part).map(|n| n..=n),
+            }
+        })
+        .collect()
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (severity, rule) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected LEVEL:RULE, e.g. critical:exit=124, got {s:?}"))?;
+        let severity = severity.parse()?;
+        let rule = rule.trim();
+        let test = if let Some(list) = rule.strip_prefix("exit=") {
+            Test::Exit(codes(list)?)
+        } else if let Some(pattern) = rule.strip_prefix("match=") {
+            Test::Match(Regex::new(pattern).map_err(|e| format!("{pattern:?}: {e}"))?)
+        } else if let Some(took) = rule.strip_prefix("took>") {
+            Test::Took(parse_duration(took)?)
+        } else {
+            return Err(format!(
+                "unknown rule {rule:?} (expected exit=CODES, match=REGEX or took>DURATION)"
+            ));
+        };
+        Ok(Rule { severity, test })
+    }
+}
+
+impl Rule {
+    pub fn holds(&self, event: &Event) -> bool {
+        match &self.test {
+            Test::Exit(codes) => event
+                .exit_code
+                .is_some_and(|code| codes.iter().any(|range| range.contains(&code))),
+            Test::Match(regex) => [&event.output, &event.stdout, &event.stderr]
+                .into_iter()
+                .flatten()
+                .any(|text| regex.is_match(text)),
+            Test::Took(limit) => event
+                .duration_secs
+                .is_some_and(|secs| secs > limit.as_secs_f64()),
+        }
+    }
+}
+
+/// The rules in `SENTINEL_SEVERITY_RULES`, separated by `;`.
+pub fn from_env() -> Result<Vec<Rule>, String> {
+    let Ok(rules) = config::env_required("SENTINEL_SEVERITY_RULES") else {
+        return Ok(Vec::new());
+    };
+    rules
+        .split(';')
+        .filter(|rule| !rule.trim().is_empty())
+        .map(|rule| {
+            rule.parse()
+                .map_err(|e| format!("SENTINEL_SEVERITY_RULES: {e}"))
+        })
+        .collect()
+}
+
+/// Sets the severity of the finished run `event` from the most severe of
+/// `rules` that holds, if any does.
+pub fn assign(rules: &[Rule], event: &mut Event) {
+    if let Some(severity) = rules
+        .iter()
+        .filter(|rule| rule.holds(event))
+        .map(|rule| rule.severity)
+        .max()
+    {
+        event.severity = severity;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::EventKind;
+
+    #[test]
+    fn the_most_severe_rule_that_holds_wins() {
+        let rules: Vec<Rule> = [
+            "warning:exit=1,3-5",
+            "critical:exit=124",
+            "critical:match=(?i)out of memory",
+            "warning:took>30m",
+        ]
+        .iter()
+        .map(|rule| rule.parse().unwrap())
+        .collect();
+        let mut event = Event {
+            exit_code: Some(4),
+            stderr: Some("retrying".to_string()),
+            ..Event::new(EventKind::Failure, "backup")
+        };
+        event.set_duration(Duration::from_secs(60));
+        assign(&rules, &mut event);
+        assert_eq!(event.severity, Severity::Warning);
+        event.stderr = Some("java.lang.Error: Out of memory".to_string());
+        assign(&rules, &mut event);
+        assert_eq!(event.severity, Severity::Critical);
+
+        let mut slow = Event {
+            exit_code: Some(0),
+            ..Event::new(EventKind::Success, "backup")
+        };
+        slow.set_duration(Duration::from_secs(31 * 60));
+        assign(&rules, &mut slow);
+        assert_eq!(slow.severity, Severity::Warning);
+        let mut none = Event::new(EventKind::Failure, "backup");
+        assign(&rules, &mut none);
+        assert_eq!(none.severity, Severity::Error);
+    }
+
+    #[test]
+    fn rules_that_do_not_parse_say_why() {
+        assert!(
+            "loud:exit=1"
+                .parse::<Rule>()
+                .unwrap_err()
+                .contains("severity")
+        );
+        assert!("error:exit=x".parse::<Rule>().is_err());
+        assert!("error:took>soon".parse::<Rule>().is_err());
+        assert!("error:match=(".parse::<Rule>().is_err());
+        assert!(
+            "error:signal"
+                .parse::<Rule>()
+                .unwrap_err()
+                .contains("unknown rule")
+        );
+        assert!("exit=1".parse::<Rule>().is_err());
+    }
+}
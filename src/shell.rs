@@ -0,0 +1,147 @@
+//! Shell integration: `sentinel-rs shell-hook zsh|bash` prints hooks that
+//! time every command typed at an interactive prompt and, when one took
+//! long enough, call `sentinel-rs shell-done` in the background to send the
+//! finish notification a wrapped run would have. Nothing is captured, so the
+//! notification has the exit code and duration but no output.
+
+use crate::RunOptions;
+use crate::config::TgConfig;
+use crate::event::{Event, EventKind};
+use crate::runner::shell_quote;
+use std::path::Path;
+use std::time::Duration;
+
+/// Programs that take as long as they are left open, so their finishing
+/// is not news.
+pub const DEFAULT_IGNORE: &str =
+    "vi,vim,nvim,nano,emacs,less,more,man,ssh,mosh,top,htop,tmux,screen,watch,tail,sentinel-rs";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+}
+
+pub fn parse_shell(s: &str) -> Result<Shell, String> {
+    match s {
+        "bash" => Ok(Shell::Bash),
+        "zsh" => Ok(Shell::Zsh),
+        _ => Err(format!("expected bash or zsh, got {s:?}")),
+    }
+}
+
+/// The hook code for `shell`, calling `exe` for commands that ran for at
+/// least `min_secs` seconds.
+pub fn hook(shell: Shell, exe: &Path, min_secs: u64, ignore: &str) -> String {
+    let call = format!(
+        "{} shell-done --ignore {} --exit-code \"$__sentinel_status\" --seconds \"$__sentinel_elapsed\" -- \"$__sentinel_cmd\" >/dev/null 2>&1",
+        shell_quote(&exe.to_string_lossy()),
+        shell_quote(ignore),
+    );
+    match shell {
+        Shell::Zsh => format!(
+            r#"# sentinel-rs: notify when a command runs for {min_secs}s or more.
+# Add to ~/.zshrc: eval "$(sentinel-rs shell-hook zsh)"
+zmodload zsh/datetime
+autoload -Uz add-zsh-hook
+__sentinel_preexec() {{
+  __sentinel_cmd=$1
+  __sentinel_start=$EPOCHSECONDS
+}}
+__sentinel_precmd() {{
+  local __sentinel_status=$?
+  [[ -n $__sentinel_start ]] || return 0
+  local __sentinel_elapsed=$(( EPOCHSECONDS - __sentinel_start ))
+  unset __sentinel_start
+  if (( __sentinel_elapsed >= {min_secs} )); then
+    {call} &!
+  fi
+}}
+add-zsh-hook preexec __sentinel_preexec
+add-zsh-hook precmd __sentinel_precmd
+"#
+        ),
+        // bash has no preexec: a DEBUG trap armed by the prompt catches the
+        // first command of each line, and the prompt command runs last so
+        // the others in PROMPT_COMMAND do not look like typed commands.
+        Shell::Bash => format!(
+            r#"# sentinel-rs: notify when a command runs for {min_secs}s or more.
+# Add to ~/.bashrc: eval "$(sentinel-rs shell-hook bash)"
+__sentinel_armed=1
+__sentinel_preexec() {{
+  [[ $__sentinel_armed == 1 ]] || return 0
+  __sentinel_armed=0
+  __sentinel_start=$SECONDS
+  __sentinel_cmd=$(HISTTIMEFORMAT= builtin history 1)
+  __sentinel_cmd=${{__sentinel_cmd#*[0-9]  }}
+}}
+__sentinel_precmd() {{
+  if [[ -n $__sentinel_start ]]; then
+    local __sentinel_elapsed=$(( SECONDS - __sentinel_start ))
+    if (( __sentinel_elapsed >= {min_secs} )); then
+      ({call} &)
+    fi
+  fi
+  unset __sentinel_start
+  __sentinel_armed=1
+}}
+trap '__sentinel_preexec' DEBUG
+PROMPT_COMMAND="__sentinel_status=\$?;${{PROMPT_COMMAND:+$PROMPT_COMMAND;}}__sentinel_precmd"
+"#
+        ),
+    }
+}
+
+/// Whether a finished shell command is worth a notification: not one of
+/// the `ignore`d programs, and not interrupted by the user (exit code 130,
+/// Ctrl-C), who is evidently at the keyboard.
+pub fn worth_notifying(command: &str, exit_code: i32, ignore: &str) -> bool {
+    let program = command
+        .split_whitespace()
+        .find(|word| !word.contains('=') && !matches!(*word, "sudo" | "time" | "nohup" | "env"))
+        .unwrap_or_default();
+    let program = program.rsplit('/').next().unwrap_or(program);
+    exit_code != 130 && !ignore.split(',').any(|name| name.trim() == program)
+}
+
+/// Sends the finish notification for `command`, which the shell ran.
+pub fn finished(command: &str, exit_code: i32, elapsed: Duration, cfg: TgConfig, opts: RunOptions) {
+    let kind = if exit_code == 0 {
+        EventKind::Success
+    } else {
+        EventKind::Failure
+    };
+    let mut event = Event {
+        exit_code: Some(exit_code),
+        output_summary: Some("Run in an interactive shell; output not captured.".to_string()),
+        ..Event::new(kind, command)
+    };
+    event.set_duration(elapsed);
+    crate::notify(event, cfg, opts);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hooks_call_back_with_the_threshold() {
+        let exe = Path::new("/opt/it's/sentinel-rs");
+        let zsh = hook(Shell::Zsh, exe, 90, "vim");
+        assert!(zsh.contains("(( __sentinel_elapsed >= 90 ))"));
+        assert!(zsh.contains(r"'/opt/it'\''s/sentinel-rs' shell-done --ignore 'vim' "));
+        assert!(hook(Shell::Bash, exe, 60, "vim").contains("trap '__sentinel_preexec' DEBUG"));
+    }
+
+    #[test]
+    fn skips_editors_and_interrupted_commands() {
+        assert!(worth_notifying("make -j8", 2, DEFAULT_IGNORE));
+        assert!(!worth_notifying("sudo vim /etc/hosts", 0, DEFAULT_IGNORE));
+        assert!(!worth_notifying(
+            "TERM=xterm /usr/bin/htop",
+            0,
+            DEFAULT_IGNORE
+        ));
+        assert!(!worth_notifying("cargo build", 130, DEFAULT_IGNORE));
+    }
+}
@@ -0,0 +1,188 @@
+//! `--status-port`: checking on a run without Telegram. `GET /status`
+//! answers with JSON about the run in progress (or the last one): its id,
+//! command, whether it is still running, how long it has run, how much it
+//! has printed and its last line of output. `GET /tail?lines=N` answers with
+//! its last N lines (100 by default, at most [`MAX_LINES`]). It listens on
+//! localhost only, since the output may hold what should not leave the
+//! machine.
+
+use crate::metrics::METRICS;
+use serde_json::json;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, mpsc};
+use std::time::{Duration, Instant};
+
+/// How many lines of output are kept for `/tail`.
+pub const MAX_LINES: usize = 1000;
+
+/// How many lines `/tail` answers with unless asked for a number.
+pub const DEFAULT_LINES: usize = 100;
+
+struct Run {
+    run_id: String,
+    command: String,
+    started: Instant,
+    /// How long it took, once it is done.
+    took: Option<Duration>,
+    exit_code: Option<i32>,
+}
+
+struct State {
+    run: Option<Run>,
+    lines: VecDeque<String>,
+    /// Sent every line from now on, until the run is over.
+    followers: Vec<mpsc::Sender<String>>,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    run: None,
+    lines: VecDeque::new(),
+    followers: Vec::new(),
+});
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+fn state() -> std::sync::MutexGuard<'static, State> {
+    STATE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Serves `/status` and `/tail` on `127.0.0.1:port` from a background
+/// thread for the rest of the process's life.
+pub fn serve(port: u16) -> std::io::Result<std::net::SocketAddr> {
+    let addr = crate::metrics::serve_routes(("127.0.0.1", port), |path, query| match path {
+        "/status" => Some(("application/json", status().to_string() + "\n")),
+        "/tail" => Some(("text/plain; charset=utf-8", tail(lines_wanted(query)))),
+        _ => None,
+    })?;
+    track();
+    Ok(addr)
+}
+
+/// Keeps the lines [`line`] is given, for [`tail`] and `last_line`.
+pub fn track() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Whether anything serves what [`line`] is given.
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Starts reporting on run `run_id` of `command`.
+pub fn started(run_id: &str, command: &str) {
+    let mut state = state();
+    state.run = Some(Run {
+        run_id: run_id.to_string(),
+        command: crate::echo::command(command),
+        started: Instant::now(),
+        took: None,
+        exit_code: None,
+    });
+    state.lines.clear();
+}
+
+/// Notes a line of the command's output.
+pub fn line(line: &str) {
+    let mut state = state();
+    if state.lines.len() == MAX_LINES {
+        state.lines.pop_front();
+    }
+    state.lines.push_back(line.to_string());
+    state
+        .followers
+        .retain(|follower| follower.send(line.to_string()).is_ok());
+}
+
+/// Notes that the run is over, with `exit_code` unless a signal ended it
+/// or it never started.
+pub fn finished(exit_code: Option<i32>) {
+    let mut state = state();
+    if let Some(run) = &mut state.run {
+        run.took = Some(run.started.elapsed());
+        run.exit_code = exit_code;
+    }
+    state.followers.clear();
+}
+
+/// The run in progress, or the last one, as `/status` shows it.
+pub fn status() -> serde_json::Value {
+    let state = state();
+    let Some(run) = &state.run else {
+        return json!({"running": false});
+    };
+    json!({
+        "run_id": run.run_id,
+        "command": run.command,
+        "running": run.took.is_none(),
+        "exit_code": run.exit_code,
+        "elapsed_secs": run.took.unwrap_or_else(|| run.started.elapsed()).as_secs_f64(),
+        "stdout_bytes": METRICS.stdout_bytes.load(Ordering::Relaxed),
+        "stderr_bytes": METRICS.stderr_bytes.load(Ordering::Relaxed),
+        "last_line": state.lines.back(),
+    })
+}
+
+/// `lines=N` from a query string, else the default.
+fn lines_wanted(query: &str) -> usize {
+    query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("lines="))
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(DEFAULT_LINES)
+        .min(MAX_LINES)
+}
+
+/// The last `lines` lines of output, each ending in a newline.
+pub fn tail(lines: usize) -> String {
+    tail_of(&state(), lines)
+}
+
+/// [`tail`] and then, while the command runs, every line it prints, with
+/// none lost or repeated in between.
+pub fn follow(lines: usize) -> (String, mpsc::Receiver<String>) {
+    let mut state = state();
+    let (tx, rx) = mpsc::channel();
+    if state.run.as_ref().is_some_and(|run| run.took.is_none()) {
+        state.followers.push(tx);
+    }
+    (tail_of(&state, lines), rx)
+}
+
+fn tail_of(state: &State, lines: usize) -> String {
+    let skip = state.lines.len().saturating_sub(lines);
+    state
+        .lines
+        .iter()
+        .skip(skip)
+        .map(|line| format!("{line}\n"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_the_run_and_its_last_lines() {
+        started("run-1", "make all");
+        for n in 1..=MAX_LINES + 5 {
+            line(&format!("line {n}"));
+        }
+        let running = status();
+        assert_eq!(running["run_id"], "run-1");
+        assert_eq!(running["running"], true);
+        assert_eq!(running["last_line"], format!("line {}", MAX_LINES + 5));
+        assert_eq!(tail(lines_wanted("lines=2")), "line 1004\nline 1005\n");
+        assert_eq!(tail(lines_wanted("")).lines().count(), DEFAULT_LINES);
+        assert_eq!(tail(lines_wanted("lines=99999")).lines().count(), MAX_LINES);
+        let (last, follower) = follow(1);
+        assert_eq!(last, "line 1005\n");
+        line("line 1006");
+        assert_eq!(follower.recv().unwrap(), "line 1006");
+        finished(Some(2));
+        assert!(follower.recv().is_err());
+        assert_eq!(status()["running"], false);
+        assert_eq!(status()["exit_code"], 2);
+    }
+}
@@ -0,0 +1,202 @@
+//! `--step`: several commands run one after another, stopping at the first
+//! that fails, or with `--parallel N` up to N at a time, all of them. Either
+//! way they are summed up in a single [`EventKind::Report`] notification
+//! listing how each step went and naming the ones that failed. `batch` runs
+//! the lines of a file the second way.
+
+use crate::LineHook;
+use crate::RunOptions;
+use crate::config::TgConfig;
+use crate::echo;
+use crate::event::{Event, EventKind, Severity, StepResult};
+use crate::fanout::step_result;
+use crate::metrics::METRICS;
+use crate::notifier::{Reporter, start_notifier};
+use crate::runner::{OnLine, Stream, run_bash};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Instant;
+
+/// Runs `steps` through `bash -c`, as `opts.exec` says, then sends one
+/// report. Without `parallel` they run in order until one fails; with it,
+/// all of them run, at most `parallel` at once. `command` is what the
+/// notifications show as the command, and `noun` what the report calls the
+/// steps ("steps", "jobs"). Returns 0 if every step succeeded, otherwise
+/// the highest exit code among the failures.
+pub fn run_steps(
+    command: &str,
+    steps: &[String],
+    noun: &str,
+    parallel: Option<usize>,
+    cfg: TgConfig,
+    opts: RunOptions,
+) -> i32 {
+    let (tx, notifier) = start_notifier(cfg, opts.plugins);
+    let reporter = Reporter::new(tx, opts.templates);
+    let remote = opts.exec.remote_host();
+    let run_as = opts.exec.run_as.as_ref().map(|r| r.user.clone());
+    let tag = move |event: &mut Event| {
+        if let Some(remote) = &remote {
+            event.set_remote_host(remote);
+        }
+        if let Some(user) = &run_as {
+            event.user = user.clone();
+        }
+    };
+    let started = Instant::now();
+    let mut start = Event::new(EventKind::Start, command);
+    tag(&mut start);
+    reporter.send(start);
+
+    let hooks = opts.line_hooks;
+    let exec = &opts.exec;
+    let run = |index: usize| {
+        let step = &steps[index];
+        // Concurrent output is mirrored line by line, marked with the step.
+        let prefix = parallel.map(|_| format!("[{}] ", index + 1));
+        let mirror = opts.tee && prefix.is_none();
+        let (tee, tag) = (opts.tee, tag.clone());
+        let on_line = line_hook(step, prefix.filter(|_| tee), &hooks, &reporter, tag);
+        let step_started = Instant::now();
+        let _running = METRICS.run_started();
+        let result = run_bash(step, exec, mirror, on_line).map(|run| run.output);
+        step_result(&echo::command(step), step_started, result)
+    };
+
+    let mut report = Event::new(EventKind::Report, command);
+    let exit = match parallel {
+        None => {
+            let mut failed = None;
+            for (index, step) in steps.iter().enumerate() {
+                if failed.is_some() {
+                    report.steps.push(StepResult {
+                        name: echo::command(step),
+                        ok: false,
+                        skipped: true,
+                        status: "not run".to_string(),
+                        exit_code: None,
+                        duration: String::new(),
+                        excerpt: None,
+                    });
+                    continue;
+                }
+                let (result, exit) = run(index);
+                if !result.ok {
+                    failed = Some((index, exit));
+                }
+                report.steps.push(result);
+            }
+            report.summary = Some(match failed {
+                None => format!("All {} {noun} succeeded.", steps.len()),
+                Some((index, _)) => format!(
+                    "Step {} of {} failed: {}",
+                    index + 1,
+                    steps.len(),
+                    echo::command(&steps[index])
+                ),
+            });
+            failed.map_or(0, |(_, exit)| exit)
+        }
+        Some(parallel) => {
+            let next = AtomicUsize::new(0);
+            let mut runs: Vec<(usize, StepResult, i32)> = thread::scope(|scope| {
+                let workers: Vec<_> = (0..parallel.clamp(1, steps.len().max(1)))
+                    .map(|_| {
+                        scope.spawn(|| {
+                            let mut done = Vec::new();
+                            loop {
+                                let index = next.fetch_add(1, Ordering::Relaxed);
+                                if index >= steps.len() {
+                                    break;
+                                }
+                                let (result, exit) = run(index);
+                                done.push((index, result, exit));
+                            }
+                            done
+                        })
+                    })
+                    .collect();
+                workers
+                    .into_iter()
+                    .flat_map(|worker| worker.join().unwrap_or_default())
+                    .collect()
+            });
+            // Failures first, then in the order given.
+            runs.sort_by_key(|(index, result, _)| (result.ok, *index));
+            let failed = runs.iter().filter(|(_, result, _)| !result.ok).count();
+            report.summary = Some(if failed == 0 {
+                format!("All {} {noun} succeeded.", runs.len())
+            } else {
+                format!("{failed} of {} {noun} failed.", runs.len())
+            });
+            let exit = runs.iter().map(|(_, _, exit)| *exit).max().unwrap_or(0);
+            report.steps = runs.into_iter().map(|(_, result, _)| result).collect();
+            exit
+        }
+    };
+    tag(&mut report);
+    report.set_duration(started.elapsed());
+    if exit != 0 {
+        report.severity = Severity::Error;
+    }
+    reporter.send(report);
+
+    drop(reporter);
+    notifier.shutdown();
+    exit
+}
+
+/// The jobs in a `batch` file: one command per line; blank lines and lines
+/// starting with `#` are left out.
+pub fn parse_jobs(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Mirrors the output of `step` behind `prefix`, if given, and sends the
+/// notices `hooks` raise about it.
+pub(crate) fn line_hook(
+    step: &str,
+    prefix: Option<String>,
+    hooks: &[Arc<dyn LineHook>],
+    reporter: &Reporter,
+    tag: impl Fn(&mut Event) + Send + Sync + 'static,
+) -> Option<OnLine> {
+    if prefix.is_none() && hooks.is_empty() {
+        return None;
+    }
+    let step = step.to_string();
+    let hooks = hooks.to_vec();
+    let reporter = reporter.clone();
+    Some(Arc::new(move |stream, line: &str| {
+        if let Some(prefix) = &prefix {
+            match stream {
+                Stream::Stdout => println!("{prefix}{line}"),
+                Stream::Stderr => eprintln!("{prefix}{line}"),
+            }
+        }
+        for notice in hooks.iter().filter_map(|hook| hook.on_line(stream, line)) {
+            let mut event = Event::message(&step, &notice.text);
+            tag(&mut event);
+            if let Some(severity) = notice.severity {
+                event.severity = severity;
+            }
+            reporter.send(event);
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_jobs_skips_blanks_and_comments() {
+        let jobs = parse_jobs("# exports\n./export acme\n\n  ./export \"#2 globex\"  \n");
+        assert_eq!(jobs, ["./export acme", "./export \"#2 globex\""]);
+    }
+}
@@ -0,0 +1,395 @@
+//! Copying a command's output to the terminal and to the `--on-failure` log
+//! as it is read. On Linux this stays in the kernel where it can: `tee(2)`
+//! duplicates what is waiting in the command's pipe into a pipe of our own
+//! without consuming it, and `splice(2)` moves it on from there, so a verbose
+//! job's output is not copied through sentinel-rs once more for every place
+//! it goes. Only what is read for the output tail and line hooks leaves the
+//! kernel. Targets it cannot splice to (a terminal, on recent kernels, or a
+//! file opened for appending) are written to as usual, as is everything on
+//! other systems.
+//!
+//! That is for [`Flush::Chunk`]. [`Flush::Line`], the default on a terminal,
+//! passes output on in whole lines, so lines of stdout and stderr do not
+//! end up in each other's middle. [`Flush::Full`] passes it on in blocks as
+//! big as the reads, for commands that print a lot in small writes.
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::fd::{AsFd, AsRawFd};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Where output is copied to, shared when both streams go there.
+pub type Sink = Arc<Mutex<File>>;
+
+pub fn sink(file: File) -> Sink {
+    Arc::new(Mutex::new(file))
+}
+
+/// When what is read is passed on to the sinks.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Flush {
+    /// As soon as it is read.
+    #[default]
+    Chunk,
+    /// A line at a time; a partial line, such as a prompt or a progress
+    /// bar, once the command has printed nothing for [`LINE_WAIT`].
+    Line,
+    /// Once a whole buffer of it has been read, and at the end.
+    Full,
+}
+
+impl FromStr for Flush {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "chunk" => Ok(Flush::Chunk),
+            "line" => Ok(Flush::Line),
+            "full" => Ok(Flush::Full),
+            _ => Err(format!(
+                "unknown flush mode {s:?} (expected chunk, line or full)"
+            )),
+        }
+    }
+}
+
+/// How long [`Flush::Line`] holds on to a partial line.
+pub const LINE_WAIT: Duration = Duration::from_millis(100);
+
+/// Reads `src`, copying everything read to each of `sinks` as `flush` says.
+/// With [`Flush::Full`], `buffer` bytes at a time. A sink that fails is
+/// warned about once and dropped.
+pub fn reader<R>(src: R, sinks: Vec<Sink>, flush: Flush, buffer: usize) -> Box<dyn Read + Send>
+where
+    R: Read + AsFd + Send + 'static,
+{
+    if sinks.is_empty() {
+        return Box::new(src);
+    }
+    if flush != Flush::Chunk {
+        return Box::new(Buffered {
+            src,
+            sinks,
+            flush,
+            buffer,
+            pending: Vec::new(),
+        });
+    }
+    #[cfg(target_os = "linux")]
+    match linux::Splicer::new(src, sinks) {
+        Ok(splicer) => Box::new(splicer),
+        Err((src, sinks)) => Box::new(Copier { src, sinks }),
+    }
+    #[cfg(not(target_os = "linux"))]
+    Box::new(Copier { src, sinks })
+}
+
+struct Copier<R> {
+    src: R,
+    sinks: Vec<Sink>,
+}
+
+impl<R: Read> Read for Copier<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.src.read(buf)?;
+        self.sinks.retain(|sink| write(sink, &buf[..read]));
+        Ok(read)
+    }
+}
+
+/// Holds on to what it reads until [`Flush`] says to pass it on.
+struct Buffered<R> {
+    src: R,
+    sinks: Vec<Sink>,
+    flush: Flush,
+    buffer: usize,
+    pending: Vec<u8>,
+}
+
+impl<R> Buffered<R> {
+    /// Passes on the first `len` bytes held.
+    fn pass_on(&mut self, len: usize) {
+        let data = &self.pending[..len];
+        self.sinks.retain(|sink| write(sink, data));
+        self.pending.drain(..len);
+    }
+}
+
+impl<R: Read + AsFd> Read for Buffered<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.flush == Flush::Line && !self.pending.is_empty() && !readable(&self.src, LINE_WAIT)
+        {
+            self.pass_on(self.pending.len());
+        }
+        let read = self.src.read(buf)?;
+        self.pending.extend_from_slice(&buf[..read]);
+        let ready = match self.flush {
+            _ if read == 0 || self.pending.len() >= self.buffer => self.pending.len(),
+            Flush::Line => self
+                .pending
+                .iter()
+                .rposition(|&b| b == b'\n')
+                .map_or(0, |at| at + 1),
+            Flush::Chunk | Flush::Full => 0,
+        };
+        if ready > 0 {
+            self.pass_on(ready);
+        }
+        Ok(read)
+    }
+}
+
+/// Whether `src` has something to read (or is at its end) within `wait`.
+fn readable(src: &impl AsFd, wait: Duration) -> bool {
+    let mut fd = libc::pollfd {
+        fd: src.as_fd().as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // SAFETY: `fd` is one valid pollfd, and the descriptor is held open.
+    let ready = unsafe { libc::poll(&mut fd, 1, wait.as_millis() as libc::c_int) };
+    // On errors, read and find out.
+    ready != 0
+}
+
+/// Writes `data` to `sink`; false if that failed.
+fn write(sink: &Sink, data: &[u8]) -> bool {
+    let mut file = sink.lock().unwrap_or_else(|e| e.into_inner());
+    match file.write_all(data) {
+        Ok(()) => true,
+        Err(e) => {
+            tracing::warn!("Failed to copy the command's output: {e}");
+            false
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::Sink;
+    use std::io::{Error, ErrorKind, PipeReader, PipeWriter, Read, Result};
+    use std::os::fd::{AsFd, AsRawFd, RawFd};
+
+    /// At most this much is passed on at a time; what a pipe holds by
+    /// default.
+    const CHUNK: usize = 64 * 1024;
+
+    struct Target {
+        sink: Sink,
+        /// What was teed for the sink, on its way there.
+        pipe: (PipeReader, PipeWriter),
+        /// Cleared once splicing to the sink turned out not to work.
+        splice: bool,
+        /// How much of the current read is waiting in `pipe`.
+        teed: usize,
+    }
+
+    pub struct Splicer<R> {
+        src: R,
+        targets: Vec<Target>,
+        /// Cleared once teeing from `src` turned out not to work.
+        tee: bool,
+    }
+
+    impl<R: Read + AsFd> Splicer<R> {
+        /// `src` and `sinks` back if there are no pipes to be had.
+        pub fn new(src: R, sinks: Vec<Sink>) -> std::result::Result<Self, (R, Vec<Sink>)> {
+            let pipes: Result<Vec<_>> = sinks.iter().map(|_| std::io::pipe()).collect();
+            let Ok(pipes) = pipes else {
+                return Err((src, sinks));
+            };
+            let targets = sinks
+                .into_iter()
+                .zip(pipes)
+                .map(|(sink, pipe)| Target {
+                    sink,
+                    pipe,
+                    splice: true,
+                    teed: 0,
+                })
+                .collect();
+            Ok(Splicer {
+                src,
+                targets,
+                tee: true,
+            })
+        }
+    }
+
+    impl<R: Read + AsFd> Read for Splicer<R> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let src = self.src.as_fd().as_raw_fd();
+            let want = buf.len().min(CHUNK);
+            // Blocks until the command writes something; 0 once it is done.
+            let teed = match self.targets.first() {
+                Some(first) if self.tee => match tee(src, first.pipe.1.as_raw_fd(), want) {
+                    Ok(teed) => Some(teed),
+                    Err(e) => {
+                        tracing::debug!("Not teeing the command's output: {e}");
+                        self.tee = false;
+                        None
+                    }
+                },
+                _ => None,
+            };
+            let Some(teed) = teed else {
+                let read = self.src.read(buf)?;
+                self.targets.retain(|t| super::write(&t.sink, &buf[..read]));
+                return Ok(read);
+            };
+            if teed == 0 {
+                return Ok(0);
+            }
+            // The rest get as much; the command's pipe holds at least that.
+            self.targets[0].teed = teed;
+            for target in &mut self.targets[1..] {
+                target.teed = tee(src, target.pipe.1.as_raw_fd(), teed).unwrap_or(0);
+            }
+            let mut read = 0;
+            while read < teed {
+                match self.src.read(&mut buf[read..teed])? {
+                    0 => break,
+                    n => read += n,
+                }
+            }
+            self.targets.retain_mut(|target| {
+                let teed = target.teed;
+                target.pass_on(teed) && super::write(&target.sink, &buf[teed.min(read)..read])
+            });
+            Ok(read)
+        }
+    }
+
+    impl Target {
+        /// Moves the `len` bytes waiting in our pipe to the sink; false if
+        /// the sink failed.
+        fn pass_on(&mut self, mut len: usize) -> bool {
+            let mut file = self.sink.lock().unwrap_or_else(|e| e.into_inner());
+            let from = self.pipe.0.as_raw_fd();
+            while len > 0 && self.splice {
+                match splice(from, file.as_raw_fd(), len) {
+                    Ok(moved) => len -= moved,
+                    Err(e) if e.raw_os_error() == Some(libc::EINVAL) => self.splice = false,
+                    Err(e) => {
+                        tracing::warn!("Failed to copy the command's output: {e}");
+                        return false;
+                    }
+                }
+            }
+            let mut chunk = [0u8; 8192];
+            while len > 0 {
+                let n = match self.pipe.0.read(&mut chunk[..len.min(8192)]) {
+                    Ok(0) | Err(_) => return false,
+                    Ok(n) => n,
+                };
+                if let Err(e) = std::io::Write::write_all(&mut *file, &chunk[..n]) {
+                    tracing::warn!("Failed to copy the command's output: {e}");
+                    return false;
+                }
+                len -= n;
+            }
+            true
+        }
+    }
+
+    fn retry(mut call: impl FnMut() -> isize) -> Result<usize> {
+        loop {
+            let n = call();
+            if n >= 0 {
+                return Ok(n as usize);
+            }
+            let e = Error::last_os_error();
+            if e.kind() != ErrorKind::Interrupted {
+                return Err(e);
+            }
+        }
+    }
+
+    fn tee(from: RawFd, to: RawFd, len: usize) -> Result<usize> {
+        // SAFETY: both are pipes we hold open; tee(2) touches no memory of
+        // ours.
+        retry(|| unsafe { libc::tee(from, to, len, 0) })
+    }
+
+    fn splice(from: RawFd, to: RawFd, len: usize) -> Result<usize> {
+        // SAFETY: both descriptors are held open; with null offsets splice(2)
+        // touches no memory of ours.
+        retry(|| unsafe {
+            libc::splice(
+                from,
+                std::ptr::null_mut(),
+                to,
+                std::ptr::null_mut(),
+                len,
+                libc::SPLICE_F_MOVE,
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copies_everything_read_to_every_sink() {
+        let (src, mut writer) = std::io::pipe().unwrap();
+        let data: Vec<u8> = (0..300_000u32).flat_map(|n| n.to_le_bytes()).collect();
+        let expected = data.clone();
+        let feeder = std::thread::spawn(move || writer.write_all(&data));
+        let dir = std::env::temp_dir();
+        let plain = dir.join(format!("sentinel-tee-{}", std::process::id()));
+        let appended = dir.join(format!("sentinel-tee-{}.append", std::process::id()));
+        let open = |path: &std::path::Path, append: bool| {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .write(true)
+                .truncate(!append)
+                .append(append)
+                .open(path)
+                .unwrap();
+            sink(file)
+        };
+        // Splicing to a file opened for appending does not work.
+        let sinks = vec![open(&plain, false), open(&appended, true)];
+        let mut read = Vec::new();
+        reader(src, sinks, Flush::Chunk, 4096)
+            .read_to_end(&mut read)
+            .unwrap();
+        feeder.join().unwrap().unwrap();
+        assert!(read == expected);
+        for path in [plain, appended] {
+            assert!(std::fs::read(&path).unwrap() == expected, "{path:?}");
+            std::fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    fn line_flushing_passes_on_whole_lines() {
+        let (src, mut writer) = std::io::pipe().unwrap();
+        let path = std::env::temp_dir().join(format!("sentinel-tee-{}.lines", std::process::id()));
+        let out = sink(std::fs::File::create(&path).unwrap());
+        let mut reader = reader(src, vec![out], Flush::Line, 4096);
+        let mut buf = [0u8; 4096];
+        writer.write_all(b"one\ntw").unwrap();
+        assert_eq!(reader.read(&mut buf).unwrap(), 6);
+        assert_eq!(std::fs::read(&path).unwrap(), b"one\n");
+        // A partial line goes out once nothing more comes.
+        let feeder = std::thread::spawn({
+            let path = path.clone();
+            move || {
+                std::thread::sleep(LINE_WAIT * 3);
+                let held = std::fs::read(&path).unwrap();
+                writer.write_all(b"o\n").map(|()| held)
+            }
+        });
+        assert_eq!(reader.read(&mut buf).unwrap(), 2);
+        assert_eq!(feeder.join().unwrap().unwrap(), b"one\ntw");
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+        assert_eq!(std::fs::read(&path).unwrap(), b"one\ntwo\n");
+        std::fs::remove_file(path).ok();
+        assert!("block".parse::<Flush>().is_err());
+    }
+}
@@ -0,0 +1,134 @@
+use crate::config::TgConfig;
+use crate::fit::{self, Overflow};
+use reqwest::blocking::Client;
+use reqwest::blocking::multipart::{Form, Part};
+use serde_json::{Value, json};
+
+pub fn telegram_payload(chat_id: &str, body: &str) -> serde_json::Value {
+    json!({
+        "chat_id": chat_id,
+        "text": body,
+        "disable_web_page_preview": true,
+    })
+}
+
+/// The longest message Telegram takes, in characters.
+pub const MAX_MESSAGE: usize = 4096;
+/// The longest caption of a file.
+pub const MAX_CAPTION: usize = 1024;
+
+/// Sends `text` to the configured chat, fitting it into one message or
+/// more as `cfg.overflow` says.
+pub fn tg_send(
+    client: &Client,
+    cfg: &TgConfig,
+    text: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    tg_send_with(client, cfg, text, &Delivery::default())
+}
+
+/// How a message is sent, besides its text.
+#[derive(Clone, Debug, Default)]
+pub struct Delivery {
+    /// Buttons under the message (`reply_markup`), e.g. an
+    /// [acknowledgement](crate::escalate::ack_button): under its last part
+    /// if it is split.
+    pub markup: Option<Value>,
+    /// Without a sound (`disable_notification`).
+    pub silent: bool,
+}
+
+/// [`tg_send`], sent as `delivery` says.
+pub fn tg_send_with(
+    client: &Client,
+    cfg: &TgConfig,
+    text: &str,
+    delivery: &Delivery,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if text.chars().count() <= MAX_MESSAGE {
+        return send_message(client, cfg, text, delivery);
+    }
+    match cfg.overflow {
+        Overflow::Truncate => {
+            send_message(client, cfg, &fit::truncate(text, MAX_MESSAGE), delivery)
+        }
+        Overflow::Split => {
+            let parts = fit::split(text, MAX_MESSAGE);
+            let unmarked = Delivery {
+                markup: None,
+                ..delivery.clone()
+            };
+            for (i, part) in parts.iter().enumerate() {
+                let last = i + 1 == parts.len();
+                send_message(client, cfg, part, if last { delivery } else { &unmarked })?;
+            }
+            Ok(())
+        }
+        Overflow::Document => send_document(client, cfg, text, delivery),
+    }
+}
+
+fn send_message(
+    client: &Client,
+    cfg: &TgConfig,
+    text: &str,
+    delivery: &Delivery,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let url = format!("{}/bot{}/sendMessage", cfg.api_base, cfg.bot_token);
+    let mut payload = telegram_payload(&cfg.chat_id, text);
+    if let Some(markup) = &delivery.markup {
+        payload["reply_markup"] = markup.clone();
+    }
+    if delivery.silent {
+        payload["disable_notification"] = json!(true);
+    }
+    client
+        .post(&url)
+        .json(&payload)
+        .send()?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// Sends `text` whole as `notification.txt`, captioned with its start and
+/// end.
+fn send_document(
+    client: &Client,
+    cfg: &TgConfig,
+    text: &str,
+    delivery: &Delivery,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let url = format!("{}/bot{}/sendDocument", cfg.api_base, cfg.bot_token);
+    let file = Part::text(text.to_string())
+        .file_name("notification.txt")
+        .mime_str("text/plain")?;
+    let mut form = Form::new()
+        .text("chat_id", cfg.chat_id.clone())
+        .text("caption", fit::truncate(text, MAX_CAPTION))
+        .part("document", file);
+    if let Some(markup) = &delivery.markup {
+        form = form.text("reply_markup", markup.to_string());
+    }
+    if delivery.silent {
+        form = form.text("disable_notification", "true");
+    }
+    client
+        .post(&url)
+        .multipart(form)
+        .send()?
+        .error_for_status()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn telegram_payload_is_expected_shape() {
+        let payload = telegram_payload("123", "body");
+        assert_eq!(payload["chat_id"], "123");
+        assert_eq!(payload["text"], "body");
+        assert_eq!(payload["disable_web_page_preview"], true);
+    }
+}
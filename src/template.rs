@@ -0,0 +1,350 @@
+//! Message templates. Every event kind has a built-in Handlebars template,
+//! which can be replaced by, in order of precedence:
+//!
+//! 1. the file named by `SENTINEL_TEMPLATE_<KIND>` (e.g. `SENTINEL_TEMPLATE_FAILURE`)
+//! 2. `<config dir>/profiles/<SENTINEL_PROFILE>/<kind>.tmpl`
+//! 3. `<config dir>/<kind>.tmpl`
+//!
+//! A template for one [severity](crate::severity) of a kind, e.g.
+//! `SENTINEL_TEMPLATE_FAILURE_CRITICAL` or `failure.critical.tmpl`, is used
+//! for it instead, looked for in the same order.
+//!
+//! Templates see the fields of [`Event`]: `timestamp`, `host`, `via`,
+//! `user`, `cwd`, `command`, `job`, `settings`, `exit_code`, `exit_meaning`,
+//! `failed_stage`, `failure_reason`, `hook_failure`, `notify_if`, `signal`,
+//! `core_dumped`, `core_file`, `oom_killed`, `peak_rss`, `output_summary`,
+//! `duration`, `duration_secs`, `stdout`, `stderr`, `output`, `error`, `message`,
+//! `severity`, `icon` and `kind`; reports add `summary` and a `steps` list (`name`, `ok`,
+//! `skipped`, `status`, `exit_code`, `duration`, `excerpt`).
+//! Container runs and runs with resource limits fill in `resources`
+//! (`cpu_avg_percent`, `cpu_peak_percent`, `memory_peak_bytes`,
+//! `oom_kills`, `limit_hits`, `summary`), and runs with `--artifact`
+//! `artifacts` (`found`, each with `path`, `bytes`, `size`, `modified` and
+//! `stale`; `more`; `missing`). With `--show-env`, start notifications have
+//! `environment` (`cwd`, `shell` and `vars`, each with `name` and `value`),
+//! with `--git` start and finish notifications have `git` (`branch`,
+//! `commit`, `dirty`, `summary`). Start notifications have `instance`
+//! (`provider`, `id`, `region`, `zone`, `public_ip`, `summary`) with
+//! `--cloud` and `origin` (`user`, `sudo_user`, `interactive`, `tty`,
+//! `ssh_from`, `summary`) with `--origin`.
+
+use crate::config;
+use crate::event::{Event, EventKind, Severity};
+use crate::locale;
+use handlebars::Handlebars;
+use std::path::{Path, PathBuf};
+
+/// The built-in template for `kind` in the `--locale` chosen.
+pub fn localized(kind: EventKind) -> String {
+    locale::translate(locale::current(), builtin(kind))
+}
+
+pub fn builtin(kind: EventKind) -> &'static str {
+    match kind {
+        EventKind::Start => {
+            "[{{timestamp}}] [{{host}}]\nStarted\n{{command}}{{#if origin}}\nStarted by: {{origin.summary}}{{/if}}{{#if settings}}\nSettings: {{settings}}{{/if}}{{#if instance}}\nInstance: {{instance.summary}}{{/if}}{{#if git}}\nGit: {{git.summary}}{{/if}}{{#if environment}}\nIn {{environment.cwd}} with {{environment.shell}}{{#each environment.vars}}\n{{name}}={{value}}{{/each}}{{/if}}"
+        }
+        EventKind::Success => {
+            "{{#if icon}}{{icon}} {{/if}}[{{timestamp}}] [{{host}}]\nFinished successfully with exit code {{exit_code}}{{#if duration}} in {{duration}}{{/if}}.{{#if notify_if}}\n{{notify_if}}{{/if}}{{#if hook_failure}}\n{{hook_failure}}{{/if}}{{#if git}}\nGit: {{git.summary}}{{/if}}{{#if resources}}\nResources: {{resources.summary}}{{/if}}{{#if artifacts}}{{#if artifacts.found}}\nArtifacts:{{#each artifacts.found}}\n{{path}}: {{size}}, {{modified}}{{#if stale}} (not from this run){{/if}}{{/each}}{{#if artifacts.more}}\nand {{artifacts.more}} more{{/if}}{{/if}}{{#if artifacts.missing}}\nMissing artifacts: {{#each artifacts.missing}}{{this}}{{#unless @last}}, {{/unless}}{{/each}}{{/if}}{{/if}}{{#if output_summary}}\n{{output_summary}}{{else}}{{#if output}}\nOutput:\n{{output}}{{else}}\nStdout:\n{{stdout}}\nStderr:\n{{stderr}}{{/if}}{{/if}}"
+        }
+        EventKind::Failure => {
+            "{{#if icon}}{{icon}} {{/if}}[{{timestamp}}] [{{host}}]\nFailed with exit code: {{exit_code}}{{#if exit_meaning}} ({{exit_meaning}}){{/if}}{{#if failed_stage}} ({{failed_stage}}){{/if}}{{#if duration}} after {{duration}}{{/if}}.{{#if failure_reason}}\n{{failure_reason}}{{/if}}{{#if hook_failure}}\n{{hook_failure}}{{/if}}{{#if git}}\nGit: {{git.summary}}{{/if}}{{#if resources}}\nResources: {{resources.summary}}{{/if}}{{#if artifacts}}{{#if artifacts.found}}\nArtifacts:{{#each artifacts.found}}\n{{path}}: {{size}}, {{modified}}{{#if stale}} (not from this run){{/if}}{{/each}}{{#if artifacts.more}}\nand {{artifacts.more}} more{{/if}}{{/if}}{{#if artifacts.missing}}\nMissing artifacts: {{#each artifacts.missing}}{{this}}{{#unless @last}}, {{/unless}}{{/each}}{{/if}}{{/if}}{{#if output_summary}}\n{{output_summary}}{{/if}}{{#if output}}\nOutput:\n{{output}}{{else}}\nStdout:\n{{stdout}}\nStderr:\n{{stderr}}{{/if}}"
+        }
+        EventKind::Signal => {
+            "{{#if icon}}{{icon}} {{/if}}[{{timestamp}}] [{{host}}]\n{{#if oom_killed}}Killed by the OOM killer{{#if duration}} after {{duration}}{{/if}}{{#if peak_rss}}, peak RSS {{peak_rss}}{{/if}}.{{else}}Process terminated by {{#if signal}}{{signal}}{{else}}signal{{/if}}{{#if core_dumped}} (core dumped{{#if core_file}}: {{core_file}}{{/if}}){{/if}}{{#if duration}} after {{duration}}{{/if}}.{{/if}}{{#if hook_failure}}\n{{hook_failure}}{{/if}}{{#if git}}\nGit: {{git.summary}}{{/if}}{{#if resources}}\nResources: {{resources.summary}}{{/if}}{{#if output}}\nOutput:\n{{output}}{{else}}\nStdout:\n{{stdout}}\nStderr:\n{{stderr}}{{/if}}"
+        }
+        EventKind::SpawnError => {
+            "{{#if icon}}{{icon}} {{/if}}[{{timestamp}}] [{{host}}]\nFailed to execute command: {{error}}{{#if hook_failure}}\n{{hook_failure}}{{/if}}"
+        }
+        EventKind::Message => "[{{timestamp}}] [{{host}}]\n{{message}}",
+        EventKind::Report => {
+            "[{{timestamp}}] [{{host}}]\n{{summary}}\n{{command}}{{#each steps}}\n{{#if skipped}}skipped {{name}}{{else}}{{#if ok}}ok{{else}}FAILED{{/if}} {{name}}: {{status}} ({{duration}}){{#if excerpt}}\n{{excerpt}}{{/if}}{{/if}}{{/each}}"
+        }
+    }
+}
+
+pub struct Templates {
+    registry: Handlebars<'static>,
+}
+
+impl Templates {
+    pub fn builtin() -> Self {
+        let mut registry = Handlebars::new();
+        // Messages are plain text, not HTML.
+        registry.register_escape_fn(handlebars::no_escape);
+        for kind in EventKind::ALL {
+            registry
+                .register_template_string(kind.name(), localized(kind))
+                .expect("built-in templates are valid");
+        }
+        Templates { registry }
+    }
+
+    /// Built-in templates overridden by the first `<kind>.tmpl` found in
+    /// `dirs`.
+    pub fn from_dirs(dirs: &[PathBuf]) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut templates = Templates::builtin();
+        for (name, _) in names() {
+            let file = format!("{name}.tmpl");
+            if let Some(path) = dirs.iter().map(|d| d.join(&file)).find(|p| p.is_file()) {
+                templates.set_from_file(&name, &path)?;
+            }
+        }
+        Ok(templates)
+    }
+
+    /// Templates from the config dir (and profile), with any
+    /// `SENTINEL_TEMPLATE_<KIND>` overrides on top.
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let mut templates = Templates::from_dirs(&config::config_dirs()?)?;
+        for (name, key) in names() {
+            let Some(path) = std::env::var_os(&key) else {
+                continue;
+            };
+            templates
+                .set_from_file(&name, Path::new(&path))
+                .map_err(|e| format!("{key}: {e}"))?;
+        }
+        Ok(templates)
+    }
+
+    fn set_from_file(&mut self, name: &str, path: &Path) -> Result<(), String> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+        self.registry
+            .register_template_string(name, source)
+            .map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    pub fn set(&mut self, kind: EventKind, source: &str) -> Result<(), handlebars::TemplateError> {
+        self.registry.register_template_string(kind.name(), source)
+    }
+
+    /// Sets the template for `kind` events of `severity` only.
+    pub fn set_for(
+        &mut self,
+        kind: EventKind,
+        severity: Severity,
+        source: &str,
+    ) -> Result<(), handlebars::TemplateError> {
+        self.registry
+            .register_template_string(&name_for(kind, severity), source)
+    }
+
+    /// Renders the message for `event`, falling back to the built-in template
+    /// if a user template fails at render time.
+    pub fn render(&self, event: &Event) -> String {
+        let name = name_for(event.kind, event.severity);
+        let name = match self.registry.has_template(&name) {
+            true => name,
+            false => event.kind.name().to_string(),
+        };
+        self.registry.render(&name, event).unwrap_or_else(|e| {
+            tracing::warn!("Failed to render {} template: {e}", event.kind.name());
+            self.registry
+                .render_template(&localized(event.kind), event)
+                .unwrap_or_default()
+        })
+    }
+}
+
+fn name_for(kind: EventKind, severity: Severity) -> String {
+    format!("{}.{}", kind.name(), severity.name())
+}
+
+/// Each template that can be set, as a file name without `.tmpl`, with the
+/// variable naming its file: the kinds, and each with each severity.
+pub fn names() -> Vec<(String, String)> {
+    let mut names = Vec::new();
+    for kind in EventKind::ALL {
+        let key = format!("SENTINEL_TEMPLATE_{}", kind.name().to_uppercase());
+        for severity in Severity::ALL {
+            names.push((
+                name_for(kind, severity),
+                format!("{key}_{}", severity.name().to_uppercase()),
+            ));
+        }
+        names.push((kind.name().to_string(), key));
+    }
+    names
+}
+
+impl Default for Templates {
+    fn default() -> Self {
+        Templates::builtin()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(kind: EventKind) -> Event {
+        Event {
+            timestamp: "2025-01-01 00:00:00".to_string(),
+            host: "host".to_string(),
+            ..Event::new(kind, "make <all> & more")
+        }
+    }
+
+    #[test]
+    fn default_message_template_includes_fields() {
+        let body = Templates::builtin().render(&Event {
+            message: Some("hello".to_string()),
+            ..event(EventKind::Message)
+        });
+        assert_eq!(body, "[2025-01-01 00:00:00] [host]\nhello");
+    }
+
+    #[test]
+    fn builtin_templates_match_plain_text_layout() {
+        let templates = Templates::builtin();
+        assert_eq!(
+            templates.render(&event(EventKind::Start)),
+            "[2025-01-01 00:00:00] [host]\nStarted\nmake <all> & more"
+        );
+        let start = Event {
+            settings: Some("nice 10, CPUs 0-3".to_string()),
+            ..event(EventKind::Start)
+        };
+        assert_eq!(
+            templates.render(&start),
+            "[2025-01-01 00:00:00] [host]\nStarted\nmake <all> & more\nSettings: nice 10, CPUs 0-3"
+        );
+        let failure = Event {
+            exit_code: Some(3),
+            stdout: Some("out".to_string()),
+            stderr: Some(String::new()),
+            ..event(EventKind::Failure)
+        };
+        assert_eq!(
+            templates.render(&failure),
+            "❌ [2025-01-01 00:00:00] [host]\nFailed with exit code: 3.\nStdout:\nout\nStderr:\n"
+        );
+        let plain = Event {
+            icon: None,
+            ..failure
+        };
+        assert!(
+            templates
+                .render(&plain)
+                .starts_with("[2025-01-01 00:00:00]")
+        );
+    }
+
+    #[test]
+    fn signal_template_explains_oom_kills_and_core_dumps() {
+        let templates = Templates::builtin();
+        let oom = Event {
+            signal: Some("SIGKILL".to_string()),
+            oom_killed: true,
+            peak_rss: Some("14.2 GiB".to_string()),
+            ..event(EventKind::Signal)
+        };
+        assert!(
+            templates
+                .render(&oom)
+                .contains("\nKilled by the OOM killer, peak RSS 14.2 GiB.\n")
+        );
+        let core = Event {
+            signal: Some("SIGSEGV".to_string()),
+            core_dumped: true,
+            core_file: Some("/srv/core.42".to_string()),
+            ..event(EventKind::Signal)
+        };
+        assert!(
+            templates
+                .render(&core)
+                .contains("\nProcess terminated by SIGSEGV (core dumped: /srv/core.42).\n")
+        );
+    }
+
+    #[test]
+    fn severity_template_wins_for_its_severity_only() {
+        let mut templates = Templates::builtin();
+        templates
+            .set_for(
+                EventKind::Failure,
+                Severity::Critical,
+                "🚨 {{command}} exited {{exit_code}}",
+            )
+            .unwrap();
+        let failure = Event {
+            exit_code: Some(124),
+            ..event(EventKind::Failure)
+        };
+        assert!(
+            templates
+                .render(&failure)
+                .contains("Failed with exit code: 124")
+        );
+        let critical = Event {
+            severity: Severity::Critical,
+            ..failure
+        };
+        assert_eq!(
+            templates.render(&critical),
+            "🚨 make <all> & more exited 124"
+        );
+    }
+
+    #[test]
+    fn user_template_overrides_one_kind() {
+        let mut templates = Templates::builtin();
+        templates
+            .set(
+                EventKind::Success,
+                "ok {{command}} as {{user}} in {{duration}}",
+            )
+            .unwrap();
+        let mut success = event(EventKind::Success);
+        success.user = "alice".to_string();
+        success.set_duration(std::time::Duration::from_millis(1300));
+        assert_eq!(
+            templates.render(&success),
+            "ok make <all> & more as alice in 1.3s"
+        );
+        assert!(
+            templates
+                .render(&event(EventKind::Start))
+                .contains("Started")
+        );
+    }
+
+    #[test]
+    fn template_files_prefer_earlier_dirs() {
+        let root = std::env::temp_dir().join(format!("sentinel-tmpl-{}", std::process::id()));
+        let profile = root.join("profiles").join("de");
+        std::fs::create_dir_all(&profile).unwrap();
+        std::fs::write(root.join("start.tmpl"), "🚀 {{command}}").unwrap();
+        std::fs::write(root.join("failure.tmpl"), "❌ {{exit_code}}").unwrap();
+        std::fs::write(profile.join("failure.tmpl"), "❌ Fehler {{exit_code}}").unwrap();
+
+        let templates = Templates::from_dirs(&[profile, root.clone()]).unwrap();
+        std::fs::remove_dir_all(&root).ok();
+
+        assert_eq!(
+            templates.render(&event(EventKind::Start)),
+            "🚀 make <all> & more"
+        );
+        let failure = Event {
+            exit_code: Some(2),
+            ..event(EventKind::Failure)
+        };
+        assert_eq!(templates.render(&failure), "❌ Fehler 2");
+        assert!(
+            templates
+                .render(&event(EventKind::Success))
+                .contains("Finished successfully")
+        );
+    }
+
+    #[test]
+    fn invalid_template_is_rejected() {
+        assert!(
+            Templates::builtin()
+                .set(EventKind::Start, "{{#if}}")
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//! `--ulimit NAME=SOFT[:HARD]`: resource limits (setrlimit) for a local
+//! command, with the names and syntax of `docker run --ulimit`. A single
+//! value sets both limits; `unlimited` (or `-1`) lifts one. Raising a hard
+//! limit needs root, as with `ulimit -H`.
+
+use std::process::Command;
+
+/// The names `docker run --ulimit` accepts, and what they control.
+const RESOURCES: [(&str, Resource); 16] = [
+    ("as", libc::RLIMIT_AS),
+    ("core", libc::RLIMIT_CORE),
+    ("cpu", libc::RLIMIT_CPU),
+    ("data", libc::RLIMIT_DATA),
+    ("fsize", libc::RLIMIT_FSIZE),
+    ("locks", libc::RLIMIT_LOCKS),
+    ("memlock", libc::RLIMIT_MEMLOCK),
+    ("msgqueue", libc::RLIMIT_MSGQUEUE),
+    ("nice", libc::RLIMIT_NICE),
+    ("nofile", libc::RLIMIT_NOFILE),
+    ("nproc", libc::RLIMIT_NPROC),
+    ("rss", libc::RLIMIT_RSS),
+    ("rtprio", libc::RLIMIT_RTPRIO),
+    ("rttime", libc::RLIMIT_RTTIME),
+    ("sigpending", libc::RLIMIT_SIGPENDING),
+    ("stack", libc::RLIMIT_STACK),
+];
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type Resource = libc::__rlimit_resource_t;
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+type Resource = libc::c_int;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ulimit {
+    pub name: &'static str,
+    resource: Resource,
+    pub soft: libc::rlim_t,
+    pub hard: libc::rlim_t,
+}
+
+impl std::fmt::Display for Ulimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = |v: libc::rlim_t| {
+            if v == libc::RLIM_INFINITY {
+                "unlimited".to_string()
+            } else {
+                v.to_string()
+            }
+        };
+        write!(f, "{}={}", self.name, value(self.soft))?;
+        if self.hard != self.soft {
+            write!(f, ":{}", value(self.hard))?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_value(s: &str) -> Result<libc::rlim_t, String> {
+    match s.trim() {
+        "unlimited" | "-1" => Ok(libc::RLIM_INFINITY),
+        value => value
+            .parse()
+            .map_err(|_| format!("expected a number or \"unlimited\", got {value:?}")),
+    }
+}
+
+/// Parses a `nofile=1024:2048` style argument.
+pub fn parse_ulimit(s: &str) -> Result<Ulimit, String> {
+    let (name, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=SOFT[:HARD], got {s:?}"))?;
+    let (name, resource) = RESOURCES
+        .iter()
+        .find(|(known, _)| *known == name)
+        .copied()
+        .ok_or_else(|| {
+            let names: Vec<_> = RESOURCES.iter().map(|(n, _)| *n).collect();
+            format!(
+                "unknown limit {name:?} (expected one of {})",
+                names.join(", ")
+            )
+        })?;
+    let (soft, hard) = match value.split_once(':') {
+        Some((soft, hard)) => (parse_value(soft)?, parse_value(hard)?),
+        None => {
+            let value = parse_value(value)?;
+            (value, value)
+        }
+    };
+    // RLIM_INFINITY is the largest value, so this also covers "unlimited".
+    if soft > hard {
+        return Err(format!(
+            "the soft {name} limit must not be above the hard one"
+        ));
+    }
+    Ok(Ulimit {
+        name,
+        resource,
+        soft,
+        hard,
+    })
+}
+
+/// Has `cmd` set `limits` on itself before it execs.
+pub fn apply(cmd: &mut Command, limits: &[Ulimit]) {
+    use std::os::unix::process::CommandExt;
+
+    let limits = limits.to_vec();
+    // SAFETY: only async-signal-safe system calls on prepared data.
+    unsafe {
+        cmd.pre_exec(move || {
+            for limit in &limits {
+                let value = libc::rlimit {
+                    rlim_cur: limit.soft,
+                    rlim_max: limit.hard,
+                };
+                if libc::setrlimit(limit.resource, &value) < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{Exec, run_bash_with_hook};
+
+    #[test]
+    fn parses_docker_ulimit_syntax() {
+        let nofile = parse_ulimit("nofile=1024:2048").unwrap();
+        assert_eq!(
+            (nofile.name, nofile.soft, nofile.hard),
+            ("nofile", 1024, 2048)
+        );
+        let core = parse_ulimit("core=0").unwrap();
+        assert_eq!((core.soft, core.hard), (0, 0));
+        let cpu = parse_ulimit("cpu=60:unlimited").unwrap();
+        assert_eq!((cpu.soft, cpu.hard), (60, libc::RLIM_INFINITY));
+        assert_eq!(parse_ulimit("stack=-1").unwrap().soft, libc::RLIM_INFINITY);
+        assert_eq!(cpu.to_string(), "cpu=60:unlimited");
+        assert_eq!(core.to_string(), "core=0");
+        assert!(parse_ulimit("nofile").is_err());
+        assert!(parse_ulimit("files=10").unwrap_err().contains("nofile"));
+        assert!(parse_ulimit("nofile=2048:1024").is_err());
+        assert!(parse_ulimit("nofile=lots").is_err());
+    }
+
+    #[test]
+    fn command_sees_its_limits() {
+        let exec = Exec {
+            ulimits: vec![
+                parse_ulimit("nofile=256").unwrap(),
+                parse_ulimit("core=0").unwrap(),
+            ],
+            ..Exec::default()
+        };
+        let output = run_bash_with_hook("ulimit -n; ulimit -Hn; ulimit -c", &exec, false, None);
+        assert_eq!(
+            String::from_utf8_lossy(&output.unwrap().output.stdout),
+            "256\n256\n0\n"
+        );
+    }
+}
@@ -0,0 +1,128 @@
+//! `--attach FILE`: files sent to the chat as documents once the finish
+//! notification is out, e.g. a job's full log. Each is streamed from disk
+//! into the `sendDocument` request rather than read into memory first, so
+//! that the large files a [local Bot API
+//! server](https://github.com/tdlib/telegram-bot-api) takes (up to
+//! [`LOCAL_LIMIT`]) can be sent too. api.telegram.org takes up to
+//! [`CLOUD_LIMIT`]; a file over the limit is not sent, and a message says so.
+
+use crate::config::{DEFAULT_HTTP_TIMEOUT, TgConfig};
+use crate::event::format_bytes;
+use crate::notifier::http_client_with;
+use crate::telegram::tg_send;
+use reqwest::blocking::Client;
+use reqwest::blocking::multipart::{Form, Part};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// The largest file api.telegram.org takes from a bot.
+pub const CLOUD_LIMIT: u64 = 50 << 20;
+/// The largest file a local Bot API server takes.
+pub const LOCAL_LIMIT: u64 = 2000 << 20;
+
+/// The largest file the configured server takes: any server other than
+/// api.telegram.org is taken for a local one.
+pub fn limit(cfg: &TgConfig) -> u64 {
+    let host = reqwest::Url::parse(&cfg.api_base)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string));
+    match host.as_deref() {
+        Some("api.telegram.org") => CLOUD_LIMIT,
+        _ => LOCAL_LIMIT,
+    }
+}
+
+/// How long sending `bytes` may take: the usual timeout, plus a second for
+/// every MiB, so a slow link still gets a large file through.
+fn timeout(cfg: &TgConfig, bytes: u64) -> Duration {
+    cfg.http.timeout.unwrap_or(DEFAULT_HTTP_TIMEOUT) + Duration::from_secs(bytes >> 20)
+}
+
+/// Sends the file at `path` to the configured chat, captioned with its
+/// name and size.
+pub fn send(client: &Client, cfg: &TgConfig, path: &Path) -> Result<(), String> {
+    let file =
+        std::fs::File::open(path).map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+    let bytes = file
+        .metadata()
+        .map_err(|e| format!("cannot read {}: {e}", path.display()))?
+        .len();
+    if bytes > limit(cfg) {
+        return Err(format!(
+            "{} is {}, more than the {} the Bot API server takes",
+            path.display(),
+            format_bytes(bytes),
+            format_bytes(limit(cfg))
+        ));
+    }
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "file".to_string());
+    let form = Form::new()
+        .text("chat_id", cfg.chat_id.clone())
+        .text("caption", format!("{name} ({})", format_bytes(bytes)))
+        .part(
+            "document",
+            Part::reader_with_length(file, bytes).file_name(name),
+        );
+    let url = format!("{}/bot{}/sendDocument", cfg.api_base, cfg.bot_token);
+    let response = client
+        .post(&url)
+        .timeout(timeout(cfg, bytes))
+        .multipart(form)
+        .send()
+        .map_err(|e| format!("cannot send {}: {e}", path.display()))?;
+    let status = response.status();
+    let body: serde_json::Value = response.json().unwrap_or_default();
+    if body["ok"] == true {
+        return Ok(());
+    }
+    let why = body["description"]
+        .as_str()
+        .map(str::to_string)
+        .unwrap_or_else(|| status.to_string());
+    Err(format!("cannot send {}: {why}", path.display()))
+}
+
+/// Sends each of `paths` in turn. Those that cannot be sent are logged,
+/// and named in one message to the chat.
+pub fn send_all(cfg: &TgConfig, paths: &[PathBuf]) {
+    let client = match http_client_with(&cfg.http) {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::warn!("Failed to send the attached files: {e}");
+            return;
+        }
+    };
+    let failures: Vec<String> = paths
+        .iter()
+        .filter_map(|path| send(&client, cfg, path).err())
+        .inspect(|e| tracing::warn!("Failed to attach a file: {e}"))
+        .collect();
+    if failures.is_empty() {
+        return;
+    }
+    let text = format!("Not attached:\n{}", failures.join("\n"));
+    if let Err(e) = tg_send(&client, cfg, &text) {
+        tracing::warn!("Failed to send telegram message: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_servers_take_larger_files() {
+        let cloud = TgConfig::new("t", "1", crate::config::DEFAULT_API_BASE);
+        assert_eq!(limit(&cloud), CLOUD_LIMIT);
+        let local = TgConfig::new("t", "1", "http://localhost:8081");
+        assert_eq!(limit(&local), LOCAL_LIMIT);
+        assert_eq!(format_bytes(LOCAL_LIMIT), "2.0 GiB");
+        assert_eq!(
+            timeout(&local, 300 << 20),
+            DEFAULT_HTTP_TIMEOUT + Duration::from_secs(300)
+        );
+    }
+}
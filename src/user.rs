@@ -0,0 +1,185 @@
+//! `--user` / `--group`: drop root privileges for the command, so a root
+//! crontab entry can run a job as a service account through the wrapper.
+//! sentinel-rs itself keeps running as root (it only needs that to switch);
+//! notifications name the user the command ran as.
+
+use std::ffi::{CStr, CString};
+use std::process::Command;
+
+/// Who a local command runs as.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunAs {
+    /// User name shown in notifications.
+    pub user: String,
+    pub uid: u32,
+    pub gid: u32,
+    /// Supplementary groups: the user's, or just `gid` with only `--group`.
+    pub groups: Vec<u32>,
+    pub home: Option<String>,
+}
+
+struct Passwd {
+    name: String,
+    uid: u32,
+    gid: u32,
+    home: String,
+}
+
+fn lookup_user(user: &str) -> Option<Passwd> {
+    let entry = match user.parse::<u32>() {
+        Ok(uid) => unsafe { libc::getpwuid(uid) },
+        Err(_) => {
+            let name = CString::new(user).ok()?;
+            unsafe { libc::getpwnam(name.as_ptr()) }
+        }
+    };
+    // SAFETY: a non-null entry points at static storage valid until the next
+    // getpw* call, and everything is copied out before returning.
+    let entry = unsafe { entry.as_ref()? };
+    let text = |p: *const libc::c_char| unsafe { CStr::from_ptr(p) }.to_string_lossy().into_owned();
+    Some(Passwd {
+        name: text(entry.pw_name),
+        uid: entry.pw_uid,
+        gid: entry.pw_gid,
+        home: text(entry.pw_dir),
+    })
+}
+
+fn lookup_group(group: &str) -> Option<u32> {
+    if let Ok(gid) = group.parse() {
+        return Some(gid);
+    }
+    let name = CString::new(group).ok()?;
+    // SAFETY: as for getpwnam above.
+    let entry = unsafe { libc::getgrnam(name.as_ptr()).as_ref()? };
+    Some(entry.gr_gid)
+}
+
+/// The groups `user` is a member of, starting with `gid`.
+fn group_list(user: &str, gid: u32) -> Vec<u32> {
+    let Ok(name) = CString::new(user) else {
+        return vec![gid];
+    };
+    let mut count: libc::c_int = 32;
+    loop {
+        let mut groups = vec![0 as libc::gid_t; count as usize];
+        let ret =
+            unsafe { libc::getgrouplist(name.as_ptr(), gid, groups.as_mut_ptr(), &mut count) };
+        if ret >= 0 {
+            groups.truncate(count as usize);
+            return groups;
+        }
+        if count as usize <= groups.len() {
+            count = groups.len() as libc::c_int * 2;
+        }
+    }
+}
+
+impl RunAs {
+    /// Resolves `--user` and `--group` (names or numeric ids). With only a
+    /// group, the command keeps the current user but runs with that group.
+    pub fn resolve(user: Option<&str>, group: Option<&str>) -> Result<RunAs, String> {
+        if unsafe { libc::geteuid() } != 0 {
+            return Err("--user and --group only work when sentinel-rs runs as root".to_string());
+        }
+        let group = group
+            .map(|g| lookup_group(g).ok_or_else(|| format!("no such group: {g}")))
+            .transpose()?;
+        let Some(user) = user else {
+            let current = std::env::var("USER").unwrap_or_else(|_| "root".to_string());
+            let gid = group.unwrap_or_default();
+            return Ok(RunAs {
+                user: current,
+                uid: 0,
+                gid,
+                groups: vec![gid],
+                home: None,
+            });
+        };
+        let entry = lookup_user(user).ok_or_else(|| format!("no such user: {user}"))?;
+        let gid = group.unwrap_or(entry.gid);
+        let mut groups = group_list(&entry.name, entry.gid);
+        if !groups.contains(&gid) {
+            groups.insert(0, gid);
+        }
+        Ok(RunAs {
+            user: entry.name,
+            uid: entry.uid,
+            gid,
+            groups,
+            home: Some(entry.home),
+        })
+    }
+
+    /// Switches `cmd` to this user and group before it execs, with `USER`,
+    /// `LOGNAME` and `HOME` to match.
+    pub fn apply(&self, cmd: &mut Command) {
+        use std::os::unix::process::CommandExt;
+
+        if let Some(home) = &self.home {
+            cmd.env("USER", &self.user)
+                .env("LOGNAME", &self.user)
+                .env("HOME", home);
+        }
+        let (uid, gid, groups) = (self.uid, self.gid, self.groups.clone());
+        // SAFETY: only async-signal-safe system calls on prepared data.
+        unsafe {
+            cmd.pre_exec(move || {
+                let check = |ret: libc::c_int| {
+                    if ret < 0 {
+                        Err(std::io::Error::last_os_error())
+                    } else {
+                        Ok(())
+                    }
+                };
+                check(libc::setgroups(groups.len() as _, groups.as_ptr()))?;
+                check(libc::setgid(gid))?;
+                check(libc::setuid(uid))
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_users_by_name_and_id() {
+        if unsafe { libc::geteuid() } != 0 {
+            assert!(RunAs::resolve(Some("root"), None).is_err());
+            return;
+        }
+        let root = RunAs::resolve(Some("root"), None).unwrap();
+        assert_eq!((root.uid, root.gid), (0, 0));
+        assert_eq!(root.user, "root");
+        assert_eq!(RunAs::resolve(Some("0"), Some("0")).unwrap().user, "root");
+        assert!(
+            RunAs::resolve(Some("no-such-user-sentinel"), None)
+                .unwrap_err()
+                .contains("no such user")
+        );
+    }
+
+    #[test]
+    fn command_runs_as_the_other_user() {
+        if unsafe { libc::geteuid() } != 0 {
+            return;
+        }
+        let Ok(nobody) = RunAs::resolve(Some("nobody"), None) else {
+            return;
+        };
+        let exec = crate::runner::Exec {
+            run_as: Some(nobody.clone()),
+            ..crate::runner::Exec::default()
+        };
+        let output =
+            crate::runner::run_bash_with_hook("id -u; id -g; echo $USER", &exec, false, None)
+                .unwrap()
+                .output;
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            format!("{}\n{}\nnobody\n", nobody.uid, nobody.gid)
+        );
+    }
+}
@@ -0,0 +1,253 @@
+//! `sentinel-rs config check`: everything wrong with the configuration at
+//! once, without contacting anything. A run that cannot load its
+//! configuration lists the same problems rather than only the first. The
+//! token and chat id are checked as `doctor` does; every other setting is
+//! parsed, the backends, plugins, hook script and templates are loaded, and
+//! variables that look like sentinel-rs's own but are not, and settings
+//! that cancel each other out, are pointed out.
+
+use crate::check::Health;
+use crate::config::{self, HttpOptions};
+use crate::doctor::{self, Finding};
+use crate::template;
+use std::path::Path;
+
+/// The variables sentinel-rs reads, or sets for `--post` and
+/// `--on-failure` commands, so that a typo in one of them is noticed.
+/// `SENTINEL_TEMPLATE_<KIND>` (and `_<SEVERITY>`) are told apart separately.
+pub const KNOWN: &[&str] = &[
+    "SENTINEL_CA_BUNDLE",
+    "SENTINEL_CLIENT_CERT",
+    "SENTINEL_CLIENT_KEY",
+    "SENTINEL_CONFIG_DIR",
+    "SENTINEL_DUMP_FILE",
+    "SENTINEL_ESCALATE_AFTER",
+    "SENTINEL_ESCALATE_CHAT_ID",
+    "SENTINEL_ESCALATE_TIMES",
+    "SENTINEL_ESCALATE_URL",
+    "SENTINEL_EXIT_CODE",
+    "SENTINEL_HTTP_IDLE_TIMEOUT",
+    "SENTINEL_HTTP_MAX_IDLE",
+    "SENTINEL_HTTP_TIMEOUT",
+    "SENTINEL_JOB",
+    "SENTINEL_LOG",
+    "SENTINEL_MAINTENANCE",
+    "SENTINEL_METADATA_URL",
+    "SENTINEL_MUTE",
+    "SENTINEL_PLUGINS",
+    "SENTINEL_PROFILE",
+    "SENTINEL_PROGRESS_INTERVAL",
+    "SENTINEL_PROXY",
+    "SENTINEL_QUEUE_POLICY",
+    "SENTINEL_QUEUE_SIZE",
+    "SENTINEL_QUIET_BREAKTHROUGH",
+    "SENTINEL_QUIET_HOURS",
+    "SENTINEL_REPEAT_WINDOW",
+    "SENTINEL_RUN_ID",
+    "SENTINEL_SCRIPT",
+    "SENTINEL_SEVERITY_RULES",
+    "SENTINEL_SHUTDOWN_TIMEOUT",
+    "SENTINEL_SILENT_BELOW",
+    "SENTINEL_STATE_DIR",
+    "SENTINEL_TELEGRAM_OVERFLOW",
+    "TG_API_BASE",
+    "TG_BOT_TOKEN",
+    "TG_BOT_TOKEN_FILE",
+    "TG_CHAT_ID",
+    "TG_CHAT_ID_CRITICAL",
+    "TG_CHAT_ID_ERROR",
+    "TG_CHAT_ID_WARNING",
+];
+
+/// How many edits apart two names are.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (diagonal + usize::from(ca != *cb))
+                .min(row[j] + 1)
+                .min(above + 1);
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+/// `None` for a variable sentinel-rs knows or that is not in its
+/// namespaces (`SENTINEL_`, `TG_`); else the known one it is closest to,
+/// if any is close.
+fn unknown(name: &str) -> Option<Option<&'static str>> {
+    if !name.starts_with("SENTINEL_") && !name.starts_with("TG_") || KNOWN.contains(&name) {
+        return None;
+    }
+    if name.starts_with("SENTINEL_TEMPLATE_")
+        && template::names().iter().any(|(_, key)| key == name)
+    {
+        return None;
+    }
+    Some(
+        KNOWN
+            .iter()
+            .map(|known| (distance(name, known), *known))
+            .filter(|(d, _)| *d <= 3)
+            .min()
+            .map(|(_, known)| known),
+    )
+}
+
+fn set(key: &str) -> bool {
+    config::env_required(key).is_ok()
+}
+
+/// Everything found wrong, warnings included; empty if nothing is.
+pub fn check(token_file: Option<&Path>) -> Vec<Finding> {
+    let mut problems = Vec::new();
+    let mut report = |finding: Finding| {
+        if finding.health != Health::Ok {
+            problems.push(finding);
+        }
+    };
+
+    match config::bot_token(token_file) {
+        Ok(token) => report(doctor::token_format(token.trim())),
+        Err(e) => report(Finding::problem(
+            "Bot token",
+            Health::Failing,
+            e,
+            "export TG_BOT_TOKEN with the token @BotFather gave you",
+        )),
+    }
+    let ignored_token = if token_file.is_some() && set("TG_BOT_TOKEN") {
+        Some("--token-file wins, so TG_BOT_TOKEN is not used")
+    } else if (token_file.is_some() || set("TG_BOT_TOKEN")) && set("TG_BOT_TOKEN_FILE") {
+        Some("TG_BOT_TOKEN_FILE is not read, since the token is given elsewhere")
+    } else {
+        None
+    };
+    if let Some(detail) = ignored_token {
+        report(Finding::problem(
+            "Bot token",
+            Health::Warning,
+            detail,
+            "give the token in one place only",
+        ));
+    }
+    match config::chat_id() {
+        Ok(chat) => report(doctor::chat_format(chat.trim())),
+        Err(e) => report(Finding::problem(
+            "Chat",
+            Health::Failing,
+            e,
+            "export TG_CHAT_ID; see README.md for how to find it",
+        )),
+    }
+    if let Err(e) = config::api_base() {
+        report(Finding::problem(
+            "Bot API server",
+            Health::Failing,
+            e,
+            "give the whole URL of the server, e.g. http://localhost:8081",
+        ));
+    }
+
+    for problem in config::setting_problems() {
+        report(Finding::problem(
+            "Settings",
+            Health::Failing,
+            problem,
+            "see README.md for the values each takes",
+        ));
+    }
+    // A setting it cannot parse is reported above.
+    if let Ok(Err(e)) = HttpOptions::from_env().map(|http| crate::notifier::http_client_with(&http))
+    {
+        report(Finding::problem(
+            "HTTP client",
+            Health::Failing,
+            e,
+            "check SENTINEL_PROXY and the SENTINEL_CA_BUNDLE/CLIENT_CERT/CLIENT_KEY files",
+        ));
+    }
+
+    for (what, loaded) in [
+        ("Grafana", crate::grafana::from_env().map(drop)),
+        ("OpenTelemetry", crate::otel::from_env().map(drop)),
+        ("Quiet hours", crate::quiet::from_env().map(drop)),
+        ("Repeated failures", crate::repeat::from_env().map(drop)),
+        ("Severity rules", crate::severity::from_env().map(drop)),
+        (
+            "Plugins",
+            crate::plugin::load_plugins()
+                .map(drop)
+                .map_err(|e| e.to_string()),
+        ),
+        (
+            "Hook script",
+            crate::script::load_script()
+                .map(drop)
+                .map_err(|e| e.to_string()),
+        ),
+        (
+            "Templates",
+            crate::template::Templates::from_env()
+                .map(drop)
+                .map_err(|e| e.to_string()),
+        ),
+    ] {
+        if let Err(e) = loaded {
+            report(Finding::problem(what, Health::Failing, e, ""));
+        }
+    }
+    for key in ["GRAFANA_TOKEN", "GRAFANA_DASHBOARD_UID"] {
+        if set(key) && !set("GRAFANA_URL") {
+            report(Finding::problem(
+                "Grafana",
+                Health::Warning,
+                format!("{key} is set but GRAFANA_URL is not, so there are no annotations"),
+                "export GRAFANA_URL too, or unset it",
+            ));
+        }
+    }
+
+    let mut names: Vec<String> = std::env::vars_os()
+        .filter_map(|(key, _)| key.into_string().ok())
+        .collect();
+    names.sort();
+    for name in names {
+        if let Some(closest) = unknown(&name) {
+            let hint = closest.map(|known| format!("did you mean {known}?"));
+            report(Finding::problem(
+                "Unknown setting",
+                Health::Warning,
+                format!("{name} is not used by sentinel-rs"),
+                hint.as_deref().unwrap_or_default(),
+            ));
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn near_misses_of_known_settings_are_pointed_out() {
+        assert_eq!(unknown("TG_CHATID"), Some(Some("TG_CHAT_ID")));
+        assert_eq!(
+            unknown("SENTINEL_QUEUE_POLCY"),
+            Some(Some("SENTINEL_QUEUE_POLICY"))
+        );
+        assert_eq!(unknown("SENTINEL_SOMETHING_ELSE"), Some(None));
+        assert_eq!(unknown("TG_BOT_TOKEN"), None);
+        assert_eq!(unknown("SENTINEL_TEMPLATE_FAILURE"), None);
+        assert_eq!(unknown("SENTINEL_TEMPLATE_FAILURE_CRITICAL"), None);
+        assert_eq!(unknown("SENTINEL_TEMPLATE_FALIURE"), Some(None));
+        assert_eq!(unknown("HTTPS_PROXY"), None);
+        assert_eq!(distance("kitten", "sitting"), 3);
+    }
+}
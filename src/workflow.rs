@@ -0,0 +1,385 @@
+//! `sentinel-rs pipeline run FILE`: named steps from a YAML file, run as a
+//! graph. A step starts once every step it `depends_on` has succeeded, up to
+//! `concurrency` at a time, and is skipped if one of them failed; steps on
+//! other branches carry on. A failing step is retried `retries` times. The
+//! whole run is summed up in one [`EventKind::Report`], and a step can ask
+//! for a notification of its own when it finishes.
+//!
+//! ```yaml
+//! name: nightly
+//! concurrency: 2
+//! steps:
+//!   - name: dump
+//!     run: pg_dump app > /srv/app.sql
+//!     retries: 2
+//!     retry_delay: 30
+//!   - name: upload
+//!     run: restic backup /srv
+//!     depends_on: [dump]
+//!     notify: failure
+//! ```
+//!
+//! (Not to be confused with [`crate::pipeline`], which is about shell
+//! pipelines.)
+
+use crate::RunOptions;
+use crate::config::TgConfig;
+use crate::event::{Event, EventKind, Severity, StepResult};
+use crate::fanout::step_result;
+use crate::metrics::METRICS;
+use crate::notifier::{Reporter, start_notifier};
+use crate::runner::{Exec, OnLine, run_bash, tail_bytes};
+use crate::steps::line_hook;
+use serde::Deserialize;
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Workflow {
+    /// Shown as the command in the notifications; the file name if unset.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// How many steps may run at once.
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Step {
+    pub name: String,
+    /// Run with `bash -c`.
+    pub run: String,
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// Further attempts after a failure.
+    #[serde(default)]
+    pub retries: u32,
+    /// Seconds to wait before each retry.
+    #[serde(default)]
+    pub retry_delay: u64,
+    /// When the step sends a notification of its own as it finishes.
+    #[serde(default)]
+    pub notify: Notify,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Notify {
+    /// Only the report mentions it.
+    #[default]
+    Never,
+    Failure,
+    Always,
+}
+
+fn default_concurrency() -> usize {
+    1
+}
+
+/// Reads and checks the pipeline in `path`: unique step names, known
+/// dependencies and no cycles.
+pub fn load(path: &Path) -> Result<Workflow, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read pipeline {}: {e}", path.display()))?;
+    let mut workflow = parse(&text).map_err(|e| format!("{}: {e}", path.display()))?;
+    if workflow.name.is_none() {
+        workflow.name = Some(path.display().to_string());
+    }
+    Ok(workflow)
+}
+
+fn parse(text: &str) -> Result<Workflow, String> {
+    let workflow: Workflow = serde_norway::from_str(text).map_err(|e| e.to_string())?;
+    if workflow.steps.is_empty() {
+        return Err("no steps".to_string());
+    }
+    if workflow.concurrency == 0 {
+        return Err("concurrency must be at least 1".to_string());
+    }
+    for (i, step) in workflow.steps.iter().enumerate() {
+        if workflow.steps[..i].iter().any(|s| s.name == step.name) {
+            return Err(format!("two steps are named {:?}", step.name));
+        }
+        if step.run.trim().is_empty() {
+            return Err(format!("step {:?} has nothing to run", step.name));
+        }
+        for dependency in &step.depends_on {
+            if !workflow.steps.iter().any(|s| &s.name == dependency) {
+                return Err(format!(
+                    "step {:?} depends on unknown step {dependency:?}",
+                    step.name
+                ));
+            }
+        }
+    }
+    if let Some(step) = cycle(&workflow.steps) {
+        return Err(format!("step {step:?} depends on itself"));
+    }
+    Ok(workflow)
+}
+
+/// A step that depends on itself, directly or not.
+fn cycle(steps: &[Step]) -> Option<&str> {
+    let index = |name: &String| steps.iter().position(|s| &s.name == name);
+    // Take away steps with no unresolved dependencies until none are left;
+    // whatever remains is on a cycle.
+    let mut done = vec![false; steps.len()];
+    loop {
+        let ready: Vec<usize> = (0..steps.len())
+            .filter(|&i| !done[i])
+            .filter(|&i| {
+                steps[i]
+                    .depends_on
+                    .iter()
+                    .filter_map(index)
+                    .all(|d| done[d])
+            })
+            .collect();
+        if ready.is_empty() {
+            break;
+        }
+        for i in ready {
+            done[i] = true;
+        }
+    }
+    (0..steps.len())
+        .find(|&i| !done[i])
+        .map(|i| steps[i].name.as_str())
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Waiting,
+    Running,
+    Succeeded,
+    Failed,
+    Skipped,
+}
+
+/// Runs `workflow` and sends its report. Returns 0 if every step
+/// succeeded, otherwise the highest exit code among the failures.
+pub fn run(workflow: &Workflow, cfg: TgConfig, opts: RunOptions) -> i32 {
+    let command = workflow.name.as_deref().unwrap_or("pipeline");
+    let steps = &workflow.steps;
+    let (tx, notifier) = start_notifier(cfg, opts.plugins);
+    let reporter = Reporter::new(tx, opts.templates);
+    let started = Instant::now();
+    reporter.send(Event::new(EventKind::Start, command));
+
+    let hooks = opts.line_hooks;
+    let exec = &opts.exec;
+    let tee = opts.tee;
+    let depends = |i: usize| {
+        steps[i]
+            .depends_on
+            .iter()
+            .filter_map(|name| steps.iter().position(|s| &s.name == name))
+    };
+    let mut states = vec![State::Waiting; steps.len()];
+    let mut results: Vec<Option<StepResult>> = vec![None; steps.len()];
+    let mut exit = 0;
+    let (done_tx, done_rx) = mpsc::channel();
+    thread::scope(|scope| {
+        let mut running = 0;
+        loop {
+            // Skipping a step can make later ones skippable, so until
+            // nothing changes.
+            let mut changed = true;
+            while changed {
+                changed = false;
+                for i in 0..steps.len() {
+                    if states[i] == State::Waiting
+                        && depends(i).any(|d| matches!(states[d], State::Failed | State::Skipped))
+                    {
+                        states[i] = State::Skipped;
+                        changed = true;
+                    }
+                }
+            }
+            for i in 0..steps.len() {
+                if running < workflow.concurrency
+                    && states[i] == State::Waiting
+                    && depends(i).all(|d| states[d] == State::Succeeded)
+                {
+                    states[i] = State::Running;
+                    running += 1;
+                    let step = &steps[i];
+                    let prefix = tee.then(|| format!("[{}] ", step.name));
+                    let on_line = line_hook(&step.run, prefix, &hooks, &reporter, |_| {});
+                    let done_tx = done_tx.clone();
+                    let reporter = &reporter;
+                    scope.spawn(move || {
+                        let (result, exit) = attempt(step, exec, on_line, reporter);
+                        done_tx.send((i, result, exit)).ok();
+                    });
+                }
+            }
+            if running == 0 {
+                break;
+            }
+            let Ok((i, result, code)) = done_rx.recv() else {
+                break;
+            };
+            running -= 1;
+            states[i] = if result.ok {
+                State::Succeeded
+            } else {
+                exit = exit.max(code);
+                State::Failed
+            };
+            results[i] = Some(result);
+        }
+    });
+
+    let failed: Vec<&str> = (0..steps.len())
+        .filter(|&i| states[i] == State::Failed)
+        .map(|i| steps[i].name.as_str())
+        .collect();
+    let skipped = states.iter().filter(|s| **s == State::Skipped).count();
+    let mut report = Event::new(EventKind::Report, command);
+    report.set_duration(started.elapsed());
+    report.summary = Some(match (failed.len(), skipped) {
+        (0, _) => format!("All {} steps succeeded.", steps.len()),
+        (n, 0) => format!("{n} of {} steps failed: {}", steps.len(), failed.join(", ")),
+        (n, _) => format!(
+            "{n} of {} steps failed: {}; {skipped} not run.",
+            steps.len(),
+            failed.join(", ")
+        ),
+    });
+    if !failed.is_empty() {
+        report.severity = Severity::Error;
+    }
+    report.steps = results
+        .into_iter()
+        .zip(steps)
+        .map(|(result, step)| {
+            result.unwrap_or_else(|| StepResult {
+                name: step.name.clone(),
+                ok: false,
+                skipped: true,
+                status: "not run".to_string(),
+                exit_code: None,
+                duration: String::new(),
+                excerpt: None,
+            })
+        })
+        .collect();
+    reporter.send(report);
+
+    drop(reporter);
+    notifier.shutdown();
+    exit
+}
+
+/// Runs `step` until it succeeds or is out of retries, sending its own
+/// notification if it asks for one.
+fn attempt(
+    step: &Step,
+    exec: &Exec,
+    on_line: Option<OnLine>,
+    reporter: &Reporter,
+) -> (StepResult, i32) {
+    let started = Instant::now();
+    let attempts = step.retries + 1;
+    let mut attempt = 1;
+    loop {
+        let output = {
+            let _running = METRICS.run_started();
+            run_bash(&step.run, exec, false, on_line.clone()).map(|run| run.output)
+        };
+        let tails = output
+            .as_ref()
+            .ok()
+            .map(|o| (tail_bytes(&o.stdout, 1500), tail_bytes(&o.stderr, 1500)));
+        let (mut result, exit) = step_result(&step.name, started, output);
+        if !result.ok && attempt < attempts {
+            tracing::info!(
+                "Step {} failed ({}), attempt {attempt} of {attempts}; retrying",
+                step.name,
+                result.status
+            );
+            attempt += 1;
+            thread::sleep(Duration::from_secs(step.retry_delay));
+            continue;
+        }
+        if attempt > 1 {
+            result
+                .status
+                .push_str(&format!(" after {attempt} attempts"));
+        }
+        let notify = match step.notify {
+            Notify::Never => false,
+            Notify::Failure => !result.ok,
+            Notify::Always => true,
+        };
+        if notify {
+            let kind = if result.ok {
+                EventKind::Success
+            } else {
+                EventKind::Failure
+            };
+            let (stdout, stderr) = tails.unzip();
+            let mut event = Event {
+                exit_code: result.exit_code,
+                stdout,
+                stderr,
+                ..Event::new(kind, &step.run)
+            };
+            event.set_duration(started.elapsed());
+            reporter.send(event);
+        }
+        return (result, exit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_and_checks_pipelines() {
+        let workflow = parse(
+            "name: nightly\n\
+             concurrency: 2\n\
+             steps:\n\
+             \x20 - name: dump\n\
+             \x20   run: pg_dump app > app.sql\n\
+             \x20   retries: 2\n\
+             \x20 - name: upload\n\
+             \x20   run: restic backup .\n\
+             \x20   depends_on: [dump]\n\
+             \x20   notify: failure\n",
+        )
+        .unwrap();
+        assert_eq!(workflow.concurrency, 2);
+        assert_eq!(workflow.steps[0].retries, 2);
+        assert_eq!(workflow.steps[1].depends_on, ["dump"]);
+        assert_eq!(workflow.steps[1].notify, Notify::Failure);
+
+        let error = |text: &str| parse(text).unwrap_err();
+        assert_eq!(
+            error("steps:\n- {name: a, run: x, depends_on: [b]}"),
+            "step \"a\" depends on unknown step \"b\""
+        );
+        assert_eq!(
+            error(
+                "steps:\n- {name: a, run: x, depends_on: [c]}\n\
+                 - {name: b, run: x, depends_on: [a]}\n\
+                 - {name: c, run: x, depends_on: [b]}\n\
+                 - {name: d, run: x}"
+            ),
+            "step \"a\" depends on itself"
+        );
+        assert_eq!(
+            error("steps:\n- {name: a, run: x}\n- {name: a, run: y}"),
+            "two steps are named \"a\""
+        );
+        assert!(error("steps:\n- {name: a, run: x, retry: 2}").contains("unknown field `retry`"));
+    }
+}
@@ -7,7 +7,11 @@ fn command_with_mock(server: &Server) -> Command {
     let mut cmd = cargo_bin_cmd!("sentinel-rs");
     cmd.env("TG_BOT_TOKEN", "TEST_TOKEN")
         .env("TG_CHAT_ID", "123")
-        .env("TG_API_BASE", server.url());
+        .env("TG_API_BASE", server.url())
+        // Keep the developer's own config dir out of the tests...
+        .env("SENTINEL_CONFIG_DIR", "/nonexistent/sentinel-rs")
+        // ...and this repo's own CI runs out of the GitHub integration.
+        .env_remove("GITHUB_ACTIONS");
     cmd
 }
 
@@ -89,22 +93,55 @@ fn missing_command_after_double_dash_exits_2() {
 }
 
 #[test]
-fn spawn_failure_exits_1() {
+fn spawn_failure_exits_125() {
     let mut server = Server::new();
     let mock = server
         .mock("POST", "/botTEST_TOKEN/sendMessage")
         .match_body(Matcher::PartialJson(json!({"chat_id": "123"})))
-        .expect(2)
+        .expect(4)
         .create();
 
     let mut cmd = command_with_mock(&server);
     cmd.env("PATH", "");
     cmd.arg("--").arg("true");
-    cmd.assert().code(1);
+    cmd.assert().code(125);
+
+    let mut cmd = command_with_mock(&server);
+    cmd.env("PATH", "");
+    cmd.args(["--exit-on-spawn-error", "70", "--", "true"]);
+    cmd.assert().code(70);
     mock.assert();
     drop(server);
 }
 
+#[test]
+fn exit_codes_for_signals_and_undelivered_notifications_can_be_chosen() {
+    let mut server = Server::new();
+    server.mock("POST", "/botTEST_TOKEN/sendMessage").create();
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["--exit-on-signal", "99", "--", "kill -TERM $$"]);
+    cmd.assert().code(99);
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["--exit-on-undelivered", "75", "--", "true"]);
+    cmd.assert().success();
+
+    let mut server = Server::new();
+    server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .with_status(502)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["--", "true"]);
+    cmd.assert().success();
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["--exit-on-undelivered", "75", "--", "true"]);
+    cmd.assert().code(75);
+    // A failure's own exit code says more.
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["--exit-on-undelivered", "75", "--", "exit 3"]);
+    cmd.assert().code(3);
+}
+
 #[test]
 fn telegram_failure_does_not_change_exit_code() {
     let mut cmd = cargo_bin_cmd!("sentinel-rs");
@@ -115,3 +152,2800 @@ fn telegram_failure_does_not_change_exit_code() {
         .arg("true");
     cmd.assert().success();
 }
+
+#[cfg(feature = "wasm-plugins")]
+#[test]
+fn wasm_plugin_can_drop_notifications() {
+    let mut server = Server::new();
+    let mock = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .expect(0)
+        .create();
+
+    let plugin = std::env::temp_dir().join(format!("sentinel-drop-{}.wat", std::process::id()));
+    std::fs::write(
+        &plugin,
+        r#"(module
+          (memory (export "memory") 1)
+          (data (i32.const 0) "[{\"type\":\"drop\"}]")
+          (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+          (func (export "on_event") (param i32 i32) (result i64) (i64.const 17)))"#,
+    )
+    .unwrap();
+
+    let mut cmd = command_with_mock(&server);
+    cmd.env("SENTINEL_PLUGINS", &plugin).arg("--").arg("true");
+    cmd.assert().success();
+    mock.assert();
+    std::fs::remove_file(plugin).ok();
+}
+
+#[cfg(not(feature = "wasm-plugins"))]
+#[test]
+fn plugins_without_wasm_support_exit_2() {
+    let mut cmd = cargo_bin_cmd!("sentinel-rs");
+    cmd.env("TG_BOT_TOKEN", "TEST_TOKEN")
+        .env("TG_CHAT_ID", "123")
+        .env("SENTINEL_PLUGINS", "/tmp/filter.wasm")
+        .arg("--")
+        .arg("true");
+    cmd.assert()
+        .code(2)
+        .stderr(predicates::str::contains("wasm-plugins"));
+}
+
+#[cfg(feature = "scripting")]
+#[test]
+fn script_hooks_suppress_start_and_notify_on_line() {
+    let mut server = Server::new();
+    let pong = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex("pong".to_string()))
+        .expect(1)
+        .create();
+    let finish = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex("Finished successfully".to_string()))
+        .expect(1)
+        .create();
+    let started = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex("Started".to_string()))
+        .expect(0)
+        .create();
+
+    let script = std::env::temp_dir().join(format!("sentinel-hooks-{}.rhai", std::process::id()));
+    std::fs::write(
+        &script,
+        r#"
+        fn on_start(ctx) { false }
+        fn on_line(line, stream) { if line == "ping" { "pong" } }
+        "#,
+    )
+    .unwrap();
+
+    let mut cmd = command_with_mock(&server);
+    cmd.env("SENTINEL_SCRIPT", &script)
+        .arg("--")
+        .arg("echo ping");
+    cmd.assert().success();
+    pong.assert();
+    finish.assert();
+    started.assert();
+    std::fs::remove_file(script).ok();
+}
+
+#[test]
+fn template_override_changes_message_text() {
+    let mut server = Server::new();
+    let start = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::PartialJson(json!({"text": "custom start: true"})))
+        .expect(1)
+        .create();
+    let finish = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex("Finished successfully".to_string()))
+        .expect(1)
+        .create();
+
+    let template = std::env::temp_dir().join(format!("sentinel-start-{}.hbs", std::process::id()));
+    std::fs::write(&template, "custom start: {{command}}").unwrap();
+
+    let mut cmd = command_with_mock(&server);
+    cmd.env("SENTINEL_TEMPLATE_START", &template)
+        .arg("--")
+        .arg("true");
+    cmd.assert().success();
+    start.assert();
+    finish.assert();
+    std::fs::remove_file(template).ok();
+}
+
+#[test]
+fn invalid_template_exits_2() {
+    let template = std::env::temp_dir().join(format!("sentinel-bad-{}.hbs", std::process::id()));
+    std::fs::write(&template, "{{#if}}").unwrap();
+
+    let mut cmd = cargo_bin_cmd!("sentinel-rs");
+    cmd.env("TG_BOT_TOKEN", "TEST_TOKEN")
+        .env("TG_CHAT_ID", "123")
+        .env("SENTINEL_TEMPLATE_FAILURE", &template)
+        .arg("--")
+        .arg("true");
+    cmd.assert()
+        .code(2)
+        .stderr(predicates::str::contains("SENTINEL_TEMPLATE_FAILURE"));
+    std::fs::remove_file(template).ok();
+}
+
+#[test]
+fn profile_template_files_are_picked_up_from_config_dir() {
+    let mut server = Server::new();
+    let failure = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::PartialJson(json!({"text": "nightly failed: 4"})))
+        .expect(1)
+        .create();
+    let start = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::PartialJson(json!({"text": "go"})))
+        .expect(1)
+        .create();
+
+    let dir = std::env::temp_dir().join(format!("sentinel-config-{}", std::process::id()));
+    let profile = dir.join("profiles").join("nightly");
+    std::fs::create_dir_all(&profile).unwrap();
+    std::fs::write(dir.join("start.tmpl"), "go").unwrap();
+    std::fs::write(dir.join("failure.tmpl"), "failed: {{exit_code}}").unwrap();
+    std::fs::write(
+        profile.join("failure.tmpl"),
+        "nightly failed: {{exit_code}}",
+    )
+    .unwrap();
+
+    let mut cmd = command_with_mock(&server);
+    cmd.env("SENTINEL_CONFIG_DIR", &dir)
+        .env("SENTINEL_PROFILE", "nightly")
+        .arg("--")
+        .arg("exit 4");
+    cmd.assert().code(4);
+    failure.assert();
+    start.assert();
+    std::fs::remove_dir_all(dir).ok();
+}
+
+/// Puts an executable `program` running the sh script `body` first on PATH.
+/// Returns the temp dir holding it and the PATH to use.
+fn fake_program(program: &str, name: &str, body: &str) -> (std::path::PathBuf, String) {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!(
+        "sentinel-fake-{program}-{name}-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    let fake = dir.join(program);
+    std::fs::write(&fake, format!("#!/bin/sh\n{body}")).unwrap();
+    std::fs::set_permissions(&fake, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!("{}:{}", dir.display(), std::env::var("PATH").unwrap());
+    (dir, path)
+}
+
+#[test]
+fn ssh_mode_runs_through_ssh_client_and_reports_remote_host() {
+    let mut server = Server::new();
+    let mock = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(r"\] \[remotebox\]\\n".to_string()))
+        .expect(2)
+        .create();
+
+    // A stand-in ssh that records its arguments and runs the remote command locally.
+    let (dir, path) = fake_program(
+        "ssh",
+        "args",
+        "printf '%s\\n' \"$@\" > \"$(dirname \"$0\")/args\"\nfor last; do :; done\nexec sh -c \"$last\"\n",
+    );
+
+    let mut cmd = command_with_mock(&server);
+    cmd.env("PATH", path).args([
+        "--ssh",
+        "deploy@remotebox",
+        "--",
+        "echo \"it's remote\"; exit 3",
+    ]);
+    cmd.assert()
+        .code(3)
+        .stdout(predicates::str::contains("it's remote"));
+    mock.assert();
+
+    let args = std::fs::read_to_string(dir.join("args")).unwrap();
+    assert!(args.starts_with("-o\nBatchMode=yes\n--\ndeploy@remotebox\nbash -c "));
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn hosts_fan_out_sends_one_report_with_failures_first() {
+    let mut server = Server::new();
+    let start = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(r"\[3 hosts\]\\nStarted".to_string()))
+        .expect(1)
+        .create();
+    let report = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r"1 of 3 hosts failed\.\\nuptime\\nFAILED db1: exit code 255 \([0-9.]+s\)\\nconnection refused\\nok web1: exit code 0 .*\\nok web2: exit code 0"
+                .to_string(),
+        ))
+        .expect(1)
+        .create();
+
+    // ssh is called as `ssh -o BatchMode=yes -- <host> <command>`; db1 is down.
+    let (dir, path) = fake_program(
+        "ssh",
+        "hosts",
+        "if [ \"$4\" = db1 ]; then echo 'connection refused' >&2; exit 255; fi\necho \"up on $4\"\n",
+    );
+    let hosts = dir.join("hosts.txt");
+    std::fs::write(&hosts, "# fleet\nweb1\ndb1\n\nweb2\n").unwrap();
+
+    let mut cmd = command_with_mock(&server);
+    cmd.env("PATH", path)
+        .arg("--hosts")
+        .arg(&hosts)
+        .args(["--concurrency", "2", "--", "uptime"]);
+    cmd.assert()
+        .code(255)
+        .stdout(predicates::str::contains("[web2] up on web2"));
+    start.assert();
+    report.assert();
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn hosts_fan_out_exits_on_undelivered_notifications_too() {
+    let mut server = Server::new();
+    server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .with_status(502)
+        .create();
+    let (dir, path) = fake_program("ssh", "hosts-undelivered", "echo \"up on $4\"\n");
+    let hosts = dir.join("hosts.txt");
+    std::fs::write(&hosts, "web1\nweb2\n").unwrap();
+    let mut cmd = command_with_mock(&server);
+    cmd.env("PATH", path).arg("--hosts").arg(&hosts).args([
+        "--exit-on-undelivered",
+        "75",
+        "--",
+        "uptime",
+    ]);
+    cmd.assert().code(75);
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn empty_hosts_file_exits_2() {
+    let server = Server::new();
+    let hosts = std::env::temp_dir().join(format!("sentinel-empty-hosts-{}", std::process::id()));
+    std::fs::write(&hosts, "# nothing yet\n").unwrap();
+    let mut cmd = command_with_mock(&server);
+    cmd.arg("--hosts").arg(&hosts).args(["--", "true"]);
+    cmd.assert()
+        .code(2)
+        .stderr(predicates::str::contains("No hosts listed"));
+    std::fs::remove_file(hosts).ok();
+}
+
+#[test]
+fn steps_stop_at_the_first_failure() {
+    let mut server = Server::new();
+    let report = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r"Step 2 of 3 failed: ls /nonexistent\\n.*\\nok echo one: exit code 0 \([0-9.]+s\)\\nFAILED ls /nonexistent: exit code 2 \([0-9.]+s\)\\nls: cannot access.*\\nskipped echo three"
+                .to_string(),
+        ))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.args([
+        "--step",
+        "echo one",
+        "--step",
+        "ls /nonexistent",
+        "--step",
+        "echo three",
+    ]);
+    cmd.assert()
+        .code(2)
+        .stdout("one\n")
+        .stderr(predicates::str::contains("cannot access"));
+    report.assert();
+
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["--step", "true", "--", "false"]);
+    cmd.assert().code(2);
+}
+
+#[test]
+fn pre_and_post_hook_failures_are_reported() {
+    let mut server = Server::new();
+    let skipped = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r"Failed with exit code: 4 after [0-9.]+s\.\\n--pre `echo busy >&2; exit 4` exited 4: busy; the command was not run"
+                .to_string(),
+        ))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["--pre", "echo busy >&2; exit 4", "--", "echo ran"]);
+    cmd.assert().code(4).stdout("");
+    skipped.assert();
+
+    let unmounted = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r"Failed with exit code: 3 after [0-9.]+s\.\\n--post `.*` exited 32: target is busy"
+                .to_string(),
+        ))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.args([
+        "--pre",
+        "echo mounted",
+        "--post",
+        "echo \"saw $SENTINEL_EXIT_CODE\"; echo target is busy >&2; exit 32",
+        "--",
+        "exit 3",
+    ]);
+    cmd.assert().code(3).stdout("mounted\nsaw 3\n");
+    unmounted.assert();
+}
+
+#[test]
+fn on_failure_runs_only_when_the_command_fails() {
+    let server = Server::new();
+    let handler =
+        r#"echo "fix $SENTINEL_EXIT_CODE: $(cat "$SENTINEL_LOG")"; test -n "$SENTINEL_RUN_ID""#;
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["--on-failure", handler, "--", "echo oops; exit 5"]);
+    cmd.assert().code(5).stdout("oops\nfix 5: oops\n");
+
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["--on-failure", handler, "--", "echo fine"]);
+    cmd.assert().success().stdout("fine\n");
+}
+
+#[test]
+fn notify_if_decides_from_the_json_output() {
+    let mut server = Server::new();
+    let quiet = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .expect(0)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.args([
+        "--notify-if",
+        ".errors > 0",
+        "--",
+        r#"echo '{"errors": 0}'"#,
+    ]);
+    cmd.assert().success();
+    quiet.assert();
+    quiet.remove();
+
+    let alert = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r"Finished successfully with exit code 0 in [0-9.]+s\.\\nOutput matched --notify-if: \.errors > 0"
+                .to_string(),
+        ))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.args([
+        "--notify-if",
+        "critical: .errors > 10",
+        "--notify-if",
+        ".errors > 0",
+        "--",
+        r#"echo scanning; echo '{"errors": 2}'"#,
+    ]);
+    cmd.assert().success();
+    alert.assert();
+
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["--notify-if", ".errors = 0", "--", "true"]);
+    cmd.assert()
+        .code(2)
+        .stderr(predicates::str::contains("compare with `==`"));
+}
+
+#[test]
+fn artifacts_are_listed_in_the_finish_notification() {
+    let dir = std::env::temp_dir().join(format!("sentinel-dist-{}", std::process::id()));
+    let dir = dir.display().to_string();
+    let mut server = Server::new();
+    let finish = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(format!(
+            r"Finished successfully with exit code 0 in [0-9.]+s\.\\nArtifacts:\\n{dir}/app\.tar\.gz: 3 B, [0-9-]+ [0-9:]+\\nMissing artifacts: {dir}/\*\.deb\\n"
+        )))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.args([
+        "--artifact",
+        &format!("{dir}/*.tar.gz"),
+        "--artifact",
+        &format!("{dir}/*.deb"),
+        "--",
+        &format!("mkdir -p {dir} && printf abc > {dir}/app.tar.gz"),
+    ]);
+    cmd.assert().success();
+    finish.assert();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn attached_files_are_sent_after_the_finish_notification() {
+    let dir = std::env::temp_dir().join(format!("sentinel-attach-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let log = dir.join("backup.log");
+    std::fs::write(&log, "line 1\nline 2\n").unwrap();
+    let missing = dir.join("missing.log");
+    let mut server = Server::new();
+    let messages = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex("Started|Finished".to_string()))
+        .expect(2)
+        .create();
+    let document = server
+        .mock("POST", "/botTEST_TOKEN/sendDocument")
+        .match_body(Matcher::AllOf(vec![
+            Matcher::Regex(r#"filename="backup.log""#.to_string()),
+            Matcher::Regex("line 1\nline 2\n".to_string()),
+            Matcher::Regex(r"backup\.log \(14 B\)".to_string()),
+        ]))
+        .with_body(r#"{"ok": true, "result": {"message_id": 2}}"#)
+        .expect(1)
+        .create();
+    let not_attached = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(format!(
+            r"Not attached:\\ncannot read {}",
+            missing.display()
+        )))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.arg("--attach")
+        .arg(&log)
+        .arg("--attach")
+        .arg(&missing)
+        .args(["--", "true"]);
+    cmd.assert().success();
+    messages.assert();
+    document.assert();
+    not_attached.assert();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn show_env_lists_the_environment_in_the_start_notification() {
+    let mut server = Server::new();
+    let start = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r"Started\\ntrue\\nIn /[^ ]* with /[^ ]*bash [0-9.]+.*\\nSENTINEL_TEST_TOKEN=<redacted>\\nSENTINEL_TEST_VAR=hello\x22"
+                .to_string(),
+        ))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.env("SENTINEL_TEST_VAR", "hello")
+        .env("SENTINEL_TEST_TOKEN", "hunter2")
+        .args(["--show-env", "SENTINEL_TEST_*", "--", "true"]);
+    cmd.assert().success();
+    start.assert();
+}
+
+#[test]
+fn git_names_the_branch_and_commit() {
+    let dir = std::env::temp_dir().join(format!("sentinel-repo-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let git = |args: &[&str]| {
+        let status = std::process::Command::new("git")
+            .current_dir(&dir)
+            .args(["-c", "user.name=t", "-c", "user.email=t@example.com"])
+            .args(args)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    };
+    git(&["init", "-q", "-b", "release"]);
+    git(&["commit", "-q", "--allow-empty", "-m", "first"]);
+
+    let mut server = Server::new();
+    let notifications = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r#"\\nGit: release @ [0-9a-f]{7,}(\\n|")"#.to_string(),
+        ))
+        .expect(2)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.current_dir(&dir).args(["--git", "--", "true"]);
+    cmd.assert().success();
+    notifications.assert();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn timestamps_follow_the_chosen_zone_and_format() {
+    let mut server = Server::new();
+    let notifications = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r#""text":"(✅ )?\[[0-9]{4}-[0-9]{2}-[0-9]{2}T[0-9:]{5} UTC\] \["#.to_string(),
+        ))
+        .expect(2)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["--utc", "--time-format", "%Y-%m-%dT%H:%M %Z", "--", "true"]);
+    cmd.assert().success();
+    notifications.assert();
+
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["--timezone", "Mars/Olympus", "--", "true"]);
+    cmd.assert()
+        .code(2)
+        .stderr(predicates::str::contains("unknown time zone"));
+}
+
+#[test]
+fn hosts_are_named_by_their_label() {
+    let mut server = Server::new();
+    let labelled = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(r"\] \[db-primary\]\\n".to_string()))
+        .expect(2)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["--host-label", "db-primary", "--", "true"]);
+    cmd.assert().success();
+    labelled.assert();
+
+    let dir = std::env::temp_dir().join(format!("sentinel-host-labels-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let real = hostname::get().unwrap().to_string_lossy().into_owned();
+    std::fs::write(dir.join("host-labels"), format!("{real} build-box\n")).unwrap();
+    let aliased = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(r"\] \[build-box\]\\n".to_string()))
+        .expect(2)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.env("SENTINEL_CONFIG_DIR", &dir).args(["--", "true"]);
+    cmd.assert().success();
+    aliased.assert();
+
+    std::fs::write(dir.join("host-labels"), format!("{real}\n")).unwrap();
+    let mut cmd = command_with_mock(&server);
+    cmd.env("SENTINEL_CONFIG_DIR", &dir).args(["--", "true"]);
+    cmd.assert()
+        .code(2)
+        .stderr(predicates::str::contains("expected `HOSTNAME LABEL`"));
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn cloud_names_the_instance_in_the_start_notification() {
+    let mut metadata = Server::new();
+    metadata
+        .mock("PUT", "/latest/api/token")
+        .match_header("X-aws-ec2-metadata-token-ttl-seconds", "60")
+        .with_body("TOKEN")
+        .create();
+    metadata
+        .mock("GET", "/latest/dynamic/instance-identity/document")
+        .match_header("X-aws-ec2-metadata-token", "TOKEN")
+        .with_body(
+            r#"{"instanceId":"i-0abc123","region":"eu-west-1","availabilityZone":"eu-west-1a"}"#,
+        )
+        .create();
+    metadata
+        .mock("GET", "/latest/meta-data/public-ipv4")
+        .with_body("203.0.113.7")
+        .create();
+    let mut server = Server::new();
+    let start = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r"Started\\ntrue\\nInstance: aws i-0abc123 in eu-west-1 \(eu-west-1a\), public IP 203\.0\.113\.7".to_string(),
+        ))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.env("SENTINEL_METADATA_URL", metadata.url())
+        .args(["--cloud", "--", "true"]);
+    cmd.assert().success();
+    start.assert();
+}
+
+#[test]
+fn outcome_icons_can_be_changed_or_left_off() {
+    let mut server = Server::new();
+    let failure = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r#""text":"🔥 \[.*Failed with exit code: 3"#.to_string(),
+        ))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["--icon", "failure=🔥", "--", "exit 3"]);
+    cmd.assert().code(3);
+    failure.assert();
+
+    let plain = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r#""text":"\[.*Finished successfully"#.to_string(),
+        ))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["--no-icons", "--", "true"]);
+    cmd.assert().success();
+    plain.assert();
+}
+
+#[test]
+fn locale_translates_the_builtin_messages() {
+    let mut server = Server::new();
+    let start = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(r"\\nGestartet\\nexit 3".to_string()))
+        .expect(1)
+        .create();
+    let failure = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r"\\nFehlgeschlagen mit Exit-Code: 3 nach [0-9.]+s\.".to_string(),
+        ))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["--locale", "de_DE.UTF-8", "--", "exit 3"]);
+    cmd.assert().code(3);
+    start.assert();
+    failure.assert();
+}
+
+#[test]
+fn origin_says_who_started_the_run() {
+    let mut server = Server::new();
+    let start = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r"Started\\ntrue\\nStarted by: deploy \(sudo from alice\), not interactive, over SSH from 203\.0\.113\.7"
+                .to_string(),
+        ))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.env("USER", "deploy")
+        .env("SUDO_USER", "alice")
+        .env("SSH_CONNECTION", "203.0.113.7 51234 10.0.0.5 22")
+        .args(["--origin", "--", "true"]);
+    cmd.assert().success();
+    start.assert();
+}
+
+#[test]
+fn failures_explain_well_known_exit_codes() {
+    let mut server = Server::new();
+    let failure = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r"Failed with exit code: 127 \(command not found\) after [0-9.]+s\.".to_string(),
+        ))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["--", "sentinel-no-such-command"]);
+    cmd.assert().code(127);
+    failure.assert();
+}
+
+#[test]
+fn the_command_can_be_redacted_or_hidden() {
+    let mut server = Server::new();
+    let redacted = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r#"Started\\ntrue --token=<redacted> --customer <redacted>""#.to_string(),
+        ))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.args([
+        "--redact",
+        r"--token=(\S+)",
+        "--redact",
+        "cust-[0-9]+",
+        "--",
+        "true --token=s3cret --customer cust-4211",
+    ]);
+    cmd.assert().success();
+    redacted.assert();
+
+    let hidden = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(r"Started\\n\(command hidden\)".to_string()))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["--hide-command", "--", "true s3cret"]);
+    cmd.assert().success();
+    hidden.assert();
+}
+
+#[test]
+fn long_messages_are_split_or_sent_as_a_file() {
+    let dir = std::env::temp_dir().join(format!("sentinel-overflow-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let template = dir.join("success.hbs");
+    std::fs::write(&template, "{{stdout}}{{stdout}}{{stdout}}").unwrap();
+
+    let mut server = Server::new();
+    let first = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r#"^\{"chat_id":"123",.*\\n\[1/2\]""#.to_string(),
+        ))
+        .expect(1)
+        .create();
+    let second = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(r#"\\n\[2/2\]""#.to_string()))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.env("SENTINEL_TEMPLATE_SUCCESS", &template)
+        .env("SENTINEL_TELEGRAM_OVERFLOW", "split")
+        .args(["--", "seq 1000 1299"]);
+    cmd.assert().success();
+    first.assert();
+    second.assert();
+
+    let document = server
+        .mock("POST", "/botTEST_TOKEN/sendDocument")
+        .match_body(Matcher::Regex("filename=\"notification.txt\"".to_string()))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.env("SENTINEL_TEMPLATE_SUCCESS", &template)
+        .env("SENTINEL_TELEGRAM_OVERFLOW", "document")
+        .args(["--", "seq 1000 1299"]);
+    cmd.assert().success();
+    document.assert();
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn output_passes_through_whatever_the_flush_mode() {
+    let server = Server::new();
+    let expected: String = (1..=20000).map(|n| format!("{n}\n")).collect();
+    for flush in ["chunk", "line", "full"] {
+        let mut cmd = command_with_mock(&server);
+        cmd.args([
+            "--flush",
+            flush,
+            "--read-buffer",
+            "65536",
+            "--",
+            "seq 1 20000",
+        ]);
+        cmd.assert().success().stdout(expected.clone());
+    }
+}
+
+#[test]
+fn merged_output_shows_both_streams_in_order() {
+    let mut server = Server::new();
+    let mock = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r#"Output:\\nfetching\\n\[stderr\] warning: slow mirror\\ndone\\n""#.to_string(),
+        ))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.args([
+        "--merge-output",
+        "--",
+        "echo fetching; sleep 0.1; echo 'warning: slow mirror' >&2; sleep 0.1; echo done",
+    ]);
+    cmd.assert().success();
+    mock.assert();
+}
+
+#[test]
+fn parallel_steps_report_the_worst_exit_code() {
+    let mut server = Server::new();
+    let report = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r"2 of 3 steps failed\.\\n.*\\nFAILED exit 3: exit code 3 .*\\nFAILED exit 5: exit code 5 .*\\nok echo b: exit code 0"
+                .to_string(),
+        ))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.args([
+        "--parallel",
+        "2",
+        "--step",
+        "exit 3",
+        "--step",
+        "echo b",
+        "--step",
+        "exit 5",
+    ]);
+    cmd.assert().code(5).stdout("[2] b\n");
+    report.assert();
+}
+
+#[test]
+fn batch_sends_one_digest_for_all_jobs() {
+    let mut server = Server::new();
+    let digest = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r"1 of 3 jobs failed\.\\nsentinel-rs batch --from -\\nFAILED exit 7: exit code 7 .*\\nok echo one: .*\\nok echo two: "
+                .to_string(),
+        ))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["batch", "--parallel", "2"])
+        .write_stdin("# nightly\necho one\n\nexit 7\necho two\n");
+    cmd.assert()
+        .code(7)
+        .stdout(predicates::str::contains("[1] one"))
+        .stdout(predicates::str::contains("[3] two"));
+    digest.assert();
+
+    let mut cmd = command_with_mock(&server);
+    cmd.arg("batch").write_stdin("# nothing\n");
+    cmd.assert()
+        .code(2)
+        .stderr(predicates::str::contains("No jobs listed in -."));
+}
+
+#[test]
+fn pipeline_runs_steps_in_dependency_order() {
+    let dir = std::env::temp_dir().join(format!("sentinel-pipeline-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("nightly.yaml");
+    std::fs::write(
+        &file,
+        "name: nightly\n\
+         concurrency: 2\n\
+         steps:\n\
+         \x20 - {name: upload, run: echo upload, depends_on: [dump]}\n\
+         \x20 - {name: dump, run: echo dump}\n\
+         \x20 - {name: flaky, run: exit 4, retries: 1, notify: failure}\n\
+         \x20 - {name: cleanup, run: echo cleanup, depends_on: [flaky]}\n",
+    )
+    .unwrap();
+    let mut server = Server::new();
+    let report = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r"1 of 4 steps failed: flaky; 1 not run\.\\nnightly\\nok upload: .*\\nok dump: .*\\nFAILED flaky: exit code 4 after 2 attempts .*\\nskipped cleanup"
+                .to_string(),
+        ))
+        .expect(1)
+        .create();
+    let step_failure = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(r"Failed with exit code: 4".to_string()))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["pipeline", "run"]).arg(&file);
+    let output = cmd.assert().code(4).get_output().stdout.clone();
+    assert_eq!(
+        String::from_utf8(output).unwrap().lines().last(),
+        Some("[upload] upload")
+    );
+    report.assert();
+    step_failure.assert();
+
+    std::fs::write(&file, "steps:\n- {name: a, run: x, depends_on: [a]}\n").unwrap();
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["pipeline", "run"]).arg(&file);
+    cmd.assert()
+        .code(2)
+        .stderr(predicates::str::contains("step \"a\" depends on itself"));
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn k8s_mode_runs_job_through_kubectl() {
+    let mut server = Server::new();
+    let mock = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(r"\] \[batch/job/sentinel-rs-".to_string()))
+        .expect(2)
+        .create();
+
+    // A stand-in kubectl for a pod that has already run and exited with 3.
+    let (dir, path) = fake_program(
+        "kubectl",
+        "job",
+        r#"dir=$(dirname "$0")
+case "$*" in
+  *"create -f -"*) cat > "$dir/manifest" ;;
+  *exitCode*) printf 3 ;;
+  *status.phase*) printf Failed ;;
+  *"logs -f job/"*) echo "pod says hi" ;;
+  *"delete job"*) touch "$dir/deleted" ;;
+esac
+"#,
+    );
+
+    let mut cmd = command_with_mock(&server);
+    cmd.env("PATH", path).args([
+        "--k8s",
+        "alpine:3",
+        "--k8s-namespace",
+        "batch",
+        "--",
+        "echo hi; exit 3",
+    ]);
+    cmd.assert()
+        .code(3)
+        .stdout(predicates::str::contains("pod says hi"));
+    mock.assert();
+
+    let manifest: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(dir.join("manifest")).unwrap()).unwrap();
+    assert_eq!(
+        manifest["spec"]["template"]["spec"]["containers"][0]["command"],
+        json!(["sh", "-c", "echo hi; exit 3"])
+    );
+    assert!(dir.join("deleted").exists());
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn docker_mode_reports_container_stats() {
+    let mut server = Server::new();
+    let start = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r"\[docker:alpine:3\]\\nStarted\\necho hi".to_string(),
+        ))
+        .expect(1)
+        .create();
+    let finish = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r"Failed with exit code: 4 after [0-9.]+s\.\\nResources: CPU avg 12\.5%, peak 12\.5%; memory peak 10\.5 MiB"
+                .to_string(),
+        ))
+        .expect(1)
+        .create();
+
+    let (dir, path) = fake_program(
+        "docker",
+        "stats",
+        r#"case "$1" in
+  run) echo "from container"; exit 4 ;;
+  stats) echo '12.50%|10.5MiB / 1.944GiB' ;;
+esac
+"#,
+    );
+
+    let mut cmd = command_with_mock(&server);
+    cmd.env("PATH", path)
+        .args(["docker", "--", "alpine:3", "echo", "hi"]);
+    cmd.assert()
+        .code(4)
+        .stdout(predicates::str::contains("from container"));
+    start.assert();
+    finish.assert();
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn github_mode_annotates_errors_and_writes_summary() {
+    let mut server = Server::new();
+    let telegram = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .expect(2)
+        .create();
+    let pending = server
+        .mock("POST", "/repos/o/r/statuses/abc123")
+        .match_header("authorization", "Bearer gh-token")
+        .match_body(Matcher::PartialJson(json!({"state": "pending"})))
+        .expect(1)
+        .create();
+    let failure = server
+        .mock("POST", "/repos/o/r/statuses/abc123")
+        .match_body(Matcher::PartialJson(
+            json!({"state": "failure", "description": "Failed with exit code 2"}),
+        ))
+        .expect(1)
+        .create();
+
+    let summary = std::env::temp_dir().join(format!("sentinel-summary-{}.md", std::process::id()));
+    let mut cmd = command_with_mock(&server);
+    cmd.env("GITHUB_STEP_SUMMARY", &summary)
+        .env("GITHUB_API_URL", server.url())
+        .env("GITHUB_REPOSITORY", "o/r")
+        .env("GITHUB_SHA", "abc123")
+        .env("GITHUB_TOKEN", "gh-token")
+        .args([
+            "--github-status",
+            "--",
+            "echo 'error: 50% broken' >&2; exit 2",
+        ]);
+    cmd.assert()
+        .code(2)
+        .stdout(predicates::str::contains("::error::error: 50%25 broken"));
+    telegram.assert();
+    pending.assert();
+    failure.assert();
+
+    let markdown = std::fs::read_to_string(&summary).unwrap();
+    std::fs::remove_file(&summary).ok();
+    assert!(markdown.contains("### sentinel-rs: Failed with exit code 2"));
+}
+
+#[test]
+fn gitlab_status_and_comment_use_ci_variables() {
+    let mut server = Server::new();
+    server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .expect(2)
+        .create();
+    let statuses = server
+        .mock("POST", "/api/v4/projects/42/statuses/abc123")
+        .match_header("private-token", "gl-token")
+        .expect(2)
+        .create();
+    let note = server
+        .mock("POST", "/api/v4/projects/42/merge_requests/5/notes")
+        .match_body(Matcher::Regex("Succeeded".to_string()))
+        .expect(1)
+        .create();
+
+    let mut cmd = command_with_mock(&server);
+    cmd.env("CI_API_V4_URL", format!("{}/api/v4", server.url()))
+        .env("CI_PROJECT_ID", "42")
+        .env("CI_COMMIT_SHA", "abc123")
+        .env("CI_MERGE_REQUEST_IID", "5")
+        .env("GITLAB_TOKEN", "gl-token")
+        .args(["--gitlab-status", "--gitlab-comment", "--", "true"]);
+    cmd.assert().success();
+    statuses.assert();
+    note.assert();
+}
+
+#[test]
+fn grafana_annotation_is_posted_on_finish() {
+    let mut server = Server::new();
+    server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .expect(2)
+        .create();
+    let annotation = server
+        .mock("POST", "/grafana/api/annotations")
+        .match_header("authorization", "Bearer glsa-test")
+        .match_body(Matcher::PartialJson(
+            json!({"tags": ["sentinel-rs", "nightly", "failure"]}),
+        ))
+        .expect(1)
+        .create();
+
+    let mut cmd = command_with_mock(&server);
+    cmd.env("GRAFANA_URL", format!("{}/grafana", server.url()))
+        .env("GRAFANA_TOKEN", "glsa-test")
+        .env("SENTINEL_JOB", "nightly")
+        .args(["--", "exit 1"]);
+    cmd.assert().code(1);
+    annotation.assert();
+}
+
+#[test]
+fn otlp_trace_is_exported_on_finish() {
+    let mut server = Server::new();
+    server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .expect(2)
+        .create();
+    let traces = server
+        .mock("POST", "/v1/traces")
+        .match_header("x-api-key", "otel-test")
+        .match_body(Matcher::PartialJson(json!({
+            "resourceSpans": [{
+                "resource": {"attributes": [
+                    {"key": "service.name", "value": {"stringValue": "batch"}}
+                ]}
+            }]
+        })))
+        .match_body(Matcher::Regex(r#""name":"run nightly""#.to_string()))
+        .expect(1)
+        .create();
+
+    let mut cmd = command_with_mock(&server);
+    cmd.env("OTEL_EXPORTER_OTLP_ENDPOINT", server.url())
+        .env("OTEL_EXPORTER_OTLP_HEADERS", "x-api-key=otel-test")
+        .env("OTEL_SERVICE_NAME", "batch")
+        .env("SENTINEL_JOB", "nightly")
+        .args(["--", "exit 3"]);
+    cmd.assert().code(3);
+    traces.assert();
+}
+
+#[test]
+fn metrics_endpoint_is_served_while_the_command_runs() {
+    let mut server = Server::new();
+    server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .expect(2)
+        .create();
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+
+    // The command scrapes its own wrapper.
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["--metrics-addr", &format!("127.0.0.1:{port}"), "--"])
+        .arg(format!(
+            "exec 3<>/dev/tcp/127.0.0.1/{port}; printf 'GET /metrics HTTP/1.0\\r\\n\\r\\n' >&3; cat <&3"
+        ));
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::contains("sentinel_running_jobs 1\n"))
+        .stdout(predicates::str::contains(
+            "# TYPE sentinel_output_bytes_total counter",
+        ));
+}
+
+#[test]
+fn status_endpoint_reports_the_run_and_its_tail() {
+    let mut server = Server::new();
+    server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .expect(2)
+        .create();
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+
+    // The command asks its own wrapper how it is doing; the answers are
+    // output too, so the tail ends in "second" either way.
+    let get = |path: &str| {
+        format!(
+            "exec 3<>/dev/tcp/127.0.0.1/{port}; printf 'GET {path} HTTP/1.0\\r\\n\\r\\n' >&3; cat <&3 >&2"
+        )
+    };
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["--status-port", &port.to_string(), "--"])
+        .arg(format!(
+            "echo first; echo second; sleep 0.3; {}; sleep 0.3; {}",
+            get("/tail?lines=1"),
+            get("/status")
+        ));
+    cmd.assert()
+        .success()
+        .stderr(predicates::str::contains(r#""running":true"#))
+        .stderr(predicates::str::contains(r#""last_line":"second""#))
+        .stderr(predicates::str::contains("\r\n\r\nsecond\n"));
+}
+
+#[test]
+fn control_socket_feeds_stdin_and_answers_status() {
+    let mut server = Server::new();
+    server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .expect(2)
+        .create();
+    let dir = std::env::temp_dir().join(format!("sentinel-control-{}", std::process::id()));
+
+    // The command talks to its own wrapper, from another process as an
+    // operator would.
+    let ctl = env!("CARGO_BIN_EXE_sentinel-rs");
+    let mut cmd = command_with_mock(&server);
+    cmd.env("XDG_RUNTIME_DIR", &dir)
+        .args(["--control", "--"])
+        .arg(format!(
+            "echo started; {ctl} ctl status >&2; {ctl} ctl stdin hello there; read line; echo \"got $line\"; {ctl} ctl eof; cat; {ctl} ctl signal NOPE || true"
+        ));
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::contains("got hello there\n"))
+        .stderr(predicates::str::contains(r#""last_line":"started""#))
+        .stderr(predicates::str::contains("unknown signal \"NOPE\""));
+    assert_eq!(
+        std::fs::read_dir(dir.join("sentinel-rs")).unwrap().count(),
+        0
+    );
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn attach_follows_the_output_and_forwards_stdin() {
+    let mut server = Server::new();
+    server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .expect(2)
+        .create();
+    let dir = std::env::temp_dir().join(format!("sentinel-attach-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let attached = dir.join("attached");
+
+    let ctl = env!("CARGO_BIN_EXE_sentinel-rs");
+    let mut cmd = command_with_mock(&server);
+    cmd.env("XDG_RUNTIME_DIR", &dir)
+        .args(["--control", "--"])
+        .arg(format!(
+            "echo one; {ctl} attach --stdin <<< typed > {} 2>&1 & read line; echo \"got $line\"; echo two",
+            attached.display()
+        ));
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::contains("got typed\n"));
+    // The client goes on its own once the run is over.
+    let mut text = String::new();
+    for _ in 0..50 {
+        text = std::fs::read_to_string(&attached).unwrap_or_default();
+        if text.ends_with("two\n") {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    assert_eq!(text, "one\ngot typed\ntwo\n");
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn bot_token_is_read_from_systemd_credential() {
+    let mut server = Server::new();
+    let mock = server
+        .mock("POST", "/botCRED_TOKEN/sendMessage")
+        .expect(2)
+        .create();
+    let dir = std::env::temp_dir().join(format!("sentinel-creds-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("tg_bot_token"), "CRED_TOKEN\n").unwrap();
+
+    let mut cmd = command_with_mock(&server);
+    cmd.env_remove("TG_BOT_TOKEN")
+        .env("CREDENTIALS_DIRECTORY", &dir)
+        .args(["--", "true"]);
+    cmd.assert().success();
+    std::fs::remove_dir_all(&dir).ok();
+    mock.assert();
+}
+
+#[cfg(not(feature = "keyring"))]
+#[test]
+fn auth_without_keyring_support_exits_2() {
+    let mut cmd = cargo_bin_cmd!("sentinel-rs");
+    cmd.args(["auth", "set", "telegram"])
+        .write_stdin("TOKEN\n123\n");
+    cmd.assert()
+        .code(2)
+        .stderr(predicates::str::contains("keyring feature"));
+}
+
+#[cfg(not(feature = "dashboard"))]
+#[test]
+fn dashboard_without_the_feature_exits_2() {
+    let mut cmd = cargo_bin_cmd!("sentinel-rs");
+    cmd.args(["dashboard", "--addr", "127.0.0.1:0"]);
+    cmd.assert()
+        .code(2)
+        .stderr(predicates::str::contains("dashboard feature"));
+}
+
+#[test]
+fn secrets_are_fetched_into_the_child_environment_only() {
+    let mut server = Server::new();
+    let messages = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex("s3cret|ssm-value".to_string()))
+        .expect(0)
+        .create();
+    server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .expect(2)
+        .create();
+    let vault = server
+        .mock("GET", "/v1/kv/data/app")
+        .match_header("x-vault-token", "vault-test")
+        .with_body(r#"{"data": {"data": {"db_password": "s3cret"}}}"#)
+        .expect(1)
+        .create();
+    let (dir, path) = fake_program(
+        "aws",
+        "ssm",
+        "[ \"$5\" = /prod/api-key ] && echo ssm-value\n",
+    );
+
+    let mut cmd = command_with_mock(&server);
+    cmd.env("PATH", path)
+        .env("VAULT_ADDR", server.url())
+        .env("VAULT_TOKEN", "vault-test")
+        .args([
+            "--secret",
+            "DB_PASSWORD=vault:kv/data/app#db_password",
+            "--secret",
+            "API_KEY=ssm:/prod/api-key",
+            "--",
+            r#"[ "$DB_PASSWORD" = s3cret ] && [ "$API_KEY" = ssm-value ]"#,
+        ]);
+    cmd.assert().success();
+    std::fs::remove_dir_all(dir).ok();
+    vault.assert();
+    messages.assert();
+}
+
+#[test]
+fn unresolvable_secret_exits_2_without_running() {
+    let mut cmd = cargo_bin_cmd!("sentinel-rs");
+    cmd.env("TG_BOT_TOKEN", "TEST_TOKEN")
+        .env("TG_CHAT_ID", "123")
+        .env_remove("VAULT_ADDR")
+        .args(["--secret", "X=vault:kv/data/app#x", "--", "echo ran"]);
+    cmd.assert()
+        .code(2)
+        .stdout(predicates::str::is_empty())
+        .stderr(predicates::str::contains("X: VAULT_ADDR is not set"));
+}
+
+#[test]
+fn proxy_flag_routes_notifications_through_the_proxy() {
+    // Plain-HTTP requests go to an HTTP proxy in absolute form, so the mock
+    // server stands in for the proxy of an unreachable API host.
+    let mut server = Server::new();
+    let mock = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .expect(2)
+        .create();
+
+    let mut cmd = command_with_mock(&server);
+    cmd.env("TG_API_BASE", "http://telegram.invalid").args([
+        "--proxy",
+        &server.url(),
+        "--",
+        "true",
+    ]);
+    cmd.assert().success();
+    mock.assert();
+}
+
+#[test]
+fn invalid_proxy_exits_2() {
+    let mut cmd = cargo_bin_cmd!("sentinel-rs");
+    cmd.env("TG_BOT_TOKEN", "TEST_TOKEN")
+        .env("TG_CHAT_ID", "123")
+        .args(["--proxy", "not a url", "--", "true"]);
+    cmd.assert()
+        .code(2)
+        .stderr(predicates::str::contains("invalid proxy not a url"));
+}
+
+#[test]
+fn unreadable_ca_bundle_exits_2() {
+    let mut cmd = cargo_bin_cmd!("sentinel-rs");
+    cmd.env("TG_BOT_TOKEN", "TEST_TOKEN")
+        .env("TG_CHAT_ID", "123")
+        .env("SENTINEL_CA_BUNDLE", "/nonexistent/ca.pem")
+        .args(["--", "echo ran"]);
+    cmd.assert()
+        .code(2)
+        .stdout(predicates::str::is_empty())
+        .stderr(predicates::str::contains("cannot read /nonexistent/ca.pem"));
+}
+
+#[test]
+fn invalid_memory_max_exits_2() {
+    let mut cmd = cargo_bin_cmd!("sentinel-rs");
+    cmd.env("TG_BOT_TOKEN", "TEST_TOKEN")
+        .env("TG_CHAT_ID", "123")
+        .args(["--memory-max", "2X", "--", "echo ran"]);
+    cmd.assert()
+        .code(2)
+        .stdout(predicates::str::is_empty())
+        .stderr(predicates::str::contains("unknown size unit"));
+}
+
+#[test]
+fn scheduling_settings_are_applied_and_shown_in_the_start_message() {
+    let mut server = Server::new();
+    let start = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r"Started\\necho.*\\nSettings: ulimit nofile=256, nice 7, ionice idle, CPUs 0"
+                .to_string(),
+        ))
+        .create();
+    let finish = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex("Finished successfully".to_string()))
+        .create();
+
+    let mut cmd = command_with_mock(&server);
+    cmd.args([
+        "--nice",
+        "7",
+        "--ionice",
+        "idle",
+        "--cpuset",
+        "0",
+        "--ulimit",
+        "nofile=256",
+        "--",
+        "echo $(nice) $(ulimit -n)",
+    ]);
+    cmd.assert().success().stdout("7 256\n");
+    start.assert();
+    finish.assert();
+}
+
+#[test]
+fn killed_command_reports_signal_name_and_exits_128_plus_n() {
+    let mut server = Server::new();
+    let start = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex("Started".to_string()))
+        .create();
+    let finish = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex("Process terminated by SIGKILL.".to_string()))
+        .create();
+
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["--", "kill -KILL $$"]);
+    cmd.assert().code(137);
+    start.assert();
+    finish.assert();
+}
+
+#[test]
+fn failed_pipeline_stage_is_named() {
+    let mut server = Server::new();
+    let start = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex("Started".to_string()))
+        .create();
+    let finish = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r"Failed with exit code: 1 \(stage 2 of 3, `false`, exited 1\) after [0-9.]+s\."
+                .to_string(),
+        ))
+        .create();
+
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["--", "echo hi | false | cat"]);
+    cmd.assert().code(1);
+    start.assert();
+    finish.assert();
+}
+
+#[test]
+fn ok_codes_count_as_success() {
+    let mut server = Server::new();
+    let finish = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r"Finished successfully with exit code 24 in [0-9.]+s\.".to_string(),
+        ))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["--ok-codes", "0,24", "--", "exit 24"]);
+    cmd.assert().success();
+    finish.assert();
+
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["--ok-codes", "0,24", "--", "exit 23"]);
+    cmd.assert().code(23);
+}
+
+#[test]
+fn invalid_ok_codes_exit_2() {
+    let mut cmd = cargo_bin_cmd!("sentinel-rs");
+    cmd.args(["--ok-codes", "0,x", "--", "true"]);
+    cmd.assert()
+        .code(2)
+        .stderr(predicates::str::contains("expected exit codes"));
+}
+
+#[test]
+fn failure_regex_fails_a_run_that_exited_0() {
+    let mut server = Server::new();
+    let start = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex("Started".to_string()))
+        .create();
+    let finish = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r"Failed with exit code: 0 after [0-9.]+s\.\\nOutput matched --failure-regex: 0 rows exported"
+                .to_string(),
+        ))
+        .create();
+
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["--failure-regex", "^0 rows", "--", "echo 0 rows exported"]);
+    cmd.assert().code(1);
+    start.assert();
+    finish.assert();
+}
+
+#[test]
+fn success_regex_must_match_for_success() {
+    let mut server = Server::new();
+    let finish = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            "No output matched --success-regex".to_string(),
+        ))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["--success-regex", "exported", "--", "echo nothing to do"]);
+    cmd.assert().code(1);
+    finish.assert();
+
+    let mut cmd = command_with_mock(&server);
+    cmd.args([
+        "--success-regex",
+        "exported",
+        "--",
+        "echo 5 rows exported >&2",
+    ]);
+    cmd.assert().success();
+}
+
+#[test]
+fn json_logs_carry_the_run_context() {
+    let server = Server::new();
+    let mut cmd = command_with_mock(&server);
+    cmd.env("RUST_LOG", "sentinel_rs=info")
+        .env("SENTINEL_JOB", "nightly-export")
+        .args(["--log-format", "json", "--", "true"]);
+    let output = cmd.assert().success().get_output().stderr.clone();
+    let line = String::from_utf8_lossy(&output)
+        .lines()
+        .find(|line| line.contains("finished successfully"))
+        .map(str::to_string)
+        .expect("a log line for the finish");
+    let record: serde_json::Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(record["level"], "INFO");
+    assert_eq!(record["span"]["job"], "nightly-export");
+    assert!(
+        record["span"]["run_id"]
+            .as_str()
+            .unwrap()
+            .starts_with("sentinel-rs-")
+    );
+}
+
+#[test]
+fn self_log_keeps_diagnostics_out_of_stderr() {
+    let log = std::env::temp_dir().join(format!("sentinel-self-{}.log", std::process::id()));
+    let mut cmd = cargo_bin_cmd!("sentinel-rs");
+    cmd.env("TG_BOT_TOKEN", "TEST_TOKEN")
+        .env("TG_CHAT_ID", "123")
+        .env("TG_API_BASE", "http://127.0.0.1:1")
+        .env("SENTINEL_CONFIG_DIR", "/nonexistent/sentinel-rs")
+        .args(["-vv", "--self-log"])
+        .arg(&log)
+        .args(["--", "echo out >&2"]);
+    cmd.assert()
+        .success()
+        .stderr(predicates::str::diff("out\n"));
+    let log_text = std::fs::read_to_string(&log).unwrap();
+    assert!(
+        log_text.contains("Bot token from TG_BOT_TOKEN"),
+        "{log_text}"
+    );
+    assert!(
+        log_text.contains("Sending start notification"),
+        "{log_text}"
+    );
+    assert!(
+        log_text.contains("Failed to send telegram message"),
+        "{log_text}"
+    );
+    std::fs::remove_file(log).ok();
+}
+
+#[test]
+fn quiet_hides_delivery_warnings() {
+    let mut cmd = cargo_bin_cmd!("sentinel-rs");
+    cmd.env("TG_BOT_TOKEN", "TEST_TOKEN")
+        .env("TG_CHAT_ID", "123")
+        .env("TG_API_BASE", "http://127.0.0.1:1")
+        .args(["-q", "--", "true"]);
+    cmd.assert().success().stderr(predicates::str::is_empty());
+}
+
+#[test]
+fn restic_parser_replaces_the_output_tail() {
+    let mut server = Server::new();
+    let finish = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r#"exit code 0 in [0-9.]+s\.\\nrestic snapshot 1a2b3c4d saved\\nAdded: 1\.2 GiB \(600 MiB stored\)\\nProcessed 10 files, 2 GiB in 0:03""#
+                .to_string(),
+        ))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.args([
+        "--parser",
+        "restic",
+        "--",
+        "echo 'Added to the repository: 1.2 GiB (600 MiB stored)'; \
+         echo 'processed 10 files, 2 GiB in 0:03'; echo 'snapshot 1a2b3c4d saved'",
+    ]);
+    cmd.assert().success();
+    finish.assert();
+}
+
+#[test]
+fn dump_parser_warns_when_the_dump_shrinks() {
+    let dir = std::env::temp_dir().join(format!("sentinel-e2e-dump-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let dump = dir.join("db.sql");
+    let mut server = Server::new();
+    let finish = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r"exit code 0 in [0-9.]+s\.\\nDump .*db\.sql: \d+ B in ".to_string(),
+        ))
+        .expect(4)
+        .create();
+    let warning = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r"db\.sql is 10 B, 90% smaller than the average of the last 3 \(100 B\)".to_string(),
+        ))
+        .expect(1)
+        .create();
+    for size in [100, 100, 100, 10] {
+        let mut cmd = command_with_mock(&server);
+        cmd.env("SENTINEL_STATE_DIR", dir.join("state")).args([
+            "--parser",
+            "dump",
+            "--",
+            &format!("head -c {size} /dev/zero > {}", dump.display()),
+        ]);
+        cmd.assert().success();
+    }
+    std::fs::remove_dir_all(&dir).ok();
+    finish.assert();
+    warning.assert();
+}
+
+#[test]
+fn train_parser_reports_progress_per_epoch() {
+    let mut server = Server::new();
+    let progress = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r"epoch 1/2 done: loss 0\.5, accuracy 0\.8\\nAbout \d+s left".to_string(),
+        ))
+        .expect(1)
+        .create();
+    let finish = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r"exit code 0 in [0-9.]+s\.\\nTraining reached epoch 2/2\\nloss 0\.3, accuracy 0\.9"
+                .to_string(),
+        ))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.env("SENTINEL_PROGRESS_INTERVAL", "0").args([
+        "--parser",
+        "train",
+        "--",
+        "echo Epoch 1/2; echo 'loss: 0.5 - accuracy: 0.8'; \
+         echo Epoch 2/2; echo 'loss: 0.3 - accuracy: 0.9'",
+    ]);
+    cmd.assert().success();
+    progress.assert();
+    finish.assert();
+}
+
+#[test]
+fn unknown_parser_exits_2() {
+    let mut cmd = cargo_bin_cmd!("sentinel-rs");
+    cmd.args(["--parser", "tar", "--", "true"]);
+    cmd.assert()
+        .code(2)
+        .stderr(predicates::str::contains("unknown parser"));
+}
+
+#[test]
+fn storage_check_notifies_when_a_disk_degrades() {
+    let (dir, path) = fake_program(
+        "smartctl",
+        "storage",
+        r#"case "$*" in
+  *--scan*) echo '{"devices": [{"name": "/dev/sda"}]}' ;;
+  *) cat "$(dirname "$0")/report.json" ;;
+esac"#,
+    );
+    let report = |reallocated: u32| {
+        let json = format!(
+            r#"{{"smart_status": {{"passed": true}}, "ata_smart_attributes": {{"table": [{{"id": 5, "raw": {{"value": {reallocated}}}}}]}}}}"#
+        );
+        std::fs::write(dir.join("report.json"), json).unwrap();
+    };
+    let mut server = Server::new();
+    let degraded = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r"Storage check: degraded\\nSMART /dev/sda: warning, 8 reallocated sectors \(was ok\)"
+                .to_string(),
+        ))
+        .expect(1)
+        .create();
+    let run = |code: i32| {
+        let mut cmd = command_with_mock(&server);
+        cmd.env("PATH", &path)
+            .env("SENTINEL_STATE_DIR", dir.join("state"))
+            .args(["check", "storage", "--no-zfs"]);
+        cmd.assert()
+            .code(code)
+            .stdout(predicates::str::contains("SMART /dev/sda: "));
+    };
+    report(0);
+    run(0);
+    report(8);
+    run(1);
+    // Still 8: nothing new to say.
+    run(1);
+    std::fs::remove_dir_all(&dir).ok();
+    degraded.assert();
+}
+
+#[test]
+fn shell_done_reports_a_command_the_shell_ran() {
+    let mut server = Server::new();
+    let failure = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r"Failed with exit code: 2 after 1m 15s\.\\nRun in an interactive shell".to_string(),
+        ))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.args([
+        "shell-done",
+        "--exit-code",
+        "2",
+        "--seconds",
+        "75",
+        "--",
+        "make -j8",
+    ]);
+    cmd.assert().success();
+    // An editor left open for an hour is not news.
+    let mut cmd = command_with_mock(&server);
+    cmd.args([
+        "shell-done",
+        "--exit-code",
+        "2",
+        "--seconds",
+        "3600",
+        "--",
+        "vim notes.txt",
+    ]);
+    cmd.assert().success();
+    failure.assert();
+
+    let mut cmd = cargo_bin_cmd!("sentinel-rs");
+    let hook = cmd.args(["shell-hook", "bash"]).assert().success();
+    let script = String::from_utf8(hook.get_output().stdout.clone()).unwrap();
+    let check = std::process::Command::new("bash")
+        .args(["-n", "-c", &script])
+        .status()
+        .unwrap();
+    assert!(check.success());
+}
+
+#[test]
+fn completions_cover_subcommands_and_flags() {
+    let mut cmd = cargo_bin_cmd!("sentinel-rs");
+    let out = cmd.args(["completions", "bash"]).assert().success();
+    let script = String::from_utf8(out.get_output().stdout.clone()).unwrap();
+    assert!(script.contains("--status-port"));
+    assert!(script.contains("shell-hook"));
+    let check = std::process::Command::new("bash")
+        .args(["-n", "-c", &script])
+        .status()
+        .unwrap();
+    assert!(check.success());
+
+    let mut cmd = cargo_bin_cmd!("sentinel-rs");
+    cmd.args(["completions", "fish"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("complete -c sentinel-rs"));
+}
+
+#[test]
+fn notify_sends_a_message_with_piped_input() {
+    let mut server = Server::new();
+    let message = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r#"\]\\nDisk usage\\n/dev/sda1 +40G""#.to_string(),
+        ))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["notify", "--stdin", "Disk", "usage"])
+        .write_stdin("/dev/sda1  40G\n\n");
+    cmd.assert().success();
+    message.assert();
+
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["notify", "--stdin"]).write_stdin("");
+    cmd.assert()
+        .code(2)
+        .stderr(predicates::str::contains("Nothing to send"));
+}
+
+#[test]
+fn doctor_checks_the_bot_and_the_chat() {
+    let mut server = Server::new();
+    let get_me = server
+        .mock("POST", "/botTEST_TOKEN/getMe")
+        .with_body(r#"{"ok": true, "result": {"id": 1, "is_bot": true, "username": "night_bot"}}"#)
+        .create();
+    let get_chat = server
+        .mock("POST", "/botTEST_TOKEN/getChat")
+        .with_body(
+            r#"{"ok": false, "error_code": 400, "description": "Bad Request: chat not found"}"#,
+        )
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["doctor", "--send-test"])
+        .env_remove("GRAFANA_URL")
+        .env_remove("SENTINEL_PLUGINS");
+    let output = cmd.assert().code(1).get_output().stdout.clone();
+    let output = String::from_utf8(output).unwrap();
+    assert!(
+        output.contains("ok       Telegram bot: @night_bot"),
+        "{output}"
+    );
+    assert!(output.contains(
+        "failing  Telegram chat: Bad Request: chat not found\n         → the bot cannot see this chat"
+    ));
+    // No test message to a chat that is not there.
+    assert!(!output.contains("Test message"));
+    get_me.assert();
+    get_chat.assert();
+
+    let mut cmd = cargo_bin_cmd!("sentinel-rs");
+    cmd.arg("doctor")
+        .env_remove("TG_BOT_TOKEN")
+        .env_remove("TG_BOT_TOKEN_FILE")
+        .env("TG_CHAT_ID", "123")
+        .env("SENTINEL_CONFIG_DIR", "/nonexistent/sentinel-rs");
+    cmd.assert().code(1).stdout(predicates::str::contains(
+        "failing  Bot token: TG_BOT_TOKEN is not set",
+    ));
+}
+
+#[test]
+fn init_writes_the_settings_that_later_runs_use() {
+    let dir = std::env::temp_dir().join(format!("sentinel-e2e-init-{}", std::process::id()));
+    let mut server = Server::new();
+    server
+        .mock("POST", "/botFILE_TOKEN/getMe")
+        .with_body(r#"{"ok": true, "result": {"id": 1, "is_bot": true, "username": "night_bot"}}"#)
+        .create();
+    server
+        .mock("POST", "/botFILE_TOKEN/getChat")
+        .with_body(r#"{"ok": true, "result": {"id": 42, "type": "private"}}"#)
+        .create();
+    let test_message = server
+        .mock("POST", "/botFILE_TOKEN/sendMessage")
+        .match_body(Matcher::PartialJson(json!({"chat_id": "42"})))
+        .with_body(r#"{"ok": true, "result": {"message_id": 1}}"#)
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.arg("init")
+        .env("SENTINEL_CONFIG_DIR", &dir)
+        .env_remove("GRAFANA_URL")
+        .env_remove("SENTINEL_PLUGINS")
+        .write_stdin("FILE_TOKEN\n42\nsplit\n\n\n\n");
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::contains("ok       Test message: sent"));
+    test_message.assert();
+    let settings = std::fs::read_to_string(dir.join("env")).unwrap();
+    assert!(settings.contains("\nTG_BOT_TOKEN=FILE_TOKEN\nTG_CHAT_ID=42\n"));
+    assert!(settings.contains("\nSENTINEL_TELEGRAM_OVERFLOW=split\n"));
+    assert!(settings.contains("\n# GRAFANA_URL="));
+
+    let mut cmd = command_with_mock(&server);
+    cmd.arg("init")
+        .env("SENTINEL_CONFIG_DIR", &dir)
+        .write_stdin("");
+    cmd.assert()
+        .code(2)
+        .stderr(predicates::str::contains("--force replaces it"));
+
+    // A run without the variables takes them from the file.
+    let run = server
+        .mock("POST", "/botFILE_TOKEN/sendMessage")
+        .match_body(Matcher::PartialJson(json!({"chat_id": "42"})))
+        .expect(2)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.env("SENTINEL_CONFIG_DIR", &dir)
+        .env_remove("TG_BOT_TOKEN")
+        .env_remove("TG_CHAT_ID")
+        .args(["--", "true"]);
+    cmd.assert().success();
+    run.assert();
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn info_lists_features_backends_and_the_files_found() {
+    let dir = std::env::temp_dir().join(format!("sentinel-e2e-info-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("profiles/night")).unwrap();
+    std::fs::write(
+        dir.join("env"),
+        "GRAFANA_URL=http://grafana:3000\nTG_CHAT_ID=1\n",
+    )
+    .unwrap();
+    std::fs::write(dir.join("profiles/night/start.tmpl"), "{{command}}").unwrap();
+    let mut cmd = cargo_bin_cmd!("sentinel-rs");
+    cmd.arg("info")
+        .env("SENTINEL_CONFIG_DIR", &dir)
+        .env("SENTINEL_PROFILE", "night")
+        .env("TG_BOT_TOKEN", "TEST_TOKEN")
+        .env("TG_CHAT_ID", "123")
+        .env_remove("GRAFANA_URL")
+        .env_remove("SENTINEL_SCRIPT");
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).unwrap();
+    std::fs::remove_dir_all(&dir).ok();
+    let built = if cfg!(feature = "dashboard") {
+        "yes"
+    } else {
+        "no"
+    };
+    assert!(
+        output.contains(&format!("\n  dashboard      {built}\n")),
+        "{output}"
+    );
+    assert!(output.contains("\n  Telegram       TG_BOT_TOKEN, from the environment\n"));
+    assert!(output.contains("\n  Grafana        GRAFANA_URL, from the settings file\n"));
+    assert!(output.contains("\n  Hook script    not set (SENTINEL_SCRIPT)\n"));
+    assert!(output.contains("Configuration (profile night, which wins):"));
+    assert!(output.contains(&format!(
+        "  {0}/profiles/night/start.tmpl\n  {0}/env: GRAFANA_URL, TG_CHAT_ID (the environment's wins)\n",
+        dir.display()
+    )));
+}
+
+#[test]
+fn config_check_reports_every_problem_at_once() {
+    let mut cmd = cargo_bin_cmd!("sentinel-rs");
+    cmd.args(["config", "check"])
+        .env("SENTINEL_CONFIG_DIR", "/nonexistent/sentinel-rs")
+        .env_remove("TG_BOT_TOKEN")
+        .env_remove("TG_BOT_TOKEN_FILE")
+        .env_remove("TG_CHAT_ID")
+        .env("TG_CHATID", "123")
+        .env("SENTINEL_QUEUE_SIZE", "none")
+        .env("SENTINEL_TELEGRAM_OVERFLOW", "wrap")
+        .env("GRAFANA_TOKEN", "glsa_x")
+        .env_remove("GRAFANA_URL");
+    let output = cmd.assert().code(1).get_output().stdout.clone();
+    let output = String::from_utf8(output).unwrap();
+    for expected in [
+        "failing  Bot token: TG_BOT_TOKEN is not set",
+        "failing  Chat: TG_CHAT_ID is not set",
+        "failing  Settings: SENTINEL_TELEGRAM_OVERFLOW: unknown overflow policy \"wrap\"",
+        "failing  Settings: SENTINEL_QUEUE_SIZE: expected a positive number",
+        "warning  Grafana: GRAFANA_TOKEN is set but GRAFANA_URL is not",
+        "warning  Unknown setting: TG_CHATID is not used by sentinel-rs\n         → did you mean TG_CHAT_ID?",
+    ] {
+        assert!(output.contains(expected), "{expected:?} not in {output}");
+    }
+
+    // A run names the first problem and the rest after it.
+    let mut cmd = cargo_bin_cmd!("sentinel-rs");
+    cmd.args(["--", "true"])
+        .env("SENTINEL_CONFIG_DIR", "/nonexistent/sentinel-rs")
+        .env("TG_BOT_TOKEN", "TEST_TOKEN")
+        .env_remove("TG_CHAT_ID")
+        .env("SENTINEL_QUEUE_SIZE", "none");
+    cmd.assert()
+        .code(2)
+        .stderr(predicates::str::contains(
+            "Failed to load Telegram configuration: TG_CHAT_ID is not set",
+        ))
+        .stderr(predicates::str::contains(
+            "Also:\n  Settings: SENTINEL_QUEUE_SIZE: expected a positive number",
+        ));
+}
+
+#[test]
+fn setup_finds_the_chat_from_a_message_to_the_bot() {
+    let dir = std::env::temp_dir().join(format!("sentinel-e2e-setup-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("env"), "# TG_CHAT_ID=\nSENTINEL_JOB=nightly\n").unwrap();
+    let mut server = Server::new();
+    server
+        .mock("POST", "/botNEW_TOKEN/getMe")
+        .with_body(r#"{"ok": true, "result": {"id": 1, "is_bot": true, "username": "night_bot"}}"#)
+        .create();
+    // The long poll gets the message; the other calls find nothing new.
+    let poll = server
+        .mock("POST", "/botNEW_TOKEN/getUpdates")
+        .match_body(Matcher::PartialJson(json!({"timeout": 20})))
+        .with_body(
+            r#"{"ok": true, "result": [{"update_id": 9, "message": {"chat": {"id": 42, "type": "private", "username": "ada"}}}]}"#,
+        )
+        .expect(1)
+        .create();
+    server
+        .mock("POST", "/botNEW_TOKEN/getUpdates")
+        .with_body(r#"{"ok": true, "result": []}"#)
+        .expect(2)
+        .create();
+    let confirmation = server
+        .mock("POST", "/botNEW_TOKEN/sendMessage")
+        .match_body(Matcher::PartialJson(json!({"chat_id": "42"})))
+        .with_body(r#"{"ok": true, "result": {"message_id": 1}}"#)
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["setup", "telegram"])
+        .env("SENTINEL_CONFIG_DIR", &dir)
+        .write_stdin("NEW_TOKEN\n");
+    cmd.assert()
+        .success()
+        .stderr(predicates::str::contains("Now send @night_bot a message"))
+        .stderr(predicates::str::contains("Found @ada (chat id 42)."));
+    poll.assert();
+    confirmation.assert();
+    let settings = std::fs::read_to_string(dir.join("env")).unwrap();
+    std::fs::remove_dir_all(&dir).ok();
+    assert_eq!(
+        settings,
+        "TG_CHAT_ID=42\nSENTINEL_JOB=nightly\nTG_BOT_TOKEN=NEW_TOKEN\n"
+    );
+}
+
+#[test]
+fn telegram_chats_lists_each_chat_the_bot_heard_from() {
+    let mut server = Server::new();
+    let updates = server
+        .mock("POST", "/botTEST_TOKEN/getUpdates")
+        .with_body(
+            r#"{"ok": true, "result": [
+                {"update_id": 1, "message": {"chat": {"id": 42, "type": "private", "username": "ada"}}},
+                {"update_id": 2, "my_chat_member": {"chat": {"id": -1001234, "type": "supergroup", "title": "Ops"}}},
+                {"update_id": 3, "message": {"chat": {"id": 42, "type": "private", "username": "ada"}}}
+            ]}"#,
+        )
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["telegram", "chats"]).env_remove("TG_CHAT_ID");
+    cmd.assert().success().stdout(
+        "CHAT ID          TYPE        NAME\n\
+         42               private     @ada\n\
+         -1001234         supergroup  Ops\n",
+    );
+    updates.assert();
+}
+
+#[test]
+fn test_reports_each_backend_with_its_latency() {
+    let mut server = Server::new();
+    let message = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex("Finished successfully".to_string()))
+        .with_body(r#"{"ok": true, "result": {"message_id": 1}}"#)
+        .expect(1)
+        .create();
+    let annotation = server
+        .mock("POST", "/grafana/api/annotations")
+        .match_body(Matcher::PartialJson(
+            json!({"tags": ["sentinel-rs", "sentinel-rs", "success"]}),
+        ))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.arg("test")
+        .env("GRAFANA_URL", format!("{}/grafana", server.url()))
+        .env("GRAFANA_TOKEN", "glsa-test")
+        .env_remove("SENTINEL_PLUGINS");
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 2, "{output}");
+    assert!(lines[0].starts_with("ok       grafana "), "{output}");
+    assert!(lines[0].ends_with(" ms  1 request"), "{output}");
+    assert!(lines[1].starts_with("ok       Telegram "), "{output}");
+    message.assert();
+    annotation.assert();
+
+    let mut server = Server::new();
+    server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .with_status(500)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.arg("test")
+        .env_remove("GRAFANA_URL")
+        .env_remove("SENTINEL_PLUGINS");
+    cmd.assert()
+        .code(1)
+        .stdout(predicates::str::starts_with("failing  Telegram "));
+}
+
+#[test]
+fn api_base_flag_picks_the_bot_api_server() {
+    let mut server = Server::new();
+    let messages = server
+        .mock("POST", "/relay/botTEST_TOKEN/sendMessage")
+        .expect(2)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.env("TG_API_BASE", "http://127.0.0.1:1")
+        .args(["--api-base", &format!("{}/relay/", server.url())])
+        .args(["--", "true"]);
+    cmd.assert().success();
+    messages.assert();
+
+    let mut cmd = command_with_mock(&server);
+    cmd.args(["--api-base", "localhost:8081", "--", "true"]);
+    cmd.assert().code(2).stderr(predicates::str::contains(
+        "expected an http:// or https:// URL",
+    ));
+
+    let mut cmd = command_with_mock(&server);
+    cmd.env("TG_API_BASE", "https://api.telegram.org/bot123:abc/")
+        .args(["--", "true"]);
+    cmd.assert()
+        .code(2)
+        .stderr(predicates::str::contains("TG_API_BASE"))
+        .stderr(predicates::str::contains("/bot<token>/"));
+}
+
+/// `SENTINEL_QUIET_HOURS` from `from` to `to` minutes from now, in UTC.
+fn quiet_hours(from: i64, to: i64) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+        / 60;
+    let time = |offset: i64| {
+        let minute = (now + offset).rem_euclid(24 * 60);
+        format!("{:02}:{:02}", minute / 60, minute % 60)
+    };
+    format!("{}-{}", time(from), time(to))
+}
+
+#[test]
+fn quiet_hours_hold_notifications_for_a_digest() {
+    let state = std::env::temp_dir().join(format!("sentinel-e2e-quiet-{}", std::process::id()));
+    let mut server = Server::new();
+    let nothing = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .expect(0)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.env("SENTINEL_STATE_DIR", &state)
+        .env("SENTINEL_QUIET_HOURS", quiet_hours(-60, 60))
+        .args(["--utc", "--", "true"]);
+    cmd.assert().success();
+    nothing.assert();
+
+    let mut server = Server::new();
+    let failure = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex("Failed with exit code: 1".to_string()))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.env("SENTINEL_STATE_DIR", &state)
+        .env("SENTINEL_QUIET_HOURS", quiet_hours(-60, 60))
+        .env("SENTINEL_QUIET_BREAKTHROUGH", "error")
+        .args(["--utc", "--", "exit 1"]);
+    cmd.assert().code(1);
+    failure.assert();
+
+    let digest = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r"Held during quiet hours \(3\):.*Started.*Finished successfully.*Started".to_string(),
+        ))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.env("SENTINEL_STATE_DIR", &state).arg("digest");
+    cmd.assert()
+        .success()
+        .stdout("Sent the 3 notification(s) held.\n");
+    digest.assert();
+    let mut cmd = command_with_mock(&server);
+    cmd.env("SENTINEL_STATE_DIR", &state).arg("digest");
+    cmd.assert().success().stdout("Nothing is held.\n");
+
+    // Once they are over, the first notification brings the digest along.
+    let mut cmd = command_with_mock(&server);
+    cmd.env("SENTINEL_STATE_DIR", &state)
+        .env("SENTINEL_QUIET_HOURS", quiet_hours(-60, 60))
+        .args(["--utc", "--", "true"]);
+    cmd.assert().success();
+    // The start message matches both; the mock made first takes it.
+    let mut server = Server::new();
+    let start = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r"Held during quiet hours \(2\):.*Finished successfully.*Started".to_string(),
+        ))
+        .expect(1)
+        .create();
+    let finish = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex("Finished successfully".to_string()))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.env("SENTINEL_STATE_DIR", &state)
+        .env("SENTINEL_QUIET_HOURS", quiet_hours(60, 120))
+        .args(["--utc", "--", "true"]);
+    cmd.assert().success();
+    start.assert();
+    finish.assert();
+    std::fs::remove_dir_all(&state).ok();
+}
+
+#[test]
+fn core_dump_location_is_reported() {
+    let pattern = std::fs::read_to_string("/proc/sys/kernel/core_pattern").unwrap_or_default();
+    if pattern.trim() != "core" {
+        // The core goes elsewhere on this machine.
+        return;
+    }
+    let dir = std::env::temp_dir().join(format!("sentinel-e2e-core-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut server = Server::new();
+    let start = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex("Started".to_string()))
+        .create();
+    let finish = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(format!(
+            r"terminated by SIGSEGV \(core dumped: {}/core[.0-9]*\)",
+            dir.display()
+        )))
+        .create();
+
+    let mut cmd = command_with_mock(&server);
+    cmd.current_dir(&dir)
+        .args(["--ulimit", "core=unlimited", "--", "kill -SEGV $$"]);
+    cmd.assert().code(139);
+    std::fs::remove_dir_all(&dir).ok();
+    start.assert();
+    finish.assert();
+}
+
+#[test]
+fn hung_telegram_api_does_not_hold_up_exit() {
+    // Accepts connections but never answers.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut cmd = cargo_bin_cmd!("sentinel-rs");
+    cmd.env("TG_BOT_TOKEN", "TEST_TOKEN")
+        .env("TG_CHAT_ID", "123")
+        .env("TG_API_BASE", format!("http://{addr}"))
+        .env("SENTINEL_CONFIG_DIR", "/nonexistent/sentinel-rs")
+        .env("SENTINEL_SHUTDOWN_TIMEOUT", "1")
+        .args(["--", "exit 3"]);
+    let started = std::time::Instant::now();
+    cmd.assert()
+        .code(3)
+        .stderr(predicates::str::contains(
+            "Gave up on 2 notification(s) still pending after 1s.",
+        ))
+        .stderr(predicates::str::contains(
+            "Undelivered failure notification:",
+        ))
+        .stderr(predicates::str::contains("Failed with exit code: 3 after"));
+    assert!(started.elapsed() < std::time::Duration::from_secs(8));
+    drop(listener);
+}
+
+#[test]
+fn invalid_shutdown_timeout_exits_2() {
+    let mut cmd = cargo_bin_cmd!("sentinel-rs");
+    cmd.env("TG_BOT_TOKEN", "TEST_TOKEN")
+        .env("TG_CHAT_ID", "123")
+        .env("SENTINEL_SHUTDOWN_TIMEOUT", "soon")
+        .args(["--", "echo ran"]);
+    cmd.assert()
+        .code(2)
+        .stdout(predicates::str::is_empty())
+        .stderr(predicates::str::contains("SENTINEL_SHUTDOWN_TIMEOUT"));
+}
+
+#[test]
+fn repeated_failures_are_counted_rather_than_sent() {
+    let state = std::env::temp_dir().join(format!("sentinel-e2e-repeat-{}", std::process::id()));
+    let run = |server: &Server, command: &str| {
+        let mut cmd = command_with_mock(server);
+        cmd.env("SENTINEL_STATE_DIR", &state)
+            .env("SENTINEL_REPEAT_WINDOW", "600")
+            .env("SENTINEL_JOB", "worker")
+            .args(["--", command]);
+        cmd.assert().failure();
+    };
+    let mut server = Server::new();
+    let first = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .expect(2)
+        .create();
+    run(&server, "echo refused >&2; exit 1");
+    first.assert();
+
+    let mut server = Server::new();
+    let nothing = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .expect(0)
+        .create();
+    for _ in 0..3 {
+        run(&server, "echo refused >&2; exit 1");
+    }
+    nothing.assert();
+
+    let mut server = Server::new();
+    let other = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r"The failure before repeated 3× in .* without being sent\..*exit code: 2".to_string(),
+        ))
+        .expect(1)
+        .create();
+    // Nor is its start message, within the window.
+    let start = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .expect(0)
+        .create();
+    run(&server, "echo refused >&2; exit 2");
+    other.assert();
+    start.assert();
+    std::fs::remove_dir_all(&state).ok();
+}
+
+#[test]
+fn failures_not_acknowledged_in_time_are_escalated() {
+    let state = std::env::temp_dir().join(format!("sentinel-e2e-escalate-{}", std::process::id()));
+    let command = |server: &Server| {
+        let mut cmd = command_with_mock(server);
+        cmd.env("SENTINEL_STATE_DIR", &state)
+            .env("SENTINEL_ESCALATE_AFTER", "1")
+            .env("SENTINEL_ESCALATE_CHAT_ID", "-100");
+        cmd
+    };
+    // The id of the failure waiting, made due as if it was sent a while ago.
+    let waiting = || {
+        let file = state.join("escalations.json");
+        let mut all: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&file).unwrap()).unwrap();
+        all[0]["raised"] = json!(all[0]["raised"].as_i64().unwrap() - 120);
+        std::fs::write(&file, all.to_string()).unwrap();
+        all[0]["id"].as_str().unwrap().to_string()
+    };
+
+    let mut server = Server::new();
+    let failure = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r"Send /ack [0-9a-f]{6} within 1 min, or this is escalated\..*exit code: 3".to_string(),
+        ))
+        .expect(1)
+        .create();
+    let start = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .expect(1)
+        .create();
+    command(&server).args(["--", "exit 3"]).assert().code(3);
+    failure.assert();
+    start.assert();
+    let id = waiting();
+
+    let mut server = Server::new();
+    let updates = server
+        .mock("POST", "/botTEST_TOKEN/getUpdates")
+        .with_body(r#"{"ok":true,"result":[]}"#)
+        .create();
+    let escalated = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::AllOf(vec![
+            Matcher::PartialJson(json!({ "chat_id": "-100" })),
+            Matcher::Regex(format!(
+                r"Escalated \(1 of 1\): not acknowledged in 1 min\..*/ack {id}"
+            )),
+        ]))
+        .expect(1)
+        .create();
+    command(&server)
+        .arg("escalate")
+        .assert()
+        .success()
+        .stdout("Acknowledged 0, escalated 1, 0 waiting.\n");
+    updates.assert();
+    escalated.assert();
+
+    // An /ack sent to the bot stops it.
+    let mut server = Server::new();
+    server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .expect(2)
+        .create();
+    command(&server).args(["--", "exit 3"]).assert().code(3);
+    let id = waiting();
+    let updates = server
+        .mock("POST", "/botTEST_TOKEN/getUpdates")
+        .with_body(
+            json!({ "ok": true, "result": [{
+                "update_id": 7,
+                "message": { "chat": { "id": 123 }, "text": format!("/ack {id}") },
+            }] })
+            .to_string(),
+        )
+        .expect(2)
+        .create();
+    let nothing = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex("Escalated".to_string()))
+        .expect(0)
+        .create();
+    command(&server)
+        .arg("escalate")
+        .assert()
+        .success()
+        .stdout("Acknowledged 1, escalated 0, 0 waiting.\n");
+    updates.assert();
+    nothing.assert();
+    command(&server)
+        .args(["escalate", "--ack", &id])
+        .assert()
+        .code(1)
+        .stderr(format!("no failure {id} is waiting to be acknowledged\n"));
+    std::fs::remove_dir_all(&state).ok();
+}
+
+#[test]
+fn ack_button_stops_escalation_and_repeats() {
+    let state = std::env::temp_dir().join(format!("sentinel-e2e-ack-{}", std::process::id()));
+    let command = |server: &Server| {
+        let mut cmd = command_with_mock(server);
+        cmd.env("SENTINEL_STATE_DIR", &state)
+            .env("SENTINEL_ESCALATE_AFTER", "1")
+            .env("SENTINEL_ESCALATE_URL", format!("{}/sms", server.url()))
+            .env("SENTINEL_REPEAT_WINDOW", "1")
+            .env("SENTINEL_JOB", "backup");
+        cmd
+    };
+    let mut server = Server::new();
+    let failure = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            r#""callback_data":"ack:[0-9a-f]{6}""#.to_string(),
+        ))
+        .expect(1)
+        .create();
+    let start = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .expect(1)
+        .create();
+    command(&server).args(["--", "exit 4"]).assert().code(4);
+    failure.assert();
+    start.assert();
+    let waiting: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(state.join("escalations.json")).unwrap())
+            .unwrap();
+    let id = waiting[0]["id"].as_str().unwrap().to_string();
+
+    let mut server = Server::new();
+    server
+        .mock("POST", "/botTEST_TOKEN/getUpdates")
+        .with_body(
+            json!({ "ok": true, "result": [{
+                "update_id": 3,
+                "callback_query": {
+                    "id": "77",
+                    "data": format!("ack:{id}"),
+                    "from": { "id": 5, "username": "ana" },
+                    "message": { "message_id": 12, "chat": { "id": 123 }, "text": "Failed" },
+                },
+            }] })
+            .to_string(),
+        )
+        .create();
+    let edited = server
+        .mock("POST", "/botTEST_TOKEN/editMessageText")
+        .match_body(Matcher::PartialJson(json!({
+            "chat_id": 123,
+            "message_id": 12,
+            "text": "Failed\n\nAcknowledged by @ana.",
+        })))
+        .with_body(r#"{"ok":true,"result":{}}"#)
+        .expect(1)
+        .create();
+    let answered = server
+        .mock("POST", "/botTEST_TOKEN/answerCallbackQuery")
+        .match_body(Matcher::PartialJson(json!({ "callback_query_id": "77" })))
+        .with_body(r#"{"ok":true,"result":true}"#)
+        .expect(1)
+        .create();
+    let sms = server.mock("POST", "/sms").expect(0).create();
+    command(&server)
+        .arg("escalate")
+        .assert()
+        .success()
+        .stdout("Acknowledged 1, escalated 0, 0 waiting.\n");
+    edited.assert();
+    answered.assert();
+    sms.assert();
+
+    // Past the repeat window, the same failure is still not sent.
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    let mut server = Server::new();
+    let nothing = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .expect(0)
+        .create();
+    command(&server).args(["--", "exit 4"]).assert().code(4);
+    nothing.assert();
+    std::fs::remove_dir_all(&state).ok();
+}
+
+#[test]
+fn severity_rules_pick_the_template_chat_and_sound() {
+    let dir = std::env::temp_dir().join(format!("sentinel-e2e-severity-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let template = dir.join("critical.tmpl");
+    std::fs::write(&template, "Timed out: {{command}} ({{severity}})").unwrap();
+    let mut server = Server::new();
+    let start = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::PartialJson(json!({
+            "chat_id": "123",
+            "disable_notification": true,
+        })))
+        .expect(1)
+        .create();
+    let critical = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::PartialJson(json!({
+            "chat_id": "-9",
+            "text": "Timed out: exit 124 (critical)",
+        })))
+        .expect(1)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.env("TG_CHAT_ID_CRITICAL", "-9")
+        .env("SENTINEL_SILENT_BELOW", "error")
+        .env("SENTINEL_TEMPLATE_FAILURE_CRITICAL", &template)
+        .env("SENTINEL_SEVERITY_RULES", "warning:exit=1-9")
+        .args(["--severity", "critical:exit=124", "--", "exit 124"]);
+    cmd.assert().code(124);
+    start.assert();
+    critical.assert();
+
+    let mut cmd = command_with_mock(&server);
+    cmd.env("SENTINEL_SEVERITY_RULES", "loud:exit=1")
+        .args(["--", "true"]);
+    cmd.assert().code(2).stderr(predicates::str::contains(
+        "SENTINEL_SEVERITY_RULES: unknown severity",
+    ));
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn maintenance_holds_back_notifications_and_says_how_many_after() {
+    let state =
+        std::env::temp_dir().join(format!("sentinel-e2e-maintenance-{}", std::process::id()));
+    let mut server = Server::new();
+    let maintenance = |server: &Server, args: &[&str]| {
+        let mut cmd = command_with_mock(server);
+        cmd.env("SENTINEL_STATE_DIR", &state)
+            .arg("maintenance")
+            .args(args);
+        cmd
+    };
+    maintenance(
+        &server,
+        &["on", "--for", "2h", "--reason", "kernel upgrade"],
+    )
+    .assert()
+    .success()
+    .stdout(predicates::str::contains("Maintenance is on until"));
+    let none = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .expect(0)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.env("SENTINEL_STATE_DIR", &state).args(["--", "exit 3"]);
+    cmd.assert().code(3);
+    none.assert();
+    none.remove();
+    maintenance(&server, &["status"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "2 notification(s) suppressed so far",
+        ))
+        .stdout(predicates::str::contains("Reason: kernel upgrade"));
+
+    // Downgraded, a failure goes to the everyday chat without a sound.
+    maintenance(&server, &["on", "--downgrade"])
+        .assert()
+        .success();
+    let downgraded = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::AllOf(vec![
+            Matcher::PartialJson(json!({
+                "chat_id": "123",
+                "disable_notification": true,
+            })),
+            Matcher::Regex(r#""text":"\[maintenance\] "#.to_string()),
+        ]))
+        .expect(2)
+        .create();
+    let mut cmd = command_with_mock(&server);
+    cmd.env("SENTINEL_STATE_DIR", &state)
+        .env("TG_CHAT_ID_ERROR", "-9")
+        .args(["--", "exit 1"]);
+    cmd.assert().code(1);
+    downgraded.assert();
+
+    let summary = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .match_body(Matcher::Regex(
+            "Maintenance ended after .*; 2 notification\\(s\\) suppressed and 2 downgraded\\."
+                .to_string(),
+        ))
+        .expect(1)
+        .create();
+    maintenance(&server, &["off"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Maintenance ended after"));
+    summary.assert();
+    maintenance(&server, &["status"])
+        .assert()
+        .success()
+        .stdout("Maintenance is not on.\n");
+
+    // SENTINEL_MAINTENANCE does the same while it is set.
+    let mut cmd = command_with_mock(&server);
+    cmd.env("SENTINEL_MAINTENANCE", "often")
+        .args(["--", "true"]);
+    cmd.assert().code(2).stderr(predicates::str::contains(
+        "SENTINEL_MAINTENANCE: unknown maintenance mode",
+    ));
+    std::fs::remove_dir_all(&state).ok();
+}
+
+#[test]
+fn muted_jobs_are_not_sent_to_telegram() {
+    let state = std::env::temp_dir().join(format!("sentinel-e2e-mute-{}", std::process::id()));
+    let mut server = Server::new();
+    let command = |server: &Server| {
+        let mut cmd = command_with_mock(server);
+        cmd.env("SENTINEL_STATE_DIR", &state);
+        cmd
+    };
+    command(&server)
+        .args([
+            "mute", "add", "--job", "noisy-*", "--for", "2h", "--reason", "flaky",
+        ])
+        .assert()
+        .success()
+        .stdout("Muted job=noisy-* as 1.\n");
+    command(&server)
+        .env("SENTINEL_MUTE", "host=staging-*,hours=09:00-17:00")
+        .args(["mute", "list"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "host=staging-*,hours=09:00-17:00 (SENTINEL_MUTE)",
+        ))
+        .stdout(predicates::str::contains("  1  job=noisy-* (another "))
+        .stdout(predicates::str::contains(": flaky"));
+
+    let none = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .expect(0)
+        .create();
+    command(&server)
+        .env("SENTINEL_JOB", "noisy-export")
+        .args(["--", "exit 1"])
+        .assert()
+        .code(1);
+    command(&server)
+        .env("SENTINEL_MUTE", "job=backup")
+        .env("SENTINEL_JOB", "backup")
+        .args(["--", "exit 1"])
+        .assert()
+        .code(1);
+    none.assert();
+    none.remove();
+
+    command(&server)
+        .args(["mute", "remove", "1"])
+        .assert()
+        .success()
+        .stdout("Unmuted 1.\n");
+    let sent = server
+        .mock("POST", "/botTEST_TOKEN/sendMessage")
+        .expect(2)
+        .create();
+    command(&server)
+        .env("SENTINEL_JOB", "noisy-export")
+        .args(["--", "exit 1"])
+        .assert()
+        .code(1);
+    sent.assert();
+    command(&server)
+        .args(["mute", "remove", "1"])
+        .assert()
+        .code(1)
+        .stderr(predicates::str::contains("nothing is muted as 1"));
+    command(&server)
+        .args(["mute", "add"])
+        .assert()
+        .code(1)
+        .stderr(predicates::str::contains("mutes everything"));
+    command(&server)
+        .env("SENTINEL_MUTE", "user=root")
+        .args(["--", "true"])
+        .assert()
+        .code(2)
+        .stderr(predicates::str::contains(
+            "SENTINEL_MUTE: unknown condition",
+        ));
+    std::fs::remove_dir_all(&state).ok();
+}